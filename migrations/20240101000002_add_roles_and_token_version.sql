@@ -0,0 +1,7 @@
+-- Create role type for access control
+CREATE TYPE user_role AS ENUM ('user', 'admin');
+
+-- Add role and token version to users
+ALTER TABLE users
+    ADD COLUMN role user_role NOT NULL DEFAULT 'user',
+    ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Track when each user last logged in successfully
+ALTER TABLE users
+    ADD COLUMN last_login_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- Create index matching the keyset pagination predicate on (created_at, id)
+CREATE INDEX idx_users_created_at_id ON users(created_at, id);
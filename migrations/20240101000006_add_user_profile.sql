@@ -0,0 +1,4 @@
+-- Optional profile fields, editable via PATCH /users/me
+ALTER TABLE users
+    ADD COLUMN name VARCHAR(100),
+    ADD COLUMN avatar_url TEXT;
@@ -0,0 +1,20 @@
+-- Outgoing webhook events and their delivery state, so retries survive a
+-- restart and the same logical event isn't sent twice within the window
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    -- Sent to receivers as event_id
+    id UUID PRIMARY KEY,
+    idempotency_key TEXT NOT NULL,
+    event TEXT NOT NULL,
+    -- Exact body that is signed and sent
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ,
+    failed_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_webhook_deliveries_idempotency_key
+    ON webhook_deliveries(idempotency_key, created_at);
+CREATE INDEX idx_webhook_deliveries_pending
+    ON webhook_deliveries(created_at)
+    WHERE delivered_at IS NULL AND failed_at IS NULL;
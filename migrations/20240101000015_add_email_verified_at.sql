@@ -0,0 +1,4 @@
+-- When the user proved they can read mail sent to `email`: by a link from
+-- the verification message, or by confirming a change to this address
+ALTER TABLE users
+    ADD COLUMN email_verified_at TIMESTAMPTZ;
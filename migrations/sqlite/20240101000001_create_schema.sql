@@ -0,0 +1,34 @@
+-- SQLite schema, equivalent to the Postgres migrations in the parent directory.
+-- UUIDs are stored as 16-byte blobs and timestamps as fixed-width RFC 3339 text
+-- so they compare correctly as strings.
+
+-- Create users table
+CREATE TABLE IF NOT EXISTS users (
+    id BLOB PRIMARY KEY NOT NULL,
+    email TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    role TEXT NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'admin')),
+    token_version INTEGER NOT NULL DEFAULT 0,
+    last_login_at TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Create index matching the keyset pagination predicate on (created_at, id)
+CREATE INDEX idx_users_created_at_id ON users(created_at, id);
+
+-- Create sessions table backing refresh tokens
+CREATE TABLE IF NOT EXISTS sessions (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- SHA-256 of the refresh token; the raw value is never stored
+    token_hash TEXT NOT NULL UNIQUE,
+    ip_address TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+-- Create index on user_id for listing a user's sessions
+CREATE INDEX idx_sessions_user_id ON sessions(user_id);
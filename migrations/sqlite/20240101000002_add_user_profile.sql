@@ -0,0 +1,3 @@
+-- Optional profile fields, editable via PATCH /users/me
+ALTER TABLE users ADD COLUMN name TEXT;
+ALTER TABLE users ADD COLUMN avatar_url TEXT;
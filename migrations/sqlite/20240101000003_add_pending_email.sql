@@ -0,0 +1,9 @@
+-- Email changes wait here until the new address is confirmed. The old
+-- address gets a cancel link; only token hashes are stored.
+ALTER TABLE users ADD COLUMN pending_email TEXT;
+ALTER TABLE users ADD COLUMN email_confirm_token_hash TEXT;
+ALTER TABLE users ADD COLUMN email_cancel_token_hash TEXT;
+ALTER TABLE users ADD COLUMN email_change_expires_at TEXT;
+
+CREATE INDEX idx_users_email_confirm_token_hash ON users(email_confirm_token_hash);
+CREATE INDEX idx_users_email_cancel_token_hash ON users(email_cancel_token_hash);
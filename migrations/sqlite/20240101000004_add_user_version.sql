@@ -0,0 +1,3 @@
+-- Optimistic concurrency for profile updates: PATCH /users/me may name the
+-- version it read and fails with 409 if another update got there first
+ALTER TABLE users ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+-- Hashes of passwords a user has since replaced, newest kept up to the
+-- configured depth, so recent passwords can't be reused
+CREATE TABLE IF NOT EXISTS password_history (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    password_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_password_history_user_id ON password_history(user_id, created_at DESC);
@@ -0,0 +1,6 @@
+-- Accounts whose owner asked for deletion; the user is purged once this
+-- passes unless the request is cancelled first
+ALTER TABLE users ADD COLUMN deletion_scheduled_at TEXT;
+
+CREATE INDEX idx_users_deletion_scheduled_at ON users(deletion_scheduled_at)
+    WHERE deletion_scheduled_at IS NOT NULL;
@@ -0,0 +1,20 @@
+-- Every request to an admin-only endpoint, including refused and failed
+-- ones, kept apart from ordinary auth events
+CREATE TABLE IF NOT EXISTS admin_audit (
+    id BLOB PRIMARY KEY NOT NULL,
+    -- The admin who made the request; for an impersonation token, the
+    -- impersonator
+    actor_id BLOB NOT NULL,
+    -- Method and route, e.g. POST /admin/users/:id/revoke-sessions
+    action TEXT NOT NULL,
+    -- The :id the route acted on, when it has one
+    target TEXT,
+    -- success or failure
+    outcome TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    request_id TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_admin_audit_actor ON admin_audit(actor_id, created_at);
+CREATE INDEX idx_admin_audit_created_at ON admin_audit(created_at);
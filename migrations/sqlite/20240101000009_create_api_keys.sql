@@ -0,0 +1,18 @@
+-- Long-lived credentials for scripts, sent as X-API-Key. Only SHA-256
+-- hashes are stored; the key itself is shown once when issued.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- Start of the key, so users can tell keys apart
+    prefix TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    -- The key replaced by the last rotation, accepted until
+    -- previous_expires_at
+    previous_key_hash TEXT UNIQUE,
+    previous_expires_at TEXT,
+    created_at TEXT NOT NULL,
+    rotated_at TEXT
+);
+
+CREATE INDEX idx_api_keys_user_id ON api_keys(user_id);
@@ -0,0 +1,3 @@
+-- Per-key quota in requests per second, for keys on a higher tier; NULL
+-- means API_KEY_RATE_LIMIT_RPS
+ALTER TABLE api_keys ADD COLUMN rate_limit_rps INTEGER CHECK (rate_limit_rps > 0);
@@ -0,0 +1,203 @@
+use crate::config::Config;
+use crate::db::Database;
+use crate::models::{Email, Role};
+use crate::routes;
+use crate::tasks::TaskManager;
+
+pub const USAGE: &str = "\
+Usage:
+  tust-starter [serve]
+  tust-starter create-user --email <EMAIL> --password <PASSWORD> [--admin]
+
+Commands:
+  serve        Run the HTTP server (default)
+  create-user  Create a user in the configured database and exit
+
+Options:
+  -h, --help   Print this help";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Serve,
+    CreateUser {
+        email: Email,
+        password: String,
+        admin: bool,
+    },
+    Help,
+}
+
+/// Parse the arguments after the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+
+    match args.next().as_deref() {
+        None | Some("serve") => match args.next() {
+            None => Ok(Command::Serve),
+            Some(arg) => Err(format!("Unexpected argument: {}", arg)),
+        },
+        Some("create-user") => parse_create_user(args),
+        Some("-h" | "--help" | "help") => Ok(Command::Help),
+        Some(other) => Err(format!("Unknown command: {}", other)),
+    }
+}
+
+fn parse_create_user(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut email = None;
+    let mut password = None;
+    let mut admin = false;
+
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} requires a value", name))
+        };
+
+        match flag.as_str() {
+            "--email" => email = Some(value("--email")?),
+            "--password" => password = Some(value("--password")?),
+            "--admin" if inline_value.is_none() => admin = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            other => return Err(format!("Unknown option for create-user: {}", other)),
+        }
+    }
+
+    let email = email.ok_or("create-user requires --email")?;
+    let email = Email::try_from(email).map_err(|e| format!("Invalid --email: {}", e))?;
+    let password = password
+        .filter(|p| !p.is_empty())
+        .ok_or("create-user requires a non-empty --password")?;
+
+    Ok(Command::CreateUser {
+        email,
+        password,
+        admin,
+    })
+}
+
+/// Create a user directly through `AuthService`, bypassing the HTTP API
+pub async fn create_user(
+    database: &Database,
+    config: &Config,
+    email: Email,
+    password: &str,
+    admin: bool,
+) -> Result<(), String> {
+    let role = if admin { Role::Admin } else { Role::User };
+    let user = routes::auth_service(database, config, &TaskManager::new())
+        .create_user(&email, password, role)
+        .await
+        .map_err(|e| format!("Failed to create user: {}", e))?;
+
+    let role = if admin { "admin" } else { "user" };
+    println!("Created {} {} ({})", role, user.email, user.id);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, PASSWORD};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn email(value: &str) -> Email {
+        Email::try_from(value.to_string()).unwrap()
+    }
+
+    #[test]
+    fn serve_is_the_default() {
+        assert_eq!(parse(args(&[])), Ok(Command::Serve));
+        assert_eq!(parse(args(&["serve"])), Ok(Command::Serve));
+        assert_eq!(parse(args(&["--help"])), Ok(Command::Help));
+    }
+
+    #[test]
+    fn create_user_accepts_both_flag_forms() {
+        let expected = || {
+            Ok(Command::CreateUser {
+                email: email("admin@example.com"),
+                password: PASSWORD.to_string(),
+                admin: true,
+            })
+        };
+        let inline_password = format!("--password={}", PASSWORD);
+
+        let separate = parse(args(&[
+            "create-user",
+            "--email",
+            "admin@example.com",
+            "--password",
+            PASSWORD,
+            "--admin",
+        ]));
+        let inline = parse(args(&[
+            "create-user",
+            "--admin",
+            "--email=ADMIN@example.com",
+            &inline_password,
+        ]));
+
+        assert_eq!(separate, expected());
+        assert_eq!(inline, expected());
+    }
+
+    #[test]
+    fn create_user_rejects_bad_arguments() {
+        let cases: [&[&str]; 6] = [
+            &["create-user", "--password", PASSWORD],
+            &["create-user", "--email", "user@example.com"],
+            &[
+                "create-user",
+                "--email",
+                "not-an-email",
+                "--password",
+                PASSWORD,
+            ],
+            &["create-user", "--email"],
+            &["create-user", "--admin=yes"],
+            &["serve", "--port"],
+        ];
+
+        for case in cases {
+            assert!(parse(args(case)).is_err(), "{:?} parsed", case);
+        }
+        assert!(parse(args(&["migrate"])).is_err());
+    }
+
+    #[tokio::test]
+    async fn create_user_stores_the_user_with_its_role() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[]);
+
+        create_user(
+            &database,
+            &config,
+            email("admin@example.com"),
+            PASSWORD,
+            true,
+        )
+        .await
+        .unwrap();
+
+        let user = database
+            .user_repository()
+            .find_by_email(&email("admin@example.com"))
+            .await
+            .unwrap()
+            .expect("user created");
+        assert_eq!(user.role, Role::Admin);
+        let app = test_support::app(&database, config);
+        test_support::log_in(&app, "admin@example.com", PASSWORD).await;
+    }
+}
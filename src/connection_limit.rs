@@ -0,0 +1,167 @@
+use axum_server::accept::Accept;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+type Active = Arc<Mutex<HashMap<IpAddr, usize>>>;
+
+/// Acceptor that caps concurrent connections per peer IP. Connections over
+/// the cap are closed right after accept, before any TLS handshake or HTTP
+/// parsing, so a single address can't exhaust file descriptors.
+#[derive(Clone)]
+pub struct ConnectionLimit<A> {
+    inner: A,
+    /// `None` tracks nothing and admits every connection
+    max_per_ip: Option<usize>,
+    active: Active,
+}
+
+impl<A> ConnectionLimit<A> {
+    pub fn new(inner: A, max_per_ip: Option<usize>) -> Self {
+        Self {
+            inner,
+            max_per_ip,
+            active: Active::default(),
+        }
+    }
+
+    fn admit(&self, ip: IpAddr) -> Option<ConnectionGuard> {
+        let mut active = self.active.lock().expect("connection table lock poisoned");
+        let count = active.entry(ip).or_insert(0);
+        if self.max_per_ip.is_some_and(|max| *count >= max) {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionGuard {
+            ip,
+            active: self.active.clone(),
+        })
+    }
+}
+
+impl<A, S> Accept<TcpStream, S> for ConnectionLimit<A>
+where
+    A: Accept<Tracked<TcpStream>, S>,
+    A::Stream: Send + 'static,
+    A::Service: Send + 'static,
+    A::Future: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(A::Stream, A::Service)>> + Send>>;
+
+    fn accept(&self, stream: TcpStream, service: S) -> Self::Future {
+        let guard = match (self.max_per_ip, stream.peer_addr()) {
+            (None, _) => None,
+            (Some(max), Ok(peer)) => match self.admit(peer.ip()) {
+                Some(guard) => Some(guard),
+                None => {
+                    tracing::debug!(peer = %peer.ip(), max, "Refusing connection over per-IP limit");
+                    return Box::pin(std::future::ready(Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        "too many connections from this address",
+                    ))));
+                }
+            },
+            (Some(_), Err(e)) => return Box::pin(std::future::ready(Err(e))),
+        };
+
+        Box::pin(self.inner.accept(
+            Tracked {
+                stream,
+                _guard: guard,
+            },
+            service,
+        ))
+    }
+}
+
+/// Releases the connection's slot when dropped
+struct ConnectionGuard {
+    ip: IpAddr,
+    active: Active,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().expect("connection table lock poisoned");
+        if let Some(count) = active.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// A stream that holds its peer's connection slot for as long as it lives
+pub struct Tracked<I> {
+    stream: I,
+    _guard: Option<ConnectionGuard>,
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for Tracked<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for Tracked<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_each_address_separately_and_frees_slots_on_drop() {
+        let limit = ConnectionLimit::new((), Some(2));
+        let a = IpAddr::from([10, 0, 0, 1]);
+        let b = IpAddr::from([10, 0, 0, 2]);
+
+        let first = limit.admit(a).expect("under the cap");
+        let _second = limit.admit(a).expect("at the cap");
+        assert!(limit.admit(a).is_none());
+        assert!(limit.admit(b).is_some());
+
+        drop(first);
+        assert!(limit.admit(a).is_some());
+    }
+}
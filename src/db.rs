@@ -0,0 +1,242 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::LevelFilter;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{ConnectOptions, PgPool, SqlitePool};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::repositories::{
+    AdminAuditRepository, ApiKeyRepository, PgAdminAuditRepository, PgApiKeyRepository,
+    PgSessionRepository, PgUserRepository, PgWebhookRepository, SessionRepository,
+    SqliteAdminAuditRepository, SqliteApiKeyRepository, SqliteSessionRepository,
+    SqliteUserRepository, SqliteWebhookRepository, UserRepository, WebhookRepository,
+};
+
+const MAX_CONNECTIONS: u32 = 5;
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Arbitrary key for the session-level advisory lock guarding migrations
+const MIGRATION_LOCK_ID: i64 = 0x7275_7374_6d69_6772;
+
+/// How sqlx logs the statements it runs, from `DB_LOG_STATEMENTS` and
+/// `DB_SLOW_STATEMENT_MS`
+#[derive(Clone, Copy, Debug)]
+pub struct StatementLogging {
+    /// Every statement at `info`; otherwise at sqlx's default `debug`
+    pub statements: bool,
+    /// Statements slower than this at `warn`; `None` never flags them
+    pub slow_threshold: Option<Duration>,
+}
+
+impl StatementLogging {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            statements: config.db_log_statements,
+            slow_threshold: config.db_slow_statement_ms.map(Duration::from_millis),
+        }
+    }
+
+    fn apply<O: ConnectOptions>(self, options: O) -> O {
+        let level = if self.statements {
+            LevelFilter::Info
+        } else {
+            LevelFilter::Debug
+        };
+        let options = options.log_statements(level);
+        match self.slow_threshold {
+            Some(threshold) => options.log_slow_statements(LevelFilter::Warn, threshold),
+            None => options.log_slow_statements(LevelFilter::Off, Duration::MAX),
+        }
+    }
+}
+
+/// Connection pool for the backend selected by the `DATABASE_URL` scheme:
+/// `postgres://` / `postgresql://` or `sqlite:`
+#[derive(Clone)]
+pub enum Database {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+impl Database {
+    pub async fn connect(url: &str, logging: StatementLogging) -> Result<Self, sqlx::Error> {
+        if url.starts_with("sqlite:") {
+            let options =
+                logging.apply(SqliteConnectOptions::from_str(url)?.create_if_missing(true));
+            let pool = SqlitePoolOptions::new()
+                .max_connections(MAX_CONNECTIONS)
+                .acquire_timeout(ACQUIRE_TIMEOUT)
+                .connect_with(options)
+                .await?;
+            return Ok(Self::Sqlite(pool));
+        }
+
+        let options = logging.apply(PgConnectOptions::from_str(url)?);
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .acquire_timeout(ACQUIRE_TIMEOUT)
+            .connect_with(options)
+            .await?;
+        Ok(Self::Postgres(pool))
+    }
+
+    pub fn backend(&self) -> &'static str {
+        match self {
+            Self::Postgres(_) => "postgres",
+            Self::Sqlite(_) => "sqlite",
+        }
+    }
+
+    pub async fn migrate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::Postgres(pool) => migrate_postgres(pool).await,
+            Self::Sqlite(pool) => Ok(sqlx::migrate!("./migrations/sqlite").run(pool).await?),
+        }
+    }
+
+    pub fn user_repository(&self) -> Arc<dyn UserRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(PgUserRepository::new(pool.clone())),
+            Self::Sqlite(pool) => Arc::new(SqliteUserRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn session_repository(&self) -> Arc<dyn SessionRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(PgSessionRepository::new(pool.clone())),
+            Self::Sqlite(pool) => Arc::new(SqliteSessionRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn webhook_repository(&self) -> Arc<dyn WebhookRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(PgWebhookRepository::new(pool.clone())),
+            Self::Sqlite(pool) => Arc::new(SqliteWebhookRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn admin_audit_repository(&self) -> Arc<dyn AdminAuditRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(PgAdminAuditRepository::new(pool.clone())),
+            Self::Sqlite(pool) => Arc::new(SqliteAdminAuditRepository::new(pool.clone())),
+        }
+    }
+
+    pub fn api_key_repository(&self) -> Arc<dyn ApiKeyRepository> {
+        match self {
+            Self::Postgres(pool) => Arc::new(PgApiKeyRepository::new(pool.clone())),
+            Self::Sqlite(pool) => Arc::new(SqliteApiKeyRepository::new(pool.clone())),
+        }
+    }
+
+    /// Run a statement and discard its result
+    pub async fn execute(&self, query: &str) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => sqlx::query(query).execute(pool).await.map(|_| ()),
+            Self::Sqlite(pool) => sqlx::query(query).execute(pool).await.map(|_| ()),
+        }
+    }
+}
+
+/// Run pending migrations while holding a Postgres advisory lock, so when
+/// several instances boot at once one migrates and the rest wait, then find
+/// nothing left to apply.
+async fn migrate_postgres(pool: &PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    // Lock and unlock must happen on the same connection
+    let mut conn = pool.acquire().await?;
+
+    tracing::debug!("Waiting for migration lock");
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_ID)
+        .execute(&mut *conn)
+        .await?;
+
+    let result = sqlx::migrate!("./migrations").run(&mut *conn).await;
+
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK_ID)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(result?)
+}
+
+/// Fixed-width UTC timestamp for SQLite, where timestamps are text and are
+/// compared as strings
+pub fn sqlite_timestamp(value: DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn log_settings(vars: &[(&str, &str)]) -> String {
+        let logging = StatementLogging::from_config(&test_support::config(vars));
+        format!("{:?}", logging.apply(SqliteConnectOptions::new()))
+    }
+
+    #[test]
+    fn connect_options_reflect_the_logging_config() {
+        assert!(log_settings(&[
+            ("DB_LOG_STATEMENTS", "true"),
+            ("DB_SLOW_STATEMENT_MS", "250"),
+        ])
+        .contains(
+            "LogSettings { statements_level: Info, slow_statements_level: Warn, \
+             slow_statements_duration: 250ms }"
+        ));
+        assert!(log_settings(&[]).contains(
+            "LogSettings { statements_level: Debug, slow_statements_level: Warn, \
+             slow_statements_duration: 1s }"
+        ));
+        assert!(log_settings(&[("DB_SLOW_STATEMENT_MS", "0")])
+            .contains("LogSettings { statements_level: Debug, slow_statements_level: Off,"));
+    }
+
+    /// Needs PostgreSQL: `TEST_DATABASE_URL=postgres://... make test-postgres`.
+    /// Runs in a throwaway schema, so any database the URL can create
+    /// schemas in will do.
+    #[tokio::test]
+    #[ignore = "needs PostgreSQL at TEST_DATABASE_URL"]
+    async fn concurrent_postgres_migrations_apply_each_migration_once() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+        let schema = format!("migrate_test_{}", uuid::Uuid::new_v4().simple());
+        let admin = PgPool::connect(&url).await.unwrap();
+        sqlx::query(&format!("CREATE SCHEMA {}", schema))
+            .execute(&admin)
+            .await
+            .unwrap();
+
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let scoped = format!("{}{}options=-csearch_path%3D{}", url, separator, schema);
+        let logging = StatementLogging {
+            statements: false,
+            slow_threshold: None,
+        };
+        let first = Database::connect(&scoped, logging).await.unwrap();
+        let second = Database::connect(&scoped, logging).await.unwrap();
+
+        let (a, b) = tokio::join!(first.migrate(), second.migrate());
+        let applied: Vec<(i64, i64)> = sqlx::query_as(&format!(
+            "SELECT version, COUNT(*) FROM {}._sqlx_migrations GROUP BY version",
+            schema
+        ))
+        .fetch_all(&admin)
+        .await
+        .unwrap();
+        sqlx::query(&format!("DROP SCHEMA {} CASCADE", schema))
+            .execute(&admin)
+            .await
+            .unwrap();
+
+        assert!(a.is_ok(), "first runner: {:?}", a.err());
+        assert!(b.is_ok(), "second runner: {:?}", b.err());
+        assert_eq!(applied.len(), sqlx::migrate!("./migrations").iter().count());
+        assert!(applied.iter().all(|(_, count)| *count == 1));
+    }
+}
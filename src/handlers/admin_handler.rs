@@ -0,0 +1,417 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use uuid::Uuid;
+
+use super::api_key_handler::ApiKeyHandlerError;
+use super::auth_handler::AuthHandlerError;
+use super::{error_response_with_detail, ErrorCode, ValidatedJson, ValidatedQuery};
+use crate::middleware::{MaintenanceMode, RateLimitLayer, UserConcurrencyLimit};
+use crate::models::{
+    ApiKeyResponse, AuditLogPage, Claims, ImpersonationResponse, ListAuditQuery, MaintenanceStatus,
+    RateLimitStatus, SetApiKeyRateLimitRequest,
+};
+use crate::services::audit_service::AuditError;
+use crate::services::{ApiKeyService, AuditService, AuthService};
+
+/// Revoke every session and outstanding token for a user
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/revoke-sessions",
+    params(("id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 204, description = "Sessions revoked"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin role required"),
+        (status = 404, description = "User not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "admin"
+)]
+pub async fn revoke_sessions(
+    State(auth_service): State<AuthService>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    auth_service.revoke_sessions(user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Act as a user. The returned access token names the admin in its `act`
+/// claim and expires after `IMPERSONATION_TOKEN_MINUTES`.
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/impersonate",
+    params(("id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Impersonation token issued", body = ImpersonationResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin role required, or caller is already impersonating"),
+        (status = 404, description = "User not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "admin"
+)]
+pub async fn impersonate(
+    State(auth_service): State<AuthService>,
+    Extension(claims): Extension<Claims>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<ImpersonationResponse>, AuthHandlerError> {
+    let response = auth_service.impersonate(&claims, user_id).await?;
+    Ok(Json(response))
+}
+
+/// Requests made to admin endpoints, newest first unless `order=asc`.
+/// Filters combine with AND; `from` is inclusive and `to` exclusive.
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    params(ListAuditQuery),
+    responses(
+        (status = 200, description = "Page of audit events", body = AuditLogPage),
+        (status = 400, description = "Invalid filter, pagination or sort parameters"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin role required")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "admin"
+)]
+pub async fn list_audit_events(
+    State(audit_service): State<AuditService>,
+    ValidatedQuery(query): ValidatedQuery<ListAuditQuery>,
+) -> Result<Json<AuditLogPage>, AuditHandlerError> {
+    let page = audit_service.list(query).await?;
+    Ok(Json(page))
+}
+
+/// The limiters `rate_limit_status` reports on
+#[derive(Clone)]
+pub struct RateLimitState {
+    /// `None` when rate limiting is disabled
+    pub rate_limit: Option<RateLimitLayer>,
+    pub concurrency: UserConcurrencyLimit,
+}
+
+/// Current rate-limit quotas and usage, to see why a client is throttled
+#[utoipa::path(
+    get,
+    path = "/admin/rate-limit/status",
+    responses(
+        (status = 200, description = "Rate-limit state", body = RateLimitStatus),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin role required")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "admin"
+)]
+pub async fn rate_limit_status(State(state): State<RateLimitState>) -> Json<RateLimitStatus> {
+    Json(RateLimitStatus {
+        global: state.rate_limit.as_ref().map(RateLimitLayer::status),
+        concurrency: state.concurrency.status(),
+    })
+}
+
+/// Move an API key to its own quota tier, or back to
+/// `API_KEY_RATE_LIMIT_RPS` with `null`. Applies from the key's next request.
+#[utoipa::path(
+    put,
+    path = "/admin/api-keys/{id}/rate-limit",
+    params(("id" = Uuid, Path, description = "API key id")),
+    request_body = SetApiKeyRateLimitRequest,
+    responses(
+        (status = 200, description = "Quota updated", body = ApiKeyResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin role required"),
+        (status = 404, description = "API key not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "admin"
+)]
+pub async fn set_api_key_rate_limit(
+    State(api_keys): State<ApiKeyService>,
+    Path(id): Path<Uuid>,
+    ValidatedJson(request): ValidatedJson<SetApiKeyRateLimitRequest>,
+) -> Result<Json<ApiKeyResponse>, ApiKeyHandlerError> {
+    let api_key = api_keys.set_rate_limit(id, request.rate_limit_rps).await?;
+    Ok(Json(api_key))
+}
+
+/// Turn maintenance mode on or off without restarting
+#[utoipa::path(
+    put,
+    path = "/admin/maintenance",
+    request_body = MaintenanceStatus,
+    responses(
+        (status = 200, description = "Maintenance mode updated", body = MaintenanceStatus),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin role required")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "admin"
+)]
+pub async fn set_maintenance(
+    State(maintenance): State<MaintenanceMode>,
+    Json(request): Json<MaintenanceStatus>,
+) -> impl IntoResponse {
+    maintenance.set_enabled(request.enabled);
+    tracing::warn!(
+        "Maintenance mode {}",
+        if request.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+
+    Json(MaintenanceStatus {
+        enabled: maintenance.is_enabled(),
+    })
+}
+
+pub struct AuditHandlerError(AuditError);
+
+impl From<AuditError> for AuditHandlerError {
+    fn from(error: AuditError) -> Self {
+        AuditHandlerError(error)
+    }
+}
+
+impl IntoResponse for AuditHandlerError {
+    fn into_response(self) -> Response {
+        let detail = match &self.0 {
+            AuditError::DatabaseError(e) => Some(e.to_string()),
+            _ => None,
+        };
+
+        let (status, code, message) = match self.0 {
+            AuditError::InvalidOutcome(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "outcome must be success or failure",
+            ),
+            AuditError::InvalidSortOrder(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidSortOrder,
+                "Invalid sort order",
+            ),
+            AuditError::InvalidTimeRange => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "to must not be before from",
+            ),
+            AuditError::DatabaseError(sqlx::Error::PoolTimedOut) => {
+                return super::pool_timed_out_response();
+            }
+            AuditError::DatabaseError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                "Database error",
+            ),
+        };
+
+        error_response_with_detail(status, code, message, detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::Claims;
+    use crate::test_support::{self, json, me, request, send, PASSWORD};
+    use axum::http::{Method, StatusCode};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use chrono::Utc;
+    use std::time::Duration;
+
+    fn claims(token: &str) -> Claims {
+        let payload = token.split('.').nth(1).unwrap();
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn revoking_sessions_rejects_existing_access_tokens() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+
+        let response = me(&app, &token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let id = json(response).await["id"].as_str().unwrap().to_string();
+
+        let uri = format!("/admin/users/{}/revoke-sessions", id);
+        let response = send(&app, request(Method::POST, &uri, Some(&admin), None)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        assert_eq!(me(&app, &token).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(me(&app, &admin).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn impersonation_token_acts_as_the_user_on_behalf_of_the_admin() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("IMPERSONATION_TOKEN_MINUTES", "5")]);
+        let app = test_support::app(&database, config);
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let user_id = json(me(&app, &token).await).await["id"].clone();
+        let admin_id = json(me(&app, &admin).await).await["id"].clone();
+
+        let uri = format!("/admin/users/{}/impersonate", user_id.as_str().unwrap());
+        let response = send(&app, request(Method::POST, &uri, Some(&admin), None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let impersonation = json(response).await["token"].as_str().unwrap().to_string();
+
+        // Authenticates as the target...
+        let response = me(&app, &impersonation).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await["email"], "user@example.com");
+        // ...while naming the admin, for a few minutes only
+        let claims = claims(&impersonation);
+        assert_eq!(claims.sub, user_id);
+        let actor = claims.act.expect("act claim");
+        assert_eq!(actor.sub, admin_id);
+        assert_eq!(actor.email, "admin@example.com");
+        assert!(claims.exp <= (Utc::now() + chrono::Duration::minutes(5)).timestamp());
+
+        let response = send(
+            &app,
+            request(Method::GET, "/admin/audit", Some(&admin), None),
+        )
+        .await;
+        let events = json(response).await["events"].as_array().unwrap().clone();
+        let recorded = events
+            .iter()
+            .find(|event| event["action"] == "POST /admin/users/:id/impersonate")
+            .expect("impersonation audited");
+        assert_eq!(recorded["actor_id"], admin_id);
+        assert_eq!(recorded["target"], user_id);
+    }
+
+    #[tokio::test]
+    async fn impersonation_token_cannot_impersonate_again() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let other = test_support::sign_up(&app, "other@example.com", PASSWORD).await;
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let other_id = json(me(&app, &other).await).await["id"].clone();
+
+        // An admin impersonating another admin still can't chain
+        let second = test_support::sign_up_admin(&app, &database, "second@example.com").await;
+        let second_id = json(me(&app, &second).await).await["id"].clone();
+        let uri = format!("/admin/users/{}/impersonate", second_id.as_str().unwrap());
+        let response = send(&app, request(Method::POST, &uri, Some(&admin), None)).await;
+        let impersonation = json(response).await["token"].as_str().unwrap().to_string();
+
+        let uri = format!("/admin/users/{}/impersonate", other_id.as_str().unwrap());
+        let response = send(
+            &app,
+            request(Method::POST, &uri, Some(&impersonation), None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_status_reflects_throttling() {
+        let database = test_support::database().await;
+        // Tokens are good across apps on the same database, so set up on an
+        // unlimited one and spend the limited app's budget only on purpose
+        let setup = test_support::app(&database, test_support::config(&[]));
+        let admin = test_support::sign_up_admin(&setup, &database, "admin@example.com").await;
+        let config = test_support::config(&[("RATE_LIMIT_RPS", "1"), ("RATE_LIMIT_BURST", "1")]);
+        let app = test_support::app(&database, config);
+
+        let healthz = || request(Method::GET, "/healthz/live", None, None);
+        assert_eq!(send(&app, healthz()).await.status(), StatusCode::OK);
+        assert_eq!(
+            send(&app, healthz()).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        // The status endpoint sits behind the same limiter
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        let uri = "/admin/rate-limit/status";
+        let response = send(&app, request(Method::GET, uri, Some(&admin), None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let global = json(response).await["global"].clone();
+        assert_eq!(global["burst_size"], 1);
+        assert_eq!(global["throttled_total"], 1);
+        assert!(global["last_throttled_at"].is_string());
+    }
+
+    /// `request_id` of each event `GET /admin/audit?{query}` lists, oldest first
+    async fn request_ids(app: &axum::Router, admin: &str, query: &str) -> Vec<String> {
+        let uri = format!("/admin/audit?order=asc&{}", query);
+        let response = send(app, request(Method::GET, &uri, Some(admin), None)).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", query);
+        json(response).await["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["request_id"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn audit_events_filter_by_action_and_time_range() {
+        use crate::models::AdminAuditEntry;
+        use chrono::TimeZone;
+
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let admin_id = json(me(&app, &admin).await).await["id"]
+            .as_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        // Hourly from midnight, alternating actions; well before the
+        // requests this test makes, which are audited too
+        let midnight = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let audit = database.admin_audit_repository();
+        for hour in 0..4 {
+            let action = if hour % 2 == 0 {
+                "POST /admin/users/:id/revoke-sessions"
+            } else {
+                "PUT /admin/maintenance"
+            };
+            let entry = AdminAuditEntry {
+                id: uuid::Uuid::new_v4(),
+                actor_id: admin_id,
+                action: action.to_string(),
+                target: None,
+                status: 204,
+                request_id: Some(format!("hour-{}", hour)),
+                created_at: midnight + chrono::Duration::hours(hour),
+            };
+            audit.record(&entry).await.unwrap();
+        }
+        assert_eq!(
+            request_ids(&app, &admin, "action=PUT%20/admin/maintenance").await,
+            ["hour-1", "hour-3"]
+        );
+        // `from` is inclusive and `to` exclusive
+        assert_eq!(
+            request_ids(
+                &app,
+                &admin,
+                "from=2024-01-01T01:00:00Z&to=2024-01-01T03:00:00Z"
+            )
+            .await,
+            ["hour-1", "hour-2"]
+        );
+        assert_eq!(
+            request_ids(
+                &app,
+                &admin,
+                "action=POST%20/admin/users/:id/revoke-sessions\
+                 &from=2024-01-01T01:00:00Z&to=2024-01-02T00:00:00Z"
+            )
+            .await,
+            ["hour-2"]
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use uuid::Uuid;
+
+use super::{error_response_with_detail, ErrorCode, ValidatedJson};
+use crate::models::{Claims, CreateApiKeyRequest};
+use crate::services::api_key_service::ApiKeyError;
+use crate::services::ApiKeyService;
+
+/// List the current user's API keys. The keys themselves are never shown
+/// again after they are issued.
+#[utoipa::path(
+    get,
+    path = "/users/me/api-keys",
+    responses(
+        (status = 200, description = "API keys", body = [ApiKeyResponse]),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn list_api_keys(
+    State(api_keys): State<ApiKeyService>,
+    Extension(claims): Extension<Claims>,
+) -> Result<impl IntoResponse, ApiKeyHandlerError> {
+    let user_id = claims.user_id().map_err(|_| ApiKeyError::InvalidKey)?;
+    Ok(Json(api_keys.list(user_id).await?))
+}
+
+/// Issue an API key, sent as `X-API-Key` in place of a bearer token on the
+/// `/users/me` routes
+#[utoipa::path(
+    post,
+    path = "/users/me/api-keys",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 201, description = "The new key, shown only this once", body = NewApiKeyResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn create_api_key(
+    State(api_keys): State<ApiKeyService>,
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(request): ValidatedJson<CreateApiKeyRequest>,
+) -> Result<impl IntoResponse, ApiKeyHandlerError> {
+    let user_id = claims.user_id().map_err(|_| ApiKeyError::InvalidKey)?;
+    let key = api_keys.create(user_id, request).await?;
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+/// Replace an API key. The old key keeps working for
+/// `API_KEY_ROTATION_OVERLAP_SECS`, then only the new one does.
+#[utoipa::path(
+    post,
+    path = "/users/me/api-keys/{id}/rotate",
+    params(("id" = Uuid, Path, description = "API key id")),
+    responses(
+        (status = 200, description = "The replacement key, shown only this once", body = NewApiKeyResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "API key not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn rotate_api_key(
+    State(api_keys): State<ApiKeyService>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiKeyHandlerError> {
+    let user_id = claims.user_id().map_err(|_| ApiKeyError::InvalidKey)?;
+    Ok(Json(api_keys.rotate(user_id, id).await?))
+}
+
+/// Revoke an API key at once, including a replaced key still in its overlap
+#[utoipa::path(
+    delete,
+    path = "/users/me/api-keys/{id}",
+    params(("id" = Uuid, Path, description = "API key id")),
+    responses(
+        (status = 204, description = "API key revoked"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "API key not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn revoke_api_key(
+    State(api_keys): State<ApiKeyService>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiKeyHandlerError> {
+    let user_id = claims.user_id().map_err(|_| ApiKeyError::InvalidKey)?;
+    api_keys.revoke(user_id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub struct ApiKeyHandlerError(ApiKeyError);
+
+impl From<ApiKeyError> for ApiKeyHandlerError {
+    fn from(error: ApiKeyError) -> Self {
+        ApiKeyHandlerError(error)
+    }
+}
+
+impl IntoResponse for ApiKeyHandlerError {
+    fn into_response(self) -> axum::response::Response {
+        let detail = match &self.0 {
+            ApiKeyError::DatabaseError(e) => Some(e.to_string()),
+            _ => None,
+        };
+
+        let (status, code, message) = match self.0 {
+            ApiKeyError::NotFound => (
+                StatusCode::NOT_FOUND,
+                ErrorCode::ApiKeyNotFound,
+                "API key not found",
+            ),
+            ApiKeyError::InvalidKey => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::InvalidApiKey,
+                "Invalid API key",
+            ),
+            ApiKeyError::DatabaseError(sqlx::Error::PoolTimedOut) => {
+                return super::pool_timed_out_response();
+            }
+            ApiKeyError::DatabaseError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                "Database error",
+            ),
+        };
+
+        error_response_with_detail(status, code, message, detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, json, request, send, PASSWORD};
+    use axum::http::{Method, Request, StatusCode};
+    use axum::{body::Body, Router};
+    use serde_json::Value;
+    use std::time::Duration;
+
+    fn with_key(key: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/users/me")
+            .header("x-api-key", key)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn create_key(app: &Router, token: &str) -> Value {
+        let body = serde_json::json!({ "name": "deploy script" });
+        let response = send(
+            app,
+            request(Method::POST, "/users/me/api-keys", Some(token), Some(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        json(response).await
+    }
+
+    async fn rotate_key(app: &Router, token: &str, id: &Value) -> String {
+        let uri = format!("/users/me/api-keys/{}/rotate", id.as_str().unwrap());
+        let response = send(app, request(Method::POST, &uri, Some(token), None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        json(response).await["key"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn created_key_authenticates_and_is_listed_without_its_secret() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+
+        let created = create_key(&app, &token).await;
+        let key = created["key"].as_str().unwrap();
+        assert!(key.starts_with("tsk_"));
+        assert!(key.starts_with(created["api_key"]["prefix"].as_str().unwrap()));
+
+        let response = send(&app, with_key(key)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await["email"], "user@example.com");
+
+        let response = send(
+            &app,
+            request(Method::GET, "/users/me/api-keys", Some(&token), None),
+        )
+        .await;
+        let listed = json(response).await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["name"], "deploy script");
+        assert!(!listed.to_string().contains(key));
+
+        let response = send(&app, with_key("tsk_not-a-key")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json(response).await["code"], "invalid_api_key");
+    }
+
+    #[tokio::test]
+    async fn both_keys_work_during_the_rotation_overlap() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let created = create_key(&app, &token).await;
+
+        let new_key = rotate_key(&app, &token, &created["api_key"]["id"]).await;
+
+        let old_key = created["key"].as_str().unwrap();
+        assert_eq!(send(&app, with_key(old_key)).await.status(), StatusCode::OK);
+        assert_eq!(
+            send(&app, with_key(&new_key)).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_new_key_works_after_the_overlap() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("API_KEY_ROTATION_OVERLAP_SECS", "1")]);
+        let app = test_support::app(&database, config);
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let created = create_key(&app, &token).await;
+
+        let new_key = rotate_key(&app, &token, &created["api_key"]["id"]).await;
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        let old_key = created["key"].as_str().unwrap();
+        let response = send(&app, with_key(old_key)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            send(&app, with_key(&new_key)).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_are_throttled_independently_at_their_own_quotas() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("API_KEY_RATE_LIMIT_RPS", "1")]);
+        let app = test_support::app(&database, config);
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let standard = create_key(&app, &token).await;
+        let premium = create_key(&app, &token).await;
+
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let uri = format!(
+            "/admin/api-keys/{}/rate-limit",
+            premium["api_key"]["id"].as_str().unwrap()
+        );
+        let body = serde_json::json!({ "rate_limit_rps": 4 });
+        let response = send(&app, request(Method::PUT, &uri, Some(&admin), Some(body))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await["rate_limit_rps"], 4);
+
+        let standard = standard["key"].as_str().unwrap();
+        let premium = premium["key"].as_str().unwrap();
+        let status = |key: &str| {
+            let app = app.clone();
+            let request = with_key(key);
+            async move { send(&app, request).await.status() }
+        };
+
+        assert_eq!(status(standard).await, StatusCode::OK);
+        let throttled = send(&app, with_key(standard)).await;
+        assert_eq!(throttled.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(throttled.headers()["x-ratelimit-limit"], "1");
+        assert_eq!(json(throttled).await["code"], "rate_limited");
+
+        // The standard key running dry leaves the premium key's budget alone
+        for _ in 0..4 {
+            assert_eq!(status(premium).await, StatusCode::OK);
+        }
+        assert_eq!(status(premium).await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status(standard).await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_override_needs_a_positive_quota_and_an_existing_key() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let created = create_key(&app, &token).await;
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let uri = |id: &str| format!("/admin/api-keys/{}/rate-limit", id);
+        let id = created["api_key"]["id"].as_str().unwrap();
+
+        let body = serde_json::json!({ "rate_limit_rps": 0 });
+        let response = send(
+            &app,
+            request(Method::PUT, &uri(id), Some(&admin), Some(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = serde_json::json!({ "rate_limit_rps": 50 });
+        let missing = uri(&uuid::Uuid::new_v4().to_string());
+        let response = send(
+            &app,
+            request(Method::PUT, &missing, Some(&admin), Some(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Keys' owners can't raise their own quota
+        let body = serde_json::json!({ "rate_limit_rps": 50 });
+        let response = send(
+            &app,
+            request(Method::PUT, &uri(id), Some(&token), Some(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // `null` returns the key to the default tier
+        let body = serde_json::json!({ "rate_limit_rps": null });
+        let response = send(
+            &app,
+            request(Method::PUT, &uri(id), Some(&admin), Some(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await["rate_limit_rps"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn revoked_key_stops_working() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let created = create_key(&app, &token).await;
+
+        let uri = format!(
+            "/users/me/api-keys/{}",
+            created["api_key"]["id"].as_str().unwrap()
+        );
+        let response = send(&app, request(Method::DELETE, &uri, Some(&token), None)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let key = created["key"].as_str().unwrap();
+        assert_eq!(
+            send(&app, with_key(key)).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let response = send(&app, request(Method::DELETE, &uri, Some(&token), None)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json(response).await["code"], "api_key_not_found");
+    }
+}
@@ -1,8 +1,28 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
-use serde_json::json;
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, FromRequestParts, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError, Extension, Json,
+};
+use futures_util::StreamExt;
+use std::net::SocketAddr;
 
-use crate::models::{LoginRequest, RegisterRequest};
-use crate::services::AuthService;
+use super::{
+    client_fingerprint, error_response, error_response_with_detail, ErrorCode, JsonBody,
+    JsonBodyError, ValidatedJson,
+};
+use crate::config::TrustedProxies;
+use crate::models::ExportRecord;
+use crate::models::{
+    ChangeEmailRequest, ChangePasswordRequest, Claims, ClientInfo, EmailChangeTokenRequest,
+    ForgotPasswordRequest, IntrospectRequest, LoginRequest, LoginResponse, RefreshRequest,
+    RegisterRequest, ResendVerificationRequest, ResetPasswordRequest, TokenClaimsResponse,
+    VerifyEmailRequest, VerifyPasswordRequest,
+};
+use crate::services::auth_service::AuthError;
+use crate::services::{AuditService, AuthService};
 
 /// Register a new user
 #[utoipa::path(
@@ -11,17 +31,21 @@ use crate::services::AuthService;
     request_body = RegisterRequest,
     responses(
         (status = 201, description = "User registered successfully", body = LoginResponse),
-        (status = 400, description = "Invalid request"),
-        (status = 409, description = "User already exists")
+        (status = 400, description = "Invalid request, password shorter than 8 characters or failed CAPTCHA"),
+        (status = 403, description = "Registration disabled or email domain not allowed"),
+        (status = 409, description = "User already exists"),
+        (status = 503, description = "Database or CAPTCHA provider temporarily unavailable")
     ),
     tag = "auth"
 )]
 pub async fn register(
     State(auth_service): State<AuthService>,
-    Json(request): Json<RegisterRequest>,
+    cookie: Option<RequestCookie>,
+    client: ClientInfo,
+    ValidatedJson(request): ValidatedJson<RegisterRequest>,
 ) -> Result<impl IntoResponse, AuthHandlerError> {
-    let response = auth_service.register(request).await?;
-    Ok((StatusCode::CREATED, Json(response)))
+    let response = auth_service.register(request, client).await?;
+    Ok((StatusCode::CREATED, token_response(cookie, response)))
 }
 
 /// Login with existing credentials
@@ -31,42 +55,1329 @@ pub async fn register(
     request_body = LoginRequest,
     responses(
         (status = 200, description = "Login successful", body = LoginResponse),
-        (status = 401, description = "Invalid credentials")
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Invalid credentials"),
+        (status = 503, description = "Database temporarily unavailable")
     ),
     tag = "auth"
 )]
 pub async fn login(
     State(auth_service): State<AuthService>,
-    Json(request): Json<LoginRequest>,
+    cookie: Option<RequestCookie>,
+    client: ClientInfo,
+    JsonBody(request): JsonBody<LoginRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let response = auth_service.login(request, client).await?;
+    Ok(token_response(cookie, response))
+}
+
+/// Exchange a refresh token for a new access and refresh token. In cookie
+/// mode the body may be empty and the token is read from the cookie.
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    request_body(content = Option<RefreshRequest>),
+    responses(
+        (status = 200, description = "Tokens refreshed", body = LoginResponse),
+        (status = 400, description = "Invalid request or missing refresh token"),
+        (status = 401, description = "Invalid or expired refresh token"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn refresh(
+    State(auth_service): State<AuthService>,
+    cookie: Option<RequestCookie>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, Response> {
+    let request =
+        refresh_request(cookie.is_some(), &headers, &body).map_err(IntoResponse::into_response)?;
+    let response = auth_service
+        .refresh(request, client)
+        .await
+        .map_err(|e| AuthHandlerError(e).into_response())?;
+
+    Ok(token_response(cookie, response))
+}
+
+/// Log out: revoke the session behind a refresh token and clear the cookie.
+/// Unknown tokens are ignored so logging out twice is harmless.
+#[utoipa::path(
+    delete,
+    path = "/auth/refresh",
+    request_body(content = Option<RefreshRequest>),
+    responses(
+        (status = 204, description = "Logged out"),
+        (status = 400, description = "Invalid request or missing refresh token"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn logout(
+    State(auth_service): State<AuthService>,
+    cookie: Option<RequestCookie>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, Response> {
+    let request =
+        refresh_request(cookie.is_some(), &headers, &body).map_err(IntoResponse::into_response)?;
+    auth_service
+        .logout(request)
+        .await
+        .map_err(|e| AuthHandlerError(e).into_response())?;
+
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    if let Some(cookie) = cookie {
+        response
+            .headers_mut()
+            .insert(header::SET_COOKIE, cookie.clear());
+    }
+    Ok(response)
+}
+
+/// Re-confirm the current user's password before a sensitive action. No
+/// token is issued. Limited to `VERIFY_PASSWORD_PER_MINUTE` calls per user.
+#[utoipa::path(
+    post,
+    path = "/users/me/verify-password",
+    request_body = VerifyPasswordRequest,
+    responses(
+        (status = 204, description = "Password matches"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token, or wrong password"),
+        (status = 429, description = "Too many attempts"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn verify_password(
+    State(auth_service): State<AuthService>,
+    Extension(claims): Extension<Claims>,
+    JsonBody(request): JsonBody<VerifyPasswordRequest>,
+) -> Result<StatusCode, AuthHandlerError> {
+    let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+    auth_service
+        .verify_current_password(user_id, &request.password)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Change the current user's password. Every session and token is revoked.
+#[utoipa::path(
+    put,
+    path = "/users/me/password",
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 204, description = "Password changed; sign in again"),
+        (status = 400, description = "Invalid request, new password shorter than 8 characters or recently used"),
+        (status = 401, description = "Missing or invalid token, or wrong current password"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn change_password(
+    State(auth_service): State<AuthService>,
+    Extension(claims): Extension<Claims>,
+    ValidatedJson(request): ValidatedJson<ChangePasswordRequest>,
 ) -> Result<impl IntoResponse, AuthHandlerError> {
-    let response = auth_service.login(request).await?;
+    let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+    auth_service
+        .change_password(user_id, &request.current_password, &request.new_password)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Schedule the current user's account for deletion after
+/// `ACCOUNT_DELETION_GRACE_DAYS`. Every session is signed out; sign in again
+/// and cancel to keep the account.
+#[utoipa::path(
+    delete,
+    path = "/users/me",
+    responses(
+        (status = 202, description = "Deletion scheduled; see `deletion_scheduled_at`", body = UserResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn delete_me(
+    State(auth_service): State<AuthService>,
+    Extension(claims): Extension<Claims>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+    let user = auth_service.schedule_deletion(user_id).await?;
+    Ok((StatusCode::ACCEPTED, Json(user)))
+}
+
+/// Keep an account that is scheduled for deletion
+#[utoipa::path(
+    post,
+    path = "/users/me/cancel-deletion",
+    responses(
+        (status = 200, description = "Deletion cancelled", body = UserResponse),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn cancel_deletion(
+    State(auth_service): State<AuthService>,
+    Extension(claims): Extension<Claims>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+    let user = auth_service.cancel_deletion(user_id).await?;
+    Ok(Json(user))
+}
+
+/// The services `export_me` reads from
+#[derive(Clone)]
+pub struct DataExportState {
+    pub auth_service: AuthService,
+    pub audit_service: AuditService,
+}
+
+/// Download everything held about the current user as newline-delimited
+/// JSON, one `ExportRecord` per line, streamed as it's read. Limited to
+/// `DATA_EXPORT_PER_HOUR` calls per user.
+#[utoipa::path(
+    get,
+    path = "/users/me/export",
+    responses(
+        (status = 200, description = "The user's data, one record per line", body = ExportRecord, content_type = "application/x-ndjson"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 429, description = "Too many exports"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn export_me(
+    State(state): State<DataExportState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+    let records = state.auth_service.export_data(user_id).await?;
+    let audit_events = state.audit_service.export_for_user(user_id);
+    let records = records.map(|record| record.map_err(BoxError::from)).chain(
+        audit_events.map(|event| event.map(ExportRecord::AuditEvent).map_err(BoxError::from)),
+    );
+
+    // A failure mid-stream can only cut the response short; the client sees
+    // a truncated body rather than an error status
+    let lines = records.map(|record| -> Result<Vec<u8>, BoxError> {
+        let record = record.inspect_err(|e| tracing::warn!("Data export failed: {}", e))?;
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        Ok(line)
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    ))
+}
+
+/// Request a new email address. It takes effect only once confirmed from
+/// the new address; the current address is notified and can cancel.
+#[utoipa::path(
+    put,
+    path = "/users/me/email",
+    request_body = ChangeEmailRequest,
+    responses(
+        (status = 202, description = "Confirmation sent; `pending_email` is set", body = UserResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Email domain not allowed"),
+        (status = 409, description = "Email already in use"),
+        (status = 503, description = "Database or email delivery temporarily unavailable")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn change_email(
+    State(auth_service): State<AuthService>,
+    Extension(claims): Extension<Claims>,
+    JsonBody(request): JsonBody<ChangeEmailRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+    let user = auth_service
+        .request_email_change(user_id, request.email)
+        .await?;
+    Ok((StatusCode::ACCEPTED, Json(user)))
+}
+
+/// Confirm a pending email change with the token sent to the new address
+#[utoipa::path(
+    post,
+    path = "/auth/email/confirm",
+    request_body = EmailChangeTokenRequest,
+    responses(
+        (status = 200, description = "Email changed", body = UserResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Invalid or expired token"),
+        (status = 409, description = "Email already in use"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn confirm_email(
+    State(auth_service): State<AuthService>,
+    JsonBody(request): JsonBody<EmailChangeTokenRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let user = auth_service.confirm_email_change(&request.token).await?;
+    Ok(Json(user))
+}
+
+/// Cancel a pending email change with the token sent to the old address.
+/// Also revokes every session of the account.
+#[utoipa::path(
+    post,
+    path = "/auth/email/cancel",
+    request_body = EmailChangeTokenRequest,
+    responses(
+        (status = 204, description = "Change cancelled and sessions revoked"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Invalid or expired token"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn cancel_email_change(
+    State(auth_service): State<AuthService>,
+    JsonBody(request): JsonBody<EmailChangeTokenRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    auth_service.cancel_email_change(&request.token).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Email a password-reset link. Always 202, whether or not the address
+/// belongs to an account.
+#[utoipa::path(
+    post,
+    path = "/auth/password/forgot",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 202, description = "Reset link sent if the account exists"),
+        (status = 400, description = "Invalid request"),
+        (status = 503, description = "Database or email delivery temporarily unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn forgot_password(
+    State(auth_service): State<AuthService>,
+    JsonBody(request): JsonBody<ForgotPasswordRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    auth_service.request_password_reset(&request.email).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Set a new password with the token from a reset link. The token works
+/// once and every session is revoked.
+#[utoipa::path(
+    post,
+    path = "/auth/password/reset",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 204, description = "Password changed; sign in again"),
+        (status = 400, description = "Invalid request, new password shorter than 8 characters or recently used"),
+        (status = 401, description = "Invalid, expired or already used token"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn reset_password(
+    State(auth_service): State<AuthService>,
+    ValidatedJson(request): ValidatedJson<ResetPasswordRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    auth_service
+        .reset_password(&request.token, &request.new_password)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Mark the account's address verified with the token from a verification
+/// link
+#[utoipa::path(
+    post,
+    path = "/auth/email/verify",
+    request_body = VerifyEmailRequest,
+    responses(
+        (status = 200, description = "Email verified", body = UserResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Invalid or expired token, or the address has changed"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn verify_email(
+    State(auth_service): State<AuthService>,
+    JsonBody(request): JsonBody<VerifyEmailRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let user = auth_service.verify_email(&request.token).await?;
+    Ok(Json(user))
+}
+
+/// Send a new verification link to `email`, or without one to the user
+/// named by the bearer token. Given an email it always answers 200: nothing
+/// is sent to unknown or verified addresses, or past
+/// `VERIFICATION_RESENDS_PER_HOUR`.
+#[utoipa::path(
+    post,
+    path = "/auth/resend-verification",
+    request_body = ResendVerificationRequest,
+    responses(
+        (status = 200, description = "Link sent if the account exists and is unverified"),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "No email given and no valid bearer token"),
+        (status = 503, description = "Database or email delivery temporarily unavailable")
+    ),
+    security((), ("bearer_auth" = [])),
+    tag = "auth"
+)]
+pub async fn resend_verification(
+    State(auth_service): State<AuthService>,
+    headers: HeaderMap,
+    JsonBody(request): JsonBody<ResendVerificationRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    match request.email {
+        Some(email) => auth_service.resend_verification(&email).await?,
+        None => {
+            let token = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .ok_or(AuthError::InvalidToken)?;
+            let claims = auth_service.verify_token(token).await?;
+            auth_service.check_token_binding(&claims, client_fingerprint(&headers).as_deref())?;
+            let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+            auth_service.resend_verification_for(user_id).await?;
+        }
+    }
+    Ok(StatusCode::OK)
+}
+
+/// Check whether an access token is currently valid (admin only). Expired,
+/// revoked or malformed tokens return `{"active": false}` rather than an error.
+#[utoipa::path(
+    post,
+    path = "/auth/introspect",
+    request_body = IntrospectRequest,
+    responses(
+        (status = 200, description = "Introspection result", body = IntrospectResponse),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin role required"),
+        (status = 503, description = "Database temporarily unavailable")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "auth"
+)]
+pub async fn introspect(
+    State(auth_service): State<AuthService>,
+    JsonBody(request): JsonBody<IntrospectRequest>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let response = auth_service.introspect(&request.token).await?;
     Ok(Json(response))
 }
 
+/// The claims of the presented access token, read from the token itself
+/// rather than the user record. Cheaper than `GET /users/me` for checking
+/// role and remaining validity.
+#[utoipa::path(
+    get,
+    path = "/auth/me",
+    responses(
+        (status = 200, description = "Claims of the presented token", body = TokenClaimsResponse),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "auth"
+)]
+pub async fn token_claims(Extension(claims): Extension<Claims>) -> impl IntoResponse {
+    Json(TokenClaimsResponse::new(
+        claims,
+        chrono::Utc::now().timestamp(),
+    ))
+}
+
+/// Public keys for verifying access tokens (RS256 only)
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "JSON Web Key Set"),
+        (status = 404, description = "Tokens are signed with a shared secret")
+    ),
+    tag = "auth"
+)]
+pub async fn jwks(State(auth_service): State<AuthService>) -> impl IntoResponse {
+    match auth_service.jwks() {
+        Some(jwks) => Json(jwks).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+const REFRESH_COOKIE_NAME: &str = "refresh_token";
+
+/// Delivers refresh tokens in an HttpOnly cookie instead of the response
+/// body. Added to the auth routes as an extension when
+/// `REFRESH_TOKEN_COOKIE` is on.
+#[derive(Clone)]
+pub struct RefreshCookie {
+    max_age_secs: i64,
+    // Served over TLS directly, so every request is HTTPS
+    tls: bool,
+    trusted_proxies: TrustedProxies,
+}
+
+impl RefreshCookie {
+    pub fn new(expiration_days: i64, tls: bool, trusted_proxies: TrustedProxies) -> Self {
+        Self {
+            max_age_secs: expiration_days * 24 * 60 * 60,
+            tls,
+            trusted_proxies,
+        }
+    }
+
+    /// Whether the client reached us over HTTPS: with TLS here, or through a
+    /// trusted proxy that says so in `X-Forwarded-Proto`. The header is
+    /// ignored from anyone else, since clients can send it too.
+    fn is_https(&self, parts: &Parts) -> bool {
+        if self.tls {
+            return true;
+        }
+
+        let trusted = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .is_some_and(|ConnectInfo(addr)| self.trusted_proxies.trusts(addr.ip()));
+        if !trusted {
+            return false;
+        }
+
+        // With several proxies the first entry is the client-facing scheme
+        parts
+            .headers
+            .get("x-forwarded-proto")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+    }
+
+    fn read(headers: &HeaderMap) -> Option<String> {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, value)| *name == REFRESH_COOKIE_NAME && !value.is_empty())
+            .map(|(_, value)| value.to_string())
+    }
+}
+
+/// `RefreshCookie` resolved for one request. Absent when cookie mode is
+/// off.
+pub struct RequestCookie {
+    max_age_secs: i64,
+    // Browsers drop Secure cookies set over plain HTTP
+    secure: bool,
+}
+
+impl RequestCookie {
+    fn set(&self, token: &str) -> HeaderValue {
+        self.header(token, self.max_age_secs)
+    }
+
+    fn clear(&self) -> HeaderValue {
+        self.header("", 0)
+    }
+
+    // Scoped to the refresh path so the token is never sent anywhere else
+    fn header(&self, value: &str, max_age_secs: i64) -> HeaderValue {
+        HeaderValue::from_str(&format!(
+            "{}={}; Path=/auth/refresh; Max-Age={}; HttpOnly;{} SameSite=Strict",
+            REFRESH_COOKIE_NAME,
+            value,
+            max_age_secs,
+            if self.secure { " Secure;" } else { "" }
+        ))
+        .expect("refresh tokens are URL-safe base64")
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestCookie {
+    // Only ever seen through `Option<RequestCookie>`, as `None`
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let cookie = parts
+            .extensions
+            .get::<RefreshCookie>()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        Ok(Self {
+            max_age_secs: cookie.max_age_secs,
+            secure: cookie.is_https(parts),
+        })
+    }
+}
+
+/// The refresh token from the JSON body, or from the cookie when the body is
+/// empty and cookie mode is on
+fn refresh_request(
+    cookie_mode: bool,
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> Result<RefreshRequest, RefreshTokenError> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return cookie_mode
+            .then(|| RefreshCookie::read(headers))
+            .flatten()
+            .map(|refresh_token| RefreshRequest { refresh_token })
+            .ok_or(RefreshTokenError::Missing);
+    }
+
+    Json::<RefreshRequest>::from_bytes(body)
+        .map(|Json(request)| request)
+        .map_err(|rejection| RefreshTokenError::Body(rejection.into()))
+}
+
+enum RefreshTokenError {
+    Missing,
+    Body(JsonBodyError),
+}
+
+impl IntoResponse for RefreshTokenError {
+    fn into_response(self) -> Response {
+        match self {
+            RefreshTokenError::Missing => error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "Missing refresh token",
+            ),
+            RefreshTokenError::Body(error) => error.into_response(),
+        }
+    }
+}
+
+/// In cookie mode the refresh token moves from the body to `Set-Cookie`
+fn token_response(cookie: Option<RequestCookie>, mut body: LoginResponse) -> Response {
+    let set_cookie =
+        cookie.and_then(|cookie| body.refresh_token.take().map(|token| cookie.set(&token)));
+
+    let mut response = Json(body).into_response();
+    if let Some(value) = set_cookie {
+        response.headers_mut().insert(header::SET_COOKIE, value);
+    }
+    response
+}
+
 // Error handling
 #[derive(Debug)]
-pub struct AuthHandlerError(crate::services::auth_service::AuthError);
+pub struct AuthHandlerError(AuthError);
 
-impl From<crate::services::auth_service::AuthError> for AuthHandlerError {
-    fn from(error: crate::services::auth_service::AuthError) -> Self {
+impl From<AuthError> for AuthHandlerError {
+    fn from(error: AuthError) -> Self {
         AuthHandlerError(error)
     }
 }
 
 impl IntoResponse for AuthHandlerError {
     fn into_response(self) -> axum::response::Response {
-        use crate::services::auth_service::AuthError;
-
-        let (status, message) = match self.0 {
-            AuthError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "Invalid credentials"),
-            AuthError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
-            AuthError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error"),
-            AuthError::PasswordHashError => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "Password hashing error")
-            }
-            AuthError::JwtError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "JWT error"),
+        // Pool exhaustion is transient overload, not a server bug
+        if let AuthError::DatabaseError(sqlx::Error::PoolTimedOut) = self.0 {
+            return super::pool_timed_out_response();
+        }
+
+        let detail = match &self.0 {
+            AuthError::DatabaseError(e) => Some(e.to_string()),
+            AuthError::JwtError(e) => Some(e.to_string()),
+            AuthError::CaptchaUnavailable(e) => Some(e.to_string()),
+            AuthError::MailUnavailable(e) => Some(e.to_string()),
+            AuthError::RegistrationHookFailed(e) => Some(format!("{:#}", e)),
+            _ => None,
+        };
+
+        let (status, code, message) = match self.0 {
+            AuthError::InvalidCredentials => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::InvalidCredentials,
+                "Invalid credentials",
+            ),
+            AuthError::PasswordReused => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::PasswordReused,
+                "Password was used recently",
+            ),
+            AuthError::UserAlreadyExists => (
+                StatusCode::CONFLICT,
+                ErrorCode::UserExists,
+                "User already exists",
+            ),
+            AuthError::UserNotFound => (
+                StatusCode::NOT_FOUND,
+                ErrorCode::UserNotFound,
+                "User not found",
+            ),
+            AuthError::InvalidToken => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::InvalidToken,
+                "Invalid token",
+            ),
+            AuthError::TokenRevoked => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::TokenRevoked,
+                "Token has been revoked",
+            ),
+            AuthError::TokenTooOld => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::TokenTooOld,
+                "Token is too old",
+            ),
+            AuthError::UnknownKeyId(_) => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::UnknownKeyId,
+                "Token signed with an unknown key",
+            ),
+            AuthError::TokenBindingMismatch => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::TokenBindingMismatch,
+                "Token is bound to a different client",
+            ),
+            AuthError::SessionNotFound => (
+                StatusCode::NOT_FOUND,
+                ErrorCode::SessionNotFound,
+                "Session not found",
+            ),
+            AuthError::SessionExpired => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::SessionExpired,
+                "Session expired after inactivity",
+            ),
+            AuthError::NestedImpersonation => (
+                StatusCode::FORBIDDEN,
+                ErrorCode::Forbidden,
+                "Impersonation tokens cannot impersonate",
+            ),
+            AuthError::RegistrationDisabled => (
+                StatusCode::FORBIDDEN,
+                ErrorCode::RegistrationDisabled,
+                "Registration is disabled",
+            ),
+            AuthError::EmailTooLong(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "Email address is too long",
+            ),
+            AuthError::EmailDomainNotAllowed(_) => (
+                StatusCode::FORBIDDEN,
+                ErrorCode::EmailDomainNotAllowed,
+                "Email domain not allowed",
+            ),
+            AuthError::CaptchaFailed => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::CaptchaFailed,
+                "CAPTCHA verification failed",
+            ),
+            AuthError::CaptchaUnavailable(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::ServiceUnavailable,
+                "CAPTCHA verification temporarily unavailable",
+            ),
+            AuthError::MailUnavailable(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::ServiceUnavailable,
+                "Email delivery temporarily unavailable",
+            ),
+            AuthError::RegistrationHookFailed(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                "Registration could not be completed",
+            ),
+            AuthError::DatabaseError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                "Database error",
+            ),
+            AuthError::PasswordHashError => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                "Password hashing error",
+            ),
+            AuthError::JwtError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                "JWT error",
+            ),
         };
 
-        (status, Json(json!({ "error": message }))).into_response()
+        error_response_with_detail(status, code, message, detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, json, request, send, RSA_KEY_PATH};
+    use axum::http::{Method, Request};
+
+    #[tokio::test]
+    async fn auth_errors_carry_stable_codes() {
+        let cases = [
+            (AuthError::InvalidCredentials, 401, "invalid_credentials"),
+            (AuthError::PasswordReused, 400, "password_reused"),
+            (AuthError::UserAlreadyExists, 409, "user_exists"),
+            (AuthError::UserNotFound, 404, "user_not_found"),
+            (AuthError::InvalidToken, 401, "invalid_token"),
+            (AuthError::TokenRevoked, 401, "token_revoked"),
+            (AuthError::TokenTooOld, 401, "token_too_old"),
+            (AuthError::UnknownKeyId("old".into()), 401, "unknown_key_id"),
+            (
+                AuthError::TokenBindingMismatch,
+                401,
+                "token_binding_mismatch",
+            ),
+            (AuthError::SessionNotFound, 404, "session_not_found"),
+            (AuthError::SessionExpired, 401, "session_expired"),
+            (AuthError::NestedImpersonation, 403, "forbidden"),
+            (
+                AuthError::RegistrationDisabled,
+                403,
+                "registration_disabled",
+            ),
+            (AuthError::EmailTooLong(255), 400, "invalid_request"),
+            (
+                AuthError::EmailDomainNotAllowed("spam.test".into()),
+                403,
+                "email_domain_not_allowed",
+            ),
+            (AuthError::CaptchaFailed, 400, "captcha_failed"),
+            (AuthError::PasswordHashError, 500, "internal_error"),
+            (
+                AuthError::DatabaseError(sqlx::Error::RowNotFound),
+                500,
+                "internal_error",
+            ),
+            (
+                AuthError::DatabaseError(sqlx::Error::PoolTimedOut),
+                503,
+                "service_unavailable",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            let name = format!("{:?}", error);
+            let response = AuthHandlerError::from(error).into_response();
+            assert_eq!(response.status().as_u16(), status, "{}", name);
+            let body = json(response).await;
+            assert_eq!(body["code"], code, "{}", name);
+            assert!(body["error"].is_string(), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn jwks_publishes_the_rs256_key() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[
+            ("JWT_ALGORITHM", "RS256"),
+            ("JWT_PRIVATE_KEY_PATH", RSA_KEY_PATH),
+            ("JWT_KEY_ID", "signing-2024"),
+        ]);
+        let app = test_support::app(&database, config);
+
+        let response = send(
+            &app,
+            request(Method::GET, "/.well-known/jwks.json", None, None),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let jwks = json(response).await;
+        let keys = jwks["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0]["kid"], "signing-2024");
+        assert_eq!(keys[0]["alg"], "RS256");
+        assert_eq!(keys[0]["kty"], "RSA");
+    }
+
+    #[tokio::test]
+    async fn jwks_is_404_for_hs256() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let response = send(
+            &app,
+            request(Method::GET, "/.well-known/jwks.json", None, None),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn login_body(vars: &[(&str, &str)]) -> serde_json::Value {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(vars));
+        test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+
+        let credentials = serde_json::json!({
+            "email": "user@example.com",
+            "password": test_support::PASSWORD,
+        });
+        let response = send(
+            &app,
+            request(Method::POST, "/auth/login", None, Some(credentials)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        json(response).await
+    }
+
+    #[tokio::test]
+    async fn login_response_includes_user_by_default() {
+        let body = login_body(&[]).await;
+
+        assert_eq!(body["user"]["email"], "user@example.com");
+        assert!(body["token"].is_string());
+    }
+
+    #[tokio::test]
+    async fn login_response_can_omit_user() {
+        let body = login_body(&[("LOGIN_RESPONSE_INCLUDE_USER", "false")]).await;
+
+        assert!(body.get("user").is_none());
+        assert!(body["token"].is_string());
+    }
+
+    #[tokio::test]
+    async fn register_is_403_when_registration_is_disabled() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("REGISTRATION_ENABLED", "false")]);
+        let app = test_support::app(&database, config);
+
+        let credentials = serde_json::json!({
+            "email": "user@example.com",
+            "password": test_support::PASSWORD,
+        });
+        let response = send(
+            &app,
+            request(Method::POST, "/auth/register", None, Some(credentials)),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json(response).await["code"], "registration_disabled");
+        let users = database.user_repository();
+        let email = crate::models::Email::try_from("user@example.com".to_string()).unwrap();
+        assert!(users.find_by_email(&email).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn register_rejects_emails_over_max_email_length() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("MAX_EMAIL_LENGTH", "20")]);
+        let app = test_support::app(&database, config);
+
+        for (email, status) in [
+            ("abcdefgh@example.com", StatusCode::CREATED),
+            ("abcdefghi@example.com", StatusCode::BAD_REQUEST),
+        ] {
+            let body = serde_json::json!({ "email": email, "password": test_support::PASSWORD });
+            let response = send(
+                &app,
+                request(Method::POST, "/auth/register", None, Some(body)),
+            )
+            .await;
+            assert_eq!(response.status(), status, "{}", email);
+        }
+    }
+
+    #[tokio::test]
+    async fn login_matches_the_email_in_any_unicode_form() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        test_support::sign_up(
+            &app,
+            "Jose\u{301}@exa\u{308}mple.com",
+            test_support::PASSWORD,
+        )
+        .await;
+
+        let token =
+            test_support::log_in(&app, "jos\u{e9}@xn--exmple-cua.com", test_support::PASSWORD)
+                .await;
+        let user = json(test_support::me(&app, &token).await).await;
+        assert_eq!(user["email"], "jos\u{e9}@xn--exmple-cua.com");
+
+        let body = serde_json::json!({
+            "email": "JOS\u{c9}@exämple.com",
+            "password": test_support::PASSWORD,
+        });
+        let response = send(
+            &app,
+            request(Method::POST, "/auth/register", None, Some(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn register_gives_new_users_the_default_role() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("DEFAULT_REGISTRATION_ROLE", "admin")]);
+        let app = test_support::app(&database, config);
+
+        let token = test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+
+        let response = test_support::me(&app, &token).await;
+        assert_eq!(json(response).await["role"], "admin");
+        // The token carries it too, so admin routes open straight away
+        let response = send(&app, request(Method::GET, "/users", Some(&token), None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    async fn verify(app: &axum::Router, token: &str, password: &str) -> Response {
+        let body = serde_json::json!({ "password": password });
+        send(
+            app,
+            request(
+                Method::POST,
+                "/users/me/verify-password",
+                Some(token),
+                Some(body),
+            ),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn verify_password_accepts_the_current_password_only() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+
+        let response = verify(&app, &token, test_support::PASSWORD).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        // No token is issued
+        assert!(test_support::body_bytes(response).await.is_empty());
+
+        let response = verify(&app, &token, "wrong-horse-battery").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json(response).await["code"], "invalid_credentials");
+    }
+
+    #[tokio::test]
+    async fn verify_password_is_rate_limited_per_user() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("VERIFY_PASSWORD_PER_MINUTE", "2")]);
+        let app = test_support::app(&database, config);
+        let token = test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+
+        for _ in 0..2 {
+            let response = verify(&app, &token, "wrong-horse-battery").await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        // Even the right password waits out the window
+        let response = verify(&app, &token, test_support::PASSWORD).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    /// The `refresh_token` cookie set by `response`
+    fn refresh_cookie(response: &Response) -> String {
+        let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let (pair, attributes) = set_cookie.split_once(';').unwrap();
+        assert!(attributes.contains("Path=/auth/refresh"));
+        assert!(attributes.contains("HttpOnly"));
+        pair.strip_prefix("refresh_token=").unwrap().to_string()
+    }
+
+    fn with_cookie(method: Method, token: &str) -> Request<axum::body::Body> {
+        Request::builder()
+            .method(method)
+            .uri("/auth/refresh")
+            .header(header::COOKIE, format!("refresh_token={}", token))
+            .body(axum::body::Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cookie_refresh_round_trip() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("REFRESH_TOKEN_COOKIE", "true")]);
+        let app = test_support::app(&database, config);
+        test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+
+        let credentials = serde_json::json!({
+            "email": "user@example.com",
+            "password": test_support::PASSWORD,
+        });
+        let login = send(
+            &app,
+            request(Method::POST, "/auth/login", None, Some(credentials)),
+        )
+        .await;
+        assert_eq!(login.status(), StatusCode::OK);
+        let first = refresh_cookie(&login);
+        assert!(json(login).await.get("refresh_token").is_none());
+
+        let refreshed = send(&app, with_cookie(Method::POST, &first)).await;
+        assert_eq!(refreshed.status(), StatusCode::OK);
+        let second = refresh_cookie(&refreshed);
+        assert_ne!(second, first);
+        assert!(json(refreshed).await["token"].is_string());
+
+        // Refresh tokens are single-use
+        let replayed = send(&app, with_cookie(Method::POST, &first)).await;
+        assert_eq!(replayed.status(), StatusCode::UNAUTHORIZED);
+
+        let logout = send(&app, with_cookie(Method::DELETE, &second)).await;
+        assert_eq!(logout.status(), StatusCode::NO_CONTENT);
+        assert!(logout.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .contains("Max-Age=0"));
+        let after_logout = send(&app, with_cookie(Method::POST, &second)).await;
+        assert_eq!(after_logout.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    /// The login `Set-Cookie` when test requests (from 127.0.0.1) say they
+    /// were forwarded over HTTPS
+    async fn forwarded_https_cookie(trusted: &str) -> String {
+        let database = test_support::database().await;
+        let config = test_support::config(&[
+            ("REFRESH_TOKEN_COOKIE", "true"),
+            ("FORWARDED_PROTO_TRUSTED", trusted),
+        ]);
+        let app = test_support::app(&database, config);
+        test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+
+        let credentials = serde_json::json!({
+            "email": "user@example.com",
+            "password": test_support::PASSWORD,
+        });
+        let mut login = request(Method::POST, "/auth/login", None, Some(credentials));
+        login
+            .headers_mut()
+            .insert("x-forwarded-proto", "https".parse().unwrap());
+        let response = send(&app, login).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        response.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn forwarded_https_from_a_trusted_proxy_sets_secure() {
+        let set_cookie = forwarded_https_cookie("127.0.0.1").await;
+        assert!(set_cookie.contains(" Secure;"), "{}", set_cookie);
+    }
+
+    #[tokio::test]
+    async fn forwarded_https_from_an_untrusted_peer_is_ignored() {
+        let set_cookie = forwarded_https_cookie("10.0.0.1").await;
+        assert!(!set_cookie.contains("Secure"), "{}", set_cookie);
+    }
+
+    /// Each line of `GET /users/me/export`, parsed
+    async fn export(app: &axum::Router, token: &str) -> Vec<serde_json::Value> {
+        let response = send(
+            app,
+            request(Method::GET, "/users/me/export", Some(token), None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = String::from_utf8(test_support::body_bytes(response).await).unwrap();
+        assert!(!body.contains("password_hash") && !body.contains("$argon2"));
+        body.lines()
+            .map(|line| serde_json::from_str(line).expect("each line is JSON"))
+            .collect()
+    }
+
+    fn of_type<'a>(records: &'a [serde_json::Value], kind: &str) -> Vec<&'a serde_json::Value> {
+        records.iter().filter(|r| r["type"] == kind).collect()
+    }
+
+    #[tokio::test]
+    async fn export_has_the_profile_and_audit_events_but_not_the_password_hash() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+        let user_id = json(test_support::me(&app, &token).await).await["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let uri = format!("/admin/users/{}/revoke-sessions", user_id);
+        send(&app, request(Method::POST, &uri, Some(&admin), None)).await;
+        let token = test_support::log_in(&app, "user@example.com", test_support::PASSWORD).await;
+
+        let records = export(&app, &token).await;
+
+        assert_eq!(records[0]["type"], "profile");
+        assert_eq!(records[0]["profile"]["email"], "user@example.com");
+        assert_eq!(of_type(&records, "session").len(), 1);
+        let events = of_type(&records, "audit_event");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["action"], "POST /admin/users/:id/revoke-sessions");
+        assert_eq!(events[0]["target"], user_id.as_str());
+    }
+
+    #[tokio::test]
+    async fn export_streams_every_record_across_batches() {
+        use crate::models::AdminAuditEntry;
+        use crate::services::auth_service::EXPORT_BATCH_SIZE;
+
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "admin@example.com", test_support::PASSWORD).await;
+        let user_id = json(test_support::me(&app, &token).await).await["id"]
+            .as_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let audit = database.admin_audit_repository();
+        let logged = 2 * EXPORT_BATCH_SIZE + 7;
+        let start = chrono::Utc::now() - chrono::Duration::hours(1);
+        for i in 0..logged {
+            let entry = AdminAuditEntry {
+                id: uuid::Uuid::new_v4(),
+                actor_id: user_id,
+                action: "GET /admin/audit".to_string(),
+                target: None,
+                status: 200,
+                request_id: Some(format!("req-{}", i)),
+                created_at: start + chrono::Duration::seconds(i),
+            };
+            audit.record(&entry).await.unwrap();
+        }
+
+        let records = export(&app, &token).await;
+
+        assert_eq!(records.len() as i64, 1 + 1 + logged);
+        let request_ids: Vec<_> = of_type(&records, "audit_event")
+            .iter()
+            .map(|event| event["request_id"].as_str().unwrap().to_string())
+            .collect();
+        let expected: Vec<_> = (0..logged).map(|i| format!("req-{}", i)).collect();
+        assert_eq!(request_ids, expected);
+    }
+
+    #[tokio::test]
+    async fn auth_me_mirrors_the_token_claims() {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[("JWT_AUDIENCE", "api")]));
+        let token = test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+        let payload = URL_SAFE_NO_PAD
+            .decode(token.split('.').nth(1).unwrap())
+            .unwrap();
+        let claims: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+
+        let response = send(&app, request(Method::GET, "/auth/me", Some(&token), None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json(response).await;
+
+        for claim in ["sub", "email", "role", "iat", "exp", "aud"] {
+            assert_eq!(body[claim], claims[claim], "{}", claim);
+        }
+        assert_eq!(body["scopes"], serde_json::json!([claims["role"]]));
+        let expires_in = claims["exp"].as_i64().unwrap() - chrono::Utc::now().timestamp();
+        assert!((body["expires_in"].as_i64().unwrap() - expires_in).abs() <= 1);
+    }
+
+    async fn introspect(app: &axum::Router, admin: &str, token: &str) -> serde_json::Value {
+        let body = serde_json::json!({ "token": token });
+        let response = send(
+            app,
+            request(Method::POST, "/auth/introspect", Some(admin), Some(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        json(response).await
+    }
+
+    #[tokio::test]
+    async fn introspect_reports_an_active_token() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let token = test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+
+        let body = introspect(&app, &admin, &token).await;
+
+        assert_eq!(body["active"], true);
+        assert_eq!(body["email"], "user@example.com");
+        assert!(body["sub"].is_string());
+        assert!(body["exp"].is_i64());
+        assert_eq!(body["scopes"], serde_json::json!(["user"]));
+    }
+
+    #[tokio::test]
+    async fn registration_needs_a_password_of_at_least_8_characters() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let register = |password: &str| {
+            let body = serde_json::json!({ "email": "user@example.com", "password": password });
+            request(Method::POST, "/auth/register", None, Some(body))
+        };
+
+        let response = send(&app, register("seven77")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = json(response).await;
+        assert_eq!(body["code"], "invalid_request");
+        assert_eq!(body["error"], "password: must be at least 8 characters");
+
+        let response = send(&app, register("eight888")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn resend_verification_takes_an_email_or_a_bearer_token() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+        let resend = |token: Option<&str>, body: serde_json::Value| {
+            request(Method::POST, "/auth/resend-verification", token, Some(body))
+        };
+
+        // Known or not, an address gets the same answer
+        for address in ["user@example.com", "nobody@example.com"] {
+            let response = send(&app, resend(None, serde_json::json!({ "email": address }))).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = send(&app, resend(Some(&token), serde_json::json!({}))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, resend(None, serde_json::json!({}))).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn introspect_reports_an_expired_token_as_inactive() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let token = test_support::sign_up(&app, "user@example.com", test_support::PASSWORD).await;
+        let expired = test_support::resign(&token, |claims| {
+            claims.iat -= 7200;
+            claims.nbf -= 7200;
+            claims.exp = claims.iat + 3600;
+        });
+
+        let body = introspect(&app, &admin, &expired).await;
+
+        assert_eq!(body, serde_json::json!({ "active": false }));
     }
 }
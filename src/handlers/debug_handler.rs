@@ -0,0 +1,44 @@
+use axum::{extract::State, Json};
+use serde_json::Value;
+
+use crate::config::Config;
+
+/// Effective configuration with secrets redacted (development only)
+#[utoipa::path(
+    get,
+    path = "/debug/config",
+    responses(
+        (status = 200, description = "Redacted configuration", body = Value)
+    ),
+    tag = "debug"
+)]
+pub async fn debug_config(State(config): State<Config>) -> Json<Value> {
+    Json(config.redacted())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, json, request, send};
+    use axum::http::{Method, StatusCode};
+
+    #[tokio::test]
+    async fn serves_redacted_config_in_development() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let response = send(&app, request(Method::GET, "/debug/config", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await["jwt_secret"], "********");
+    }
+
+    #[tokio::test]
+    async fn not_found_in_production() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[("ENV", "production")]));
+
+        let response = send(&app, request(Method::GET, "/debug/config", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
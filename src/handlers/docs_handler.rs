@@ -0,0 +1,287 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderValue},
+    response::IntoResponse,
+};
+use serde_json::Value;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::middleware::json_case::to_camel_case;
+
+/// The OpenAPI document, rendered once at startup in both formats
+#[derive(Clone)]
+pub struct OpenApiDocs {
+    json: Arc<str>,
+    yaml: Arc<str>,
+}
+
+impl OpenApiDocs {
+    pub fn new(spec: &Value) -> Self {
+        Self {
+            json: spec.to_string().into(),
+            yaml: to_yaml(spec).into(),
+        }
+    }
+}
+
+/// The OpenAPI document as JSON (development only). Swagger UI loads this.
+pub async fn openapi_json(State(docs): State<OpenApiDocs>) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )],
+        docs.json.to_string(),
+    )
+}
+
+/// The OpenAPI document as YAML (development only); see `to_yaml`
+pub async fn openapi_yaml(State(docs): State<OpenApiDocs>) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/yaml"),
+        )],
+        docs.yaml.to_string(),
+    )
+}
+
+/// Render a JSON value as block-style YAML. Every string is emitted as a
+/// JSON-escaped double-quoted scalar, which YAML reads back verbatim, so no
+/// value can be mistaken for a number, boolean or null.
+pub fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => write_block(&mut out, value, 0),
+        Value::Array(items) if !items.is_empty() => write_block(&mut out, value, 0),
+        scalar => {
+            out.push_str(&scalar_yaml(scalar));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn write_block(out: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                write_entry(out, &format!("{}{}:", pad, key_yaml(key)), value, indent);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                write_entry(out, &format!("{}-", pad), item, indent);
+            }
+        }
+        _ => unreachable!("write_block is only called with containers"),
+    }
+}
+
+// Non-empty containers go on the following lines, everything else inline
+fn write_entry(out: &mut String, prefix: &str, value: &Value, indent: usize) {
+    let nested = match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    };
+
+    if nested {
+        let _ = writeln!(out, "{}", prefix);
+        write_block(out, value, indent + 2);
+    } else {
+        let _ = writeln!(out, "{} {}", prefix, scalar_yaml(value));
+    }
+}
+
+// Plain identifiers stay bare for readability; anything YAML might read as
+// something other than a string (`on`, `null`, paths, ...) is quoted
+fn key_yaml(key: &str) -> String {
+    let plain = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !matches!(
+            key.to_ascii_lowercase().as_str(),
+            "true" | "false" | "null" | "yes" | "no" | "on" | "off" | "y" | "n"
+        );
+
+    if plain {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+fn scalar_yaml(value: &Value) -> String {
+    match value {
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        // JSON's null, booleans, numbers and quoted strings are all valid YAML
+        other => other.to_string(),
+    }
+}
+
+/// Rename schema property names, and their `required` entries, to camelCase
+/// so the document matches responses under `JSON_CASE=camel`
+pub fn camel_case_schemas(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::Object(properties)) = map.get_mut("properties") {
+                *properties = std::mem::take(properties)
+                    .into_iter()
+                    .map(|(name, schema)| (to_camel_case(&name), schema))
+                    .collect();
+            }
+            if let Some(Value::Array(required)) = map.get_mut("required") {
+                for name in required.iter_mut() {
+                    if let Value::String(name) = name {
+                        *name = to_camel_case(name);
+                    }
+                }
+            }
+            map.values_mut().for_each(camel_case_schemas);
+        }
+        Value::Array(items) => items.iter_mut().for_each(camel_case_schemas),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, body_bytes, json, request, send};
+    use axum::http::{Method, StatusCode};
+    use serde_json::Map;
+
+    /// Reads back the block-style subset of YAML that `to_yaml` writes
+    fn from_yaml(yaml: &str) -> Value {
+        let lines: Vec<(usize, &str)> = yaml
+            .lines()
+            .map(|line| {
+                let content = line.trim_start_matches(' ');
+                (line.len() - content.len(), content)
+            })
+            .collect();
+        let mut next = 0;
+        let value = read_block(&lines, &mut next, 0);
+        assert_eq!(next, lines.len(), "trailing lines");
+        value
+    }
+
+    fn read_block(lines: &[(usize, &str)], next: &mut usize, indent: usize) -> Value {
+        let is_sequence = lines[*next].1.starts_with('-');
+        let mut items = Vec::new();
+        let mut map = Map::new();
+
+        while let Some(&(line_indent, content)) = lines.get(*next) {
+            if line_indent < indent {
+                break;
+            }
+            assert_eq!(line_indent, indent, "unexpected indent: {}", content);
+            *next += 1;
+
+            if is_sequence {
+                let rest = content.strip_prefix('-').expect("sequence entry");
+                items.push(read_value(lines, next, indent, rest));
+            } else {
+                let (key, rest) = read_key(content);
+                let rest = rest.strip_prefix(':').expect("mapping entry");
+                map.insert(key, read_value(lines, next, indent, rest));
+            }
+        }
+
+        if is_sequence {
+            Value::Array(items)
+        } else {
+            Value::Object(map)
+        }
+    }
+
+    // Inline scalars follow a space; nested blocks start on the next line
+    fn read_value(lines: &[(usize, &str)], next: &mut usize, indent: usize, rest: &str) -> Value {
+        match rest.strip_prefix(' ') {
+            Some(scalar) => serde_json::from_str(scalar).expect("scalar is JSON"),
+            None => read_block(lines, next, indent + 2),
+        }
+    }
+
+    fn read_key(content: &str) -> (String, &str) {
+        if content.starts_with('"') {
+            let mut keys = serde_json::Deserializer::from_str(content).into_iter::<String>();
+            let key = keys.next().expect("quoted key").expect("valid key");
+            (key, &content[keys.byte_offset()..])
+        } else {
+            let end = content.find(':').expect("key ends with a colon");
+            (content[..end].to_string(), &content[end..])
+        }
+    }
+
+    #[tokio::test]
+    async fn yaml_spec_parses_back_to_the_json_spec() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let yaml = send(
+            &app,
+            request(Method::GET, "/api-docs/openapi.yaml", None, None),
+        )
+        .await;
+        assert_eq!(yaml.status(), StatusCode::OK);
+        assert_eq!(yaml.headers()[header::CONTENT_TYPE], "application/yaml");
+        let yaml = String::from_utf8(body_bytes(yaml).await).unwrap();
+        let spec = json(
+            send(
+                &app,
+                request(Method::GET, "/api-docs/openapi.json", None, None),
+            )
+            .await,
+        )
+        .await;
+
+        assert_eq!(from_yaml(&yaml), spec);
+    }
+
+    #[tokio::test]
+    async fn password_schemas_carry_the_validated_minimum_length() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let spec = json(
+            send(
+                &app,
+                request(Method::GET, "/api-docs/openapi.json", None, None),
+            )
+            .await,
+        )
+        .await;
+
+        let schemas = &spec["components"]["schemas"];
+        assert_eq!(
+            schemas["RegisterRequest"]["properties"]["password"]["minLength"],
+            8
+        );
+        for schema in ["ChangePasswordRequest", "ResetPasswordRequest"] {
+            assert_eq!(
+                schemas[schema]["properties"]["new_password"]["minLength"], 8,
+                "{}",
+                schema
+            );
+        }
+    }
+
+    #[test]
+    fn awkward_keys_and_strings_round_trip() {
+        let value = serde_json::json!({
+            "/users/{id}": { "on": "yes", "empty": {}, "none": [] },
+            "list": [1, "2", null, true, [], ["nested"], { "a: b": "c\nd" }],
+        });
+
+        assert_eq!(from_yaml(&to_yaml(&value)), value);
+    }
+}
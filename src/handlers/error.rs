@@ -0,0 +1,71 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::json;
+
+/// Stable, machine-readable error codes sent alongside the human `error`
+/// message. Clients should branch on these, never on the message text.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    InvalidCredentials,
+    UserExists,
+    UserNotFound,
+    SessionNotFound,
+    ApiKeyNotFound,
+    SessionExpired,
+    MissingToken,
+    InvalidToken,
+    InvalidApiKey,
+    TokenRevoked,
+    TokenTooOld,
+    InvalidAudience,
+    UnknownKeyId,
+    TokenBindingMismatch,
+    Forbidden,
+    RegistrationDisabled,
+    EmailDomainNotAllowed,
+    InvalidSortColumn,
+    InvalidSortOrder,
+    InvalidCursor,
+    CaptchaFailed,
+    PasswordReused,
+    VersionConflict,
+    PreconditionFailed,
+    PayloadTooLarge,
+    RateLimited,
+    TooManyConcurrentRequests,
+    Maintenance,
+    ServiceUnavailable,
+    RequestTimeout,
+    InternalError,
+}
+
+/// JSON error body shared by every error response: `{"error", "code"}`
+pub fn error_response(status: StatusCode, code: ErrorCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message, "code": code }))).into_response()
+}
+
+/// Underlying cause of an error response. Attached as a response extension
+/// and only written into the body by `expose_error_detail`, which is never
+/// installed in production.
+#[derive(Debug, Clone)]
+pub struct ErrorDetail(pub String);
+
+/// Like `error_response`, carrying `detail` for non-production environments
+pub fn error_response_with_detail(
+    status: StatusCode,
+    code: ErrorCode,
+    message: &str,
+    detail: Option<String>,
+) -> Response {
+    let mut response = error_response(status, code, message);
+    if let Some(detail) = detail {
+        response.extensions_mut().insert(ErrorDetail(detail));
+    }
+    response
+}
@@ -1,6 +1,81 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use serde_json::{json, Value};
-use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::Database;
+use crate::lifecycle::Lifecycle;
+use crate::metrics::Metrics;
+use crate::models::{DependencyState, DependencyStatus};
+use crate::services::{HealthRegistry, Readiness};
+
+// Postgres SQLSTATE for "relation does not exist"
+const UNDEFINED_TABLE: &str = "42P01";
+
+// SQLite has no distinct code for a missing table, only the message
+fn is_undefined_table(error: &dyn sqlx::error::DatabaseError) -> bool {
+    error.code().as_deref() == Some(UNDEFINED_TABLE) || error.message().starts_with("no such table")
+}
+
+#[derive(Clone)]
+pub struct HealthState {
+    pub database: Database,
+    pub readiness_query: Arc<str>,
+    /// A hung database fails the probe after this instead of hanging it
+    pub check_timeout: Duration,
+    pub lifecycle: Lifecycle,
+    pub dependencies: HealthRegistry,
+}
+
+/// Liveness probe - answers as long as the process is serving requests.
+/// Deliberately checks no dependencies, so a database outage doesn't get
+/// healthy pods restarted.
+#[utoipa::path(
+    get,
+    path = "/healthz/live",
+    responses(
+        (status = 200, description = "Process is alive", body = Value)
+    ),
+    tag = "health"
+)]
+pub async fn live() -> Json<Value> {
+    Json(json!({ "status": "alive" }))
+}
+
+/// Build version, so deploys can be verified from outside
+#[utoipa::path(
+    get,
+    path = "/version",
+    responses(
+        (status = 200, description = "Running version", body = Value)
+    ),
+    tag = "health"
+)]
+pub async fn version() -> Json<Value> {
+    Json(json!({ "version": env!("CARGO_PKG_VERSION") }))
+}
+
+/// Prometheus metrics. OpenMetrics, with exemplars, under `METRICS_EXEMPLARS`.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String)
+    ),
+    tag = "health"
+)]
+pub async fn metrics(State(metrics): State<Metrics>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, metrics.content_type())],
+        metrics.render(),
+    )
+}
 
 /// Health check endpoint - verifies database connectivity
 #[utoipa::path(
@@ -12,28 +87,377 @@ use sqlx::PgPool;
     ),
     tag = "health"
 )]
-pub async fn healthz(State(pool): State<PgPool>) -> Result<Json<Value>, StatusCode> {
+pub async fn healthz(State(state): State<HealthState>) -> Result<Json<Value>, Response> {
     // Check database connection
-    match sqlx::query("SELECT 1").fetch_one(&pool).await {
-        Ok(_) => Ok(Json(json!({
+    let query = state.database.execute("SELECT 1");
+    match tokio::time::timeout(state.check_timeout, query).await {
+        Ok(Ok(_)) => Ok(Json(json!({
             "status": "healthy",
             "database": "connected"
         }))),
-        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
+        Ok(Err(sqlx::Error::PoolTimedOut)) => Err(super::pool_timed_out_response()),
+        Ok(Err(_)) => Err(StatusCode::SERVICE_UNAVAILABLE.into_response()),
+        Err(_) => {
+            tracing::warn!(
+                "Health check query timed out after {:?}",
+                state.check_timeout
+            );
+            Err(StatusCode::SERVICE_UNAVAILABLE.into_response())
+        }
     }
 }
 
-/// Readiness check endpoint
+/// Status of every configured dependency, for dashboards. Always 200 so a
+/// partial outage can be shown; read each dependency's `status`. Results
+/// are cached for `HEALTH_DEPENDENCIES_CACHE_SECS`.
+#[utoipa::path(
+    get,
+    path = "/healthz/dependencies",
+    responses(
+        (status = 200, description = "Per-dependency status, keyed by name", body = BTreeMap<String, DependencyStatus>)
+    ),
+    tag = "health"
+)]
+pub async fn dependencies(
+    State(state): State<HealthState>,
+) -> Json<BTreeMap<String, DependencyStatus>> {
+    Json(state.dependencies.report().await)
+}
+
+/// Readiness check endpoint - verifies the application schema is queryable,
+/// and that Redis answers when `REDIS_URL` is set
 #[utoipa::path(
     get,
     path = "/ready",
     responses(
-        (status = 200, description = "Service is ready", body = Value)
+        (status = 200, description = "Service is ready, or degraded by an optional dependency being down", body = Value),
+        (status = 503, description = "Shutting down, database unreachable, schema missing or a required dependency down", body = Value)
     ),
     tag = "health"
 )]
-pub async fn ready() -> Json<Value> {
-    Json(json!({
-        "status": "ready"
-    }))
+pub async fn ready(State(state): State<HealthState>) -> Result<Json<Value>, Response> {
+    // Fail readiness as soon as shutdown begins so traffic is routed away
+    // while in-flight requests drain
+    if state.lifecycle.is_shutting_down() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "shutting_down" })),
+        )
+            .into_response());
+    }
+
+    let query = state.database.execute(&state.readiness_query);
+    let result = match tokio::time::timeout(state.check_timeout, query).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::warn!("Readiness query timed out after {:?}", state.check_timeout);
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({
+                    "status": "not_ready",
+                    "database": "timeout"
+                })),
+            )
+                .into_response());
+        }
+    };
+
+    match result {
+        Ok(_) => dependencies_ready(&state.dependencies).await,
+        Err(sqlx::Error::PoolTimedOut) => Err(super::pool_timed_out_response()),
+        Err(sqlx::Error::Database(e)) if is_undefined_table(e.as_ref()) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "status": "not_ready",
+                "database": "connected",
+                "schema": "missing"
+            })),
+        )
+            .into_response()),
+        Err(sqlx::Error::Database(e)) => {
+            tracing::warn!("Readiness query failed: {}", e);
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({
+                    "status": "not_ready",
+                    "database": "connected",
+                    "schema": "error"
+                })),
+            )
+                .into_response())
+        }
+        Err(e) => {
+            tracing::warn!("Readiness check failed: {}", e);
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({
+                    "status": "not_ready",
+                    "database": "disconnected"
+                })),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Once the database is ready: `ready` with each dependency `/ready` checks
+/// up, `degraded` when only optional ones are down, 503 when a required one is
+async fn dependencies_ready(dependencies: &HealthRegistry) -> Result<Json<Value>, Response> {
+    let mut body = json!({
+        "status": "ready",
+        "database": "connected",
+        "schema": "ready"
+    });
+    let mut required_down = false;
+    for (name, (readiness, state)) in dependencies.readiness().await {
+        if state == DependencyState::Down {
+            match readiness {
+                Readiness::Required => required_down = true,
+                _ => body["status"] = json!("degraded"),
+            }
+        }
+        body[name] = json!(state);
+    }
+
+    if required_down {
+        body["status"] = json!("not_ready");
+        return Err((StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response());
+    }
+    Ok(Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lifecycle::Lifecycle;
+    use crate::test_support::{self, body_bytes, json, request, send};
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A stand-in Redis answering every command with `+PONG`, as `REDIS_URL`
+    async fn fake_redis() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buffer = [0; 64];
+                while matches!(socket.read(&mut buffer).await, Ok(n) if n > 0) {
+                    let _ = socket.write_all(b"+PONG\r\n").await;
+                }
+            }
+        });
+        url
+    }
+
+    /// A `REDIS_URL` nothing listens on
+    async fn dead_redis() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("redis://{}", listener.local_addr().unwrap())
+    }
+
+    #[tokio::test]
+    async fn ready_when_schema_is_migrated() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let response = send(&app, request(Method::GET, "/ready", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json(response).await,
+            json!({ "status": "ready", "database": "connected", "schema": "ready" })
+        );
+    }
+
+    #[tokio::test]
+    async fn not_ready_when_schema_is_missing() {
+        let database = test_support::unmigrated_database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let response = send(&app, request(Method::GET, "/ready", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            json(response).await,
+            json!({ "status": "not_ready", "database": "connected", "schema": "missing" })
+        );
+    }
+
+    #[tokio::test]
+    async fn not_ready_once_shutdown_begins() {
+        let database = test_support::database().await;
+        let lifecycle = Lifecycle::new();
+        let app = test_support::app_with_lifecycle(
+            &database,
+            test_support::config(&[]),
+            lifecycle.clone(),
+        );
+        let response = send(&app, request(Method::GET, "/ready", None, None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        lifecycle.begin_shutdown();
+
+        let response = send(&app, request(Method::GET, "/ready", None, None)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json(response).await, json!({ "status": "shutting_down" }));
+        // Liveness is unaffected while in-flight requests drain
+        let response = send(&app, request(Method::GET, "/healthz/live", None, None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn slow_readiness_query_fails_within_the_timeout() {
+        let database = test_support::database().await;
+        // Seconds of work for SQLite, far past the bound
+        let slow = "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n \
+                    WHERE x < 1000000000) SELECT count(*) FROM n";
+        let config = test_support::config(&[
+            ("READINESS_QUERY", slow),
+            ("HEALTH_CHECK_TIMEOUT_MS", "100"),
+        ]);
+        let app = test_support::app(&database, config);
+
+        let started = std::time::Instant::now();
+        let response = send(&app, request(Method::GET, "/ready", None, None)).await;
+
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json(response).await["database"], "timeout");
+    }
+
+    #[tokio::test]
+    async fn ready_when_redis_answers_ping() {
+        let database = test_support::database().await;
+        let url = fake_redis().await;
+        let app = test_support::app(&database, test_support::config(&[("REDIS_URL", &url)]));
+
+        let response = send(&app, request(Method::GET, "/ready", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json(response).await,
+            json!({ "status": "ready", "database": "connected", "schema": "ready", "redis": "up" })
+        );
+    }
+
+    #[tokio::test]
+    async fn not_ready_when_required_redis_is_down() {
+        let database = test_support::database().await;
+        let url = dead_redis().await;
+        let app = test_support::app(&database, test_support::config(&[("REDIS_URL", &url)]));
+
+        let response = send(&app, request(Method::GET, "/ready", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = json(response).await;
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["redis"], "down");
+    }
+
+    #[tokio::test]
+    async fn degraded_when_optional_redis_is_down() {
+        let database = test_support::database().await;
+        let url = dead_redis().await;
+        let config = test_support::config(&[("REDIS_URL", &url), ("REDIS_REQUIRED", "false")]);
+        let app = test_support::app(&database, config);
+
+        let response = send(&app, request(Method::GET, "/ready", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json(response).await;
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["redis"], "down");
+    }
+
+    #[tokio::test]
+    async fn live_while_the_database_check_fails() {
+        let database = test_support::database().await;
+        let (exhausted, _held) = database.exhausted().await;
+        let app = test_support::app(&exhausted, test_support::config(&[]));
+
+        let response = send(&app, request(Method::GET, "/healthz", None, None)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = send(&app, request(Method::GET, "/healthz/live", None, None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await, json!({ "status": "alive" }));
+    }
+
+    #[tokio::test]
+    async fn dependencies_answer_200_during_a_partial_outage() {
+        let database = test_support::database().await;
+        // Nothing listens on port 1, so the webhook check fails
+        let config = test_support::config(&[("WEBHOOK_URL", "http://127.0.0.1:1/hook")]);
+        let app = test_support::app(&database, config);
+
+        let response = send(
+            &app,
+            request(Method::GET, "/healthz/dependencies", None, None),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json(response).await;
+        assert_eq!(body["database"]["status"], "up");
+        assert_eq!(body["webhook"]["status"], "down");
+        assert!(body["webhook"]["checked_at"].is_string());
+    }
+
+    #[tokio::test]
+    async fn metrics_link_latency_to_the_request_id() {
+        let database = test_support::database().await;
+        let config =
+            test_support::config(&[("METRICS_ENABLED", "true"), ("METRICS_EXEMPLARS", "true")]);
+        let app = test_support::app(&database, config);
+
+        let mut live = request(Method::GET, "/healthz/live", None, None);
+        live.headers_mut()
+            .insert("x-request-id", "req-1234".parse().unwrap());
+        assert_eq!(send(&app, live).await.status(), StatusCode::OK);
+
+        let response = send(&app, request(Method::GET, "/metrics", None, None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("application/openmetrics-text"));
+        let body = String::from_utf8(body_bytes(response).await).unwrap();
+        assert!(
+            body.lines().any(
+                |line| line.starts_with("http_request_duration_seconds_bucket")
+                    && line.contains(r#"# {request_id="req-1234"}"#)
+            ),
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_include_runtime_gauges_under_runtime_metrics() {
+        let database = test_support::database().await;
+        let config =
+            test_support::config(&[("METRICS_ENABLED", "true"), ("RUNTIME_METRICS", "true")]);
+        let app = test_support::app(&database, config);
+
+        let response = send(&app, request(Method::GET, "/metrics", None, None)).await;
+        let body = String::from_utf8(body_bytes(response).await).unwrap();
+        for name in [
+            "tokio_workers",
+            "tokio_alive_tasks",
+            "tokio_global_queue_depth",
+        ] {
+            assert!(body.contains(&format!("\n{} ", name)), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_are_off_by_default() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let response = send(&app, request(Method::GET, "/metrics", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
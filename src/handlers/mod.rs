@@ -1,5 +1,372 @@
+pub mod admin_handler;
+pub mod api_key_handler;
 pub mod auth_handler;
+pub mod debug_handler;
+pub mod docs_handler;
+pub mod error;
 pub mod health_handler;
+pub mod session_handler;
+pub mod strict_json;
+pub mod user_handler;
 
-pub use auth_handler::{login, register};
-pub use health_handler::{healthz, ready};
+pub use admin_handler::{
+    impersonate, list_audit_events, rate_limit_status, revoke_sessions, set_api_key_rate_limit,
+    set_maintenance, RateLimitState,
+};
+pub use api_key_handler::{create_api_key, list_api_keys, revoke_api_key, rotate_api_key};
+pub use auth_handler::{
+    cancel_deletion, cancel_email_change, change_email, change_password, confirm_email, delete_me,
+    export_me, forgot_password, introspect, jwks, login, logout, refresh, register,
+    resend_verification, reset_password, token_claims, verify_email, verify_password,
+    DataExportState, RefreshCookie,
+};
+pub use debug_handler::debug_config;
+pub use docs_handler::{openapi_json, openapi_yaml, OpenApiDocs};
+pub use error::{error_response, error_response_with_detail, ErrorCode, ErrorDetail};
+pub use health_handler::{dependencies, healthz, live, metrics, ready, version, HealthState};
+pub use session_handler::{list_sessions, revoke_session};
+pub use strict_json::StrictJson;
+pub use user_handler::{list_users, me, update_me};
+
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        ConnectInfo, FromRequest, FromRequestParts, Query, Request,
+    },
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use validator::{Validate, ValidationErrors};
+
+use crate::models::ClientInfo;
+use strict_json::unknown_field;
+
+/// Seconds clients should wait before retrying when the database pool is
+/// exhausted, unless `POOL_TIMEOUT_RETRY_AFTER_SECS` says otherwise
+pub const POOL_TIMEOUT_RETRY_AFTER_SECS: u64 = 5;
+
+/// Marks a response as caused by pool exhaustion, for
+/// `pool_timeout_middleware` to apply the configured body and `Retry-After`
+#[derive(Debug, Clone, Copy)]
+pub struct PoolTimedOut;
+
+pub fn pool_timed_out_response() -> Response {
+    let mut response = error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::ServiceUnavailable,
+        "Service temporarily unavailable",
+    );
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(POOL_TIMEOUT_RETRY_AFTER_SECS),
+    );
+    response.extensions_mut().insert(PoolTimedOut);
+    response
+}
+
+/// Client-generated value that, with the user agent, fingerprints a client
+pub const CLIENT_FINGERPRINT_HEADER: &str = "x-client-fingerprint";
+
+/// The fingerprint a request presents: a SHA-256 of its user agent and
+/// `X-Client-Fingerprint`. `None` without that header.
+pub fn client_fingerprint(headers: &HeaderMap) -> Option<String> {
+    let value = headers
+        .get(CLIENT_FINGERPRINT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())?;
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    hasher.update(user_agent.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(value.as_bytes());
+    Some(URL_SAFE_NO_PAD.encode(hasher.finalize()))
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Connect info is absent when the router is served without it
+        let ip_address = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        Ok(Self {
+            ip_address,
+            user_agent,
+            fingerprint: client_fingerprint(&parts.headers),
+        })
+    }
+}
+
+/// `Json` extractor that answers malformed or invalid bodies with 400, and
+/// bodies over `MAX_BODY_BYTES` with 413. Under `StrictJson`, a body with a
+/// key the struct doesn't declare is also a 400.
+pub struct JsonBody<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = JsonBodyError;
+
+    async fn from_request(mut request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if request.extensions().get::<StrictJson>().is_none() {
+            let Json(value) = Json::<T>::from_request(request, state).await?;
+            return Ok(Self(value));
+        }
+
+        // Buffer the body under the same size limit, so it can be parsed as
+        // usual and then checked for unknown keys
+        let mut buffered = Request::new(std::mem::take(request.body_mut()));
+        *buffered.extensions_mut() = request.extensions().clone();
+        let bytes = Bytes::from_request(buffered, state)
+            .await
+            .map_err(JsonRejection::from)?;
+        *request.body_mut() = Body::from(bytes.clone());
+
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        if let Some(field) = unknown_field::<T>(&bytes) {
+            return Err(JsonBodyError::UnknownField(field));
+        }
+        Ok(Self(value))
+    }
+}
+
+#[derive(Debug)]
+pub enum JsonBodyError {
+    Rejected(JsonRejection),
+    UnknownField(String),
+}
+
+impl From<JsonRejection> for JsonBodyError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::Rejected(rejection)
+    }
+}
+
+impl IntoResponse for JsonBodyError {
+    fn into_response(self) -> Response {
+        let rejection = match self {
+            JsonBodyError::Rejected(rejection) => rejection,
+            JsonBodyError::UnknownField(field) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::InvalidRequest,
+                    &format!("Unknown field `{}`", field),
+                );
+            }
+        };
+
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                ErrorCode::PayloadTooLarge,
+                "Request body too large",
+            );
+        }
+
+        error_response(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            &rejection.body_text(),
+        )
+    }
+}
+
+/// `Query` extractor that also runs the struct's `Validate` rules. Parse and
+/// validation failures are answered with 400, like `JsonBody`.
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ValidatedQueryError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(ValidatedQueryError::Parse)?;
+        value.validate().map_err(ValidatedQueryError::Invalid)?;
+
+        Ok(Self(value))
+    }
+}
+
+#[derive(Debug)]
+pub enum ValidatedQueryError {
+    Parse(QueryRejection),
+    Invalid(ValidationErrors),
+}
+
+impl IntoResponse for ValidatedQueryError {
+    fn into_response(self) -> Response {
+        let message = match self {
+            ValidatedQueryError::Parse(rejection) => rejection.body_text(),
+            ValidatedQueryError::Invalid(errors) => validation_message(&errors),
+        };
+
+        error_response(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, &message)
+    }
+}
+
+/// `JsonBody` that also runs the struct's `Validate` rules
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ValidatedJsonError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let JsonBody(value) = JsonBody::<T>::from_request(request, state)
+            .await
+            .map_err(ValidatedJsonError::Parse)?;
+        value.validate().map_err(ValidatedJsonError::Invalid)?;
+
+        Ok(Self(value))
+    }
+}
+
+#[derive(Debug)]
+pub enum ValidatedJsonError {
+    Parse(JsonBodyError),
+    Invalid(ValidationErrors),
+}
+
+impl IntoResponse for ValidatedJsonError {
+    fn into_response(self) -> Response {
+        match self {
+            ValidatedJsonError::Parse(error) => error.into_response(),
+            ValidatedJsonError::Invalid(errors) => error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                &validation_message(&errors),
+            ),
+        }
+    }
+}
+
+/// One `field: message` per failed rule, sorted so responses are stable
+fn validation_message(errors: &ValidationErrors) -> String {
+    let mut fields: Vec<String> = errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |error| match &error.message {
+                Some(message) => format!("{}: {}", field, message),
+                None => format!("{}: invalid value", field),
+            })
+        })
+        .collect();
+    fields.sort();
+    fields.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, json, request, send};
+    use axum::http::Method;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn pool_exhaustion_is_503_with_retry_after() {
+        let database = test_support::database().await;
+        let (exhausted, _held) = database.exhausted().await;
+        let app = test_support::app(&exhausted, test_support::config(&[]));
+
+        let credentials = json!({ "email": "user@example.com", "password": "password123" });
+        for request in [
+            request(Method::POST, "/auth/login", None, Some(credentials)),
+            request(Method::GET, "/healthz", None, None),
+        ] {
+            let response = send(&app, request).await;
+
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(
+                response.headers()[header::RETRY_AFTER],
+                POOL_TIMEOUT_RETRY_AFTER_SECS.to_string().as_str()
+            );
+            assert_eq!(json(response).await["code"], "service_unavailable");
+        }
+    }
+
+    fn list_users_query_app() -> axum::Router {
+        use crate::models::ListUsersQuery;
+        axum::Router::new().route(
+            "/users",
+            axum::routing::get(
+                |ValidatedQuery(query): ValidatedQuery<ListUsersQuery>| async move {
+                    Json(json!({ "page": query.page, "per_page": query.per_page }))
+                },
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn validated_query_accepts_valid_params() {
+        let app = list_users_query_app();
+
+        let response = send(
+            &app,
+            request(Method::GET, "/users?page=2&per_page=5", None, None),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await, json!({ "page": 2, "per_page": 5 }));
+    }
+
+    #[tokio::test]
+    async fn validated_query_rejects_out_of_range_per_page() {
+        let app = list_users_query_app();
+
+        let response = send(&app, request(Method::GET, "/users?per_page=0", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = json(response).await;
+        assert_eq!(body["code"], "invalid_request");
+        assert!(body["error"].as_str().unwrap().contains("per_page"));
+    }
+
+    #[tokio::test]
+    async fn validated_query_rejects_non_numeric_page() {
+        let app = list_users_query_app();
+
+        let response = send(&app, request(Method::GET, "/users?page=two", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = json(response).await;
+        assert_eq!(body["code"], "invalid_request");
+        assert!(body["error"].as_str().unwrap().contains("invalid digit"));
+    }
+}
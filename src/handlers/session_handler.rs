@@ -0,0 +1,148 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use uuid::Uuid;
+
+use super::auth_handler::AuthHandlerError;
+use crate::models::Claims;
+use crate::services::auth_service::AuthError;
+use crate::services::AuthService;
+
+/// List the current user's active sessions
+#[utoipa::path(
+    get,
+    path = "/users/me/sessions",
+    responses(
+        (status = 200, description = "Active sessions", body = [SessionResponse]),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn list_sessions(
+    State(auth_service): State<AuthService>,
+    Extension(claims): Extension<Claims>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+    let sessions = auth_service.list_sessions(user_id).await?;
+    Ok(Json(sessions))
+}
+
+/// Revoke one of the current user's sessions
+#[utoipa::path(
+    delete,
+    path = "/users/me/sessions/{id}",
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses(
+        (status = 204, description = "Session revoked"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 404, description = "Session not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn revoke_session(
+    State(auth_service): State<AuthService>,
+    Extension(claims): Extension<Claims>,
+    Path(session_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AuthHandlerError> {
+    let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+    auth_service.revoke_session(user_id, session_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, json, request, send, PASSWORD};
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::Value;
+
+    async fn sessions(app: &axum::Router, token: &str) -> Vec<Value> {
+        let response = send(
+            app,
+            request(Method::GET, "/users/me/sessions", Some(token), None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        json(response).await.as_array().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn lists_sessions_without_token_values() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let mut login = request(
+            Method::POST,
+            "/auth/login",
+            None,
+            Some(serde_json::json!({ "email": "user@example.com", "password": PASSWORD })),
+        );
+        login
+            .headers_mut()
+            .insert(header::USER_AGENT, "test-agent/1.0".parse().unwrap());
+        let response = send(&app, login).await;
+        let token = json(response).await["token"].as_str().unwrap().to_string();
+
+        let sessions = sessions(&app, &token).await;
+
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions
+            .iter()
+            .any(|s| s["user_agent"] == "test-agent/1.0" && s["ip_address"] == "127.0.0.1"));
+        for session in &sessions {
+            assert!(session.get("token_hash").is_none());
+            assert!(session.get("refresh_token").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn revokes_one_session() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let token = test_support::log_in(&app, "user@example.com", PASSWORD).await;
+        let before = sessions(&app, &token).await;
+        let revoked = before[0]["id"].as_str().unwrap();
+
+        let uri = format!("/users/me/sessions/{}", revoked);
+        let response = send(&app, request(Method::DELETE, &uri, Some(&token), None)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let after = sessions(&app, &token).await;
+        assert_eq!(after.len(), 1);
+        assert_ne!(after[0]["id"], revoked);
+
+        let response = send(&app, request(Method::DELETE, &uri, Some(&token), None)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn login_evicts_the_oldest_session_beyond_the_cap() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("MAX_SESSIONS_PER_USER", "2")]);
+        let app = test_support::app(&database, config);
+        let first = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let first_session = sessions(&app, &first).await[0]["id"].clone();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        test_support::log_in(&app, "user@example.com", PASSWORD).await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let token = test_support::log_in(&app, "user@example.com", PASSWORD).await;
+
+        // Pruning runs in the background after each login
+        let mut remaining = sessions(&app, &token).await;
+        for _ in 0..50 {
+            if remaining.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            remaining = sessions(&app, &token).await;
+        }
+
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining.iter().all(|s| s["id"] != first_session));
+    }
+}
@@ -0,0 +1,122 @@
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Request extension that makes `JsonBody` refuse object keys the target
+/// struct doesn't declare. Installed with `STRICT_JSON_BODIES=true`.
+#[derive(Debug, Clone, Copy)]
+pub struct StrictJson;
+
+/// The first top-level key in `body` that `T` neither declares nor aliases.
+/// Bodies that aren't objects, and types that aren't plain structs (maps,
+/// `#[serde(flatten)]`), are never reported.
+pub fn unknown_field<'de, T: Deserialize<'de>>(body: &[u8]) -> Option<String> {
+    let fields = struct_fields::<T>()?;
+    let object: Map<String, Value> = serde_json::from_slice(body).ok()?;
+    object
+        .into_iter()
+        .map(|(key, _)| key)
+        .find(|key| !fields.contains(&key.as_str()))
+}
+
+// Derived impls hand `deserialize_struct` their field names, aliases
+// included; this deserializer records them and stops
+fn struct_fields<'de, T: Deserialize<'de>>() -> Option<&'static [&'static str]> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+struct FieldNames<'a>(&'a mut Option<&'static [&'static str]>);
+
+#[derive(Debug)]
+struct Stop;
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("field names collected")
+    }
+}
+
+impl std::error::Error for Stop {}
+
+impl de::Error for Stop {
+    fn custom<M: fmt::Display>(_msg: M) -> Self {
+        Stop
+    }
+}
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = Stop;
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Stop> {
+        *self.0 = Some(fields);
+        Err(Stop)
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Stop> {
+        Err(Stop)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RegisterRequest;
+    use crate::test_support::{self, json, request, send, PASSWORD};
+    use axum::http::{Method, StatusCode};
+
+    #[test]
+    fn reports_the_first_undeclared_key() {
+        let body = br#"{"email": "a@example.com", "password": "x", "passwrod": "x"}"#;
+        assert_eq!(
+            unknown_field::<RegisterRequest>(body).as_deref(),
+            Some("passwrod")
+        );
+        let body = br#"{"email": "a@example.com", "password": "x"}"#;
+        assert_eq!(unknown_field::<RegisterRequest>(body), None);
+    }
+
+    #[tokio::test]
+    async fn unknown_fields_are_rejected_only_in_strict_mode() {
+        let database = test_support::database().await;
+        for (strict, email) in [
+            ("true", "strict@example.com"),
+            ("false", "lenient@example.com"),
+        ] {
+            let config = test_support::config(&[("STRICT_JSON_BODIES", strict)]);
+            let app = test_support::app(&database, config);
+            let body = serde_json::json!({
+                "email": email,
+                "password": PASSWORD,
+                "passwrod": PASSWORD,
+            });
+
+            let response = send(
+                &app,
+                request(Method::POST, "/auth/register", None, Some(body)),
+            )
+            .await;
+
+            if strict == "true" {
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+                let body = json(response).await;
+                assert_eq!(body["code"], "invalid_request");
+                assert!(body["error"].as_str().unwrap().contains("`passwrod`"));
+            } else {
+                assert_eq!(response.status(), StatusCode::CREATED);
+            }
+        }
+    }
+}
@@ -0,0 +1,552 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use super::{error_response_with_detail, ErrorCode, ValidatedJson, ValidatedQuery};
+use crate::models::{Claims, ListUsersQuery, UpdateProfileRequest, UserListResponse};
+use crate::services::user_service::UserError;
+use crate::services::UserService;
+
+/// Get the current user. Responses carry an `ETag`; send it back as
+/// `If-None-Match` to get 304 while the user is unchanged.
+#[utoipa::path(
+    get,
+    path = "/users/me",
+    responses(
+        (status = 200, description = "Current user", body = UserResponse,
+            headers(("ETag" = String, description = "Validator for `If-None-Match` and `If-Match`"))),
+        (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn me(
+    State(user_service): State<UserService>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, UserHandlerError> {
+    let user_id = claims.user_id().map_err(|_| UserError::UserNotFound)?;
+    let user = user_service.get(user_id).await?;
+    let etag = user.etag();
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag_matches(value, &etag, true));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    Ok(([(header::ETAG, etag)], Json(user)).into_response())
+}
+
+/// Update the current user's profile. Only fields present in the body are
+/// changed; `null` clears a field. The update only applies while the user
+/// is still at the body's `version`, or, with `If-Match`, while its `ETag`
+/// is still the one given.
+#[utoipa::path(
+    patch,
+    path = "/users/me",
+    request_body = UpdateProfileRequest,
+    responses(
+        (status = 200, description = "Updated user", body = UserResponse),
+        (status = 400, description = "Invalid request, or neither `version` nor `If-Match` sent"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 409, description = "`version` is stale"),
+        (status = 412, description = "`If-Match` is stale")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn update_me(
+    State(user_service): State<UserService>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    ValidatedJson(mut request): ValidatedJson<UpdateProfileRequest>,
+) -> Result<impl IntoResponse, UserHandlerError> {
+    let user_id = claims.user_id().map_err(|_| UserError::UserNotFound)?;
+
+    let if_match = headers
+        .get(header::IF_MATCH)
+        .and_then(|value| value.to_str().ok());
+    if let Some(if_match) = if_match {
+        let current = user_service.get(user_id).await?;
+        if !etag_matches(if_match, &current.etag(), false) {
+            return Err(UserError::PreconditionFailed.into());
+        }
+        // Pin the write to the version just matched, so an update racing in
+        // between still fails instead of being overwritten
+        request.version.get_or_insert(current.version);
+    }
+    if request.version.is_none() {
+        return Err(UserError::VersionRequired.into());
+    }
+
+    let user = user_service
+        .update_profile(user_id, request)
+        .await
+        .map_err(|e| match e {
+            UserError::VersionConflict if if_match.is_some() => UserError::PreconditionFailed,
+            e => e,
+        })?;
+    let etag = user.etag();
+    Ok(([(header::ETAG, etag)], Json(user)))
+}
+
+// `If-None-Match` uses weak comparison and `If-Match` strong (RFC 9110
+// section 8.8.3.2); either accepts a list of tags or `*`
+fn etag_matches(header: &str, etag: &str, weak: bool) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag || (weak && tag.strip_prefix("W/") == Some(etag)))
+}
+
+/// List users with pagination and sorting. Offset pages also carry RFC 8288
+/// `Link` headers (`first`, `prev`, `next`, `last`).
+#[utoipa::path(
+    get,
+    path = "/users",
+    params(ListUsersQuery),
+    responses(
+        (status = 200, description = "Page of users", body = UserListResponse,
+            headers(("Link" = String, description = "`first`, `prev`, `next` and `last` page URLs"))),
+        (status = 200, description = "Page of users when keyset paging with `after`/`limit`", body = UserCursorPage),
+        (status = 400, description = "Invalid pagination or sort parameters"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Admin role required")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn list_users(
+    State(user_service): State<UserService>,
+    uri: Uri,
+    ValidatedQuery(query): ValidatedQuery<ListUsersQuery>,
+) -> Result<impl IntoResponse, UserHandlerError> {
+    if query.is_keyset() {
+        let response = user_service.list_after(query).await?;
+        return Ok(Json(response).into_response());
+    }
+
+    let sort = sort_params(&query);
+    let response = user_service.list(query).await?;
+    let links = page_links(uri.path(), &sort, &response);
+
+    let mut response = Json(response).into_response();
+    if let Ok(value) = HeaderValue::from_str(&links) {
+        response.headers_mut().insert(header::LINK, value);
+    }
+    Ok(response)
+}
+
+// `sort_by` and `order` carried into every page link. Both were checked
+// against fixed lists by the time a page exists, so need no escaping.
+fn sort_params(query: &ListUsersQuery) -> String {
+    let mut params = String::new();
+    if let Some(sort_by) = &query.sort_by {
+        params.push_str(&format!("&sort_by={}", sort_by));
+    }
+    if let Some(order) = &query.order {
+        params.push_str(&format!("&order={}", order));
+    }
+    params
+}
+
+fn page_links(path: &str, sort: &str, page: &UserListResponse) -> String {
+    let per_page = i64::from(page.per_page.max(1));
+    let last = ((page.total + per_page - 1) / per_page).max(1);
+    let current = i64::from(page.page);
+    let link = |number: i64, rel: &str| {
+        format!(
+            "<{}?page={}&per_page={}{}>; rel=\"{}\"",
+            path, number, per_page, sort, rel
+        )
+    };
+
+    let mut links = vec![link(1, "first")];
+    if current > 1 {
+        links.push(link((current - 1).min(last), "prev"));
+    }
+    if current < last {
+        links.push(link(current + 1, "next"));
+    }
+    links.push(link(last, "last"));
+    links.join(", ")
+}
+
+// Error handling
+#[derive(Debug)]
+pub struct UserHandlerError(UserError);
+
+impl From<UserError> for UserHandlerError {
+    fn from(error: UserError) -> Self {
+        UserHandlerError(error)
+    }
+}
+
+impl IntoResponse for UserHandlerError {
+    fn into_response(self) -> axum::response::Response {
+        let detail = match &self.0 {
+            UserError::DatabaseError(e) => Some(e.to_string()),
+            _ => None,
+        };
+
+        let (status, code, message) = match self.0 {
+            UserError::InvalidSortColumn(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidSortColumn,
+                "Invalid sort column",
+            ),
+            UserError::InvalidSortOrder(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidSortOrder,
+                "Invalid sort order",
+            ),
+            UserError::InvalidCursor => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidCursor,
+                "Invalid cursor",
+            ),
+            UserError::MixedPagination => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "Cursor paging can't be combined with page or sort parameters",
+            ),
+            UserError::UserNotFound => (
+                StatusCode::NOT_FOUND,
+                ErrorCode::UserNotFound,
+                "User not found",
+            ),
+            UserError::VersionRequired => (
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "`version` is required unless If-Match is sent",
+            ),
+            UserError::VersionConflict => (
+                StatusCode::CONFLICT,
+                ErrorCode::VersionConflict,
+                "User was modified by another request; reload and retry",
+            ),
+            UserError::PreconditionFailed => (
+                StatusCode::PRECONDITION_FAILED,
+                ErrorCode::PreconditionFailed,
+                "User has changed since the If-Match ETag; reload and retry",
+            ),
+            UserError::DatabaseError(sqlx::Error::PoolTimedOut) => {
+                return super::pool_timed_out_response();
+            }
+            UserError::DatabaseError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                "Database error",
+            ),
+        };
+
+        error_response_with_detail(status, code, message, detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, json, request, send, PASSWORD};
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn user_errors_carry_stable_codes() {
+        let cases = [
+            (
+                UserError::InvalidSortColumn("password".into()),
+                400,
+                "invalid_sort_column",
+            ),
+            (
+                UserError::InvalidSortOrder("up".into()),
+                400,
+                "invalid_sort_order",
+            ),
+            (UserError::InvalidCursor, 400, "invalid_cursor"),
+            (UserError::MixedPagination, 400, "invalid_request"),
+            (UserError::UserNotFound, 404, "user_not_found"),
+            (UserError::VersionRequired, 400, "invalid_request"),
+            (UserError::VersionConflict, 409, "version_conflict"),
+            (UserError::PreconditionFailed, 412, "precondition_failed"),
+            (
+                UserError::DatabaseError(sqlx::Error::RowNotFound),
+                500,
+                "internal_error",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            let name = format!("{:?}", error);
+            let response = UserHandlerError::from(error).into_response();
+            assert_eq!(response.status().as_u16(), status, "{}", name);
+            assert_eq!(json(response).await["code"], code, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn link_header_has_next_on_a_middle_page_but_not_the_last() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        for email in [
+            "a@example.com",
+            "b@example.com",
+            "c@example.com",
+            "d@example.com",
+        ] {
+            test_support::sign_up(&app, email, PASSWORD).await;
+        }
+        // Five users in all: pages of two make three pages
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let links = |page: u32| {
+            let uri = format!("/users?page={}&per_page=2&sort_by=email", page);
+            let app = app.clone();
+            let admin = admin.clone();
+            async move {
+                let response = send(&app, request(Method::GET, &uri, Some(&admin), None)).await;
+                assert_eq!(response.status(), StatusCode::OK);
+                response.headers()[header::LINK]
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            }
+        };
+
+        let middle = links(2).await;
+        assert!(
+            middle.contains("</users?page=3&per_page=2&sort_by=email>; rel=\"next\""),
+            "{}",
+            middle
+        );
+        assert!(middle.contains("</users?page=1&per_page=2&sort_by=email>; rel=\"prev\""));
+
+        let last = links(3).await;
+        assert!(!last.contains("rel=\"next\""), "{}", last);
+        assert!(last.contains("</users?page=3&per_page=2&sort_by=email>; rel=\"last\""));
+    }
+
+    fn emails(page: &Value) -> Vec<&str> {
+        page["users"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|user| user["email"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lists_users_in_requested_and_default_order() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        for email in ["b@example.com", "c@example.com", "a@example.com"] {
+            test_support::sign_up(&app, email, PASSWORD).await;
+        }
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+
+        let response = send(
+            &app,
+            request(
+                Method::GET,
+                "/users?sort_by=email&order=asc",
+                Some(&admin),
+                None,
+            ),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            emails(&json(response).await),
+            [
+                "a@example.com",
+                "admin@example.com",
+                "b@example.com",
+                "c@example.com"
+            ]
+        );
+
+        // Newest first
+        let response = send(&app, request(Method::GET, "/users", Some(&admin), None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            emails(&json(response).await),
+            [
+                "admin@example.com",
+                "a@example.com",
+                "c@example.com",
+                "b@example.com"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_sort_column() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+
+        let response = send(
+            &app,
+            request(
+                Method::GET,
+                "/users?sort_by=password_hash",
+                Some(&admin),
+                None,
+            ),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    async fn patch_me(app: &axum::Router, token: &str, body: Value) -> axum::response::Response {
+        send(
+            app,
+            request(Method::PATCH, "/users/me", Some(token), Some(body)),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn patch_updates_only_the_fields_given() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let user = json(test_support::me(&app, &token).await).await;
+        let body = serde_json::json!({
+            "name": "Ada",
+            "avatar_url": "https://example.com/ada.png",
+            "version": user["version"],
+        });
+        let user = json(patch_me(&app, &token, body).await).await;
+
+        let body = serde_json::json!({ "name": "Ada Lovelace", "version": user["version"] });
+        let renamed = patch_me(&app, &token, body).await;
+        assert_eq!(renamed.status(), StatusCode::OK);
+        let renamed = json(renamed).await;
+        assert_eq!(renamed["name"], "Ada Lovelace");
+        assert_eq!(renamed["avatar_url"], "https://example.com/ada.png");
+
+        // `null` clears, absence leaves alone
+        let body = serde_json::json!({ "avatar_url": null, "version": renamed["version"] });
+        let cleared = json(patch_me(&app, &token, body).await).await;
+        assert_eq!(cleared["name"], "Ada Lovelace");
+        assert_eq!(cleared["avatar_url"], Value::Null);
+        assert_eq!(json(test_support::me(&app, &token).await).await, cleared);
+    }
+
+    #[tokio::test]
+    async fn patch_requires_a_version_or_if_match() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+
+        let response = patch_me(&app, &token, serde_json::json!({ "name": "Ada" })).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json(response).await["code"], "invalid_request");
+
+        let etag = test_support::me(&app, &token).await.headers()[header::ETAG].clone();
+        let mut patch = request(
+            Method::PATCH,
+            "/users/me",
+            Some(&token),
+            Some(serde_json::json!({ "name": "Ada" })),
+        );
+        patch.headers_mut().insert(header::IF_MATCH, etag);
+        let response = send(&app, patch).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await["name"], "Ada");
+    }
+
+    #[tokio::test]
+    async fn patch_with_a_stale_version_conflicts() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let read = json(test_support::me(&app, &token).await).await;
+
+        let body = serde_json::json!({ "name": "Ada", "version": read["version"] });
+        let fresh = patch_me(&app, &token, body).await;
+        assert_eq!(fresh.status(), StatusCode::OK);
+        let fresh = json(fresh).await;
+        assert_ne!(fresh["version"], read["version"]);
+
+        // A second writer still holding the first read loses
+        let body = serde_json::json!({ "name": "Grace", "version": read["version"] });
+        let stale = patch_me(&app, &token, body).await;
+        assert_eq!(stale.status(), StatusCode::CONFLICT);
+        assert_eq!(json(stale).await["code"], "version_conflict");
+        assert_eq!(
+            json(test_support::me(&app, &token).await).await["name"],
+            "Ada"
+        );
+    }
+
+    fn me_if(token: &str, name: header::HeaderName, etag: &HeaderValue) -> Request<Body> {
+        let mut get = request(Method::GET, "/users/me", Some(token), None);
+        get.headers_mut().insert(name, etag.clone());
+        get
+    }
+
+    #[tokio::test]
+    async fn matching_if_none_match_is_304_until_the_user_changes() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let read = test_support::me(&app, &token).await;
+        let etag = read.headers()[header::ETAG].clone();
+        let version = json(read).await["version"].clone();
+
+        let response = send(&app, me_if(&token, header::IF_NONE_MATCH, &etag)).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+        assert!(test_support::body_bytes(response).await.is_empty());
+
+        // Signing in again only moves `last_login_at`, which the ETag ignores
+        test_support::log_in(&app, "user@example.com", PASSWORD).await;
+        let response = send(&app, me_if(&token, header::IF_NONE_MATCH, &etag)).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let body = serde_json::json!({ "name": "Ada", "version": version });
+        assert_eq!(patch_me(&app, &token, body).await.status(), StatusCode::OK);
+        let response = send(&app, me_if(&token, header::IF_NONE_MATCH, &etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[header::ETAG], etag);
+    }
+
+    #[tokio::test]
+    async fn patch_with_a_stale_if_match_is_412() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let read = test_support::me(&app, &token).await;
+        let etag = read.headers()[header::ETAG].clone();
+        let version = json(read).await["version"].clone();
+
+        let body = serde_json::json!({ "name": "Ada", "version": version });
+        assert_eq!(patch_me(&app, &token, body).await.status(), StatusCode::OK);
+
+        let mut patch = request(
+            Method::PATCH,
+            "/users/me",
+            Some(&token),
+            Some(serde_json::json!({ "name": "Grace" })),
+        );
+        patch.headers_mut().insert(header::IF_MATCH, etag);
+        let response = send(&app, patch).await;
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(json(response).await["code"], "precondition_failed");
+        assert_eq!(
+            json(test_support::me(&app, &token).await).await["name"],
+            "Ada"
+        );
+    }
+}
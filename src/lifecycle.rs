@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+use crate::tasks::TaskManager;
+
+type ReloadHook = Box<dyn Fn(&Config) + Send + Sync>;
+
+/// Process-wide lifecycle state, shared with handlers that report it
+#[derive(Clone, Default)]
+pub struct Lifecycle {
+    shutting_down: Arc<AtomicBool>,
+    tasks: TaskManager,
+    reload_hooks: Arc<Mutex<Vec<ReloadHook>>>,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the process as shutting down. `/ready` fails from here on.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub fn tasks(&self) -> &TaskManager {
+        &self.tasks
+    }
+
+    /// Run `hook` with the new config on every reload. Settings without a
+    /// hook only change on restart.
+    pub fn on_reload(&self, hook: impl Fn(&Config) + Send + Sync + 'static) {
+        self.reload_hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// Apply a freshly loaded config, as on `SIGHUP`
+    pub fn reload(&self, config: &Config) {
+        for hook in self.reload_hooks.lock().unwrap().iter() {
+            hook(config);
+        }
+    }
+}
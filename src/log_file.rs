@@ -0,0 +1,275 @@
+use chrono::Utc;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::JoinHandle;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::config::{Config, LogRotation};
+
+/// Lines queued for the writer thread; past this, new lines are dropped
+/// rather than blocking the task that logged them
+const QUEUE_CAPACITY: usize = 128_000;
+
+enum Message {
+    Line(Vec<u8>),
+    Shutdown,
+}
+
+/// The process's subscriber: `RUST_LOG` filtering, output to stdout and,
+/// with `LOG_FILE` set, the same lines without colors to that file. Keep the
+/// guard alive as long as the subscriber.
+pub fn subscriber(
+    config: &Config,
+) -> io::Result<(impl Subscriber + Send + Sync, Option<LogFileGuard>)> {
+    let (log_file, guard) = match &config.log_file {
+        Some(path) => {
+            let (writer, guard) = LogFile::open(path, config.log_rotation)?;
+            (Some(writer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "info,tust_starter=debug,tower_http=debug".into()),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .with(log_file.map(|writer| {
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(writer)
+        }));
+
+    Ok((subscriber, guard))
+}
+
+/// Non-blocking writer for a rotated log file. Events are handed to a
+/// background thread, which appends them to `<path>.<period>` (or `path`
+/// itself with `LogRotation::Never`) and starts a new file when the period
+/// changes.
+#[derive(Clone)]
+pub struct LogFile {
+    sender: SyncSender<Message>,
+}
+
+/// Flushes the log file and stops the writer thread when dropped. Keep it
+/// alive until the process exits, or lines still queued are lost.
+#[must_use = "dropping the guard stops writing to the log file"]
+pub struct LogFileGuard {
+    sender: SyncSender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LogFile {
+    pub fn open(path: &Path, rotation: LogRotation) -> io::Result<(Self, LogFileGuard)> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Open the first file here so a bad path fails startup instead of
+        // silently losing every line
+        let rolling = Rolling::new(path.to_path_buf(), rotation)?;
+
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let thread = std::thread::Builder::new()
+            .name("log-file".to_string())
+            .spawn(move || rolling.run(receiver))?;
+
+        Ok((
+            Self {
+                sender: sender.clone(),
+            },
+            LogFileGuard {
+                sender,
+                thread: Some(thread),
+            },
+        ))
+    }
+}
+
+impl Drop for LogFileGuard {
+    fn drop(&mut self) {
+        let _ = self.sender.send(Message::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = EventWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        EventWriter {
+            sender: &self.sender,
+            buf: Vec::new(),
+        }
+    }
+}
+
+/// Collects one formatted event and queues it when dropped, so lines from
+/// concurrent events never interleave
+pub struct EventWriter<'a> {
+    sender: &'a SyncSender<Message>,
+    buf: Vec<u8>,
+}
+
+impl Write for EventWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EventWriter<'_> {
+    fn drop(&mut self) {
+        if self.buf.is_empty() {
+            return;
+        }
+        // A full queue drops the line: logging about that would only add to it
+        let _ = self
+            .sender
+            .try_send(Message::Line(std::mem::take(&mut self.buf)));
+    }
+}
+
+struct Rolling {
+    path: PathBuf,
+    rotation: LogRotation,
+    period: String,
+    file: BufWriter<File>,
+}
+
+impl Rolling {
+    fn new(path: PathBuf, rotation: LogRotation) -> io::Result<Self> {
+        let period = current_period(rotation);
+        let file = open_append(&file_name(&path, &period))?;
+        Ok(Self {
+            path,
+            rotation,
+            period,
+            file,
+        })
+    }
+
+    fn run(mut self, receiver: Receiver<Message>) {
+        while let Ok(Message::Line(line)) = receiver.recv() {
+            self.write(&line);
+            // Drain whatever else is queued before paying for a flush
+            while let Ok(message) = receiver.try_recv() {
+                match message {
+                    Message::Line(line) => self.write(&line),
+                    Message::Shutdown => {
+                        let _ = self.file.flush();
+                        return;
+                    }
+                }
+            }
+            let _ = self.file.flush();
+        }
+        let _ = self.file.flush();
+    }
+
+    fn write(&mut self, line: &[u8]) {
+        self.roll_if_due();
+        if let Err(e) = self.file.write_all(line) {
+            eprintln!("Failed to write log file: {}", e);
+        }
+    }
+
+    fn roll_if_due(&mut self) {
+        let period = current_period(self.rotation);
+        if period == self.period {
+            return;
+        }
+        let _ = self.file.flush();
+        match open_append(&file_name(&self.path, &period)) {
+            Ok(file) => {
+                self.file = file;
+                self.period = period;
+            }
+            // Keep writing to the old file rather than dropping lines
+            Err(e) => eprintln!("Failed to rotate log file: {}", e),
+        }
+    }
+}
+
+fn current_period(rotation: LogRotation) -> String {
+    let now = Utc::now();
+    match rotation {
+        LogRotation::Daily => now.format("%Y-%m-%d").to_string(),
+        LogRotation::Hourly => now.format("%Y-%m-%d-%H").to_string(),
+        LogRotation::Never => String::new(),
+    }
+}
+
+fn file_name(path: &Path, period: &str) -> PathBuf {
+    if period.is_empty() {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(period);
+    PathBuf::from(name)
+}
+
+fn open_append(path: &Path) -> io::Result<BufWriter<File>> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(BufWriter::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn subscriber_also_writes_to_the_log_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("logs").join("app.log");
+        let config = test_support::config(&[
+            ("LOG_FILE", path.to_str().unwrap()),
+            ("LOG_ROTATION", "never"),
+        ]);
+
+        let (subscriber, guard) = subscriber(&config).unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(user_id = "u-1", "written to the file");
+        });
+        // Dropping the guard flushes what the writer thread still holds
+        drop(guard);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("written to the file"), "{}", contents);
+        assert!(contents.contains("user_id=\"u-1\""), "{}", contents);
+        assert!(!contents.contains('\x1b'), "no colors in the file");
+    }
+
+    #[test]
+    fn rotated_files_are_named_after_their_period() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+
+        let before = current_period(LogRotation::Daily);
+        let (_writer, guard) = LogFile::open(&path, LogRotation::Daily).unwrap();
+        drop(guard);
+        let after = current_period(LogRotation::Daily);
+
+        // Either side of midnight, the file carries its day
+        assert!([before, after]
+            .iter()
+            .any(|day| file_name(&path, day).exists()));
+        assert!(!path.exists());
+    }
+}
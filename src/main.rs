@@ -1,81 +1,180 @@
+// `Config::redacted` builds one large `json!` literal
+#![recursion_limit = "512"]
+
+mod cli;
 mod config;
+mod connection_limit;
+mod db;
 mod handlers;
+mod lifecycle;
+mod log_file;
+mod metrics;
 mod middleware;
 mod models;
 mod repositories;
 mod routes;
+mod runtime_metrics;
+mod server;
 mod services;
+mod tasks;
+mod tls;
+
+#[cfg(test)]
+mod test_support;
 
-use sqlx::postgres::PgPoolOptions;
 use std::time::Duration;
 use tokio::signal;
+use tower_http::request_id::{MakeRequestUuid, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::util::SubscriberInitExt;
 
+use cli::Command;
 use config::Config;
+use db::{Database, StatementLogging};
+use lifecycle::Lifecycle;
+use middleware::{mark_quiet_responses, RequestTrace};
 use routes::create_routes;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "info,tust_starter=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
-    tracing::info!("Starting Rust Starter API");
+    let command = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    // Load configuration
+    // Load configuration first, since it says where logs go
     let config = Config::from_env().expect("Failed to load configuration");
+
+    // Initialize tracing. The guard flushes the log file when main returns,
+    // so it has to stay bound for the whole process
+    let (subscriber, _log_file_guard) =
+        log_file::subscriber(&config).expect("Failed to open LOG_FILE");
+    subscriber.init();
+
+    tracing::info!("Starting Rust Starter API");
     tracing::info!("Configuration loaded successfully");
     tracing::info!("Environment: {:?}", config.environment);
 
     // Create database connection pool
     tracing::info!("Connecting to database...");
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .acquire_timeout(Duration::from_secs(3))
-        .connect(&config.database_url)
+    let logging = StatementLogging::from_config(&config);
+    let database = Database::connect(&config.database_url, logging)
         .await
         .expect("Failed to create database pool");
-    tracing::info!("Database connection pool created");
+    tracing::info!("Database connection pool created ({})", database.backend());
 
     // Run migrations
     tracing::info!("Running database migrations...");
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await
-        .expect("Failed to run migrations");
+    database.migrate().await.expect("Failed to run migrations");
     tracing::info!("Database migrations completed");
 
-    // Create router
-    let app = create_routes(pool.clone(), config.clone()).layer(TraceLayer::new_for_http());
+    if let Command::CreateUser {
+        email,
+        password,
+        admin,
+    } = command
+    {
+        if let Err(message) = cli::create_user(&database, &config, email, &password, admin).await {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    // Start server
-    let addr = format!("{}:{}", config.server_host, config.server_port);
-    tracing::info!("Server listening on {}", addr);
+    let lifecycle = Lifecycle::new();
 
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
-        .expect("Failed to bind to address");
+    // Pick up webhook deliveries interrupted by the last shutdown
+    routes::webhook_service(&database, &config, lifecycle.tasks())
+        .resume_pending()
+        .await;
 
-    tracing::info!("API Documentation: http://{}/api-docs", addr);
+    let auth_service = routes::auth_service(&database, &config, lifecycle.tasks());
 
-    // Serve with graceful shutdown
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .expect("Failed to start server");
+    // Hard-delete accounts once their deletion grace period is over
+    lifecycle
+        .tasks()
+        .spawn(auth_service.clone().run_account_purge(
+            Duration::from_secs(config.account_purge_interval_secs),
+            lifecycle.tasks().token(),
+        ));
+
+    if let Some(secs) = config.runtime_metrics_interval_secs {
+        lifecycle.tasks().spawn(runtime_metrics::run(
+            Duration::from_secs(secs),
+            lifecycle.tasks().token(),
+        ));
+    }
+
+    #[cfg(unix)]
+    lifecycle.tasks().spawn(reload_on_hangup(lifecycle.clone()));
+
+    // Create router
+    let trace = RequestTrace::new(config.trace_quiet_paths.clone());
+    let app = create_routes(database, config.clone(), lifecycle.clone(), auth_service)
+        .layer(axum::middleware::from_fn_with_state(
+            trace.clone(),
+            mark_quiet_responses,
+        ))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(trace.clone())
+                .on_request(trace.clone())
+                .on_response(trace),
+        )
+        // Outside the trace layer so the request span can record the id
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
+
+    // Start server with graceful shutdown
+    let drain = Duration::from_secs(config.shutdown_drain_secs);
+    server::serve(app, &config, shutdown_signal(lifecycle.clone(), drain)).await;
+
+    // Let in-flight background work, such as webhook deliveries, finish
+    lifecycle
+        .tasks()
+        .shutdown(Duration::from_secs(config.shutdown_tasks_timeout_secs))
+        .await;
 
     tracing::info!("Server shutdown complete");
 
     Ok(())
 }
 
-async fn shutdown_signal() {
+/// Reload the configuration on each SIGHUP, until shutdown. A config that
+/// fails to load is logged and the running one kept.
+#[cfg(unix)]
+async fn reload_on_hangup(lifecycle: Lifecycle) {
+    let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+        .expect("Failed to install signal handler");
+    let shutdown = lifecycle.tasks().token();
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            received = hangup.recv() => {
+                if received.is_none() {
+                    return;
+                }
+                tracing::info!("Received hangup signal, reloading configuration");
+                match Config::from_env() {
+                    Ok(config) => lifecycle.reload(&config),
+                    Err(e) => tracing::error!("Keeping the current configuration: {}", e),
+                }
+            }
+        }
+    }
+}
+
+/// Resolves once the server should stop accepting connections. On SIGTERM,
+/// `/ready` starts failing first and the server keeps serving for `drain` so
+/// the orchestrator can stop routing traffic before the socket closes.
+async fn shutdown_signal(lifecycle: Lifecycle, drain: Duration) {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -96,9 +195,15 @@ async fn shutdown_signal() {
     tokio::select! {
         _ = ctrl_c => {
             tracing::info!("Received Ctrl+C signal");
+            lifecycle.begin_shutdown();
         },
         _ = terminate => {
             tracing::info!("Received terminate signal");
+            lifecycle.begin_shutdown();
+            if !drain.is_zero() {
+                tracing::info!("Draining for {:?} before closing connections", drain);
+                tokio::time::sleep(drain).await;
+            }
         },
     }
 
@@ -0,0 +1,262 @@
+use chrono::Utc;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Exemplars only exist in OpenMetrics, so with them on `/metrics` speaks it
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// Seconds, as in the official Prometheus clients
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// OpenMetrics caps an exemplar's label names and values at 128 characters
+const MAX_EXEMPLAR_LABELS_LEN: usize = 128;
+
+/// Metrics served at `/metrics` in the Prometheus text format. Kept by hand:
+/// a handful of series doesn't justify a metrics stack.
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    exemplars: bool,
+    runtime: bool,
+    request_duration: Mutex<Histogram>,
+    pool_timeouts: AtomicU64,
+}
+
+#[derive(Default)]
+struct Histogram {
+    // Per bucket rather than cumulative; the last is `+Inf`
+    counts: [u64; LATENCY_BUCKETS.len() + 1],
+    // The latest observation to land in each bucket
+    exemplars: [Option<Exemplar>; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+struct Exemplar {
+    request_id: String,
+    value: f64,
+    timestamp: f64,
+}
+
+impl Metrics {
+    /// With `exemplars`, each latency bucket links to the `x-request-id` of
+    /// a request it counted. With `runtime`, each scrape also samples the
+    /// Tokio scheduler.
+    pub fn new(exemplars: bool, runtime: bool) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                exemplars,
+                runtime,
+                request_duration: Mutex::default(),
+                pool_timeouts: AtomicU64::default(),
+            }),
+        }
+    }
+
+    pub fn observe_request(&self, elapsed: Duration, request_id: Option<&str>) {
+        let value = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&le| value <= le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+
+        let mut histogram = self.inner.request_duration.lock().unwrap();
+        histogram.counts[bucket] += 1;
+        histogram.sum += value;
+        histogram.count += 1;
+
+        let request_id = request_id
+            .filter(|_| self.inner.exemplars)
+            .filter(|id| "request_id".len() + id.chars().count() <= MAX_EXEMPLAR_LABELS_LEN);
+        if let Some(request_id) = request_id {
+            histogram.exemplars[bucket] = Some(Exemplar {
+                request_id: request_id.to_string(),
+                value,
+                timestamp: Utc::now().timestamp_micros() as f64 / 1e6,
+            });
+        }
+    }
+
+    /// A request answered 503 because the database pool was exhausted
+    pub fn count_pool_timeout(&self) {
+        self.inner.pool_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        if self.inner.exemplars {
+            OPENMETRICS_CONTENT_TYPE
+        } else {
+            PROMETHEUS_CONTENT_TYPE
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let histogram = self.inner.request_duration.lock().unwrap();
+        let name = "http_request_duration_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {name} Time from receiving a request to sending its response headers"
+        );
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        let bounds = LATENCY_BUCKETS.iter().map(|le| format!("{:?}", le));
+        for (i, le) in bounds.chain(["+Inf".to_string()]).enumerate() {
+            cumulative += histogram.counts[i];
+            let _ = write!(out, "{name}_bucket{{le=\"{le}\"}} {cumulative}");
+            if let Some(exemplar) = &histogram.exemplars[i] {
+                let _ = write!(
+                    out,
+                    " # {{request_id=\"{}\"}} {} {}",
+                    escape(&exemplar.request_id),
+                    exemplar.value,
+                    exemplar.timestamp
+                );
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{name}_sum {}", histogram.sum);
+        let _ = writeln!(out, "{name}_count {}", histogram.count);
+        drop(histogram);
+
+        // OpenMetrics names the counter family without `_total`; the older
+        // text format names it after the sample
+        let name = if self.inner.exemplars {
+            "pool_timeouts"
+        } else {
+            "pool_timeouts_total"
+        };
+        let _ = writeln!(
+            out,
+            "# HELP {name} Requests answered 503 because the database pool was exhausted"
+        );
+        let _ = writeln!(out, "# TYPE {name} counter");
+        let _ = writeln!(
+            out,
+            "pool_timeouts_total {}",
+            self.inner.pool_timeouts.load(Ordering::Relaxed)
+        );
+
+        if self.inner.runtime {
+            if let Ok(runtime) = Handle::try_current() {
+                let runtime = runtime.metrics();
+                let gauges = [
+                    (
+                        "tokio_workers",
+                        "Worker threads of the runtime",
+                        runtime.num_workers(),
+                    ),
+                    (
+                        "tokio_alive_tasks",
+                        "Tasks spawned and not yet finished",
+                        runtime.num_alive_tasks(),
+                    ),
+                    (
+                        "tokio_global_queue_depth",
+                        "Tasks waiting in the global queue; growth means the workers can't keep up",
+                        runtime.global_queue_depth(),
+                    ),
+                ];
+                for (name, help, value) in gauges {
+                    let _ = writeln!(out, "# HELP {name} {help}");
+                    let _ = writeln!(out, "# TYPE {name} gauge");
+                    let _ = writeln!(out, "{name} {value}");
+                }
+            }
+        }
+
+        if self.inner.exemplars {
+            out.push_str("# EOF\n");
+        }
+        out
+    }
+}
+
+fn escape(label_value: &str) -> String {
+    label_value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_cumulative() {
+        let metrics = Metrics::new(false, false);
+        metrics.observe_request(Duration::from_millis(3), Some("a"));
+        metrics.observe_request(Duration::from_millis(70), Some("b"));
+        metrics.observe_request(Duration::from_secs(30), None);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("http_request_duration_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(rendered.contains("http_request_duration_seconds_bucket{le=\"0.1\"} 2\n"));
+        assert!(rendered.contains("http_request_duration_seconds_bucket{le=\"10.0\"} 2\n"));
+        assert!(rendered.contains("http_request_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(rendered.contains("http_request_duration_seconds_count 3\n"));
+        // Without exemplars, plain Prometheus text
+        assert!(!rendered.contains("request_id"));
+        assert!(!rendered.contains("# EOF"));
+    }
+
+    #[test]
+    fn exemplars_name_the_latest_request_in_each_bucket() {
+        let metrics = Metrics::new(true, false);
+        metrics.observe_request(Duration::from_millis(70), Some("first"));
+        metrics.observe_request(Duration::from_millis(80), Some("say \"hi\""));
+
+        let rendered = metrics.render();
+        let bucket = rendered
+            .lines()
+            .find(|line| line.starts_with("http_request_duration_seconds_bucket{le=\"0.1\"}"))
+            .unwrap();
+        assert!(
+            bucket.starts_with(
+                "http_request_duration_seconds_bucket{le=\"0.1\"} 2 # {request_id=\"say \\\"hi\\\"\"} 0.08 "
+            ),
+            "{}",
+            bucket
+        );
+        assert!(rendered.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn pool_timeouts_are_a_counter_in_either_format() {
+        let metrics = Metrics::new(false, false);
+        metrics.count_pool_timeout();
+        metrics.count_pool_timeout();
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE pool_timeouts_total counter\n"));
+        assert!(rendered.contains("\npool_timeouts_total 2\n"));
+
+        let metrics = Metrics::new(true, false);
+        metrics.count_pool_timeout();
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE pool_timeouts counter\n"));
+        assert!(rendered.contains("\npool_timeouts_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn runtime_gauges_are_sampled_when_enabled() {
+        let rendered = Metrics::new(false, true).render();
+        assert!(rendered.contains("# TYPE tokio_workers gauge\n"));
+        assert!(rendered.contains("\ntokio_alive_tasks "));
+        assert!(rendered.contains("\ntokio_global_queue_depth "));
+
+        let rendered = Metrics::new(false, false).render();
+        assert!(!rendered.contains("tokio_"));
+    }
+}
@@ -0,0 +1,148 @@
+use axum::{
+    extract::{MatchedPath, RawPathParams, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::auth::ClaimsExt;
+use crate::models::AdminAuditEntry;
+use crate::repositories::AdminAuditRepository;
+
+/// Records every authenticated request to an admin-only route in
+/// `admin_audit`, refused and failed ones included. Must run after
+/// `auth_middleware` and before `require_admin`, so requests from non-admins
+/// are recorded too.
+pub async fn admin_audit_middleware(
+    State(audit): State<Arc<dyn AdminAuditRepository>>,
+    matched_path: Option<MatchedPath>,
+    path_params: Option<RawPathParams>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(claims) = request.claims() else {
+        return next.run(request).await;
+    };
+
+    // Impersonation tokens carry the target user as `sub`
+    let actor = claims.act.as_ref().map_or(&claims.sub, |actor| &actor.sub);
+    let Ok(actor_id) = Uuid::parse_str(actor) else {
+        return next.run(request).await;
+    };
+
+    let route = matched_path
+        .as_ref()
+        .map_or(request.uri().path(), |p| p.as_str());
+    let action = format!("{} {}", request.method(), route);
+    let target = path_params.and_then(|params| {
+        params
+            .iter()
+            .find(|(name, _)| *name == "id")
+            .map(|(_, value)| value.to_string())
+    });
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(request).await;
+
+    let entry = AdminAuditEntry {
+        id: Uuid::new_v4(),
+        actor_id,
+        action,
+        target,
+        status: i32::from(response.status().as_u16()),
+        request_id,
+        created_at: Utc::now(),
+    };
+    // The action has already happened, so a lost record is logged rather
+    // than turned into an error response
+    if let Err(e) = audit.record(&entry).await {
+        tracing::error!(
+            actor_id = %entry.actor_id,
+            action = %entry.action,
+            status = entry.status,
+            "Failed to record admin audit entry: {}",
+            e
+        );
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{AdminAuditFilter, AuditOutcome, SortOrder};
+    use crate::test_support::{self, json, me, request, send, PASSWORD};
+    use axum::http::{Method, StatusCode};
+
+    #[tokio::test]
+    async fn successful_and_failed_admin_actions_are_both_recorded() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let admin = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let user_id = json(me(&app, &token).await).await["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let uri = format!("/admin/users/{}/revoke-sessions", user_id);
+        let mut revoke = request(Method::POST, &uri, Some(&admin), None);
+        revoke
+            .headers_mut()
+            .insert("x-request-id", "req-ok".parse().unwrap());
+        assert_eq!(send(&app, revoke).await.status(), StatusCode::NO_CONTENT);
+
+        let missing = uuid::Uuid::new_v4();
+        let uri = format!("/admin/users/{}/revoke-sessions", missing);
+        let response = send(&app, request(Method::POST, &uri, Some(&admin), None)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let entries = database
+            .admin_audit_repository()
+            .query(&AdminAuditFilter::default(), SortOrder::Asc, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let (succeeded, failed) = (&entries[0], &entries[1]);
+        assert_eq!(succeeded.action, "POST /admin/users/:id/revoke-sessions");
+        assert_eq!(succeeded.target.as_deref(), Some(user_id.as_str()));
+        assert_eq!(succeeded.outcome(), AuditOutcome::Success);
+        assert_eq!(succeeded.request_id.as_deref(), Some("req-ok"));
+        assert_eq!(failed.target, Some(missing.to_string()));
+        assert_eq!(failed.status, 404);
+        assert_eq!(failed.outcome(), AuditOutcome::Failure);
+        assert_eq!(failed.actor_id, succeeded.actor_id);
+    }
+
+    #[tokio::test]
+    async fn requests_refused_to_non_admins_are_recorded() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let user_id = json(me(&app, &token).await).await["id"].clone();
+
+        let response = send(
+            &app,
+            request(Method::GET, "/admin/audit", Some(&token), None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let entries = database
+            .admin_audit_repository()
+            .query(&AdminAuditFilter::default(), SortOrder::Asc, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor_id.to_string(), user_id.as_str().unwrap());
+        assert_eq!(entries[0].status, 403);
+        assert_eq!(entries[0].outcome(), AuditOutcome::Failure);
+    }
+}
@@ -1,20 +1,156 @@
 use axum::{
     extract::{Request, State},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     middleware::Next,
-    response::{IntoResponse, Response},
-    Json,
+    response::{Html, IntoResponse, Redirect, Response},
 };
-use serde_json::json;
+use std::sync::Arc;
 
+use super::rate_limit::ApiKeyRateLimit;
+use crate::config::UnauthenticatedHtml;
+use crate::handlers::{client_fingerprint, error_response_with_detail, ErrorCode};
 use crate::models::Claims;
-use crate::services::AuthService;
+use crate::services::api_key_service::ApiKeyError;
+use crate::services::auth_service::AuthError as ServiceError;
+use crate::services::{ApiKeyService, AuthService};
+
+/// Header carrying an API key in place of `Authorization: Bearer`
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// State for `auth_middleware`: the service that verifies tokens and,
+/// optionally, an audience every token on this mount must name in `aud`
+#[derive(Clone)]
+pub struct AuthGate {
+    auth_service: AuthService,
+    audience: Option<String>,
+    html: HtmlSignIn,
+    api_keys: Option<(ApiKeyService, ApiKeyRateLimit)>,
+}
+
+impl AuthGate {
+    pub fn new(auth_service: AuthService, audience: Option<String>, html: HtmlSignIn) -> Self {
+        Self {
+            auth_service,
+            audience,
+            html,
+            api_keys: None,
+        }
+    }
+
+    /// Also accept `X-API-Key` on this mount, within each key's quota.
+    /// Keys aren't tokens, so the audience and binding checks don't apply
+    /// to them.
+    pub fn with_api_keys(mut self, api_keys: ApiKeyService, rate_limit: ApiKeyRateLimit) -> Self {
+        self.api_keys = Some((api_keys, rate_limit));
+        self
+    }
+}
+
+/// How a 401 is answered for browsers that ask for HTML rather than JSON
+#[derive(Clone)]
+pub struct HtmlSignIn {
+    mode: UnauthenticatedHtml,
+    login_url: Arc<str>,
+}
+
+impl HtmlSignIn {
+    pub fn new(mode: UnauthenticatedHtml, login_url: &str) -> Self {
+        Self {
+            mode,
+            login_url: login_url.into(),
+        }
+    }
+
+    /// Whether a 401 for this request should be this HTML response rather
+    /// than the JSON error
+    fn applies(&self, headers: &HeaderMap) -> bool {
+        self.mode != UnauthenticatedHtml::Json && prefers_html(headers)
+    }
+
+    fn response(&self) -> Response {
+        match self.mode {
+            UnauthenticatedHtml::Redirect => Redirect::to(&self.login_url).into_response(),
+            _ => (
+                StatusCode::UNAUTHORIZED,
+                Html(format!(
+                    "<!doctype html>\n<html><head><meta charset=\"utf-8\">\
+                     <title>Sign in required</title></head>\n<body><h1>Sign in required</h1>\
+                     <p><a href=\"{}\">Sign in</a> to continue.</p></body></html>\n",
+                    escape_html(&self.login_url)
+                )),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// Whether `Accept` names `text/html` and not `application/json`, as
+/// browser navigations do; API clients that accept both get JSON
+fn prefers_html(headers: &HeaderMap) -> bool {
+    let media_types: Vec<&str> = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| item.split(';').next().unwrap_or_default().trim())
+        .collect();
+
+    media_types
+        .iter()
+        .any(|media_type| media_type.eq_ignore_ascii_case("text/html"))
+        && !media_types
+            .iter()
+            .any(|media_type| media_type.eq_ignore_ascii_case("application/json"))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
 
 pub async fn auth_middleware(
-    State(auth_service): State<AuthService>,
+    State(gate): State<AuthGate>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let html = gate.html.applies(request.headers());
+
+    match authenticate(&gate, request, next).await {
+        Ok(response) => response,
+        Err(e) if html && e.is_unauthenticated() => gate.html.response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn authenticate(
+    AuthGate {
+        auth_service,
+        audience,
+        api_keys,
+        ..
+    }: &AuthGate,
     mut request: Request,
     next: Next,
 ) -> Result<Response, AuthError> {
+    let api_key = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|h| h.to_str().ok());
+    if let (Some((api_keys, rate_limit)), Some(key)) = (api_keys, api_key) {
+        let (api_key, claims) = api_keys.authenticate(key).await.map_err(|e| match e {
+            ApiKeyError::DatabaseError(e) => AuthError::Database(e),
+            _ => AuthError::InvalidApiKey,
+        })?;
+        tracing::Span::current().record("user_id", claims.sub.as_str());
+        if let Err(throttled) = rate_limit.check(api_key.id, api_key.rate_limit_rps()) {
+            return Ok(throttled.into_response());
+        }
+        request.extensions_mut().insert(claims);
+        return Ok(next.run(request).await);
+    }
+
     let auth_header = request
         .headers()
         .get("Authorization")
@@ -27,7 +163,29 @@ pub async fn auth_middleware(
 
     let claims = auth_service
         .verify_token(token)
-        .map_err(|_| AuthError::InvalidToken)?;
+        .await
+        .map_err(|e| match e {
+            ServiceError::DatabaseError(e) => AuthError::Database(e),
+            ServiceError::UnknownKeyId(_) => AuthError::UnknownKeyId,
+            ServiceError::TokenTooOld => AuthError::TokenTooOld,
+            _ => AuthError::InvalidToken,
+        })?;
+
+    if let Some(audience) = &audience {
+        if !claims.aud.contains(audience) {
+            return Err(AuthError::WrongAudience);
+        }
+    }
+
+    auth_service
+        .check_token_binding(&claims, client_fingerprint(request.headers()).as_deref())
+        .map_err(|_| AuthError::TokenBindingMismatch)?;
+
+    // Correlate everything logged for this request with the user
+    tracing::Span::current().record("user_id", claims.sub.as_str());
+    if let Some(actor) = &claims.act {
+        tracing::Span::current().record("actor_id", actor.sub.as_str());
+    }
 
     // Insert claims into request extensions so handlers can access them
     request.extensions_mut().insert(claims);
@@ -35,20 +193,95 @@ pub async fn auth_middleware(
     Ok(next.run(request).await)
 }
 
+/// Must run after `auth_middleware`
+pub async fn require_admin(request: Request, next: Next) -> Result<Response, AuthError> {
+    match request.claims() {
+        Some(claims) if claims.is_admin() => Ok(next.run(request).await),
+        Some(_) => Err(AuthError::Forbidden),
+        None => Err(AuthError::MissingToken),
+    }
+}
+
 #[derive(Debug)]
 pub enum AuthError {
     MissingToken,
     InvalidToken,
+    InvalidApiKey,
+    UnknownKeyId,
+    TokenTooOld,
+    WrongAudience,
+    TokenBindingMismatch,
+    Forbidden,
+    Database(sqlx::Error),
+}
+
+impl AuthError {
+    /// Answered with 401: no usable token, rather than a forbidden or
+    /// failed request
+    fn is_unauthenticated(&self) -> bool {
+        !matches!(self, AuthError::Forbidden | AuthError::Database(_))
+    }
 }
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AuthError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing authorization token"),
-            AuthError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid authorization token"),
+        let detail = match &self {
+            AuthError::Database(e) => Some(e.to_string()),
+            _ => None,
         };
 
-        (status, Json(json!({ "error": message }))).into_response()
+        let (status, code, message) = match self {
+            AuthError::MissingToken => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::MissingToken,
+                "Missing authorization token",
+            ),
+            AuthError::InvalidToken => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::InvalidToken,
+                "Invalid authorization token",
+            ),
+            AuthError::InvalidApiKey => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::InvalidApiKey,
+                "Invalid API key",
+            ),
+            AuthError::UnknownKeyId => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::UnknownKeyId,
+                "Token signed with an unknown key",
+            ),
+            AuthError::TokenTooOld => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::TokenTooOld,
+                "Token is too old",
+            ),
+            AuthError::WrongAudience => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::InvalidAudience,
+                "Token is not meant for this audience",
+            ),
+            AuthError::TokenBindingMismatch => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::TokenBindingMismatch,
+                "Token is bound to a different client",
+            ),
+            AuthError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                ErrorCode::Forbidden,
+                "Insufficient permissions",
+            ),
+            AuthError::Database(sqlx::Error::PoolTimedOut) => {
+                return crate::handlers::pool_timed_out_response();
+            }
+            AuthError::Database(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                "Database error",
+            ),
+        };
+
+        error_response_with_detail(status, code, message, detail)
     }
 }
 
@@ -62,3 +295,204 @@ impl ClaimsExt for Request {
         self.extensions().get::<Claims>()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::CLIENT_FINGERPRINT_HEADER;
+    use crate::test_support::{self, body_bytes, json, me, request, send, PASSWORD};
+    use axum::http::{header, Method, StatusCode};
+    use axum::response::Response;
+
+    #[tokio::test]
+    async fn missing_and_invalid_tokens_carry_stable_codes() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let response = test_support::send(
+            &app,
+            test_support::request(Method::GET, "/users/me", None, None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json(response).await["code"], "missing_token");
+
+        let response = me(&app, "not-a-jwt").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json(response).await["code"], "invalid_token");
+    }
+
+    #[tokio::test]
+    async fn public_audience_token_is_rejected_on_admin_routes() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[
+            ("JWT_AUDIENCE", "public,admin"),
+            ("JWT_API_AUDIENCE", "public"),
+            ("JWT_ADMIN_AUDIENCE", "admin"),
+        ]);
+        let app = test_support::app(&database, config);
+        let token = test_support::sign_up_admin(&app, &database, "admin@example.com").await;
+        let for_audience =
+            |aud: &str| test_support::resign(&token, |claims| claims.aud = vec![aud.to_string()]);
+        let audit = |token: String| request(Method::GET, "/admin/audit", Some(&token), None);
+
+        let public = for_audience("public");
+        assert_eq!(me(&app, &public).await.status(), StatusCode::OK);
+        let response = send(&app, audit(public)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json(response).await["code"], "invalid_audience");
+
+        let response = send(&app, audit(for_audience("admin"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn minimal_claims_pick_up_role_changes_immediately() {
+        use crate::models::{Email, Role};
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        let database = test_support::database().await;
+        let config = test_support::config(&[("JWT_MINIMAL_CLAIMS", "true")]);
+        let app = test_support::app(&database, config);
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let payload = URL_SAFE_NO_PAD
+            .decode(token.split('.').nth(1).unwrap())
+            .unwrap();
+        let claims: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert!(claims.get("role").is_none(), "{}", claims);
+        let list_users = || request(Method::GET, "/users", Some(&token), None);
+        assert_eq!(
+            send(&app, list_users()).await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let users = database.user_repository();
+        let email = Email::try_from("user@example.com".to_string()).unwrap();
+        let user = users.find_by_email(&email).await.unwrap().unwrap();
+        users.set_role(user.id, Role::Admin).await.unwrap();
+        assert_eq!(send(&app, list_users()).await.status(), StatusCode::OK);
+
+        users.set_role(user.id, Role::User).await.unwrap();
+        assert_eq!(
+            send(&app, list_users()).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    /// `GET /users/me` without a token, sending `accept` if given
+    async fn me_accepting(app: &axum::Router, accept: Option<&str>) -> Response {
+        let mut request = request(Method::GET, "/users/me", None, None);
+        if let Some(accept) = accept {
+            request
+                .headers_mut()
+                .insert(header::ACCEPT, accept.parse().unwrap());
+        }
+        send(app, request).await
+    }
+
+    #[tokio::test]
+    async fn browsers_get_the_sign_in_page_and_api_clients_json() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[
+            ("UNAUTHENTICATED_HTML", "page"),
+            ("LOGIN_URL", "https://app.example.com/login?next=/a&b"),
+        ]);
+        let app = test_support::app(&database, config);
+
+        let response = me_accepting(&app, Some("text/html,application/xhtml+xml;q=0.9")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        let page = String::from_utf8(body_bytes(response).await.to_vec()).unwrap();
+        assert!(
+            page.contains(r#"href="https://app.example.com/login?next=/a&amp;b""#),
+            "{}",
+            page
+        );
+
+        // Clients that take JSON, or don't say, get the usual error
+        for accept in [Some("text/html, application/json"), Some("*/*"), None] {
+            let response = me_accepting(&app, accept).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                json(response).await["code"],
+                "missing_token",
+                "{:?}",
+                accept
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn browsers_are_redirected_to_sign_in_in_redirect_mode() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[
+            ("UNAUTHENTICATED_HTML", "redirect"),
+            ("LOGIN_URL", "https://app.example.com/login"),
+        ]);
+        let app = test_support::app(&database, config);
+
+        let response = me_accepting(&app, Some("text/html")).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://app.example.com/login"
+        );
+
+        let response = me_accepting(&app, Some("application/json")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn browsers_get_json_by_default() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let response = me_accepting(&app, Some("text/html")).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json(response).await["code"], "missing_token");
+    }
+
+    /// `GET /users/me` with `token`, presenting `fingerprint` if given
+    async fn me_from(app: &axum::Router, token: &str, fingerprint: Option<&str>) -> StatusCode {
+        let mut request = request(Method::GET, "/users/me", Some(token), None);
+        if let Some(fingerprint) = fingerprint {
+            let value = fingerprint.parse().unwrap();
+            request
+                .headers_mut()
+                .insert(CLIENT_FINGERPRINT_HEADER, value);
+        }
+        send(app, request).await.status()
+    }
+
+    #[tokio::test]
+    async fn bound_token_is_rejected_from_another_client() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("TOKEN_BINDING_ENABLED", "true")]);
+        let app = test_support::app(&database, config);
+        test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let credentials = serde_json::json!({ "email": "user@example.com", "password": PASSWORD });
+        let mut login = request(Method::POST, "/auth/login", None, Some(credentials));
+        login
+            .headers_mut()
+            .insert(CLIENT_FINGERPRINT_HEADER, "device-1".parse().unwrap());
+        let token = json(send(&app, login).await).await["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            me_from(&app, &token, Some("device-1")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            me_from(&app, &token, Some("device-2")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(me_from(&app, &token, None).await, StatusCode::UNAUTHORIZED);
+        let response = me(&app, &token).await;
+        assert_eq!(json(response).await["code"], "token_binding_mismatch");
+    }
+}
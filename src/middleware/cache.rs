@@ -0,0 +1,21 @@
+use axum::http::{header, HeaderValue};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+pub type CacheControlLayer = SetResponseHeaderLayer<HeaderValue>;
+
+/// For anything tied to a user or a credential; must never sit in a cache
+pub fn no_store() -> CacheControlLayer {
+    cache_control(HeaderValue::from_static("no-store"))
+}
+
+/// For public responses that are safe to share between clients briefly
+pub fn public_max_age(secs: u64) -> CacheControlLayer {
+    let value = HeaderValue::from_str(&format!("public, max-age={}", secs))
+        .expect("Cache-Control value is valid ASCII");
+    cache_control(value)
+}
+
+// Handlers that set their own Cache-Control keep it
+fn cache_control(value: HeaderValue) -> CacheControlLayer {
+    SetResponseHeaderLayer::if_not_present(header::CACHE_CONTROL, value)
+}
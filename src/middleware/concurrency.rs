@@ -0,0 +1,217 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use super::auth::ClaimsExt;
+use crate::handlers::{error_response, ErrorCode};
+use crate::models::{ConcurrencyStatus, ThrottledUser, UserInFlight};
+
+// Bounds on what the status endpoint reports and remembers
+const STATUS_MAX_USERS: usize = 100;
+const RECENTLY_THROTTLED_LEN: usize = 50;
+
+/// Caps how many requests a single user can have in flight at once.
+/// Unlike rate limiting this doesn't care how fast requests arrive, only how
+/// many are running simultaneously. `None` disables the cap.
+#[derive(Clone)]
+pub struct UserConcurrencyLimit {
+    max_in_flight: Option<usize>,
+    in_flight: Arc<Mutex<HashMap<String, usize>>>,
+    recently_throttled: Arc<Mutex<VecDeque<ThrottledUser>>>,
+}
+
+impl UserConcurrencyLimit {
+    pub fn new(max_in_flight: Option<usize>) -> Self {
+        Self {
+            max_in_flight,
+            in_flight: Arc::default(),
+            recently_throttled: Arc::default(),
+        }
+    }
+
+    pub fn status(&self) -> ConcurrencyStatus {
+        let in_flight = self.in_flight.lock().unwrap();
+        let mut users: Vec<UserInFlight> = in_flight
+            .iter()
+            .map(|(user_id, &count)| UserInFlight {
+                user_id: user_id.clone(),
+                in_flight: count,
+                remaining: self.max_in_flight.unwrap_or(0).saturating_sub(count),
+            })
+            .collect();
+        users.sort_by_key(|user| Reverse(user.in_flight));
+        let truncated = users.len() > STATUS_MAX_USERS;
+        users.truncate(STATUS_MAX_USERS);
+
+        let recently_throttled = self
+            .recently_throttled
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .cloned()
+            .collect();
+
+        ConcurrencyStatus {
+            max_in_flight: self.max_in_flight,
+            users,
+            truncated,
+            recently_throttled,
+        }
+    }
+
+    fn record_throttled(&self, user_id: &str) {
+        let mut recent = self.recently_throttled.lock().unwrap();
+        if recent.len() == RECENTLY_THROTTLED_LEN {
+            recent.pop_front();
+        }
+        recent.push_back(ThrottledUser {
+            user_id: user_id.to_string(),
+            at: Utc::now(),
+        });
+    }
+
+    fn try_acquire(&self, user_id: &str, max_in_flight: usize) -> Option<InFlightGuard> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.entry(user_id.to_string()).or_insert(0);
+        if *count >= max_in_flight {
+            return None;
+        }
+        *count += 1;
+
+        Some(InFlightGuard {
+            limit: self.clone(),
+            user_id: user_id.to_string(),
+        })
+    }
+}
+
+/// Releases the user's slot when the request finishes, even if the handler
+/// panics or the client disconnects
+struct InFlightGuard {
+    limit: UserConcurrencyLimit,
+    user_id: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut in_flight = self.limit.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(&self.user_id) {
+            *count -= 1;
+            // Don't keep an entry around for every user ever seen
+            if *count == 0 {
+                in_flight.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+/// Must run after `auth_middleware`; requests without claims pass through.
+/// Rejected requests get 429, like rate limiting.
+pub async fn user_concurrency_middleware(
+    State(limit): State<UserConcurrencyLimit>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (Some(max_in_flight), Some(claims)) = (limit.max_in_flight, request.claims()) else {
+        return next.run(request).await;
+    };
+    let user_id = claims.sub.clone();
+
+    let Some(_guard) = limit.try_acquire(&user_id, max_in_flight) else {
+        tracing::warn!("User {} exceeded the concurrent request limit", user_id);
+        limit.record_throttled(&user_id);
+        return error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::TooManyConcurrentRequests,
+            "Too many concurrent requests",
+        );
+    };
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Claims, Role};
+    use crate::test_support::{json, request, send};
+    use axum::{http::Method, middleware, routing::get, Router};
+    use tokio::sync::Semaphore;
+
+    const MAX_IN_FLIGHT: usize = 2;
+
+    fn claims() -> Claims {
+        Claims {
+            sub: "user-1".to_string(),
+            email: "user@example.com".to_string(),
+            role: Role::User,
+            token_version: 0,
+            exp: 0,
+            iat: 0,
+            nbf: 0,
+            aud: Vec::new(),
+            act: None,
+            cnf: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn request_past_the_limit_is_rejected() {
+        let limit = UserConcurrencyLimit::new(Some(MAX_IN_FLIGHT));
+        // Handlers block until the test hands out permits
+        let gate = Arc::new(Semaphore::new(0));
+        let handler_gate = gate.clone();
+        let app = Router::new()
+            .route(
+                "/work",
+                get(move || async move {
+                    handler_gate.acquire().await.unwrap().forget();
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                limit.clone(),
+                user_concurrency_middleware,
+            ))
+            // Stands in for `auth_middleware`
+            .layer(middleware::from_fn(
+                |mut request: Request, next: Next| async move {
+                    request.extensions_mut().insert(claims());
+                    next.run(request).await
+                },
+            ));
+
+        let held: Vec<_> = (0..MAX_IN_FLIGHT)
+            .map(|_| {
+                let app = app.clone();
+                tokio::spawn(
+                    async move { send(&app, request(Method::GET, "/work", None, None)).await },
+                )
+            })
+            .collect();
+        while limit.status().users.first().map(|user| user.in_flight) != Some(MAX_IN_FLIGHT) {
+            tokio::task::yield_now().await;
+        }
+
+        let rejected = send(&app, request(Method::GET, "/work", None, None)).await;
+        assert_eq!(rejected.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(json(rejected).await["code"], "too_many_concurrent_requests");
+        let throttled = limit.status().recently_throttled;
+        assert_eq!(throttled.len(), 1);
+        assert_eq!(throttled[0].user_id, "user-1");
+
+        gate.add_permits(MAX_IN_FLIGHT + 1);
+        for request in held {
+            assert_eq!(request.await.unwrap().status(), StatusCode::OK);
+        }
+        let after = send(&app, request(Method::GET, "/work", None, None)).await;
+        assert_eq!(after.status(), StatusCode::OK);
+    }
+}
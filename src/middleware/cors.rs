@@ -0,0 +1,248 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderName, HeaderValue, Method},
+    Router,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use super::API_KEY_HEADER;
+use crate::config::Config;
+use crate::handlers::CLIENT_FINGERPRINT_HEADER;
+
+/// Policy for everything outside `CORS_PUBLIC_PATHS`. A request's `Origin`
+/// is echoed back only when it's on `ALLOWED_ORIGINS`; any other origin gets
+/// no `Access-Control-Allow-Origin` at all. `Vary: Origin` (the layer's
+/// default) keeps caches from handing one origin's answer to another.
+pub fn cors_layer(config: &Config) -> CorsLayer {
+    // Config refuses `*` together with credentials
+    let origins = if config.allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        origin_list(&config.allowed_origins).into()
+    };
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::IF_MATCH,
+            header::IF_NONE_MATCH,
+            HeaderName::from_static(CLIENT_FINGERPRINT_HEADER),
+            HeaderName::from_static(API_KEY_HEADER),
+        ])
+        .expose_headers(exposed_headers(config))
+        .allow_credentials(config.cors_allow_credentials)
+}
+
+/// Read-only policy for `CORS_PUBLIC_PATHS`, such as health checks and
+/// JWKS: `CORS_PUBLIC_ORIGINS` (any origin by default), no credentials
+pub fn public_cors_layer(config: &Config) -> CorsLayer {
+    let origins = if config.cors_public_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        origin_list(&config.cors_public_origins).into()
+    };
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods([Method::GET, Method::HEAD, Method::OPTIONS])
+        .expose_headers(exposed_headers(config))
+}
+
+/// Answer `CORS_PUBLIC_PATHS` with the public policy and everything else
+/// with the allowlist. Both wrap the whole app, so responses produced by
+/// outer middleware such as 429s still carry CORS headers.
+pub fn with_cors(app: Router, config: &Config) -> Router {
+    let public_paths: Arc<[String]> = config.cors_public_paths.clone().into();
+    let public = app.clone().layer(public_cors_layer(config));
+    let private = app.layer(cors_layer(config));
+
+    Router::new().fallback_service(tower::service_fn(move |request: Request| {
+        let router = if public_paths.iter().any(|p| p == request.uri().path()) {
+            public.clone()
+        } else {
+            private.clone()
+        };
+        router.oneshot(request)
+    }))
+}
+
+fn origin_list(origins: &[String]) -> Vec<HeaderValue> {
+    origins
+        .iter()
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin: {}", origin);
+                None
+            }
+        })
+        .collect()
+}
+
+// Browsers only let scripts read non-safelisted response headers
+// (request id, rate limit state) when they are listed here
+fn exposed_headers(config: &Config) -> Vec<HeaderName> {
+    config
+        .cors_exposed_headers
+        .iter()
+        .filter_map(|name| match HeaderName::from_bytes(name.as_bytes()) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS exposed header: {}", name);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, send};
+    use axum::{body::Body, http::StatusCode, routing::get};
+
+    fn app(config: &Config) -> Router {
+        let routes = Router::new()
+            .route("/auth/login", get(|| async {}))
+            .route("/healthz", get(|| async {}));
+        with_cors(routes, config)
+    }
+
+    fn from_origin(origin: &str) -> Request {
+        get_from("/auth/login", origin)
+    }
+
+    fn get_from(path: &str, origin: &str) -> Request {
+        Request::builder()
+            .uri(path)
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn allowed_origin(response: &axum::response::Response) -> Option<&str> {
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap())
+    }
+
+    fn exposed(response: &axum::response::Response) -> Vec<String> {
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+            .expect("exposed headers are listed")
+            .to_str()
+            .unwrap()
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn exposes_request_id_and_rate_limit_headers_by_default() {
+        let config = test_support::config(&[]);
+        let response = send(&app(&config), from_origin("http://localhost:3000")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let exposed = exposed(&response);
+        for name in ["x-request-id", "x-ratelimit-limit", "x-ratelimit-remaining"] {
+            assert!(
+                exposed.iter().any(|h| h == name),
+                "{} not in {:?}",
+                name,
+                exposed
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn exposes_configured_headers() {
+        let config = test_support::config(&[("CORS_EXPOSED_HEADERS", "x-request-id, x-custom")]);
+        let response = send(&app(&config), from_origin("http://localhost:3000")).await;
+
+        assert_eq!(exposed(&response), ["x-request-id", "x-custom"]);
+    }
+
+    #[tokio::test]
+    async fn public_paths_allow_any_origin_but_others_use_the_allowlist() {
+        let config = test_support::config(&[]);
+        let app = app(&config);
+
+        let response = send(&app, get_from("/healthz", "https://dashboard.example")).await;
+        assert_eq!(allowed_origin(&response), Some("*"));
+
+        let response = send(&app, from_origin("https://dashboard.example")).await;
+        assert_eq!(allowed_origin(&response), None);
+        let response = send(&app, from_origin("http://localhost:3000")).await;
+        assert_eq!(allowed_origin(&response), Some("http://localhost:3000"));
+    }
+
+    fn varies_on_origin(response: &axum::response::Response) -> bool {
+        response
+            .headers()
+            .get_all(header::VARY)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap().split(','))
+            .any(|name| name.trim().eq_ignore_ascii_case("origin"))
+    }
+
+    #[tokio::test]
+    async fn allowlisted_origin_is_reflected_with_credentials_and_vary() {
+        let config = test_support::config(&[
+            (
+                "ALLOWED_ORIGINS",
+                "https://app.example,https://admin.example",
+            ),
+            ("CORS_ALLOW_CREDENTIALS", "true"),
+        ]);
+        let app = app(&config);
+
+        for origin in ["https://app.example", "https://admin.example"] {
+            let response = send(&app, from_origin(origin)).await;
+            assert_eq!(allowed_origin(&response), Some(origin));
+            assert_eq!(
+                response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+                "true"
+            );
+            assert!(varies_on_origin(&response));
+        }
+    }
+
+    #[tokio::test]
+    async fn other_origins_get_no_allow_origin_header() {
+        let config = test_support::config(&[
+            ("ALLOWED_ORIGINS", "https://app.example"),
+            ("CORS_ALLOW_CREDENTIALS", "true"),
+        ]);
+        let app = app(&config);
+
+        let response = send(&app, from_origin("https://evil.example")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(allowed_origin(&response), None);
+        // Still marked, so a cache can't serve this answer to an allowed origin
+        assert!(varies_on_origin(&response));
+
+        let preflight = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/auth/login")
+            .header(header::ORIGIN, "https://evil.example")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+        let response = send(&app, preflight).await;
+        assert_eq!(allowed_origin(&response), None);
+    }
+}
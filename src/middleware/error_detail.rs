@@ -0,0 +1,80 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+
+use crate::handlers::ErrorDetail;
+
+// Error bodies are small; anything bigger isn't one of ours
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// Adds the underlying cause to JSON error bodies as `detail`. Development
+/// only: internal errors can reveal queries, constraint names and the like.
+pub async fn expose_error_detail(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+
+    let Some(ErrorDetail(detail)) = response.extensions_mut().remove::<ErrorDetail>() else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_ERROR_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+
+    let body = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(mut map)) => {
+            map.insert("detail".to_string(), Value::String(detail));
+            let body = Value::Object(map).to_string();
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(body)
+        }
+        _ => Body::from(bytes),
+    };
+
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, json, request, send};
+    use axum::http::{Method, StatusCode};
+    use serde_json::{json, Value};
+
+    // Without a users table, login fails on its first query
+    async fn database_error(environment: &str) -> Value {
+        let database = test_support::unmigrated_database().await;
+        let config = test_support::config(&[("ENV", environment)]);
+        let app = test_support::app(&database, config);
+
+        let credentials = json!({ "email": "user@example.com", "password": "password123" });
+        let response = send(
+            &app,
+            request(Method::POST, "/auth/login", None, Some(credentials)),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        json(response).await
+    }
+
+    #[tokio::test]
+    async fn detail_is_exposed_in_development() {
+        let body = database_error("development").await;
+
+        assert!(body["detail"].as_str().unwrap().contains("no such table"));
+    }
+
+    #[tokio::test]
+    async fn detail_is_absent_in_production() {
+        let body = database_error("production").await;
+
+        assert_eq!(body["code"], "internal_error");
+        assert!(body.get("detail").is_none());
+    }
+}
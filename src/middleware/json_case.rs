@@ -0,0 +1,152 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+
+/// Renames object keys in JSON response bodies from snake_case to
+/// camelCase. Handlers always serialize snake_case; this is only installed
+/// when `JSON_CASE=camel`.
+pub async fn camel_case_json(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    rewrite_json(response, |value| camel_case_keys(value).to_string()).await
+}
+
+/// Re-indents JSON response bodies for reading by hand. Development only,
+/// installed with `JSON_PRETTY=true`.
+pub async fn pretty_json(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    rewrite_json(response, |value| {
+        serde_json::to_string_pretty(&value).unwrap_or_else(|_| value.to_string())
+    })
+    .await
+}
+
+// Passes non-JSON responses, and bodies that don't parse, through unchanged
+async fn rewrite_json(response: Response, render: impl FnOnce(Value) -> String) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    // Bodies here come from our own handlers, so no size cap is needed
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+
+    let body = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(value) => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(render(value))
+        }
+        Err(_) => Body::from(bytes),
+    };
+
+    Response::from_parts(parts, body)
+}
+
+/// Recursively rename every object key; values are left alone
+pub fn camel_case_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (to_camel_case(&key), camel_case_keys(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(camel_case_keys).collect()),
+        other => other,
+    }
+}
+
+/// `created_at` -> `createdAt`
+pub fn to_camel_case(key: &str) -> String {
+    let mut words = key.split('_');
+    let mut camel = words.next().unwrap_or_default().to_string();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, body_bytes, json, me, request, send, PASSWORD};
+    use axum::http::Method;
+
+    #[tokio::test]
+    async fn responses_use_the_configured_case() {
+        let database = test_support::database().await;
+        for (case, key) in [("snake", "created_at"), ("camel", "createdAt")] {
+            let config = test_support::config(&[("JSON_CASE", case)]);
+            let app = test_support::app(&database, config);
+            let email = format!("{}@example.com", case);
+            let token = test_support::sign_up(&app, &email, PASSWORD).await;
+
+            let body = json(me(&app, &token).await).await;
+
+            assert!(body.get(key).is_some(), "{} case: {}", case, body);
+            assert!(body.get("last_login_at").is_some() == (case == "snake"));
+            assert!(body.get("lastLoginAt").is_some() == (case == "camel"));
+        }
+    }
+
+    #[tokio::test]
+    async fn camel_case_requests_are_accepted() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("JSON_CASE", "camel")]);
+        let app = test_support::app(&database, config);
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let user = json(me(&app, &token).await).await;
+
+        let body = serde_json::json!({
+            "avatarUrl": "https://example.com/me.png",
+            "version": user["version"],
+        });
+        let response = send(
+            &app,
+            request(Method::PATCH, "/users/me", Some(&token), Some(body)),
+        )
+        .await;
+
+        assert_eq!(
+            json(response).await["avatarUrl"],
+            "https://example.com/me.png"
+        );
+    }
+
+    #[tokio::test]
+    async fn pretty_json_indents_bodies_in_development_only() {
+        let database = test_support::database().await;
+        for (vars, pretty) in [
+            (&[("JSON_PRETTY", "true")][..], true),
+            (&[][..], false),
+            (&[("JSON_PRETTY", "true"), ("ENV", "production")][..], false),
+        ] {
+            let app = test_support::app(&database, test_support::config(vars));
+            let response = send(&app, request(Method::GET, "/users/me", None, None)).await;
+            let body = String::from_utf8(body_bytes(response).await).unwrap();
+
+            assert_eq!(
+                body.contains("\n  \"code\": "),
+                pretty,
+                "{:?}: {}",
+                vars,
+                body
+            );
+            assert!(serde_json::from_str::<serde_json::Value>(&body).is_ok());
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::handlers::{error_response, ErrorCode};
+
+/// Runtime maintenance switch shared between the middleware and the admin
+/// endpoint that toggles it
+#[derive(Clone)]
+pub struct MaintenanceMode {
+    enabled: Arc<AtomicBool>,
+    retry_after_secs: u64,
+}
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool, retry_after_secs: u64) -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(enabled)),
+            retry_after_secs,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Rejects requests with 503 while maintenance mode is on. Only layered onto
+/// routes that should go dark; health and admin routes stay reachable.
+pub async fn maintenance_middleware(
+    State(maintenance): State<MaintenanceMode>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if maintenance.is_enabled() {
+        let mut response = error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Maintenance,
+            "Service is under maintenance",
+        );
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(maintenance.retry_after_secs),
+        );
+        return response;
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, json, request, send, PASSWORD};
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn blocks_login_but_not_health_checks_or_admin_routes() {
+        let database = test_support::database().await;
+        // Tokens are good across apps on the same database, so sign the
+        // admin in before maintenance starts
+        let setup = test_support::app(&database, test_support::config(&[]));
+        let admin = test_support::sign_up_admin(&setup, &database, "admin@example.com").await;
+        let config = test_support::config(&[
+            ("MAINTENANCE_MODE", "true"),
+            ("MAINTENANCE_RETRY_AFTER_SECS", "120"),
+        ]);
+        let app = test_support::app(&database, config);
+
+        let credentials = json!({ "email": "user@example.com", "password": PASSWORD });
+        let response = send(
+            &app,
+            request(Method::POST, "/auth/login", None, Some(credentials)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "120");
+        assert_eq!(json(response).await["code"], "maintenance");
+
+        let response = send(&app, request(Method::GET, "/healthz", None, None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send(&app, request(Method::GET, "/users", Some(&admin), None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,28 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+
+use crate::metrics::Metrics;
+
+/// Records every request's latency, with its `x-request-id` as the
+/// exemplar when exemplars are on
+pub async fn metrics_middleware(
+    State(metrics): State<Metrics>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    metrics.observe_request(start.elapsed(), request_id.as_deref());
+    response
+}
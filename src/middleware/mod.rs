@@ -1,4 +1,35 @@
+pub mod admin_audit;
 pub mod auth;
+pub mod cache;
+pub mod concurrency;
+pub mod cors;
+pub mod error_detail;
+pub mod json_case;
+pub mod maintenance;
+pub mod metrics;
+pub mod panic;
+pub mod pool_timeout;
 pub mod rate_limit;
+pub mod slow_request;
+pub mod timeout;
+pub mod trace;
+pub mod trailing_slash;
 
-pub use rate_limit::{rate_limit_middleware, RateLimitLayer};
+pub use admin_audit::admin_audit_middleware;
+pub use auth::{auth_middleware, require_admin, AuthGate, HtmlSignIn, API_KEY_HEADER};
+pub use concurrency::{user_concurrency_middleware, UserConcurrencyLimit};
+pub use cors::with_cors;
+pub use error_detail::expose_error_detail;
+pub use json_case::{camel_case_json, pretty_json};
+pub use maintenance::{maintenance_middleware, MaintenanceMode};
+pub use metrics::metrics_middleware;
+pub use panic::panic_response;
+pub use pool_timeout::{pool_timeout_middleware, PoolTimeoutPolicy};
+pub use rate_limit::{
+    rate_limit_middleware, user_rate_limit_middleware, ApiKeyRateLimit, RateLimitLayer,
+    UserRateLimit,
+};
+pub use slow_request::slow_request_middleware;
+pub use timeout::request_timeout_middleware;
+pub use trace::{mark_quiet_responses, RequestTrace};
+pub use trailing_slash::trailing_slash_middleware;
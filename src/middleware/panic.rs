@@ -0,0 +1,54 @@
+use axum::{http::StatusCode, response::Response};
+use std::any::Any;
+
+use crate::handlers::{error_response, ErrorCode};
+
+/// Response for a request whose handler panicked, installed through
+/// `CatchPanicLayer`. The panic message is logged, inside the request's span
+/// so it carries the request id, but never sent to the client.
+pub fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("non-string panic payload");
+    tracing::error!("Request handler panicked: {}", message);
+
+    error_response(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::InternalError,
+        "Internal server error",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{json, request, send, Captured};
+    use axum::{http::Method, routing::get, Router};
+    use tower_http::catch_panic::CatchPanicLayer;
+
+    async fn boom() -> &'static str {
+        panic!("secret connection string")
+    }
+
+    #[tokio::test]
+    async fn panicking_handler_gets_a_structured_500_without_the_message() {
+        let (captured, _guard) = Captured::install();
+        let app = Router::new()
+            .route("/boom", get(boom))
+            .layer(CatchPanicLayer::custom(panic_response));
+
+        let response = send(&app, request(Method::GET, "/boom", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = json(response).await;
+        assert_eq!(body["code"], "internal_error");
+        assert!(!body.to_string().contains("secret"));
+        assert!(captured
+            .events()
+            .iter()
+            .any(|event| event.level == tracing::Level::ERROR
+                && event.fields["message"].contains("secret connection string")));
+    }
+}
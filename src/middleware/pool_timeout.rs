@@ -0,0 +1,103 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::handlers::{error_response, ErrorCode, PoolTimedOut};
+use crate::metrics::Metrics;
+
+/// What clients are told when the database pool is exhausted. Handlers only
+/// mark the response with `PoolTimedOut`; this is the one place that shapes
+/// it, so every route answers the same way.
+#[derive(Clone)]
+pub struct PoolTimeoutPolicy {
+    retry_after_secs: u64,
+    message: Arc<str>,
+    total: Arc<AtomicU64>,
+    metrics: Option<Metrics>,
+}
+
+impl PoolTimeoutPolicy {
+    pub fn new(retry_after_secs: u64, message: &str) -> Self {
+        Self {
+            retry_after_secs,
+            message: message.into(),
+            total: Arc::default(),
+            metrics: None,
+        }
+    }
+
+    /// Also count timeouts as `pool_timeouts_total` at `/metrics`
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+pub async fn pool_timeout_middleware(
+    State(policy): State<PoolTimeoutPolicy>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    if response.extensions().get::<PoolTimedOut>().is_none() {
+        return response;
+    }
+
+    // Running total as a log field, so capacity problems show up in log metrics
+    let total = policy.total.fetch_add(1, Ordering::Relaxed) + 1;
+    tracing::warn!(
+        pool_timeouts_total = total,
+        "Database pool exhausted, answered 503"
+    );
+    if let Some(metrics) = &policy.metrics {
+        metrics.count_pool_timeout();
+    }
+
+    let mut response = error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::ServiceUnavailable,
+        &policy.message,
+    );
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(policy.retry_after_secs),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, body_bytes, json, request, send, PASSWORD};
+    use axum::http::{header, Method, StatusCode};
+
+    #[tokio::test]
+    async fn exhausted_pool_answers_503_with_the_configured_retry_after() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[
+            ("POOL_TIMEOUT_RETRY_AFTER_SECS", "7"),
+            ("METRICS_ENABLED", "true"),
+        ]);
+        let token = test_support::sign_up(
+            &test_support::app(&database, config.clone()),
+            "user@example.com",
+            PASSWORD,
+        )
+        .await;
+        let (exhausted, _held) = database.exhausted().await;
+        let app = test_support::app(&exhausted, config);
+
+        let response = test_support::me(&app, &token).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "7");
+        assert_eq!(json(response).await["code"], "service_unavailable");
+
+        let response = send(&app, request(Method::GET, "/metrics", None, None)).await;
+        let body = String::from_utf8(body_bytes(response).await).unwrap();
+        assert!(body.contains("\npool_timeouts_total 1\n"), "{}", body);
+    }
+}
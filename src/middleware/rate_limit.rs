@@ -1,62 +1,287 @@
 use axum::{
-    extract::Request,
-    http::StatusCode,
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
-    Json,
 };
+use chrono::{DateTime, Utc};
 use governor::{
-    clock::DefaultClock,
+    clock::{Clock, DefaultClock},
+    middleware::StateInformationMiddleware,
     state::{InMemoryState, NotKeyed},
-    Quota, RateLimiter,
+    DefaultKeyedRateLimiter, Quota, RateLimiter,
 };
-use serde_json::json;
-use std::sync::Arc;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use uuid::Uuid;
 
-pub type SharedRateLimiter = Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>;
+use super::auth::ClaimsExt;
+use crate::handlers::{error_response, ErrorCode};
+use crate::models::GlobalRateLimitStatus;
+
+// Past this many tracked users, forget the ones whose budget has refilled
+const USER_RATE_LIMIT_PRUNE_AT: usize = 10_000;
+
+pub const X_RATELIMIT_LIMIT: &str = "x-ratelimit-limit";
+pub const X_RATELIMIT_REMAINING: &str = "x-ratelimit-remaining";
+
+pub type SharedRateLimiter =
+    Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock, StateInformationMiddleware>>;
 
 #[derive(Clone)]
 pub struct RateLimitLayer {
     limiter: SharedRateLimiter,
+    quota: Quota,
+    // governor can't be queried without spending budget, so the status
+    // endpoint works from what the middleware last saw
+    stats: Arc<Mutex<RateLimitStats>>,
+}
+
+#[derive(Default)]
+struct RateLimitStats {
+    last_check: Option<(Instant, u32)>,
+    throttled_total: u64,
+    last_throttled_at: Option<DateTime<Utc>>,
 }
 
 impl RateLimitLayer {
-    pub fn new(_requests_per_second: u32, burst_size: u32) -> Self {
-        let quota = Quota::with_period(Duration::from_secs(1))
-            .unwrap()
-            .allow_burst(std::num::NonZeroU32::new(burst_size).unwrap());
+    pub fn new(requests_per_second: u32, burst_size: u32) -> Self {
+        let quota = Quota::per_second(
+            NonZeroU32::new(requests_per_second).expect("RATE_LIMIT_RPS is checked to be positive"),
+        )
+        .allow_burst(
+            NonZeroU32::new(burst_size).expect("RATE_LIMIT_BURST is checked to be positive"),
+        );
 
-        let limiter = Arc::new(RateLimiter::direct(quota));
+        let limiter =
+            Arc::new(RateLimiter::direct(quota).with_middleware::<StateInformationMiddleware>());
+
+        Self {
+            limiter,
+            quota,
+            stats: Arc::default(),
+        }
+    }
+
+    pub fn status(&self) -> GlobalRateLimitStatus {
+        let stats = self.stats.lock().unwrap();
+        let burst_size = self.quota.burst_size().get();
+        let interval = self.quota.replenish_interval();
+
+        // Budget refills one cell per interval from where the last check left it
+        let remaining = match stats.last_check {
+            Some((at, remaining)) => {
+                let refilled = at.elapsed().as_nanos() / interval.as_nanos().max(1);
+                (remaining as u128 + refilled).min(burst_size as u128) as u32
+            }
+            None => burst_size,
+        };
+
+        GlobalRateLimitStatus {
+            burst_size,
+            replenish_interval_ms: interval.as_millis() as u64,
+            remaining,
+            throttled_total: stats.throttled_total,
+            last_throttled_at: stats.last_throttled_at,
+        }
+    }
 
-        Self { limiter }
+    fn record_allowed(&self, remaining: u32) {
+        self.stats.lock().unwrap().last_check = Some((Instant::now(), remaining));
     }
 
-    pub fn limiter(&self) -> SharedRateLimiter {
-        self.limiter.clone()
+    fn record_throttled(&self) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.last_check = Some((Instant::now(), 0));
+        stats.throttled_total += 1;
+        stats.last_throttled_at = Some(Utc::now());
     }
 }
 
 pub async fn rate_limit_middleware(
-    limiter: SharedRateLimiter,
+    rate_limit: RateLimitLayer,
+    request: Request,
+    next: Next,
+) -> Result<Response, RateLimitError> {
+    match rate_limit.limiter.check() {
+        Ok(snapshot) => {
+            rate_limit.record_allowed(snapshot.remaining_burst_capacity());
+            let mut response = next.run(request).await;
+            let headers = response.headers_mut();
+            headers.insert(
+                X_RATELIMIT_LIMIT,
+                HeaderValue::from(snapshot.quota().burst_size().get()),
+            );
+            headers.insert(
+                X_RATELIMIT_REMAINING,
+                HeaderValue::from(snapshot.remaining_burst_capacity()),
+            );
+            Ok(response)
+        }
+        Err(not_until) => {
+            rate_limit.record_throttled();
+            Err(RateLimitError {
+                limit: not_until.quota().burst_size().get(),
+                retry_after: not_until
+                    .wait_time_from(DefaultClock::default().now())
+                    .as_secs()
+                    + 1,
+            })
+        }
+    }
+}
+
+/// Per-user budget for endpoints that would otherwise make good
+/// brute-force oracles, on top of the global limit
+#[derive(Clone)]
+pub struct UserRateLimit {
+    limiter: Arc<DefaultKeyedRateLimiter<String>>,
+}
+
+impl UserRateLimit {
+    pub fn per_minute(requests: u32) -> Self {
+        let quota = Quota::per_minute(NonZeroU32::new(requests).unwrap());
+        Self {
+            limiter: Arc::new(RateLimiter::keyed(quota)),
+        }
+    }
+
+    pub fn per_hour(requests: u32) -> Self {
+        let quota = Quota::per_hour(NonZeroU32::new(requests).unwrap());
+        Self {
+            limiter: Arc::new(RateLimiter::keyed(quota)),
+        }
+    }
+}
+
+/// Must run after `auth_middleware`; requests without claims pass through
+pub async fn user_rate_limit_middleware(
+    State(limit): State<UserRateLimit>,
     request: Request,
     next: Next,
 ) -> Result<Response, RateLimitError> {
-    match limiter.check() {
-        Ok(_) => Ok(next.run(request).await),
-        Err(_) => Err(RateLimitError),
+    let Some(user_id) = request.claims().map(|claims| claims.sub.clone()) else {
+        return Ok(next.run(request).await);
+    };
+
+    if limit.limiter.len() > USER_RATE_LIMIT_PRUNE_AT {
+        limit.limiter.retain_recent();
+    }
+
+    match limit.limiter.check_key(&user_id) {
+        Ok(()) => Ok(next.run(request).await),
+        Err(not_until) => {
+            tracing::warn!(
+                "User {} exceeded the rate limit for {}",
+                user_id,
+                request.uri().path()
+            );
+            Err(RateLimitError {
+                limit: not_until.quota().burst_size().get(),
+                retry_after: not_until
+                    .wait_time_from(DefaultClock::default().now())
+                    .as_secs()
+                    + 1,
+            })
+        }
+    }
+}
+
+/// Per-API-key budget, checked by `auth_middleware` for requests it lets
+/// in on a key. Each key gets the `rate_limit_rps` on its record, or the
+/// default tier, with a second's worth of burst.
+#[derive(Clone)]
+pub struct ApiKeyRateLimit {
+    default_rps: u32,
+    // A governor keyed limiter has a single quota, so one per tier in use
+    tiers: Arc<Mutex<HashMap<u32, Arc<DefaultKeyedRateLimiter<Uuid>>>>>,
+}
+
+impl ApiKeyRateLimit {
+    pub fn new(default_rps: u32) -> Self {
+        Self {
+            default_rps,
+            tiers: Arc::default(),
+        }
+    }
+
+    pub fn check(&self, key_id: Uuid, rate_limit_rps: Option<u32>) -> Result<(), RateLimitError> {
+        let rps = rate_limit_rps.unwrap_or(self.default_rps);
+        let limiter = self
+            .tiers
+            .lock()
+            .unwrap()
+            .entry(rps)
+            .or_insert_with(|| {
+                let rps = NonZeroU32::new(rps).expect("API key quotas are checked to be positive");
+                Arc::new(RateLimiter::keyed(Quota::per_second(rps)))
+            })
+            .clone();
+
+        if limiter.len() > USER_RATE_LIMIT_PRUNE_AT {
+            limiter.retain_recent();
+        }
+
+        limiter.check_key(&key_id).map_err(|not_until| {
+            tracing::warn!("API key {} exceeded its rate limit of {}/s", key_id, rps);
+            RateLimitError {
+                limit: not_until.quota().burst_size().get(),
+                retry_after: not_until
+                    .wait_time_from(DefaultClock::default().now())
+                    .as_secs()
+                    + 1,
+            }
+        })
     }
 }
 
 #[derive(Debug)]
-pub struct RateLimitError;
+pub struct RateLimitError {
+    limit: u32,
+    retry_after: u64,
+}
 
 impl IntoResponse for RateLimitError {
     fn into_response(self) -> Response {
-        (
+        let mut response = error_response(
             StatusCode::TOO_MANY_REQUESTS,
-            Json(json!({ "error": "Rate limit exceeded" })),
-        )
-            .into_response()
+            ErrorCode::RateLimited,
+            "Rate limit exceeded",
+        );
+        let headers = response.headers_mut();
+        headers.insert(header::RETRY_AFTER, HeaderValue::from(self.retry_after));
+        headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(0));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::json;
+
+    #[test]
+    fn quota_refills_at_the_configured_rate() {
+        let status = RateLimitLayer::new(4, 8).status();
+
+        assert_eq!(status.replenish_interval_ms, 250);
+        assert_eq!(status.burst_size, 8);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_error_carries_stable_code() {
+        let response = RateLimitError {
+            limit: 20,
+            retry_after: 3,
+        }
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+        assert_eq!(response.headers()[X_RATELIMIT_LIMIT], "20");
+        assert_eq!(json(response).await["code"], "rate_limited");
     }
 }
@@ -0,0 +1,80 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::time::{Duration, Instant};
+
+/// Logs every request's duration: at WARN when it took longer than the
+/// threshold, at DEBUG otherwise
+pub async fn slow_request_middleware(
+    State(threshold): State<Duration>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    let elapsed = start.elapsed();
+    let status = response.status().as_u16();
+    if elapsed > threshold {
+        tracing::warn!(
+            %method,
+            %path,
+            status,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "Slow request"
+        );
+    } else {
+        tracing::debug!(
+            %method,
+            %path,
+            status,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "Request completed"
+        );
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{request, send, Captured};
+    use axum::{http::Method, middleware, routing::get, Router};
+    use tracing::Level;
+
+    #[tokio::test]
+    async fn slow_request_logs_a_warning() {
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(|| async { tokio::time::sleep(Duration::from_millis(50)).await }),
+            )
+            .route("/fast", get(|| async {}))
+            .layer(middleware::from_fn_with_state(
+                Duration::from_millis(20),
+                slow_request_middleware,
+            ));
+
+        let (captured, _guard) = Captured::install();
+        send(&app, request(Method::GET, "/fast", None, None)).await;
+        send(&app, request(Method::GET, "/slow", None, None)).await;
+
+        let events = captured.events();
+        let logged = |path: &str| {
+            let event = events.iter().find(|event| event.fields["path"] == path);
+            event.expect("request logged").clone()
+        };
+        let slow = logged("/slow");
+        assert_eq!(slow.level, Level::WARN);
+        assert_eq!(slow.fields["message"], "Slow request");
+        assert_eq!(slow.fields["method"], "GET");
+        assert_eq!(slow.fields["status"], "200");
+        assert_eq!(logged("/fast").level, Level::DEBUG);
+    }
+}
@@ -0,0 +1,26 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use std::time::Duration;
+
+use crate::handlers::{error_response, ErrorCode};
+
+/// Abandons requests still running after the timeout. Dropping the handler
+/// rolls back any open transaction.
+pub async fn request_timeout_middleware(
+    State(timeout): State<Duration>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::RequestTimeout,
+            "Request timed out",
+        ),
+    }
+}
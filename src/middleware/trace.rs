@@ -0,0 +1,198 @@
+use axum::{
+    extract::{Request, State},
+    http,
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::trace::{DefaultOnRequest, DefaultOnResponse, MakeSpan, OnRequest, OnResponse};
+use tracing::{Level, Span};
+
+/// Request logging for `TraceLayer`. Requests to the quiet paths (health
+/// probes by default) are logged at TRACE instead of DEBUG so they don't
+/// drown out real traffic. Failures are still logged at ERROR.
+#[derive(Clone)]
+pub struct RequestTrace {
+    quiet_paths: Arc<[String]>,
+}
+
+/// Marks responses to quiet paths, since `OnResponse` never sees the request
+#[derive(Clone, Copy)]
+struct QuietResponse;
+
+impl RequestTrace {
+    pub fn new(quiet_paths: Vec<String>) -> Self {
+        Self {
+            quiet_paths: quiet_paths.into(),
+        }
+    }
+
+    fn is_quiet(&self, path: &str) -> bool {
+        self.quiet_paths.iter().any(|p| p == path)
+    }
+
+    fn level(quiet: bool) -> Level {
+        if quiet {
+            Level::TRACE
+        } else {
+            Level::DEBUG
+        }
+    }
+}
+
+/// Root span for each request, tagged with its `x-request-id` so every log
+/// line can be tied back to the response the client saw. `user_id` starts
+/// empty and is filled in by
+/// `auth_middleware` once a token has been verified; `actor_id` too, when
+/// an admin is impersonating that user.
+impl<B> MakeSpan<B> for RequestTrace {
+    fn make_span(&mut self, request: &http::Request<B>) -> Span {
+        let request_id = request
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        if self.is_quiet(request.uri().path()) {
+            tracing::trace_span!(
+                "request",
+                method = %request.method(),
+                uri = %request.uri(),
+                version = ?request.version(),
+                request_id = %request_id,
+                user_id = tracing::field::Empty,
+                actor_id = tracing::field::Empty,
+            )
+        } else {
+            tracing::debug_span!(
+                "request",
+                method = %request.method(),
+                uri = %request.uri(),
+                version = ?request.version(),
+                request_id = %request_id,
+                user_id = tracing::field::Empty,
+                actor_id = tracing::field::Empty,
+            )
+        }
+    }
+}
+
+impl<B> OnRequest<B> for RequestTrace {
+    fn on_request(&mut self, request: &http::Request<B>, span: &Span) {
+        let level = Self::level(self.is_quiet(request.uri().path()));
+        DefaultOnRequest::new()
+            .level(level)
+            .on_request(request, span);
+    }
+}
+
+impl<B> OnResponse<B> for RequestTrace {
+    fn on_response(self, response: &http::Response<B>, latency: Duration, span: &Span) {
+        let quiet = response.extensions().get::<QuietResponse>().is_some();
+        DefaultOnResponse::new()
+            .level(Self::level(quiet))
+            .on_response(response, latency, span);
+    }
+}
+
+/// Tags responses to quiet paths for `RequestTrace`. Must be layered inside
+/// the `TraceLayer`.
+pub async fn mark_quiet_responses(
+    State(trace): State<RequestTrace>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let quiet = trace.is_quiet(request.uri().path());
+    let mut response = next.run(request).await;
+    if quiet {
+        response.extensions_mut().insert(QuietResponse);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, json, me, request, send, Captured, PASSWORD};
+    use axum::http::{Method, StatusCode};
+    use tower_http::trace::TraceLayer;
+
+    #[tokio::test]
+    async fn request_span_carries_authenticated_user_id() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]))
+            .layer(TraceLayer::new_for_http().make_span_with(RequestTrace::new(Vec::new())));
+        let token = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+        let id = json(me(&app, &token).await).await["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let (captured, _guard) = Captured::install();
+        assert_eq!(me(&app, &token).await.status(), StatusCode::OK);
+        let response = send(&app, request(Method::GET, "/healthz/live", None, None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let spans = captured.spans("request");
+        let user_id = |uri: &str| {
+            let span = spans
+                .iter()
+                .find(|span| span.fields["uri"] == uri)
+                .expect("request span");
+            span.fields.get("user_id").cloned()
+        };
+        assert_eq!(user_id("/users/me"), Some(id));
+        assert_eq!(user_id("/healthz/live"), None);
+    }
+
+    #[tokio::test]
+    async fn quiet_paths_log_below_other_requests() {
+        let database = test_support::database().await;
+        let trace = RequestTrace::new(vec!["/healthz/live".to_string()]);
+        let app = test_support::app(&database, test_support::config(&[]))
+            .layer(axum::middleware::from_fn_with_state(
+                trace.clone(),
+                mark_quiet_responses,
+            ))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(trace.clone())
+                    .on_request(trace.clone())
+                    .on_response(trace),
+            );
+
+        let (captured, _guard) = Captured::install();
+        send(&app, request(Method::GET, "/healthz/live", None, None)).await;
+        let health_events = captured.events().len();
+        let credentials =
+            serde_json::json!({ "email": "nobody@example.com", "password": PASSWORD });
+        send(
+            &app,
+            request(Method::POST, "/auth/login", None, Some(credentials)),
+        )
+        .await;
+
+        let level = |uri: &str| {
+            let spans = captured.spans("request");
+            let span = spans.iter().find(|span| span.fields["uri"] == uri);
+            span.expect("request span").level
+        };
+        assert_eq!(level("/healthz/live"), Level::TRACE);
+        assert_eq!(level("/auth/login"), Level::DEBUG);
+
+        // on_request and on_response log at the span's level
+        let events = captured.events();
+        let (health, auth) = events.split_at(health_events);
+        let logged = |events: &[test_support::Record], message: &str| {
+            let event = events
+                .iter()
+                .find(|event| event.fields["message"] == message);
+            event.expect("trace event").level
+        };
+        assert_eq!(logged(health, "started processing request"), Level::TRACE);
+        assert_eq!(logged(health, "finished processing request"), Level::TRACE);
+        assert_eq!(logged(auth, "started processing request"), Level::DEBUG);
+        assert_eq!(logged(auth, "finished processing request"), Level::DEBUG);
+    }
+}
@@ -0,0 +1,138 @@
+use axum::{
+    extract::{Request, State},
+    http::Uri,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+
+use crate::config::TrailingSlash;
+
+/// Sends paths ending in `/` to the route without it, either with a `308`
+/// or by rewriting the request. Must wrap the router rather than be layered
+/// onto it, since routes are matched before a router's own layers run.
+pub async fn trailing_slash_middleware(
+    State(mode): State<TrailingSlash>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let trimmed = path.trim_end_matches('/');
+    // `/` itself, and paths already in canonical form, are left alone
+    if trimmed.is_empty() || trimmed.len() == path.len() {
+        return next.run(request).await;
+    }
+
+    // `//evil.com/` would otherwise become `Location: //evil.com`, which
+    // browsers read as another host
+    let trimmed = format!("/{}", trimmed.trim_start_matches('/'));
+    let canonical = match request.uri().query() {
+        Some(query) => format!("{}?{}", trimmed, query),
+        None => trimmed,
+    };
+
+    match mode {
+        TrailingSlash::Strict => next.run(request).await,
+        TrailingSlash::Redirect => Redirect::permanent(&canonical).into_response(),
+        TrailingSlash::Rewrite => {
+            let mut parts = request.uri().clone().into_parts();
+            parts.path_and_query = Some(
+                canonical
+                    .parse()
+                    .expect("trimming a valid path keeps it valid"),
+            );
+            *request.uri_mut() = Uri::from_parts(parts).expect("only the path changed");
+            next.run(request).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, json, request, send, PASSWORD};
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn each_mode_handles_a_trailing_slash_its_own_way() {
+        let database = test_support::database().await;
+        let app =
+            |mode| test_support::app(&database, test_support::config(&[("TRAILING_SLASH", mode)]));
+        let get = || request(Method::GET, "/healthz/live/?probe=1", None, None);
+
+        let response = send(&app("strict"), get()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = send(&app("redirect"), get()).await;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "/healthz/live?probe=1"
+        );
+
+        let response = send(&app("rewrite"), get()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Canonical paths are untouched in every mode
+        for mode in ["strict", "redirect", "rewrite"] {
+            let response = send(
+                &app(mode),
+                request(Method::GET, "/healthz/live", None, None),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", mode);
+        }
+    }
+
+    #[tokio::test]
+    async fn login_with_a_trailing_slash_reaches_the_login_handler() {
+        let database = test_support::database().await;
+        let app =
+            |mode| test_support::app(&database, test_support::config(&[("TRAILING_SLASH", mode)]));
+        test_support::sign_up(&app("strict"), "user@example.com", PASSWORD).await;
+        let login = |uri: &str| {
+            let credentials = json!({ "email": "user@example.com", "password": PASSWORD });
+            request(Method::POST, uri, None, Some(credentials))
+        };
+
+        let response = send(&app("strict"), login("/auth/login/")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // 308 keeps the method and body, so clients re-POST to the target
+        let redirect = app("redirect");
+        let response = send(&redirect, login("/auth/login/")).await;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert_eq!(location, "/auth/login");
+        let response = send(&redirect, login(location)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(json(response).await["token"].is_string());
+
+        let response = send(&app("rewrite"), login("/auth/login/")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(json(response).await["token"].is_string());
+    }
+
+    #[tokio::test]
+    async fn redirect_never_points_off_site() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("TRAILING_SLASH", "redirect")]);
+        let app = test_support::app(&database, config);
+
+        for target in ["//evil.com/", "///evil.com/", "//evil.com/path/?q=1"] {
+            let response = send(&app, request(Method::GET, target, None, None)).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::PERMANENT_REDIRECT,
+                "{}",
+                target
+            );
+            let location = response.headers()[header::LOCATION].to_str().unwrap();
+            assert!(
+                location.starts_with('/') && !location.starts_with("//"),
+                "{} -> {}",
+                target,
+                location
+            );
+        }
+    }
+}
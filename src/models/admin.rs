@@ -0,0 +1,187 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+/// One request to an admin-only endpoint, whether it succeeded or not
+#[derive(Debug, Clone, FromRow)]
+pub struct AdminAuditEntry {
+    pub id: Uuid,
+    /// The admin who made the request; the impersonator for an
+    /// impersonation token
+    pub actor_id: Uuid,
+    /// Method and matched route, e.g. `POST /admin/users/:id/impersonate`
+    pub action: String,
+    /// The route's `:id`, if it has one
+    pub target: Option<String>,
+    pub status: i32,
+    pub request_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AdminAuditEntry {
+    pub fn outcome(&self) -> AuditOutcome {
+        if (200..400).contains(&self.status) {
+            AuditOutcome::Success
+        } else {
+            AuditOutcome::Failure
+        }
+    }
+}
+
+/// Whether an admin request got a 2xx/3xx response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "success" => Some(Self::Success),
+            "failure" => Some(Self::Failure),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+/// Conditions an audit entry must meet to be listed; `None` matches anything
+#[derive(Debug, Clone, Default)]
+pub struct AdminAuditFilter {
+    pub actor_id: Option<Uuid>,
+    /// Entries where this user is either the admin or the target
+    pub involving: Option<Uuid>,
+    pub action: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    /// Inclusive lower bound on `created_at`
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on `created_at`
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct ListAuditQuery {
+    /// Only requests made by this admin
+    pub actor_id: Option<Uuid>,
+    /// Only this method and route, e.g. `POST /admin/users/:id/impersonate`
+    pub action: Option<String>,
+    /// `success` or `failure`
+    pub outcome: Option<String>,
+    /// Only requests at or after this RFC 3339 time
+    pub from: Option<DateTime<Utc>>,
+    /// Only requests before this RFC 3339 time
+    pub to: Option<DateTime<Utc>>,
+    /// Page number, starting at 1
+    #[param(minimum = 1)]
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub page: Option<u32>,
+    /// Number of events per page, capped at `MAX_PAGE_SIZE`
+    #[param(minimum = 1)]
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub per_page: Option<u32>,
+    /// Timestamp order: `asc` or `desc` (newest first, the default)
+    pub order: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditEventResponse {
+    pub id: Uuid,
+    pub actor_id: Uuid,
+    pub action: String,
+    pub target: Option<String>,
+    pub outcome: AuditOutcome,
+    pub status: i32,
+    pub request_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<AdminAuditEntry> for AuditEventResponse {
+    fn from(entry: AdminAuditEntry) -> Self {
+        Self {
+            outcome: entry.outcome(),
+            id: entry.id,
+            actor_id: entry.actor_id,
+            action: entry.action,
+            target: entry.target,
+            status: entry.status,
+            request_id: entry.request_id,
+            created_at: entry.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLogPage {
+    pub events: Vec<AuditEventResponse>,
+    pub page: u32,
+    pub per_page: u32,
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+}
+
+/// A short-lived access token for the target user. There is no refresh
+/// token; once it expires the admin must impersonate again.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImpersonationResponse {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RateLimitStatus {
+    /// The process-wide limiter; `None` when `RATE_LIMIT_RPS` is unset
+    pub global: Option<GlobalRateLimitStatus>,
+    pub concurrency: ConcurrencyStatus,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GlobalRateLimitStatus {
+    pub burst_size: u32,
+    /// One request's worth of budget comes back every interval
+    pub replenish_interval_ms: u64,
+    /// Estimated from the last request and the time since
+    pub remaining: u32,
+    pub throttled_total: u64,
+    pub last_throttled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConcurrencyStatus {
+    /// `None` when `MAX_CONCURRENT_PER_USER` is unset
+    pub max_in_flight: Option<usize>,
+    /// Users with requests in flight, busiest first
+    pub users: Vec<UserInFlight>,
+    /// More users were active than are listed
+    pub truncated: bool,
+    /// Latest rejections, newest first
+    pub recently_throttled: Vec<ThrottledUser>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserInFlight {
+    pub user_id: String,
+    pub in_flight: usize,
+    pub remaining: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ThrottledUser {
+    pub user_id: String,
+    pub at: DateTime<Utc>,
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub previous_expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    /// This key's own quota; `None` for the default tier
+    pub rate_limit_rps: Option<i32>,
+}
+
+impl ApiKey {
+    /// The key's own quota, if it has a valid one
+    pub fn rate_limit_rps(&self) -> Option<u32> {
+        self.rate_limit_rps
+            .and_then(|rps| u32::try_from(rps).ok())
+            .filter(|rps| *rps > 0)
+    }
+}
+
+/// An API key as listed; never the key or its hash
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    /// Start of the key, to tell keys apart
+    #[schema(example = "tsk_3q2xN7")]
+    pub prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    /// Until when the key replaced by the last rotation still works
+    pub previous_expires_at: Option<DateTime<Utc>>,
+    /// Requests per second this key may make; `null` for the default
+    /// `API_KEY_RATE_LIMIT_RPS`
+    pub rate_limit_rps: Option<u32>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            rate_limit_rps: key.rate_limit_rps(),
+            id: key.id,
+            name: key.name,
+            prefix: key.prefix,
+            created_at: key.created_at,
+            rotated_at: key.rotated_at,
+            // Past the overlap it's just noise
+            previous_expires_at: key.previous_expires_at.filter(|at| *at > Utc::now()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct CreateApiKeyRequest {
+    #[schema(min_length = 1, max_length = 100, example = "deploy script")]
+    #[validate(length(min = 1, max = 100, message = "must be 1 to 100 characters"))]
+    pub name: String,
+}
+
+/// Body of `PUT /admin/api-keys/{id}/rate-limit`
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct SetApiKeyRateLimitRequest {
+    /// Requests per second; `null` returns the key to the default tier
+    #[schema(minimum = 1, maximum = 100000, example = 100)]
+    #[validate(range(min = 1, max = 100_000, message = "must be 1 to 100000"))]
+    pub rate_limit_rps: Option<u32>,
+}
+
+/// A newly issued or rotated key. `key` is shown only this once.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NewApiKeyResponse {
+    #[schema(example = "tsk_3q2xN7pLr0v9bWd4Jk1sYcHfTgUaEe8MzQiOo5x6A2")]
+    pub key: String,
+    pub api_key: ApiKeyResponse,
+}
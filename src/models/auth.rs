@@ -1,42 +1,301 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
+use validator::Validate;
 
-use super::user::UserResponse;
+use super::email::Email;
+use super::user::{Role, UserResponse};
 
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Deserialize, ToSchema, Validate)]
 pub struct RegisterRequest {
-    #[schema(example = "user@example.com")]
-    pub email: String,
-    #[schema(example = "password123")]
+    pub email: Email,
+    #[schema(min_length = 8, example = "password123")]
+    #[validate(length(min = 8, message = "must be at least 8 characters"))]
     pub password: String,
+    /// Required when `CAPTCHA_ENABLED=true`
+    #[serde(default, alias = "captchaToken")]
+    pub captcha_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
-    #[schema(example = "user@example.com")]
-    pub email: String,
+    pub email: Email,
     #[schema(example = "password123")]
     pub password: String,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    #[serde(alias = "refreshToken")]
+    pub refresh_token: String,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct LoginResponse {
     pub token: String,
-    pub user: UserResponse,
+    /// Omitted when the refresh token is delivered as a cookie
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// Omitted when `LOGIN_RESPONSE_INCLUDE_USER=false`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<UserResponse>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct ChangePasswordRequest {
+    #[serde(alias = "currentPassword")]
+    pub current_password: String,
+    #[serde(alias = "newPassword")]
+    #[schema(min_length = 8)]
+    #[validate(length(min = 8, message = "must be at least 8 characters"))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VerifyPasswordRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ForgotPasswordRequest {
+    pub email: Email,
+}
+
+/// Token from a password-reset link, and the password to set
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    #[serde(alias = "newPassword")]
+    #[schema(min_length = 8)]
+    #[validate(length(min = 8, message = "must be at least 8 characters"))]
+    pub new_password: String,
+}
+
+pub const PASSWORD_RESET_PURPOSE: &str = "password_reset";
+
+/// Claims of a signed password-reset token. Nothing is stored server-side:
+/// `pwh` fingerprints the password hash at issue time, so the token stops
+/// working once the password changes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetClaims {
+    pub sub: String,
+    pub purpose: String,
+    pub pwh: String,
+    pub exp: i64,
+    pub iat: i64,
+}
+
+/// Token from an email-verification link
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+/// Whose verification email to re-send: `email`, or when it's omitted the
+/// user named by the bearer token
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResendVerificationRequest {
+    #[serde(default)]
+    pub email: Option<Email>,
+}
+
+pub const EMAIL_VERIFICATION_PURPOSE: &str = "email_verification";
+
+/// Claims of a signed email-verification token. `email` is the address the
+/// link was sent to: after a change of address the token no longer matches.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerificationClaims {
+    pub sub: String,
+    pub purpose: String,
+    pub email: String,
+    pub exp: i64,
+    pub iat: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangeEmailRequest {
+    pub email: Email,
+}
+
+/// Token from a confirm or cancel link in an email-change message
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EmailChangeTokenRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct IntrospectRequest {
+    pub token: String,
+}
+
+/// Token introspection result, shaped after RFC 7662. Inactive tokens carry
+/// no other fields.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IntrospectResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    /// Derived from the user's role
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<Role>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Vec<String>>,
+    /// The admin behind an impersonation token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+impl IntrospectResponse {
+    pub fn inactive() -> Self {
+        Self {
+            active: false,
+            sub: None,
+            email: None,
+            exp: None,
+            scopes: None,
+            aud: None,
+            act: None,
+        }
+    }
+}
+
+impl From<Claims> for IntrospectResponse {
+    fn from(claims: Claims) -> Self {
+        Self {
+            active: true,
+            sub: Some(claims.sub),
+            email: Some(claims.email),
+            exp: Some(claims.exp),
+            scopes: Some(vec![claims.role]),
+            aud: (!claims.aud.is_empty()).then_some(claims.aud),
+            act: claims.act,
+        }
+    }
+}
+
+/// Claims of the caller's own access token, as returned by `GET /auth/me`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenClaimsResponse {
+    pub sub: String,
+    pub email: String,
+    pub role: Role,
+    /// Derived from the role, as in introspection
+    pub scopes: Vec<Role>,
+    pub iat: i64,
+    pub exp: i64,
+    /// Seconds until `exp`
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aud: Vec<String>,
+    /// The admin behind an impersonation token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+impl TokenClaimsResponse {
+    pub fn new(claims: Claims, now: i64) -> Self {
+        Self {
+            sub: claims.sub,
+            email: claims.email,
+            role: claims.role,
+            scopes: vec![claims.role],
+            iat: claims.iat,
+            exp: claims.exp,
+            expires_in: (claims.exp - now).max(0),
+            aud: claims.aud,
+            act: claims.act,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user id
+    // Both absent from minimal tokens, then filled in from the user record
+    // once the token is verified
+    #[serde(default)]
     pub email: String,
+    #[serde(default = "minimal_token_role")]
+    pub role: Role,
+    pub token_version: i32,
     pub exp: i64, // expiration time
     pub iat: i64, // issued at
+    // Not valid before; tokens issued before this claim existed lack it
+    #[serde(default)]
+    pub nbf: i64,
+    /// Services the token is meant for; empty when `JWT_AUDIENCE` is unset
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aud: Vec<String>,
+    /// Set on impersonation tokens: the admin acting as `sub` (RFC 8693)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    /// Client fingerprint the token is bound to (RFC 7800 confirmation)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
+}
+
+// Never acted on: verification replaces it with the stored role
+fn minimal_token_role() -> Role {
+    Role::User
+}
+
+/// What a token carries under `JWT_MINIMAL_CLAIMS`: `Claims` without the
+/// email and role
+#[derive(Serialize)]
+pub struct MinimalClaims<'a> {
+    sub: &'a str,
+    token_version: i32,
+    exp: i64,
+    iat: i64,
+    nbf: i64,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    aud: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    act: &'a Option<Actor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cnf: &'a Option<Confirmation>,
+}
+
+impl<'a> From<&'a Claims> for MinimalClaims<'a> {
+    fn from(claims: &'a Claims) -> Self {
+        Self {
+            sub: &claims.sub,
+            token_version: claims.token_version,
+            exp: claims.exp,
+            iat: claims.iat,
+            nbf: claims.nbf,
+            aud: &claims.aud,
+            act: &claims.act,
+            cnf: &claims.cnf,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Actor {
+    pub sub: String,
+    pub email: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Confirmation {
+    pub fingerprint: String,
 }
 
 impl Claims {
-    #[allow(dead_code)]
     pub fn user_id(&self) -> Result<Uuid, uuid::Error> {
         Uuid::parse_str(&self.sub)
     }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    pub fn is_impersonation(&self) -> bool {
+        self.act.is_some()
+    }
 }
@@ -0,0 +1,176 @@
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt;
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaFormat, SchemaType};
+use utoipa::ToSchema;
+
+/// Width of the users.email column; `MAX_EMAIL_LENGTH` can only lower it
+pub const MAX_EMAIL_LENGTH: usize = 255;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum EmailError {
+    #[error("email must not be empty")]
+    Empty,
+    #[error("email must be at most {MAX_EMAIL_LENGTH} characters")]
+    TooLong,
+    #[error("email is not a valid address")]
+    InvalidFormat,
+    #[error("email domain is not a valid domain name")]
+    InvalidDomain,
+}
+
+/// A trimmed, lowercased, NFC-normalized email address whose domain is in
+/// its ASCII (punycode) form, so `user@exämple.com` and
+/// `user@xn--exmple-cua.com` are the same address. Validated on construction,
+/// so malformed input is rejected while the request body is deserialized.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Email(String);
+
+impl Email {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Everything after the `@`, lowercased and in ASCII form
+    pub fn domain(&self) -> &str {
+        self.0.split_once('@').map_or("", |(_, domain)| domain)
+    }
+}
+
+// Written by hand so the documented limits are the ones `try_from` enforces
+impl<'s> ToSchema<'s> for Email {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .format(Some(SchemaFormat::Custom("email".to_string())))
+            .max_length(Some(MAX_EMAIL_LENGTH))
+            .description(Some(
+                "Trimmed, lowercased and NFC-normalized; the domain is kept in ASCII (punycode) form",
+            ))
+            .example(Some(json!("user@example.com")))
+            .build();
+        ("Email", schema.into())
+    }
+}
+
+impl TryFrom<String> for Email {
+    type Error = EmailError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let value = value.trim();
+
+        if value.is_empty() {
+            return Err(EmailError::Empty);
+        }
+        // Checked before normalizing too, so huge inputs are turned away cheaply
+        if value.chars().count() > MAX_EMAIL_LENGTH {
+            return Err(EmailError::TooLong);
+        }
+
+        let email = value.to_lowercase().nfc().collect::<String>();
+        let (local, domain) = email.split_once('@').ok_or(EmailError::InvalidFormat)?;
+        let domain = idna::domain_to_ascii(domain).map_err(|_| EmailError::InvalidDomain)?;
+        let email = format!("{}@{}", local, domain);
+
+        if email.chars().count() > MAX_EMAIL_LENGTH {
+            return Err(EmailError::TooLong);
+        }
+
+        let valid = !local.is_empty()
+            && !domain.contains('@')
+            && domain.contains('.')
+            && domain.split('.').all(|label| !label.is_empty())
+            && !email.chars().any(char::is_whitespace);
+
+        if !valid {
+            return Err(EmailError::InvalidFormat);
+        }
+
+        Ok(Self(email))
+    }
+}
+
+impl fmt::Display for Email {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RegisterRequest;
+
+    fn parse(value: &str) -> Result<Email, EmailError> {
+        Email::try_from(value.to_string())
+    }
+
+    #[test]
+    fn trims_surrounding_whitespace() {
+        assert_eq!(
+            parse("  user@example.com\t").unwrap().as_str(),
+            "user@example.com"
+        );
+    }
+
+    #[test]
+    fn lowercases() {
+        assert_eq!(
+            parse("User@Example.COM").unwrap().as_str(),
+            "user@example.com"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_formats() {
+        assert_eq!(parse(""), Err(EmailError::Empty));
+        assert_eq!(parse("   "), Err(EmailError::Empty));
+        for invalid in [
+            "user",
+            "@example.com",
+            "user@example",
+            "user@@example.com",
+            "user@example..com",
+            "us er@example.com",
+        ] {
+            assert_eq!(
+                parse(invalid),
+                Err(EmailError::InvalidFormat),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_addresses_over_the_column_width() {
+        let domain = "@example.com";
+        let longest = format!("{}{}", "a".repeat(MAX_EMAIL_LENGTH - domain.len()), domain);
+        assert!(parse(&longest).is_ok());
+        assert_eq!(parse(&format!("a{}", longest)), Err(EmailError::TooLong));
+    }
+
+    #[test]
+    fn composed_and_decomposed_forms_are_one_address() {
+        // "josé" with a precomposed é, and with e + combining acute accent
+        let composed = parse("jos\u{e9}@exämple.com").unwrap();
+        let decomposed = parse("jose\u{301}@EXA\u{308}MPLE.com").unwrap();
+        assert_eq!(composed, decomposed);
+        assert_eq!(composed.as_str(), "jos\u{e9}@xn--exmple-cua.com");
+    }
+
+    #[test]
+    fn register_request_normalizes_while_deserializing() {
+        let request: RegisterRequest =
+            serde_json::from_str(r#"{"email": " New@Example.com", "password": "secret123"}"#)
+                .unwrap();
+        assert_eq!(request.email.as_str(), "new@example.com");
+
+        let invalid =
+            serde_json::from_str::<RegisterRequest>(r#"{"email": "new", "password": "secret123"}"#);
+        assert!(invalid.is_err());
+    }
+}
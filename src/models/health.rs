@@ -0,0 +1,18 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyState {
+    Up,
+    Down,
+}
+
+/// Result of the latest check of one dependency
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DependencyStatus {
+    pub status: DependencyState,
+    pub latency_ms: u64,
+    pub checked_at: DateTime<Utc>,
+}
@@ -1,5 +1,33 @@
+pub mod admin;
+pub mod api_key;
 pub mod auth;
+pub mod email;
+pub mod health;
+pub mod session;
 pub mod user;
+pub mod webhook;
 
-pub use auth::{Claims, LoginRequest, LoginResponse, RegisterRequest};
-pub use user::{User, UserResponse};
+pub use admin::{
+    AdminAuditEntry, AdminAuditFilter, AuditEventResponse, AuditLogPage, AuditOutcome,
+    ConcurrencyStatus, GlobalRateLimitStatus, ImpersonationResponse, ListAuditQuery,
+    MaintenanceStatus, RateLimitStatus, ThrottledUser, UserInFlight,
+};
+pub use api_key::{
+    ApiKey, ApiKeyResponse, CreateApiKeyRequest, NewApiKeyResponse, SetApiKeyRateLimitRequest,
+};
+pub use auth::{
+    Actor, ChangeEmailRequest, ChangePasswordRequest, Claims, Confirmation,
+    EmailChangeTokenRequest, EmailVerificationClaims, ForgotPasswordRequest, IntrospectRequest,
+    IntrospectResponse, LoginRequest, LoginResponse, MinimalClaims, PasswordResetClaims,
+    RefreshRequest, RegisterRequest, ResendVerificationRequest, ResetPasswordRequest,
+    TokenClaimsResponse, VerifyEmailRequest, VerifyPasswordRequest, EMAIL_VERIFICATION_PURPOSE,
+    PASSWORD_RESET_PURPOSE,
+};
+pub use email::Email;
+pub use health::{DependencyState, DependencyStatus};
+pub use session::{ClientInfo, Session, SessionResponse};
+pub use user::{
+    ExportRecord, ListUsersQuery, Role, SortOrder, UpdateProfileRequest, User, UserCursor,
+    UserCursorPage, UserListResponse, UserResponse, UserSortColumn,
+};
+pub use webhook::WebhookDelivery;
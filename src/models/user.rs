@@ -1,8 +1,21 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::FromRow;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
+use validator::Validate;
+
+use super::{AuditEventResponse, SessionResponse};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct User {
@@ -10,6 +23,20 @@ pub struct User {
     pub email: String,
     #[serde(skip_serializing)]
     pub password_hash: String,
+    pub role: Role,
+    // Bumped to invalidate every token issued to this user
+    pub token_version: i32,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+    // Requested new address, not usable until confirmed
+    pub pending_email: Option<String>,
+    // When `email` was last proven reachable; `None` until then
+    pub email_verified_at: Option<DateTime<Utc>>,
+    // Bumped on every profile update
+    pub version: i32,
+    // The account is purged after this unless the request is cancelled
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+    pub last_login_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -18,15 +45,278 @@ pub struct User {
 pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
+    pub role: Role,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+    /// Set while an email change awaits confirmation
+    pub pending_email: Option<String>,
+    /// When the user followed a verification link for `email`, or confirmed
+    /// a change to it; `null` while unverified
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// Send back as `version` in `PATCH /users/me` to detect concurrent edits
+    pub version: i32,
+    /// Set while account deletion is pending; cancel before then to keep it
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+    pub last_login_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
+impl UserResponse {
+    /// Strong validator for this representation, sent as `ETag`. Leaves out
+    /// `last_login_at`, so signing in elsewhere doesn't invalidate cached
+    /// copies or fail a pending `If-Match` edit.
+    pub fn etag(&self) -> String {
+        let mut fields = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = fields.as_object_mut() {
+            fields.remove("last_login_at");
+        }
+        let body = serde_json::to_vec(&fields).unwrap_or_default();
+        format!(
+            "\"{}\"",
+            URL_SAFE_NO_PAD.encode(&Sha256::digest(body)[..16])
+        )
+    }
+}
+
 impl From<User> for UserResponse {
     fn from(user: User) -> Self {
         Self {
             id: user.id,
             email: user.email,
+            role: user.role,
+            name: user.name,
+            avatar_url: user.avatar_url,
+            pending_email: user.pending_email,
+            email_verified_at: user.email_verified_at,
+            version: user.version,
+            deletion_scheduled_at: user.deletion_scheduled_at,
+            last_login_at: user.last_login_at,
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// One line of the NDJSON stream from `GET /users/me/export`, tagged by
+/// `type`: the profile first, then each active session, then each audit
+/// event. Built fresh from the repositories on each request; the password
+/// hash is never included.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportRecord {
+    Profile {
+        exported_at: DateTime<Utc>,
+        profile: UserResponse,
+        updated_at: DateTime<Utc>,
+    },
+    /// Where and when the user signed in, never the token
+    Session(SessionResponse),
+    /// An admin request made by the user or about them
+    AuditEvent(AuditEventResponse),
+}
+
+/// Body of `PATCH /users/me`. An absent field is left unchanged; an explicit
+/// `null` clears it. The update only applies if the profile is still at
+/// `version`.
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct UpdateProfileRequest {
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<String>, min_length = 1, max_length = 100, example = "Ada Lovelace")]
+    #[validate(length(min = 1, max = 100, message = "must be 1 to 100 characters"))]
+    pub name: Option<Option<String>>,
+    #[serde(default, alias = "avatarUrl", deserialize_with = "present")]
+    #[schema(
+        value_type = Option<String>,
+        format = "uri",
+        max_length = 2048,
+        example = "https://example.com/ada.png"
+    )]
+    #[validate(
+        url(message = "must be a URL"),
+        length(max = 2048, message = "must be at most 2048 characters")
+    )]
+    pub avatar_url: Option<Option<String>>,
+    /// `version` from the user as last read. Required unless `If-Match` is sent
+    #[schema(example = 3)]
+    pub version: Option<i32>,
+}
+
+impl UpdateProfileRequest {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.avatar_url.is_none()
+    }
+}
+
+// Only called for fields present in the body, so `null` becomes `Some(None)`
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct ListUsersQuery {
+    /// Page number, starting at 1
+    #[param(minimum = 1)]
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub page: Option<u32>,
+    /// Number of users per page, capped at `MAX_PAGE_SIZE`
+    #[param(minimum = 1)]
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub per_page: Option<u32>,
+    /// Column to sort by: `created_at` or `email`
+    pub sort_by: Option<String>,
+    /// Sort direction: `asc` or `desc`
+    pub order: Option<String>,
+    /// Cursor from a previous response's `next_cursor`; switches to keyset paging
+    pub after: Option<String>,
+    /// Number of users per page when keyset paging, capped at `MAX_PAGE_SIZE`
+    #[param(minimum = 1)]
+    #[validate(range(min = 1, message = "must be at least 1"))]
+    pub limit: Option<u32>,
+}
+
+impl ListUsersQuery {
+    /// Keyset paging is used whenever `after` or `limit` is given
+    pub fn is_keyset(&self) -> bool {
+        self.after.is_some() || self.limit.is_some()
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserListResponse {
+    pub users: Vec<UserResponse>,
+    pub page: u32,
+    pub per_page: u32,
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserCursorPage {
+    pub users: Vec<UserResponse>,
+    /// Pass as `after` to fetch the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Position of the last row seen when keyset paging, ordered by
+/// `(created_at, id)`. Opaque to clients.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UserCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl UserCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}|{}", self.created_at.to_rfc3339(), self.id))
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(value).ok()?;
+        let decoded = String::from_utf8(bytes).ok()?;
+        let (created_at, id) = decoded.split_once('|')?;
+
+        Some(Self {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .ok()?
+                .with_timezone(&Utc),
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+impl From<&User> for UserCursor {
+    fn from(user: &User) -> Self {
+        Self {
             created_at: user.created_at,
+            id: user.id,
+        }
+    }
+}
+
+/// Columns users can be sorted by. Only these are ever interpolated into SQL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserSortColumn {
+    CreatedAt,
+    Email,
+}
+
+impl UserSortColumn {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "created_at" => Some(Self::CreatedAt),
+            "email" => Some(Self::Email),
+            _ => None,
+        }
+    }
+
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Self::CreatedAt => "created_at",
+            Self::Email => "email",
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "asc" => Some(Self::Asc),
+            "desc" => Some(Self::Desc),
+            _ => None,
+        }
+    }
+
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> UserResponse {
+        UserResponse {
+            id: Uuid::nil(),
+            email: "user@example.com".to_string(),
+            role: Role::User,
+            name: None,
+            avatar_url: None,
+            pending_email: None,
+            email_verified_at: None,
+            version: 1,
+            deletion_scheduled_at: None,
+            last_login_at: None,
+            created_at: DateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn etag_ignores_last_login_at_but_not_the_profile() {
+        let etag = user().etag();
+
+        let logged_in = UserResponse {
+            last_login_at: Some(Utc::now()),
+            ..user()
+        };
+        assert_eq!(logged_in.etag(), etag);
+
+        let renamed = UserResponse {
+            name: Some("Ada".to_string()),
+            ..user()
+        };
+        assert_ne!(renamed.etag(), etag);
+    }
+}
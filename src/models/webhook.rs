@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// An outgoing webhook event. Pending until delivered or given up on.
+#[derive(Debug, Clone, FromRow)]
+pub struct WebhookDelivery {
+    /// Sent as `event_id`; the same across retries
+    pub id: Uuid,
+    /// Identifies the logical event, e.g. `user.registered:<user id>`
+    pub idempotency_key: String,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,138 @@
+use async_trait::async_trait;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use super::retry::retry_on_disconnect;
+use crate::models::{AdminAuditEntry, AdminAuditFilter, SortOrder};
+
+const AUDIT_COLUMNS: &str = "id, actor_id, action, target, status, request_id, created_at";
+
+#[async_trait]
+pub trait AdminAuditRepository: Send + Sync {
+    async fn record(&self, entry: &AdminAuditEntry) -> Result<(), sqlx::Error>;
+
+    /// Entries matching `filter`, ordered by `created_at`
+    async fn query(
+        &self,
+        filter: &AdminAuditFilter,
+        order: SortOrder,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AdminAuditEntry>, sqlx::Error>;
+
+    async fn count(&self, filter: &AdminAuditFilter) -> Result<i64, sqlx::Error>;
+}
+
+#[derive(Clone)]
+pub struct PgAdminAuditRepository {
+    pool: PgPool,
+}
+
+impl PgAdminAuditRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AdminAuditRepository for PgAdminAuditRepository {
+    async fn record(&self, entry: &AdminAuditEntry) -> Result<(), sqlx::Error> {
+        // Safe to retry: a repeated insert of the same id is a no-op
+        retry_on_disconnect(|| {
+            sqlx::query(
+                r#"
+                INSERT INTO admin_audit
+                    (id, actor_id, action, target, outcome, status, request_id, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (id) DO NOTHING
+                "#,
+            )
+            .bind(entry.id)
+            .bind(entry.actor_id)
+            .bind(&entry.action)
+            .bind(&entry.target)
+            .bind(entry.outcome().as_str())
+            .bind(entry.status)
+            .bind(&entry.request_id)
+            .bind(entry.created_at)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        filter: &AdminAuditFilter,
+        order: SortOrder,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AdminAuditEntry>, sqlx::Error> {
+        let mut query =
+            QueryBuilder::<Postgres>::new(format!("SELECT {AUDIT_COLUMNS} FROM admin_audit"));
+        push_filter(&mut query, filter);
+        // Direction comes from a fixed enum, never from raw input
+        query.push(format!(
+            " ORDER BY created_at {order}, id {order}",
+            order = order.as_sql()
+        ));
+        query.push(" LIMIT ").push_bind(limit);
+        query.push(" OFFSET ").push_bind(offset);
+
+        let entries = query
+            .build_query_as::<AdminAuditEntry>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(entries)
+    }
+
+    async fn count(&self, filter: &AdminAuditFilter) -> Result<i64, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM admin_audit");
+        push_filter(&mut query, filter);
+
+        let count = query
+            .build_query_scalar::<i64>()
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+}
+
+fn push_filter(query: &mut QueryBuilder<'_, Postgres>, filter: &AdminAuditFilter) {
+    let mut keyword = " WHERE ";
+    let mut condition = |query: &mut QueryBuilder<'_, Postgres>, sql: &str| {
+        query.push(keyword).push(sql);
+        keyword = " AND ";
+    };
+
+    if let Some(actor_id) = filter.actor_id {
+        condition(query, "actor_id = ");
+        query.push_bind(actor_id);
+    }
+    if let Some(user_id) = filter.involving {
+        // `target` is the route's `:id` as text
+        condition(query, "(actor_id = ");
+        query.push_bind(user_id);
+        query.push(" OR target = ");
+        query.push_bind(user_id.to_string());
+        query.push(")");
+    }
+    if let Some(action) = &filter.action {
+        condition(query, "action = ");
+        query.push_bind(action.clone());
+    }
+    if let Some(outcome) = filter.outcome {
+        condition(query, "outcome = ");
+        query.push_bind(outcome.as_str());
+    }
+    if let Some(from) = filter.from {
+        condition(query, "created_at >= ");
+        query.push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        condition(query, "created_at < ");
+        query.push_bind(to);
+    }
+}
@@ -0,0 +1,210 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::retry::retry_on_disconnect;
+use crate::models::ApiKey;
+
+const API_KEY_COLUMNS: &str =
+    "id, user_id, name, prefix, previous_expires_at, created_at, rotated_at, rate_limit_rps";
+
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<ApiKey, sqlx::Error>;
+
+    /// Oldest first
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<ApiKey>, sqlx::Error>;
+
+    /// The key whose current hash is `key_hash`, or whose previous one is
+    /// and was still accepted at `now`
+    async fn find_by_hash(
+        &self,
+        key_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, sqlx::Error>;
+
+    /// Swap in a new key, keeping the current one as the previous key until
+    /// `previous_expires_at`. Any older previous key stops working at once.
+    async fn rotate(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        prefix: &str,
+        key_hash: &str,
+        previous_expires_at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, sqlx::Error>;
+
+    /// Returns whether a key was deleted
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error>;
+
+    /// Set or clear (`None`) the key's own quota, whoever owns it
+    async fn set_rate_limit(
+        &self,
+        id: Uuid,
+        rate_limit_rps: Option<i32>,
+    ) -> Result<Option<ApiKey>, sqlx::Error>;
+}
+
+#[derive(Clone)]
+pub struct PgApiKeyRepository {
+    pool: PgPool,
+}
+
+impl PgApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for PgApiKeyRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<ApiKey, sqlx::Error> {
+        let query = format!(
+            r#"
+            INSERT INTO api_keys (user_id, name, prefix, key_hash)
+            VALUES ($1, $2, $3, $4)
+            RETURNING {API_KEY_COLUMNS}
+            "#
+        );
+
+        // Not retried: a lost acknowledgement would leave a duplicate key
+        let key = sqlx::query_as::<_, ApiKey>(&query)
+            .bind(user_id)
+            .bind(name)
+            .bind(prefix)
+            .bind(key_hash)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(key)
+    }
+
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {API_KEY_COLUMNS}
+            FROM api_keys
+            WHERE user_id = $1
+            ORDER BY created_at, id
+            "#
+        );
+
+        let keys = retry_on_disconnect(|| {
+            sqlx::query_as::<_, ApiKey>(&query)
+                .bind(user_id)
+                .fetch_all(&self.pool)
+        })
+        .await?;
+
+        Ok(keys)
+    }
+
+    async fn find_by_hash(
+        &self,
+        key_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {API_KEY_COLUMNS}
+            FROM api_keys
+            WHERE key_hash = $1
+               OR (previous_key_hash = $1 AND previous_expires_at > $2)
+            "#
+        );
+
+        let key = retry_on_disconnect(|| {
+            sqlx::query_as::<_, ApiKey>(&query)
+                .bind(key_hash)
+                .bind(now)
+                .fetch_optional(&self.pool)
+        })
+        .await?;
+
+        Ok(key)
+    }
+
+    async fn rotate(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        prefix: &str,
+        key_hash: &str,
+        previous_expires_at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE api_keys
+            SET previous_key_hash = key_hash,
+                previous_expires_at = $5,
+                key_hash = $4,
+                prefix = $3,
+                rotated_at = NOW()
+            WHERE id = $1 AND user_id = $2
+            RETURNING {API_KEY_COLUMNS}
+            "#
+        );
+
+        // Not retried: a repeat would retire the key just issued
+        let key = sqlx::query_as::<_, ApiKey>(&query)
+            .bind(id)
+            .bind(user_id)
+            .bind(prefix)
+            .bind(key_hash)
+            .bind(previous_expires_at)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(key)
+    }
+
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        // Not retried: a repeat after a lost acknowledgement would report
+        // the key as already gone
+        let result = sqlx::query("DELETE FROM api_keys WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_rate_limit(
+        &self,
+        id: Uuid,
+        rate_limit_rps: Option<i32>,
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE api_keys
+            SET rate_limit_rps = $2
+            WHERE id = $1
+            RETURNING {API_KEY_COLUMNS}
+            "#
+        );
+
+        let key = retry_on_disconnect(|| {
+            sqlx::query_as::<_, ApiKey>(&query)
+                .bind(id)
+                .bind(rate_limit_rps)
+                .fetch_optional(&self.pool)
+        })
+        .await?;
+
+        Ok(key)
+    }
+}
@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+use super::SessionRepository;
+use crate::models::{ClientInfo, Session};
+
+/// `HashMap`-backed counterpart of `InMemoryUserRepository` for sessions
+#[derive(Clone, Default)]
+pub struct InMemorySessionRepository {
+    // Sessions with their refresh token hash
+    sessions: Arc<RwLock<HashMap<Uuid, (Session, String)>>>,
+}
+
+impl InMemorySessionRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn active_for_user(&self, user_id: Uuid) -> Vec<Session> {
+        let now = Utc::now();
+        self.sessions
+            .read()
+            .unwrap()
+            .values()
+            .map(|(session, _)| session)
+            .filter(|s| s.user_id == user_id && s.expires_at > now)
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl SessionRepository for InMemorySessionRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        client: &ClientInfo,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session, sqlx::Error> {
+        let now = Utc::now();
+        let session = Session {
+            id: Uuid::new_v4(),
+            user_id,
+            ip_address: client.ip_address.clone(),
+            user_agent: client.user_agent.clone(),
+            created_at: now,
+            last_used_at: now,
+            expires_at,
+        };
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.id, (session.clone(), token_hash.to_string()));
+
+        Ok(session)
+    }
+
+    async fn find_by_token_hash(&self, token_hash: &str) -> Result<Option<Session>, sqlx::Error> {
+        let sessions = self.sessions.read().unwrap();
+        Ok(sessions
+            .values()
+            .find(|(_, hash)| hash == token_hash)
+            .map(|(session, _)| session.clone()))
+    }
+
+    async fn rotate(&self, id: Uuid, token_hash: &str) -> Result<(), sqlx::Error> {
+        if let Some((session, hash)) = self.sessions.write().unwrap().get_mut(&id) {
+            session.last_used_at = Utc::now();
+            *hash = token_hash.to_string();
+        }
+        Ok(())
+    }
+
+    async fn list_active_for_user(&self, user_id: Uuid) -> Result<Vec<Session>, sqlx::Error> {
+        let mut sessions = self.active_for_user(user_id);
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_used_at));
+        Ok(sessions)
+    }
+
+    async fn list_active_for_user_after(
+        &self,
+        user_id: Uuid,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Session>, sqlx::Error> {
+        let mut sessions: Vec<Session> = self
+            .active_for_user(user_id)
+            .into_iter()
+            .filter(|s| after.is_none_or(|after| s.id > after))
+            .collect();
+        sessions.sort_by_key(|s| s.id);
+        sessions.truncate(limit.max(0) as usize);
+        Ok(sessions)
+    }
+
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let mut sessions = self.sessions.write().unwrap();
+        match sessions.get(&id) {
+            Some((session, _)) if session.user_id == user_id => {
+                sessions.remove(&id);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_all_for_user(&self, user_id: Uuid) -> Result<u64, sqlx::Error> {
+        let mut sessions = self.sessions.write().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, (session, _)| session.user_id != user_id);
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn prune_for_user(&self, user_id: Uuid, keep: Option<i64>) -> Result<u64, sqlx::Error> {
+        let mut active = self.active_for_user(user_id);
+        active.sort_by_key(|s| std::cmp::Reverse((s.last_used_at, s.created_at)));
+        active.truncate(keep.map_or(usize::MAX, |keep| keep.max(0) as usize));
+
+        let mut sessions = self.sessions.write().unwrap();
+        let before = sessions.len();
+        sessions.retain(|id, (session, _)| {
+            session.user_id != user_id || active.iter().any(|kept| kept.id == *id)
+        });
+        Ok((before - sessions.len()) as u64)
+    }
+}
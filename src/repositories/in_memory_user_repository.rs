@@ -0,0 +1,387 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+use super::UserRepository;
+use crate::models::{
+    Email, Role, SortOrder, UpdateProfileRequest, User, UserCursor, UserSortColumn,
+};
+
+/// `HashMap`-backed repository for exercising services without a database
+#[derive(Clone, Default)]
+pub struct InMemoryUserRepository {
+    users: Arc<RwLock<HashMap<Uuid, User>>>,
+    // Token hashes and expiry of pending email changes, keyed by user id
+    email_changes: Arc<RwLock<HashMap<Uuid, EmailChange>>>,
+    // Replaced password hashes, newest first, keyed by user id
+    password_history: Arc<RwLock<HashMap<Uuid, Vec<String>>>>,
+}
+
+#[derive(Clone)]
+struct EmailChange {
+    confirm_token_hash: String,
+    cancel_token_hash: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// User id of the unexpired email change matching `matches`
+    fn find_email_change(&self, matches: impl Fn(&EmailChange) -> bool) -> Option<Uuid> {
+        self.email_changes
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(_, change)| change.expires_at > Utc::now() && matches(change))
+            .map(|(id, _)| *id)
+    }
+}
+
+#[async_trait]
+impl UserRepository for InMemoryUserRepository {
+    async fn create(&self, email: &Email, password_hash: &str) -> Result<User, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+
+        // Mirror the unique constraint on users.email
+        if users.values().any(|u| u.email == email.as_str()) {
+            return Err(sqlx::Error::Protocol(format!(
+                "duplicate key value violates unique constraint: {}",
+                email
+            )));
+        }
+
+        let now = Utc::now();
+        let user = User {
+            id: Uuid::new_v4(),
+            email: email.to_string(),
+            password_hash: password_hash.to_string(),
+            role: Role::User,
+            token_version: 0,
+            name: None,
+            avatar_url: None,
+            pending_email: None,
+            email_verified_at: None,
+            version: 0,
+            deletion_scheduled_at: None,
+            last_login_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        users.insert(user.id, user.clone());
+
+        Ok(user)
+    }
+
+    async fn find_by_email(&self, email: &Email) -> Result<Option<User>, sqlx::Error> {
+        let users = self.users.read().unwrap();
+        Ok(users.values().find(|u| u.email == email.as_str()).cloned())
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, sqlx::Error> {
+        let users = self.users.read().unwrap();
+        Ok(users.get(&id).cloned())
+    }
+
+    async fn list(
+        &self,
+        sort_by: UserSortColumn,
+        order: SortOrder,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        let mut users: Vec<User> = self.users.read().unwrap().values().cloned().collect();
+
+        users.sort_by(|a, b| {
+            let ordering = match sort_by {
+                UserSortColumn::CreatedAt => a.created_at.cmp(&b.created_at),
+                UserSortColumn::Email => a.email.cmp(&b.email),
+            }
+            .then_with(|| a.id.cmp(&b.id));
+
+            match order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        });
+
+        Ok(users
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    async fn list_after(
+        &self,
+        cursor: Option<UserCursor>,
+        limit: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        let key = |u: &User| (u.created_at, u.id);
+        let mut users: Vec<User> = self
+            .users
+            .read()
+            .unwrap()
+            .values()
+            .filter(|u| cursor.is_none_or(|c| key(u) > (c.created_at, c.id)))
+            .cloned()
+            .collect();
+
+        users.sort_by_key(key);
+        users.truncate(limit.max(0) as usize);
+
+        Ok(users)
+    }
+
+    async fn count(&self) -> Result<i64, sqlx::Error> {
+        Ok(self.users.read().unwrap().len() as i64)
+    }
+
+    async fn increment_token_version(&self, id: Uuid) -> Result<Option<i32>, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+
+        Ok(users.get_mut(&id).map(|user| {
+            user.token_version += 1;
+            user.updated_at = Utc::now();
+            user.token_version
+        }))
+    }
+
+    async fn touch_last_login(&self, id: Uuid) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+
+        Ok(users.get_mut(&id).map(|user| {
+            let now = Utc::now();
+            user.last_login_at = Some(now);
+            now
+        }))
+    }
+
+    async fn replace_password_hash(
+        &self,
+        id: Uuid,
+        current_hash: &str,
+        new_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+
+        match users.get_mut(&id) {
+            Some(user) if user.password_hash == current_hash => {
+                user.password_hash = new_hash.to_string();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn set_role(&self, id: Uuid, role: Role) -> Result<Option<User>, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+
+        Ok(users.get_mut(&id).map(|user| {
+            user.role = role;
+            user.updated_at = Utc::now();
+            user.clone()
+        }))
+    }
+
+    async fn update_profile(
+        &self,
+        id: Uuid,
+        changes: &UpdateProfileRequest,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+        let Some(user) = users.get_mut(&id) else {
+            return Ok(None);
+        };
+        if changes.is_empty() {
+            return Ok(Some(user.clone()));
+        }
+        if changes
+            .version
+            .is_some_and(|version| version != user.version)
+        {
+            return Ok(None);
+        }
+
+        if let Some(name) = &changes.name {
+            user.name = name.clone();
+        }
+        if let Some(avatar_url) = &changes.avatar_url {
+            user.avatar_url = avatar_url.clone();
+        }
+        user.version += 1;
+        user.updated_at = Utc::now();
+
+        Ok(Some(user.clone()))
+    }
+
+    async fn set_pending_email(
+        &self,
+        id: Uuid,
+        email: &Email,
+        confirm_token_hash: &str,
+        cancel_token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+        let Some(user) = users.get_mut(&id) else {
+            return Ok(None);
+        };
+
+        user.pending_email = Some(email.to_string());
+        user.updated_at = Utc::now();
+        self.email_changes.write().unwrap().insert(
+            id,
+            EmailChange {
+                confirm_token_hash: confirm_token_hash.to_string(),
+                cancel_token_hash: cancel_token_hash.to_string(),
+                expires_at,
+            },
+        );
+
+        Ok(Some(user.clone()))
+    }
+
+    async fn confirm_pending_email(
+        &self,
+        confirm_token_hash: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let Some(id) =
+            self.find_email_change(|change| change.confirm_token_hash == confirm_token_hash)
+        else {
+            return Ok(None);
+        };
+
+        let mut users = self.users.write().unwrap();
+        let Some(email) = users.get(&id).and_then(|user| user.pending_email.clone()) else {
+            return Ok(None);
+        };
+        if users.values().any(|u| u.email == email) {
+            return Err(sqlx::Error::Protocol(format!(
+                "duplicate key value violates unique constraint: {}",
+                email
+            )));
+        }
+
+        self.email_changes.write().unwrap().remove(&id);
+        Ok(users.get_mut(&id).map(|user| {
+            user.email = email;
+            user.pending_email = None;
+            user.email_verified_at = Some(Utc::now());
+            user.updated_at = Utc::now();
+            user.clone()
+        }))
+    }
+
+    async fn mark_email_verified(
+        &self,
+        id: Uuid,
+        email: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+
+        Ok(users
+            .get_mut(&id)
+            .filter(|user| user.email == email)
+            .map(|user| {
+                user.email_verified_at.get_or_insert_with(Utc::now);
+                user.updated_at = Utc::now();
+                user.clone()
+            }))
+    }
+
+    async fn cancel_pending_email(
+        &self,
+        cancel_token_hash: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let Some(id) =
+            self.find_email_change(|change| change.cancel_token_hash == cancel_token_hash)
+        else {
+            return Ok(None);
+        };
+
+        self.email_changes.write().unwrap().remove(&id);
+        let mut users = self.users.write().unwrap();
+
+        Ok(users.get_mut(&id).map(|user| {
+            user.pending_email = None;
+            user.updated_at = Utc::now();
+            user.clone()
+        }))
+    }
+
+    async fn password_history(&self, id: Uuid, limit: i64) -> Result<Vec<String>, sqlx::Error> {
+        let history = self.password_history.read().unwrap();
+
+        Ok(history
+            .get(&id)
+            .map(|hashes| hashes.iter().take(limit as usize).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn update_password(
+        &self,
+        id: Uuid,
+        password_hash: &str,
+        history_len: i64,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+        let Some(user) = users.get_mut(&id) else {
+            return Ok(None);
+        };
+
+        // Newest first, like the SQL repositories return it
+        let mut history = self.password_history.write().unwrap();
+        let hashes = history.entry(id).or_default();
+        hashes.insert(
+            0,
+            std::mem::replace(&mut user.password_hash, password_hash.to_string()),
+        );
+        hashes.truncate(history_len.max(0) as usize);
+
+        user.token_version += 1;
+        user.updated_at = Utc::now();
+        Ok(Some(user.clone()))
+    }
+
+    async fn set_deletion_scheduled_at(
+        &self,
+        id: Uuid,
+        at: Option<DateTime<Utc>>,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+
+        Ok(users.get_mut(&id).map(|user| {
+            user.deletion_scheduled_at = at;
+            user.updated_at = Utc::now();
+            user.clone()
+        }))
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        self.email_changes.write().unwrap().remove(&id);
+        self.password_history.write().unwrap().remove(&id);
+        Ok(self.users.write().unwrap().remove(&id).is_some())
+    }
+
+    async fn purge_scheduled_deletions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let mut users = self.users.write().unwrap();
+        let due: Vec<Uuid> = users
+            .values()
+            .filter(|user| user.deletion_scheduled_at.is_some_and(|at| at <= now))
+            .map(|user| user.id)
+            .collect();
+
+        for id in &due {
+            users.remove(id);
+            self.email_changes.write().unwrap().remove(id);
+            self.password_history.write().unwrap().remove(id);
+        }
+
+        Ok(due.len() as u64)
+    }
+}
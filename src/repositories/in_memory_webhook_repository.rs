@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+use super::WebhookRepository;
+use crate::models::WebhookDelivery;
+
+/// `Vec`-backed counterpart of `InMemoryUserRepository` for webhook
+/// deliveries
+#[derive(Clone, Default)]
+pub struct InMemoryWebhookRepository {
+    deliveries: Arc<RwLock<Vec<(WebhookDelivery, DeliveryState)>>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DeliveryState {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl InMemoryWebhookRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&self, id: Uuid, update: impl FnOnce(&mut WebhookDelivery, &mut DeliveryState)) {
+        let mut deliveries = self.deliveries.write().unwrap();
+        if let Some((delivery, state)) = deliveries.iter_mut().find(|(d, _)| d.id == id) {
+            update(delivery, state);
+        }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for InMemoryWebhookRepository {
+    async fn create(
+        &self,
+        delivery: &WebhookDelivery,
+        since: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut deliveries = self.deliveries.write().unwrap();
+        if deliveries
+            .iter()
+            .any(|(d, _)| d.idempotency_key == delivery.idempotency_key && d.created_at > since)
+        {
+            return Ok(false);
+        }
+
+        deliveries.push((delivery.clone(), DeliveryState::Pending));
+        Ok(true)
+    }
+
+    async fn list_pending(&self) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let deliveries = self.deliveries.read().unwrap();
+        Ok(deliveries
+            .iter()
+            .filter(|(_, state)| *state == DeliveryState::Pending)
+            .map(|(delivery, _)| delivery.clone())
+            .collect())
+    }
+
+    async fn record_attempt(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        self.update(id, |delivery, _| delivery.attempts += 1);
+        Ok(())
+    }
+
+    async fn mark_delivered(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        self.update(id, |_, state| *state = DeliveryState::Delivered);
+        Ok(())
+    }
+
+    async fn mark_failed(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        self.update(id, |_, state| *state = DeliveryState::Failed);
+        Ok(())
+    }
+}
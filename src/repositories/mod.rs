@@ -1,3 +1,34 @@
+pub mod admin_audit_repository;
+pub mod api_key_repository;
+#[cfg(test)]
+pub mod in_memory_session_repository;
+#[cfg(test)]
+pub mod in_memory_user_repository;
+#[cfg(test)]
+pub mod in_memory_webhook_repository;
+pub mod retry;
+pub mod session_repository;
+pub mod sqlite_admin_audit_repository;
+pub mod sqlite_api_key_repository;
+pub mod sqlite_session_repository;
+pub mod sqlite_user_repository;
+pub mod sqlite_webhook_repository;
 pub mod user_repository;
+pub mod webhook_repository;
 
-pub use user_repository::UserRepository;
+pub use admin_audit_repository::{AdminAuditRepository, PgAdminAuditRepository};
+pub use api_key_repository::{ApiKeyRepository, PgApiKeyRepository};
+#[cfg(test)]
+pub use in_memory_session_repository::InMemorySessionRepository;
+#[cfg(test)]
+pub use in_memory_user_repository::InMemoryUserRepository;
+#[cfg(test)]
+pub use in_memory_webhook_repository::InMemoryWebhookRepository;
+pub use session_repository::{PgSessionRepository, SessionRepository};
+pub use sqlite_admin_audit_repository::SqliteAdminAuditRepository;
+pub use sqlite_api_key_repository::SqliteApiKeyRepository;
+pub use sqlite_session_repository::SqliteSessionRepository;
+pub use sqlite_user_repository::SqliteUserRepository;
+pub use sqlite_webhook_repository::SqliteWebhookRepository;
+pub use user_repository::{PgUserRepository, UserRepository};
+pub use webhook_repository::{PgWebhookRepository, WebhookRepository};
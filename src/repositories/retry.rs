@@ -0,0 +1,85 @@
+use std::future::Future;
+
+/// Run an idempotent query, retrying once if it failed because the
+/// connection was lost. The pool hands out a fresh connection for the retry.
+///
+/// Only use this for statements that are safe to run twice: the first
+/// attempt may have committed before the connection dropped.
+pub async fn retry_on_disconnect<T, F, Fut>(mut query: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    match query().await {
+        Err(err) if is_connection_error(&err) => {
+            tracing::warn!("Database connection lost, retrying once: {}", err);
+            query().await
+        }
+        result => result,
+    }
+}
+
+/// Whether the error means the connection is unusable, as opposed to the
+/// statement itself failing
+pub fn is_connection_error(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::Protocol(_) => true,
+        // Class 08 is connection exceptions; 57P01-57P03 are server shutdown
+        sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| {
+            code.starts_with("08") || matches!(&*code, "57P01" | "57P02" | "57P03")
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io;
+
+    #[tokio::test]
+    async fn stale_connection_is_retried_once() {
+        let attempts = Cell::new(0);
+
+        let result = retry_on_disconnect(|| async {
+            attempts.set(attempts.get() + 1);
+            match attempts.get() {
+                1 => Err(sqlx::Error::Io(io::ErrorKind::ConnectionReset.into())),
+                _ => Ok("row"),
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), "row");
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn statement_errors_are_not_retried() {
+        let attempts = Cell::new(0);
+
+        let result: Result<(), _> = retry_on_disconnect(|| async {
+            attempts.set(attempts.get() + 1);
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_second_disconnect_is_returned() {
+        let attempts = Cell::new(0);
+
+        let result: Result<(), _> = retry_on_disconnect(|| async {
+            attempts.set(attempts.get() + 1);
+            Err(sqlx::Error::Io(io::ErrorKind::BrokenPipe.into()))
+        })
+        .await;
+
+        assert!(matches!(result, Err(sqlx::Error::Io(_))));
+        assert_eq!(attempts.get(), 2);
+    }
+}
@@ -0,0 +1,224 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::retry::retry_on_disconnect;
+use crate::models::{ClientInfo, Session};
+
+const SESSION_COLUMNS: &str =
+    "id, user_id, ip_address, user_agent, created_at, last_used_at, expires_at";
+
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        client: &ClientInfo,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session, sqlx::Error>;
+
+    async fn find_by_token_hash(&self, token_hash: &str) -> Result<Option<Session>, sqlx::Error>;
+
+    /// Swap in a new refresh token hash and mark the session as used
+    async fn rotate(&self, id: Uuid, token_hash: &str) -> Result<(), sqlx::Error>;
+
+    async fn list_active_for_user(&self, user_id: Uuid) -> Result<Vec<Session>, sqlx::Error>;
+
+    /// Up to `limit` of the user's active sessions in id order, starting
+    /// after `after`, for reading them in batches
+    async fn list_active_for_user_after(
+        &self,
+        user_id: Uuid,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Session>, sqlx::Error>;
+
+    /// Returns whether a session was deleted
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error>;
+
+    async fn delete_all_for_user(&self, user_id: Uuid) -> Result<u64, sqlx::Error>;
+
+    /// Delete the user's expired sessions and, with `keep` set, all but the
+    /// `keep` most recently used active ones. Returns how many were deleted.
+    async fn prune_for_user(&self, user_id: Uuid, keep: Option<i64>) -> Result<u64, sqlx::Error>;
+}
+
+#[derive(Clone)]
+pub struct PgSessionRepository {
+    pool: PgPool,
+}
+
+impl PgSessionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for PgSessionRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        client: &ClientInfo,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session, sqlx::Error> {
+        let query = format!(
+            r#"
+            INSERT INTO sessions (user_id, token_hash, ip_address, user_agent, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING {SESSION_COLUMNS}
+            "#
+        );
+
+        // Not retried: a lost acknowledgement would leave a duplicate session
+        let session = sqlx::query_as::<_, Session>(&query)
+            .bind(user_id)
+            .bind(token_hash)
+            .bind(&client.ip_address)
+            .bind(&client.user_agent)
+            .bind(expires_at)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(session)
+    }
+
+    async fn find_by_token_hash(&self, token_hash: &str) -> Result<Option<Session>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {SESSION_COLUMNS}
+            FROM sessions
+            WHERE token_hash = $1
+            "#
+        );
+
+        let session = retry_on_disconnect(|| {
+            sqlx::query_as::<_, Session>(&query)
+                .bind(token_hash)
+                .fetch_optional(&self.pool)
+        })
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn rotate(&self, id: Uuid, token_hash: &str) -> Result<(), sqlx::Error> {
+        // Writing the same hash twice is idempotent
+        retry_on_disconnect(|| {
+            sqlx::query(
+                r#"
+                UPDATE sessions
+                SET token_hash = $2, last_used_at = NOW()
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .bind(token_hash)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_active_for_user(&self, user_id: Uuid) -> Result<Vec<Session>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {SESSION_COLUMNS}
+            FROM sessions
+            WHERE user_id = $1 AND expires_at > NOW()
+            ORDER BY last_used_at DESC
+            "#
+        );
+
+        let sessions = retry_on_disconnect(|| {
+            sqlx::query_as::<_, Session>(&query)
+                .bind(user_id)
+                .fetch_all(&self.pool)
+        })
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn list_active_for_user_after(
+        &self,
+        user_id: Uuid,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Session>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {SESSION_COLUMNS}
+            FROM sessions
+            WHERE user_id = $1 AND expires_at > NOW() AND ($2::uuid IS NULL OR id > $2)
+            ORDER BY id
+            LIMIT $3
+            "#
+        );
+
+        let sessions = retry_on_disconnect(|| {
+            sqlx::query_as::<_, Session>(&query)
+                .bind(user_id)
+                .bind(after)
+                .bind(limit)
+                .fetch_all(&self.pool)
+        })
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        // Not retried: a repeat after a lost acknowledgement would report
+        // the session as already gone
+        let result = sqlx::query("DELETE FROM sessions WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_all_for_user(&self, user_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = retry_on_disconnect(|| {
+            sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn prune_for_user(&self, user_id: Uuid, keep: Option<i64>) -> Result<u64, sqlx::Error> {
+        // `LIMIT NULL` keeps every active session
+        let result = retry_on_disconnect(|| {
+            sqlx::query(
+                r#"
+                DELETE FROM sessions
+                WHERE user_id = $1
+                  AND (
+                    expires_at <= NOW()
+                    OR id NOT IN (
+                        SELECT id FROM sessions
+                        WHERE user_id = $1 AND expires_at > NOW()
+                        ORDER BY last_used_at DESC, created_at DESC
+                        LIMIT $2
+                    )
+                  )
+                "#,
+            )
+            .bind(user_id)
+            .bind(keep)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,219 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::ApiKeyRepository;
+use crate::db::sqlite_timestamp;
+use crate::models::ApiKey;
+
+const API_KEY_COLUMNS: &str =
+    "id, user_id, name, prefix, previous_expires_at, created_at, rotated_at, rate_limit_rps";
+
+#[derive(Clone)]
+pub struct SqliteApiKeyRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteApiKeyRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for SqliteApiKeyRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<ApiKey, sqlx::Error> {
+        let query = format!(
+            r#"
+            INSERT INTO api_keys (id, user_id, name, prefix, key_hash, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            RETURNING {API_KEY_COLUMNS}
+            "#
+        );
+
+        let key = sqlx::query_as::<_, ApiKey>(&query)
+            .bind(Uuid::new_v4())
+            .bind(user_id)
+            .bind(name)
+            .bind(prefix)
+            .bind(key_hash)
+            .bind(sqlite_timestamp(Utc::now()))
+            // Not `fetch_one`: see the note on `RETURNING` in sqlite_user_repository
+            .fetch_all(&self.pool)
+            .await?
+            .pop()
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        Ok(key)
+    }
+
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {API_KEY_COLUMNS}
+            FROM api_keys
+            WHERE user_id = ?1
+            ORDER BY created_at, id
+            "#
+        );
+
+        let keys = sqlx::query_as::<_, ApiKey>(&query)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(keys)
+    }
+
+    async fn find_by_hash(
+        &self,
+        key_hash: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {API_KEY_COLUMNS}
+            FROM api_keys
+            WHERE key_hash = ?1
+               OR (previous_key_hash = ?1 AND previous_expires_at > ?2)
+            "#
+        );
+
+        let key = sqlx::query_as::<_, ApiKey>(&query)
+            .bind(key_hash)
+            .bind(sqlite_timestamp(now))
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(key)
+    }
+
+    async fn rotate(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        prefix: &str,
+        key_hash: &str,
+        previous_expires_at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE api_keys
+            SET previous_key_hash = key_hash,
+                previous_expires_at = ?5,
+                key_hash = ?4,
+                prefix = ?3,
+                rotated_at = ?6
+            WHERE id = ?1 AND user_id = ?2
+            RETURNING {API_KEY_COLUMNS}
+            "#
+        );
+
+        let key = sqlx::query_as::<_, ApiKey>(&query)
+            .bind(id)
+            .bind(user_id)
+            .bind(prefix)
+            .bind(key_hash)
+            .bind(sqlite_timestamp(previous_expires_at))
+            .bind(sqlite_timestamp(Utc::now()))
+            // Not `fetch_optional`: see the note on `RETURNING` in sqlite_user_repository
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(key)
+    }
+
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE id = ?1 AND user_id = ?2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_rate_limit(
+        &self,
+        id: Uuid,
+        rate_limit_rps: Option<i32>,
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE api_keys
+            SET rate_limit_rps = ?2
+            WHERE id = ?1
+            RETURNING {API_KEY_COLUMNS}
+            "#
+        );
+
+        let key = sqlx::query_as::<_, ApiKey>(&query)
+            .bind(id)
+            .bind(rate_limit_rps)
+            // Not `fetch_optional`: see the note on `RETURNING` in sqlite_user_repository
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Email;
+    use crate::test_support;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn rotation_keeps_the_previous_hash_until_it_expires() {
+        let database = test_support::database().await;
+        let email = Email::try_from("user@example.com".to_string()).unwrap();
+        let user = database.user_repository().create(&email, "hash").await;
+        let user_id = user.unwrap().id;
+        let keys = database.api_key_repository();
+        let now = Utc::now();
+
+        let key = keys
+            .create(user_id, "ci", "tsk_first", "first")
+            .await
+            .unwrap();
+        let expires_at = now + Duration::minutes(5);
+        let rotated = keys
+            .rotate(key.id, user_id, "tsk_second", "second", expires_at)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rotated.prefix, "tsk_second");
+        assert!(rotated.rotated_at.is_some());
+
+        let found = |hash, at| {
+            let keys = keys.clone();
+            async move { keys.find_by_hash(hash, at).await.unwrap().map(|k| k.id) }
+        };
+        assert_eq!(found("second", now).await, Some(key.id));
+        assert_eq!(found("first", now).await, Some(key.id));
+        assert_eq!(found("first", expires_at).await, None);
+
+        // Another user can't rotate or delete it
+        assert!(keys
+            .rotate(key.id, Uuid::new_v4(), "tsk_x", "x", expires_at)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!keys.delete(key.id, Uuid::new_v4()).await.unwrap());
+
+        assert!(keys.delete(key.id, user_id).await.unwrap());
+        assert_eq!(found("second", now).await, None);
+        assert_eq!(found("first", now).await, None);
+    }
+}
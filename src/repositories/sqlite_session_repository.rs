@@ -0,0 +1,269 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::SessionRepository;
+use crate::db::sqlite_timestamp;
+use crate::models::{ClientInfo, Session};
+
+const SESSION_COLUMNS: &str =
+    "id, user_id, ip_address, user_agent, created_at, last_used_at, expires_at";
+
+#[derive(Clone)]
+pub struct SqliteSessionRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteSessionRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SessionRepository for SqliteSessionRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        client: &ClientInfo,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session, sqlx::Error> {
+        let now = sqlite_timestamp(Utc::now());
+        let query = format!(
+            r#"
+            INSERT INTO sessions
+                (id, user_id, token_hash, ip_address, user_agent, created_at, last_used_at, expires_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7)
+            RETURNING {SESSION_COLUMNS}
+            "#
+        );
+
+        let session = sqlx::query_as::<_, Session>(&query)
+            .bind(Uuid::new_v4())
+            .bind(user_id)
+            .bind(token_hash)
+            .bind(&client.ip_address)
+            .bind(&client.user_agent)
+            .bind(now)
+            .bind(sqlite_timestamp(expires_at))
+            // Not `fetch_one`: see the note on `RETURNING` in sqlite_user_repository
+            .fetch_all(&self.pool)
+            .await?
+            .pop()
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        Ok(session)
+    }
+
+    async fn find_by_token_hash(&self, token_hash: &str) -> Result<Option<Session>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {SESSION_COLUMNS}
+            FROM sessions
+            WHERE token_hash = ?1
+            "#
+        );
+
+        let session = sqlx::query_as::<_, Session>(&query)
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(session)
+    }
+
+    async fn rotate(&self, id: Uuid, token_hash: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE sessions
+            SET token_hash = ?2, last_used_at = ?3
+            WHERE id = ?1
+            "#,
+        )
+        .bind(id)
+        .bind(token_hash)
+        .bind(sqlite_timestamp(Utc::now()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_active_for_user(&self, user_id: Uuid) -> Result<Vec<Session>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {SESSION_COLUMNS}
+            FROM sessions
+            WHERE user_id = ?1 AND expires_at > ?2
+            ORDER BY last_used_at DESC
+            "#
+        );
+
+        let sessions = sqlx::query_as::<_, Session>(&query)
+            .bind(user_id)
+            .bind(sqlite_timestamp(Utc::now()))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(sessions)
+    }
+
+    async fn list_active_for_user_after(
+        &self,
+        user_id: Uuid,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Session>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {SESSION_COLUMNS}
+            FROM sessions
+            WHERE user_id = ?1 AND expires_at > ?2 AND (?3 IS NULL OR id > ?3)
+            ORDER BY id
+            LIMIT ?4
+            "#
+        );
+
+        let sessions = sqlx::query_as::<_, Session>(&query)
+            .bind(user_id)
+            .bind(sqlite_timestamp(Utc::now()))
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(sessions)
+    }
+
+    async fn delete(&self, id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM sessions WHERE id = ?1 AND user_id = ?2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_all_for_user(&self, user_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM sessions WHERE user_id = ?1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn prune_for_user(&self, user_id: Uuid, keep: Option<i64>) -> Result<u64, sqlx::Error> {
+        // A negative LIMIT is unbounded in SQLite
+        let result = sqlx::query(
+            r#"
+            DELETE FROM sessions
+            WHERE user_id = ?1
+              AND (
+                expires_at <= ?2
+                OR id NOT IN (
+                    SELECT id FROM sessions
+                    WHERE user_id = ?1 AND expires_at > ?2
+                    ORDER BY last_used_at DESC, created_at DESC
+                    LIMIT ?3
+                )
+              )
+            "#,
+        )
+        .bind(user_id)
+        .bind(sqlite_timestamp(Utc::now()))
+        .bind(keep.unwrap_or(-1))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Email;
+    use crate::test_support;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn session_lifecycle() {
+        let database = test_support::database().await;
+        let email = Email::try_from("user@example.com".to_string()).unwrap();
+        let user = database.user_repository().create(&email, "hash").await;
+        let user_id = user.unwrap().id;
+        let sessions = database.session_repository();
+        let expires_at = Utc::now() + Duration::days(1);
+
+        let session = sessions
+            .create(user_id, "first", &ClientInfo::default(), expires_at)
+            .await
+            .unwrap();
+        let found = sessions.find_by_token_hash("first").await.unwrap();
+        assert_eq!(found.map(|s| s.id), Some(session.id));
+
+        sessions.rotate(session.id, "second").await.unwrap();
+        assert!(sessions
+            .find_by_token_hash("first")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(sessions
+            .find_by_token_hash("second")
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            sessions.list_active_for_user(user_id).await.unwrap().len(),
+            1
+        );
+
+        assert!(sessions.delete(session.id, user_id).await.unwrap());
+        assert!(sessions
+            .list_active_for_user(user_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn prune_evicts_expired_and_least_recently_used_sessions() {
+        let database = test_support::database().await;
+        let email = Email::try_from("user@example.com".to_string()).unwrap();
+        let user = database.user_repository().create(&email, "hash").await;
+        let user_id = user.unwrap().id;
+        let sessions = database.session_repository();
+        let client = ClientInfo::default();
+        let expires_at = Utc::now() + Duration::days(1);
+
+        sessions
+            .create(user_id, "expired", &client, Utc::now() - Duration::days(1))
+            .await
+            .unwrap();
+        for hash in ["oldest", "middle", "newest"] {
+            sessions
+                .create(user_id, hash, &client, expires_at)
+                .await
+                .unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+
+        assert_eq!(sessions.prune_for_user(user_id, Some(2)).await.unwrap(), 2);
+
+        for (hash, kept) in [
+            ("expired", false),
+            ("oldest", false),
+            ("middle", true),
+            ("newest", true),
+        ] {
+            let found = sessions.find_by_token_hash(hash).await.unwrap();
+            assert_eq!(found.is_some(), kept, "{}", hash);
+        }
+        // Without a cap only expired sessions go
+        assert_eq!(sessions.prune_for_user(user_id, None).await.unwrap(), 0);
+    }
+}
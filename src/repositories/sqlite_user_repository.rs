@@ -0,0 +1,703 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use uuid::Uuid;
+
+use super::UserRepository;
+use crate::db::sqlite_timestamp;
+use crate::models::{
+    Email, Role, SortOrder, UpdateProfileRequest, User, UserCursor, UserSortColumn,
+};
+
+const USER_COLUMNS: &str = "id, email, password_hash, role, token_version, name, avatar_url, \
+    pending_email, email_verified_at, version, deletion_scheduled_at, last_login_at, created_at, \
+    updated_at";
+
+// SQLite doesn't commit a `RETURNING` statement until it's stepped past its
+// last row, which `fetch_one` and `fetch_optional` stop short of, leaving the
+// write invisible to other connections. So those statements use `fetch_all`.
+
+#[derive(Clone)]
+pub struct SqliteUserRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteUserRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqliteUserRepository {
+    async fn create(&self, email: &Email, password_hash: &str) -> Result<User, sqlx::Error> {
+        // SQLite has no UUID or clock defaults, so both come from here
+        let now = sqlite_timestamp(Utc::now());
+        let query = format!(
+            r#"
+            INSERT INTO users (id, email, password_hash, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?4)
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(Uuid::new_v4())
+            .bind(email.as_str())
+            .bind(password_hash)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await?
+            .pop()
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        Ok(user)
+    }
+
+    async fn find_by_email(&self, email: &Email) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {USER_COLUMNS}
+            FROM users
+            WHERE email = ?1
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(email.as_str())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {USER_COLUMNS}
+            FROM users
+            WHERE id = ?1
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn list(
+        &self,
+        sort_by: UserSortColumn,
+        order: SortOrder,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        // Sort column and direction come from fixed enums, never from raw input
+        let query = format!(
+            r#"
+            SELECT {USER_COLUMNS}
+            FROM users
+            ORDER BY {column} {order}, id {order}
+            LIMIT ?1 OFFSET ?2
+            "#,
+            column = sort_by.as_sql(),
+            order = order.as_sql(),
+        );
+
+        let users = sqlx::query_as::<_, User>(&query)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(users)
+    }
+
+    async fn list_after(
+        &self,
+        cursor: Option<UserCursor>,
+        limit: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        let query = match cursor {
+            None => format!(
+                r#"
+                SELECT {USER_COLUMNS}
+                FROM users
+                ORDER BY created_at ASC, id ASC
+                LIMIT ?1
+                "#
+            ),
+            Some(_) => format!(
+                r#"
+                SELECT {USER_COLUMNS}
+                FROM users
+                WHERE (created_at, id) > (?1, ?2)
+                ORDER BY created_at ASC, id ASC
+                LIMIT ?3
+                "#
+            ),
+        };
+
+        let mut query = sqlx::query_as::<_, User>(&query);
+        if let Some(cursor) = cursor {
+            query = query
+                .bind(sqlite_timestamp(cursor.created_at))
+                .bind(cursor.id);
+        }
+        let users = query.bind(limit).fetch_all(&self.pool).await?;
+
+        Ok(users)
+    }
+
+    async fn count(&self) -> Result<i64, sqlx::Error> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn increment_token_version(&self, id: Uuid) -> Result<Option<i32>, sqlx::Error> {
+        let version: Option<i32> = sqlx::query_scalar(
+            r#"
+            UPDATE users
+            SET token_version = token_version + 1, updated_at = ?2
+            WHERE id = ?1
+            RETURNING token_version
+            "#,
+        )
+        .bind(id)
+        .bind(sqlite_timestamp(Utc::now()))
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+
+        Ok(version)
+    }
+
+    async fn touch_last_login(&self, id: Uuid) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let last_login_at: Option<DateTime<Utc>> = sqlx::query_scalar(
+            r#"
+            UPDATE users
+            SET last_login_at = ?2
+            WHERE id = ?1
+            RETURNING last_login_at
+            "#,
+        )
+        .bind(id)
+        .bind(sqlite_timestamp(Utc::now()))
+        .fetch_all(&self.pool)
+        .await?
+        .pop();
+
+        Ok(last_login_at)
+    }
+
+    async fn replace_password_hash(
+        &self,
+        id: Uuid,
+        current_hash: &str,
+        new_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result =
+            sqlx::query("UPDATE users SET password_hash = ?3 WHERE id = ?1 AND password_hash = ?2")
+                .bind(id)
+                .bind(current_hash)
+                .bind(new_hash)
+                .execute(&self.pool)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_role(&self, id: Uuid, role: Role) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET role = ?2, updated_at = ?3
+            WHERE id = ?1
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(role)
+            .bind(sqlite_timestamp(Utc::now()))
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(user)
+    }
+
+    async fn update_profile(
+        &self,
+        id: Uuid,
+        changes: &UpdateProfileRequest,
+    ) -> Result<Option<User>, sqlx::Error> {
+        if changes.is_empty() {
+            return self.find_by_id(id).await;
+        }
+
+        // No trigger maintains updated_at here, so set it alongside the fields
+        let mut query =
+            QueryBuilder::<Sqlite>::new("UPDATE users SET version = version + 1, updated_at = ");
+        query.push_bind(sqlite_timestamp(Utc::now()));
+        if let Some(name) = &changes.name {
+            query.push(", name = ").push_bind(name);
+        }
+        if let Some(avatar_url) = &changes.avatar_url {
+            query.push(", avatar_url = ").push_bind(avatar_url);
+        }
+        query.push(" WHERE id = ").push_bind(id);
+        if let Some(version) = changes.version {
+            query.push(" AND version = ").push_bind(version);
+        }
+        query.push(format!(" RETURNING {USER_COLUMNS}"));
+
+        let user = query
+            .build_query_as::<User>()
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(user)
+    }
+
+    async fn set_pending_email(
+        &self,
+        id: Uuid,
+        email: &Email,
+        confirm_token_hash: &str,
+        cancel_token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET pending_email = ?2,
+                email_confirm_token_hash = ?3,
+                email_cancel_token_hash = ?4,
+                email_change_expires_at = ?5,
+                updated_at = ?6
+            WHERE id = ?1
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(email.as_str())
+            .bind(confirm_token_hash)
+            .bind(cancel_token_hash)
+            .bind(sqlite_timestamp(expires_at))
+            .bind(sqlite_timestamp(Utc::now()))
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(user)
+    }
+
+    async fn confirm_pending_email(
+        &self,
+        confirm_token_hash: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        // Fails with a unique violation if the address was taken meanwhile
+        let query = format!(
+            r#"
+            UPDATE users
+            SET email = pending_email,
+                pending_email = NULL,
+                email_verified_at = ?2,
+                email_confirm_token_hash = NULL,
+                email_cancel_token_hash = NULL,
+                email_change_expires_at = NULL,
+                updated_at = ?2
+            WHERE email_confirm_token_hash = ?1
+              AND email_change_expires_at > ?2
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(confirm_token_hash)
+            .bind(sqlite_timestamp(Utc::now()))
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(user)
+    }
+
+    async fn mark_email_verified(
+        &self,
+        id: Uuid,
+        email: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET email_verified_at = COALESCE(email_verified_at, ?3), updated_at = ?3
+            WHERE id = ?1 AND email = ?2
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(email)
+            .bind(sqlite_timestamp(Utc::now()))
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(user)
+    }
+
+    async fn cancel_pending_email(
+        &self,
+        cancel_token_hash: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET pending_email = NULL,
+                email_confirm_token_hash = NULL,
+                email_cancel_token_hash = NULL,
+                email_change_expires_at = NULL,
+                updated_at = ?2
+            WHERE email_cancel_token_hash = ?1
+              AND email_change_expires_at > ?2
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(cancel_token_hash)
+            .bind(sqlite_timestamp(Utc::now()))
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(user)
+    }
+
+    async fn password_history(&self, id: Uuid, limit: i64) -> Result<Vec<String>, sqlx::Error> {
+        let hashes = sqlx::query_scalar(
+            r#"
+            SELECT password_hash
+            FROM password_history
+            WHERE user_id = ?1
+            ORDER BY created_at DESC
+            LIMIT ?2
+            "#,
+        )
+        .bind(id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hashes)
+    }
+
+    async fn update_password(
+        &self,
+        id: Uuid,
+        password_hash: &str,
+        history_len: i64,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let now = sqlite_timestamp(Utc::now());
+        let mut tx = self.pool.begin().await?;
+
+        let old_hash: Option<String> =
+            sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?1")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let Some(old_hash) = old_hash else {
+            return Ok(None);
+        };
+
+        if history_len > 0 {
+            sqlx::query(
+                r#"
+                INSERT INTO password_history (id, user_id, password_hash, created_at)
+                VALUES (?1, ?2, ?3, ?4)
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(id)
+            .bind(&old_hash)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query(
+            r#"
+            DELETE FROM password_history
+            WHERE user_id = ?1
+              AND id NOT IN (
+                SELECT id FROM password_history
+                WHERE user_id = ?1
+                ORDER BY created_at DESC
+                LIMIT ?2
+              )
+            "#,
+        )
+        .bind(id)
+        .bind(history_len)
+        .execute(&mut *tx)
+        .await?;
+
+        let query = format!(
+            r#"
+            UPDATE users
+            SET password_hash = ?2, token_version = token_version + 1, updated_at = ?3
+            WHERE id = ?1
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(password_hash)
+            .bind(&now)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    async fn set_deletion_scheduled_at(
+        &self,
+        id: Uuid,
+        at: Option<DateTime<Utc>>,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET deletion_scheduled_at = ?2, updated_at = ?3
+            WHERE id = ?1
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(at.map(sqlite_timestamp))
+            .bind(sqlite_timestamp(Utc::now()))
+            .fetch_all(&self.pool)
+            .await?
+            .pop();
+
+        Ok(user)
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM users WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_scheduled_deletions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        // Sessions and password history go with the user via ON DELETE CASCADE
+        let result = sqlx::query("DELETE FROM users WHERE deletion_scheduled_at <= ?1")
+            .bind(sqlite_timestamp(now))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::collections::HashSet;
+
+    fn email(value: &str) -> Email {
+        Email::try_from(value.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn create_then_find_by_email_and_id() {
+        let database = test_support::database().await;
+        let users = database.user_repository();
+
+        let created = users
+            .create(&email("user@example.com"), "hash")
+            .await
+            .unwrap();
+
+        let by_email = users.find_by_email(&email("user@example.com")).await;
+        let by_id = users.find_by_id(created.id).await;
+        assert_eq!(by_email.unwrap().unwrap().id, created.id);
+        let found = by_id.unwrap().unwrap();
+        assert_eq!(found.email, "user@example.com");
+        assert_eq!(found.password_hash, "hash");
+        assert_eq!(found.role, Role::User);
+        assert_eq!(users.count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn duplicate_email_is_a_unique_violation() {
+        let database = test_support::database().await;
+        let users = database.user_repository();
+        users
+            .create(&email("user@example.com"), "hash")
+            .await
+            .unwrap();
+
+        let error = users
+            .create(&email("user@example.com"), "hash")
+            .await
+            .unwrap_err();
+
+        assert!(error
+            .as_database_error()
+            .is_some_and(|db| db.is_unique_violation()));
+    }
+
+    #[tokio::test]
+    async fn updates_return_the_new_state() {
+        let database = test_support::database().await;
+        let users = database.user_repository();
+        let user = users
+            .create(&email("user@example.com"), "hash")
+            .await
+            .unwrap();
+
+        let token_version = users.increment_token_version(user.id).await.unwrap();
+        let last_login_at = users.touch_last_login(user.id).await.unwrap();
+        let promoted = users.set_role(user.id, Role::Admin).await.unwrap();
+
+        assert_eq!(token_version, Some(user.token_version + 1));
+        let found = users.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.last_login_at, last_login_at);
+        assert_eq!(promoted.unwrap().role, Role::Admin);
+        assert_eq!(
+            users.increment_token_version(Uuid::new_v4()).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn replace_password_hash_only_from_the_current_hash() {
+        let database = test_support::database().await;
+        let users = database.user_repository();
+        let user = users
+            .create(&email("user@example.com"), "old")
+            .await
+            .unwrap();
+
+        assert!(!users
+            .replace_password_hash(user.id, "stale", "new")
+            .await
+            .unwrap());
+        assert!(users
+            .replace_password_hash(user.id, "old", "new")
+            .await
+            .unwrap());
+        let found = users.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.password_hash, "new");
+    }
+
+    #[tokio::test]
+    async fn update_password_keeps_only_the_latest_history() {
+        let database = test_support::database().await;
+        let users = database.user_repository();
+        let user = users
+            .create(&email("user@example.com"), "first")
+            .await
+            .unwrap();
+
+        for hash in ["second", "third", "fourth"] {
+            users.update_password(user.id, hash, 2).await.unwrap();
+        }
+
+        let found = users.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.password_hash, "fourth");
+        assert_eq!(
+            users.password_history(user.id, 10).await.unwrap(),
+            ["third", "second"]
+        );
+    }
+
+    #[tokio::test]
+    async fn update_profile_checks_the_version() {
+        let database = test_support::database().await;
+        let users = database.user_repository();
+        let user = users
+            .create(&email("user@example.com"), "hash")
+            .await
+            .unwrap();
+        let rename = |version| UpdateProfileRequest {
+            name: Some(Some("Ada".to_string())),
+            avatar_url: None,
+            version: Some(version),
+        };
+
+        let updated = users.update_profile(user.id, &rename(user.version)).await;
+        let stale = users.update_profile(user.id, &rename(user.version)).await;
+
+        let updated = updated.unwrap().expect("version matched");
+        assert_eq!(updated.name.as_deref(), Some("Ada"));
+        assert_eq!(updated.version, user.version + 1);
+        assert!(stale.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn delete_removes_the_user() {
+        let database = test_support::database().await;
+        let users = database.user_repository();
+        let user = users
+            .create(&email("user@example.com"), "hash")
+            .await
+            .unwrap();
+
+        assert!(users.delete(user.id).await.unwrap());
+        assert!(!users.delete(user.id).await.unwrap());
+        assert!(users.find_by_id(user.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn cursor_walk_visits_every_user_once() {
+        let database = test_support::database().await;
+        let users = database.user_repository();
+        let mut created = HashSet::new();
+        for n in 0..7 {
+            let email = email(&format!("user{}@example.com", n));
+            created.insert(users.create(&email, "hash").await.unwrap().id);
+        }
+        // Ties on created_at must be broken by id, not skipped or repeated
+        database
+            .execute("UPDATE users SET created_at = (SELECT MIN(created_at) FROM users)")
+            .await
+            .unwrap();
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = users.list_after(cursor, 3).await.unwrap();
+            let Some(last) = page.last() else { break };
+            cursor = Some(UserCursor {
+                created_at: last.created_at,
+                id: last.id,
+            });
+            seen.extend(page.iter().map(|user| user.id));
+        }
+
+        assert_eq!(seen.len(), created.len());
+        assert_eq!(seen.into_iter().collect::<HashSet<_>>(), created);
+    }
+}
@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::WebhookRepository;
+use crate::db::sqlite_timestamp;
+use crate::models::WebhookDelivery;
+
+const DELIVERY_COLUMNS: &str = "id, idempotency_key, event, payload, attempts, created_at";
+
+#[derive(Clone)]
+pub struct SqliteWebhookRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteWebhookRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for SqliteWebhookRepository {
+    async fn create(
+        &self,
+        delivery: &WebhookDelivery,
+        since: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (id, idempotency_key, event, payload, created_at)
+            SELECT ?1, ?2, ?3, ?4, ?5
+            WHERE NOT EXISTS (
+                SELECT 1 FROM webhook_deliveries
+                WHERE idempotency_key = ?2 AND created_at > ?6
+            )
+            "#,
+        )
+        .bind(delivery.id)
+        .bind(&delivery.idempotency_key)
+        .bind(&delivery.event)
+        .bind(&delivery.payload)
+        .bind(sqlite_timestamp(delivery.created_at))
+        .bind(sqlite_timestamp(since))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_pending(&self) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {DELIVERY_COLUMNS}
+            FROM webhook_deliveries
+            WHERE delivered_at IS NULL AND failed_at IS NULL
+            ORDER BY created_at ASC
+            "#
+        );
+
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(&query)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(deliveries)
+    }
+
+    async fn record_attempt(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE webhook_deliveries SET attempts = attempts + 1 WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_delivered(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE webhook_deliveries SET delivered_at = ?2 WHERE id = ?1")
+            .bind(id)
+            .bind(sqlite_timestamp(Utc::now()))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE webhook_deliveries SET failed_at = ?2 WHERE id = ?1")
+            .bind(id)
+            .bind(sqlite_timestamp(Utc::now()))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,62 +1,616 @@
-use sqlx::PgPool;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder};
 use uuid::Uuid;
 
-use crate::models::User;
+use super::retry::retry_on_disconnect;
+use crate::models::{
+    Email, Role, SortOrder, UpdateProfileRequest, User, UserCursor, UserSortColumn,
+};
+
+const USER_COLUMNS: &str = "id, email, password_hash, role, token_version, name, avatar_url, \
+    pending_email, email_verified_at, version, deletion_scheduled_at, last_login_at, created_at, \
+    updated_at";
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create(&self, email: &Email, password_hash: &str) -> Result<User, sqlx::Error>;
+
+    async fn find_by_email(&self, email: &Email) -> Result<Option<User>, sqlx::Error>;
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, sqlx::Error>;
+
+    async fn list(
+        &self,
+        sort_by: UserSortColumn,
+        order: SortOrder,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>, sqlx::Error>;
+
+    /// Users ordered by `(created_at, id)`, starting after `cursor`
+    async fn list_after(
+        &self,
+        cursor: Option<UserCursor>,
+        limit: i64,
+    ) -> Result<Vec<User>, sqlx::Error>;
+
+    async fn count(&self) -> Result<i64, sqlx::Error>;
+
+    /// Returns the new token version, or `None` if the user doesn't exist
+    async fn increment_token_version(&self, id: Uuid) -> Result<Option<i32>, sqlx::Error>;
+
+    /// Record a successful login now. Returns the new timestamp, or `None` if
+    /// the user doesn't exist.
+    async fn touch_last_login(&self, id: Uuid) -> Result<Option<DateTime<Utc>>, sqlx::Error>;
+
+    /// Swap the stored hash of the unchanged password for one made with
+    /// current settings. Only applies while the stored hash is still
+    /// `current_hash`, so a concurrent password change wins. Returns whether
+    /// it applied.
+    async fn replace_password_hash(
+        &self,
+        id: Uuid,
+        current_hash: &str,
+        new_hash: &str,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Returns the updated user, or `None` if the user doesn't exist
+    async fn set_role(&self, id: Uuid, role: Role) -> Result<Option<User>, sqlx::Error>;
+
+    /// Update only the profile fields present in `changes` and bump the
+    /// version. Returns the updated user, or `None` if the user doesn't exist
+    /// or isn't at `changes.version`.
+    async fn update_profile(
+        &self,
+        id: Uuid,
+        changes: &UpdateProfileRequest,
+    ) -> Result<Option<User>, sqlx::Error>;
+
+    /// Hold `email` as the user's pending address until one of the tokens is
+    /// redeemed, replacing any earlier request. Returns the updated user, or
+    /// `None` if the user doesn't exist.
+    async fn set_pending_email(
+        &self,
+        id: Uuid,
+        email: &Email,
+        confirm_token_hash: &str,
+        cancel_token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<User>, sqlx::Error>;
+
+    /// Make the pending address current. Returns the updated user, or `None`
+    /// if no unexpired change matches the token.
+    async fn confirm_pending_email(
+        &self,
+        confirm_token_hash: &str,
+    ) -> Result<Option<User>, sqlx::Error>;
+
+    /// Record that the user verified `email`, if that is still their
+    /// address. An earlier verification time is kept. Returns the updated
+    /// user, or `None` if no user has this id and address.
+    async fn mark_email_verified(&self, id: Uuid, email: &str)
+        -> Result<Option<User>, sqlx::Error>;
+
+    /// Drop the pending address. Returns the updated user, or `None` if no
+    /// unexpired change matches the token.
+    async fn cancel_pending_email(
+        &self,
+        cancel_token_hash: &str,
+    ) -> Result<Option<User>, sqlx::Error>;
+    /// Hashes of passwords the user has replaced, newest first
+    async fn password_history(&self, id: Uuid, limit: i64) -> Result<Vec<String>, sqlx::Error>;
+
+    /// Replace the password and bump the token version, invalidating every
+    /// outstanding token. The old hash joins the history, which is pruned to
+    /// the newest `history_len` entries. Returns the updated user, or `None`
+    /// if the user doesn't exist.
+    async fn update_password(
+        &self,
+        id: Uuid,
+        password_hash: &str,
+        history_len: i64,
+    ) -> Result<Option<User>, sqlx::Error>;
+
+    /// Schedule the account for deletion at `at`, or cancel with `None`.
+    /// Returns the updated user, or `None` if the user doesn't exist.
+    async fn set_deletion_scheduled_at(
+        &self,
+        id: Uuid,
+        at: Option<DateTime<Utc>>,
+    ) -> Result<Option<User>, sqlx::Error>;
+
+    /// Delete the user outright. Returns whether it existed.
+    async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error>;
+
+    /// Delete every user whose scheduled deletion is at or before `now`.
+    /// Returns how many were deleted.
+    async fn purge_scheduled_deletions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+}
 
 #[derive(Clone)]
-pub struct UserRepository {
+pub struct PgUserRepository {
     pool: PgPool,
 }
 
-impl UserRepository {
+impl PgUserRepository {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+}
 
-    pub async fn create(&self, email: &str, password_hash: &str) -> Result<User, sqlx::Error> {
-        let user = sqlx::query_as::<_, User>(
+#[async_trait]
+impl UserRepository for PgUserRepository {
+    async fn create(&self, email: &Email, password_hash: &str) -> Result<User, sqlx::Error> {
+        let query = format!(
             r#"
             INSERT INTO users (email, password_hash)
             VALUES ($1, $2)
-            RETURNING id, email, password_hash, created_at, updated_at
-            "#,
-        )
-        .bind(email)
-        .bind(password_hash)
-        .fetch_one(&self.pool)
-        .await?;
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(email.as_str())
+            .bind(password_hash)
+            .fetch_one(&self.pool)
+            .await?;
 
         Ok(user)
     }
 
-    pub async fn find_by_email(&self, email: &str) -> Result<Option<User>, sqlx::Error> {
-        let user = sqlx::query_as::<_, User>(
+    async fn find_by_email(&self, email: &Email) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
             r#"
-            SELECT id, email, password_hash, created_at, updated_at
+            SELECT {USER_COLUMNS}
             FROM users
             WHERE email = $1
+            "#
+        );
+
+        let user = retry_on_disconnect(|| {
+            sqlx::query_as::<_, User>(&query)
+                .bind(email.as_str())
+                .fetch_optional(&self.pool)
+        })
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {USER_COLUMNS}
+            FROM users
+            WHERE id = $1
+            "#
+        );
+
+        let user = retry_on_disconnect(|| {
+            sqlx::query_as::<_, User>(&query)
+                .bind(id)
+                .fetch_optional(&self.pool)
+        })
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn list(
+        &self,
+        sort_by: UserSortColumn,
+        order: SortOrder,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        // Sort column and direction come from fixed enums, never from raw input
+        let query = format!(
+            r#"
+            SELECT {USER_COLUMNS}
+            FROM users
+            ORDER BY {column} {order}, id {order}
+            LIMIT $1 OFFSET $2
+            "#,
+            column = sort_by.as_sql(),
+            order = order.as_sql(),
+        );
+
+        let users = retry_on_disconnect(|| {
+            sqlx::query_as::<_, User>(&query)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&self.pool)
+        })
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn list_after(
+        &self,
+        cursor: Option<UserCursor>,
+        limit: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        // Separate statements so each plans as a plain seek on
+        // idx_users_created_at_id, which an `IS NULL OR` guard would defeat
+        let query = match cursor {
+            None => format!(
+                r#"
+                SELECT {USER_COLUMNS}
+                FROM users
+                ORDER BY created_at ASC, id ASC
+                LIMIT $1
+                "#
+            ),
+            Some(_) => format!(
+                r#"
+                SELECT {USER_COLUMNS}
+                FROM users
+                WHERE (created_at, id) > ($1, $2)
+                ORDER BY created_at ASC, id ASC
+                LIMIT $3
+                "#
+            ),
+        };
+
+        let users = retry_on_disconnect(|| {
+            let mut query = sqlx::query_as::<_, User>(&query);
+            if let Some(cursor) = cursor {
+                query = query.bind(cursor.created_at).bind(cursor.id);
+            }
+            query.bind(limit).fetch_all(&self.pool)
+        })
+        .await?;
+
+        Ok(users)
+    }
+
+    async fn count(&self) -> Result<i64, sqlx::Error> {
+        let count: i64 = retry_on_disconnect(|| {
+            sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&self.pool)
+        })
+        .await?;
+
+        Ok(count)
+    }
+
+    async fn increment_token_version(&self, id: Uuid) -> Result<Option<i32>, sqlx::Error> {
+        // Not retried: a lost acknowledgement would bump the version twice
+        let version: Option<i32> = sqlx::query_scalar(
+            r#"
+            UPDATE users
+            SET token_version = token_version + 1
+            WHERE id = $1
+            RETURNING token_version
             "#,
         )
-        .bind(email)
+        .bind(id)
         .fetch_optional(&self.pool)
         .await?;
 
+        Ok(version)
+    }
+
+    async fn touch_last_login(&self, id: Uuid) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        // Setting the timestamp again is harmless, so this is safe to retry
+        let last_login_at: Option<DateTime<Utc>> = retry_on_disconnect(|| {
+            sqlx::query_scalar(
+                r#"
+                UPDATE users
+                SET last_login_at = NOW()
+                WHERE id = $1
+                RETURNING last_login_at
+                "#,
+            )
+            .bind(id)
+            .fetch_optional(&self.pool)
+        })
+        .await?;
+
+        Ok(last_login_at)
+    }
+
+    async fn replace_password_hash(
+        &self,
+        id: Uuid,
+        current_hash: &str,
+        new_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        // Safe to retry: once applied, the condition no longer matches
+        let result = retry_on_disconnect(|| {
+            sqlx::query("UPDATE users SET password_hash = $3 WHERE id = $1 AND password_hash = $2")
+                .bind(id)
+                .bind(current_hash)
+                .bind(new_hash)
+                .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_role(&self, id: Uuid, role: Role) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET role = $2
+            WHERE id = $1
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(role)
+            .fetch_optional(&self.pool)
+            .await?;
+
         Ok(user)
     }
 
-    #[allow(dead_code)]
-    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<User>, sqlx::Error> {
-        let user = sqlx::query_as::<_, User>(
+    async fn update_profile(
+        &self,
+        id: Uuid,
+        changes: &UpdateProfileRequest,
+    ) -> Result<Option<User>, sqlx::Error> {
+        if changes.is_empty() {
+            return self.find_by_id(id).await;
+        }
+
+        // Column names are fixed here; only the values come from the request
+        let mut query = QueryBuilder::<Postgres>::new("UPDATE users SET ");
+        let mut fields = query.separated(", ");
+        fields.push("version = version + 1");
+        if let Some(name) = &changes.name {
+            fields.push("name = ").push_bind_unseparated(name);
+        }
+        if let Some(avatar_url) = &changes.avatar_url {
+            fields
+                .push("avatar_url = ")
+                .push_bind_unseparated(avatar_url);
+        }
+        query.push(" WHERE id = ").push_bind(id);
+        if let Some(version) = changes.version {
+            query.push(" AND version = ").push_bind(version);
+        }
+        query.push(format!(" RETURNING {USER_COLUMNS}"));
+
+        let user = query
+            .build_query_as::<User>()
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn set_pending_email(
+        &self,
+        id: Uuid,
+        email: &Email,
+        confirm_token_hash: &str,
+        cancel_token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
             r#"
-            SELECT id, email, password_hash, created_at, updated_at
-            FROM users
+            UPDATE users
+            SET pending_email = $2,
+                email_confirm_token_hash = $3,
+                email_cancel_token_hash = $4,
+                email_change_expires_at = $5
             WHERE id = $1
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(email.as_str())
+            .bind(confirm_token_hash)
+            .bind(cancel_token_hash)
+            .bind(expires_at)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn confirm_pending_email(
+        &self,
+        confirm_token_hash: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        // Fails with a unique violation if the address was taken meanwhile
+        let query = format!(
+            r#"
+            UPDATE users
+            SET email = pending_email,
+                pending_email = NULL,
+                email_verified_at = NOW(),
+                email_confirm_token_hash = NULL,
+                email_cancel_token_hash = NULL,
+                email_change_expires_at = NULL
+            WHERE email_confirm_token_hash = $1
+              AND email_change_expires_at > NOW()
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(confirm_token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn mark_email_verified(
+        &self,
+        id: Uuid,
+        email: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET email_verified_at = COALESCE(email_verified_at, NOW())
+            WHERE id = $1 AND email = $2
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = retry_on_disconnect(|| {
+            sqlx::query_as::<_, User>(&query)
+                .bind(id)
+                .bind(email)
+                .fetch_optional(&self.pool)
+        })
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn cancel_pending_email(
+        &self,
+        cancel_token_hash: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET pending_email = NULL,
+                email_confirm_token_hash = NULL,
+                email_cancel_token_hash = NULL,
+                email_change_expires_at = NULL
+            WHERE email_cancel_token_hash = $1
+              AND email_change_expires_at > NOW()
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(cancel_token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn password_history(&self, id: Uuid, limit: i64) -> Result<Vec<String>, sqlx::Error> {
+        let hashes = sqlx::query_scalar(
+            r#"
+            SELECT password_hash
+            FROM password_history
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(limit)
+        .fetch_all(&self.pool)
         .await?;
 
+        Ok(hashes)
+    }
+
+    async fn update_password(
+        &self,
+        id: Uuid,
+        password_hash: &str,
+        history_len: i64,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let old_hash: Option<String> =
+            sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let Some(old_hash) = old_hash else {
+            return Ok(None);
+        };
+
+        if history_len > 0 {
+            sqlx::query("INSERT INTO password_history (user_id, password_hash) VALUES ($1, $2)")
+                .bind(id)
+                .bind(&old_hash)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query(
+            r#"
+            DELETE FROM password_history
+            WHERE user_id = $1
+              AND id NOT IN (
+                SELECT id FROM password_history
+                WHERE user_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+              )
+            "#,
+        )
+        .bind(id)
+        .bind(history_len)
+        .execute(&mut *tx)
+        .await?;
+
+        let query = format!(
+            r#"
+            UPDATE users
+            SET password_hash = $2, token_version = token_version + 1
+            WHERE id = $1
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(password_hash)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    async fn set_deletion_scheduled_at(
+        &self,
+        id: Uuid,
+        at: Option<DateTime<Utc>>,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let query = format!(
+            r#"
+            UPDATE users
+            SET deletion_scheduled_at = $2
+            WHERE id = $1
+            RETURNING {USER_COLUMNS}
+            "#
+        );
+
+        let user = sqlx::query_as::<_, User>(&query)
+            .bind(id)
+            .bind(at)
+            .fetch_optional(&self.pool)
+            .await?;
+
         Ok(user)
     }
+
+    async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_scheduled_deletions(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        // Sessions and password history go with the user via ON DELETE CASCADE
+        let result = sqlx::query("DELETE FROM users WHERE deletion_scheduled_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
 }
@@ -0,0 +1,121 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::retry::retry_on_disconnect;
+use crate::models::WebhookDelivery;
+
+const DELIVERY_COLUMNS: &str = "id, idempotency_key, event, payload, attempts, created_at";
+
+#[async_trait]
+pub trait WebhookRepository: Send + Sync {
+    /// Record a pending delivery. Returns `false` and records nothing if a
+    /// delivery with the same idempotency key was created after `since`.
+    async fn create(
+        &self,
+        delivery: &WebhookDelivery,
+        since: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Deliveries neither delivered nor given up on, oldest first
+    async fn list_pending(&self) -> Result<Vec<WebhookDelivery>, sqlx::Error>;
+
+    async fn record_attempt(&self, id: Uuid) -> Result<(), sqlx::Error>;
+
+    async fn mark_delivered(&self, id: Uuid) -> Result<(), sqlx::Error>;
+
+    async fn mark_failed(&self, id: Uuid) -> Result<(), sqlx::Error>;
+}
+
+#[derive(Clone)]
+pub struct PgWebhookRepository {
+    pool: PgPool,
+}
+
+impl PgWebhookRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for PgWebhookRepository {
+    async fn create(
+        &self,
+        delivery: &WebhookDelivery,
+        since: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        // Not retried: a lost acknowledgement would look like a duplicate
+        let result = sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (id, idempotency_key, event, payload, created_at)
+            SELECT $1, $2, $3, $4, $5
+            WHERE NOT EXISTS (
+                SELECT 1 FROM webhook_deliveries
+                WHERE idempotency_key = $2 AND created_at > $6
+            )
+            "#,
+        )
+        .bind(delivery.id)
+        .bind(&delivery.idempotency_key)
+        .bind(&delivery.event)
+        .bind(&delivery.payload)
+        .bind(delivery.created_at)
+        .bind(since)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn list_pending(&self) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let query = format!(
+            r#"
+            SELECT {DELIVERY_COLUMNS}
+            FROM webhook_deliveries
+            WHERE delivered_at IS NULL AND failed_at IS NULL
+            ORDER BY created_at ASC
+            "#
+        );
+
+        let deliveries = retry_on_disconnect(|| {
+            sqlx::query_as::<_, WebhookDelivery>(&query).fetch_all(&self.pool)
+        })
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    async fn record_attempt(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        // Not retried: a lost acknowledgement would count the attempt twice
+        sqlx::query("UPDATE webhook_deliveries SET attempts = attempts + 1 WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_delivered(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        retry_on_disconnect(|| {
+            sqlx::query("UPDATE webhook_deliveries SET delivered_at = NOW() WHERE id = $1")
+                .bind(id)
+                .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        retry_on_disconnect(|| {
+            sqlx::query("UPDATE webhook_deliveries SET failed_at = NOW() WHERE id = $1")
+                .bind(id)
+                .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+}
@@ -1,83 +1,745 @@
 use axum::{
+    extract::DefaultBodyLimit,
     middleware,
-    routing::{get, post},
-    Router,
+    routing::{delete, get, post, put},
+    Extension, Router,
 };
-use sqlx::PgPool;
-use utoipa::OpenApi;
-use utoipa_swagger_ui::SwaggerUi;
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::request_id::PropagateRequestIdLayer;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+use utoipa_swagger_ui::{Config as SwaggerConfig, SwaggerUi};
 
-use crate::config::Config;
+use crate::config::{Config, JsonCase, TrailingSlash};
+use crate::db::Database;
 use crate::handlers;
-use crate::handlers::auth_handler::{__path_login, __path_register};
-use crate::handlers::health_handler::{__path_healthz, __path_ready};
-use crate::middleware::{rate_limit_middleware, RateLimitLayer};
-use crate::repositories::UserRepository;
-use crate::services::AuthService;
+use crate::handlers::admin_handler::{
+    __path_impersonate, __path_list_audit_events, __path_rate_limit_status, __path_revoke_sessions,
+    __path_set_api_key_rate_limit, __path_set_maintenance,
+};
+use crate::handlers::api_key_handler::{
+    __path_create_api_key, __path_list_api_keys, __path_revoke_api_key, __path_rotate_api_key,
+};
+use crate::handlers::auth_handler::{
+    __path_cancel_deletion, __path_cancel_email_change, __path_change_email,
+    __path_change_password, __path_confirm_email, __path_delete_me, __path_export_me,
+    __path_forgot_password, __path_introspect, __path_jwks, __path_login, __path_logout,
+    __path_refresh, __path_register, __path_resend_verification, __path_reset_password,
+    __path_token_claims, __path_verify_email, __path_verify_password,
+};
+use crate::handlers::debug_handler::__path_debug_config;
+use crate::handlers::health_handler::{
+    __path_dependencies, __path_healthz, __path_live, __path_metrics, __path_ready, __path_version,
+};
+use crate::handlers::session_handler::{__path_list_sessions, __path_revoke_session};
+use crate::handlers::user_handler::{__path_list_users, __path_me, __path_update_me};
+use crate::handlers::HealthState;
+use crate::lifecycle::Lifecycle;
+use crate::metrics::Metrics;
+use crate::middleware::{
+    admin_audit_middleware, auth_middleware, cache, camel_case_json, expose_error_detail,
+    maintenance_middleware, metrics_middleware, panic_response, pool_timeout_middleware,
+    pretty_json, rate_limit_middleware, request_timeout_middleware, require_admin,
+    slow_request_middleware, trailing_slash_middleware, user_concurrency_middleware,
+    user_rate_limit_middleware, with_cors, ApiKeyRateLimit, AuthGate, HtmlSignIn, MaintenanceMode,
+    PoolTimeoutPolicy, RateLimitLayer, UserConcurrencyLimit, UserRateLimit,
+};
+use crate::repositories::{SessionRepository, UserRepository};
+use crate::services::{
+    ApiKeyService, AuditService, AuthService, CaptchaVerifier, DatabaseChecker, EmailDomainPolicy,
+    HealthRegistry, HttpChecker, JwtKeys, LogMailer, Mailer, PasswordHashing, PostRegistrationHook,
+    Readiness, RedisChecker, RegistrationHooks, SiteverifyCaptcha, UserService, WebhookService,
+    WelcomeEmail,
+};
+use crate::tasks::TaskManager;
+
+/// How long clients and proxies may reuse a `/version` response
+const VERSION_CACHE_MAX_AGE_SECS: u64 = 60;
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
+        live,
         healthz,
+        dependencies,
         ready,
+        version,
+        metrics,
         register,
         login,
+        refresh,
+        logout,
+        confirm_email,
+        cancel_email_change,
+        introspect,
+        jwks,
+        list_users,
+        me,
+        update_me,
+        delete_me,
+        cancel_deletion,
+        export_me,
+        change_password,
+        verify_password,
+        token_claims,
+        forgot_password,
+        reset_password,
+        verify_email,
+        resend_verification,
+        change_email,
+        list_sessions,
+        revoke_session,
+        list_api_keys,
+        create_api_key,
+        rotate_api_key,
+        revoke_api_key,
+        revoke_sessions,
+        impersonate,
+        set_maintenance,
+        rate_limit_status,
+        set_api_key_rate_limit,
+        list_audit_events,
+        debug_config,
     ),
     components(
         schemas(
             crate::models::RegisterRequest,
             crate::models::LoginRequest,
             crate::models::LoginResponse,
-            crate::models::user::UserResponse,
+            crate::models::RefreshRequest,
+            crate::models::ChangePasswordRequest,
+            crate::models::VerifyPasswordRequest,
+            crate::models::TokenClaimsResponse,
+            crate::models::ForgotPasswordRequest,
+            crate::models::ResetPasswordRequest,
+            crate::models::VerifyEmailRequest,
+            crate::models::ResendVerificationRequest,
+            crate::models::ChangeEmailRequest,
+            crate::models::EmailChangeTokenRequest,
+            crate::models::IntrospectRequest,
+            crate::models::IntrospectResponse,
+            crate::models::Email,
+            crate::models::Role,
+            crate::models::UserResponse,
+            crate::models::ExportRecord,
+            crate::models::UpdateProfileRequest,
+            crate::models::UserListResponse,
+            crate::models::UserCursorPage,
+            crate::models::SessionResponse,
+            crate::models::ApiKeyResponse,
+            crate::models::CreateApiKeyRequest,
+            crate::models::NewApiKeyResponse,
+            crate::models::SetApiKeyRateLimitRequest,
+            crate::models::MaintenanceStatus,
+            crate::models::ImpersonationResponse,
+            crate::models::Actor,
+            crate::models::DependencyStatus,
+            crate::models::DependencyState,
+            crate::models::RateLimitStatus,
+            crate::models::GlobalRateLimitStatus,
+            crate::models::ConcurrencyStatus,
+            crate::models::UserInFlight,
+            crate::models::ThrottledUser,
+            crate::models::AuditEventResponse,
+            crate::models::AuditOutcome,
+            crate::models::AuditLogPage,
         )
     ),
+    modifiers(&SecurityAddon),
     tags(
         (name = "auth", description = "Authentication endpoints"),
-        (name = "health", description = "Health check endpoints")
+        (name = "health", description = "Health check endpoints"),
+        (name = "users", description = "User management endpoints"),
+        (name = "admin", description = "Administrative endpoints"),
+        (name = "debug", description = "Development-only diagnostics")
     )
 )]
 pub struct ApiDoc;
 
-pub fn create_routes(pool: PgPool, config: Config) -> Router {
-    // Initialize repositories
-    let user_repository = UserRepository::new(pool.clone());
+struct SecurityAddon;
 
-    // Initialize services
-    let auth_service = AuthService::new(
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer_auth",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .build(),
+                ),
+            );
+        }
+    }
+}
+
+pub fn webhook_service(
+    database: &Database,
+    config: &Config,
+    tasks: &TaskManager,
+) -> WebhookService {
+    WebhookService::new(
+        config.webhook_url.clone(),
+        config.webhook_secret.clone(),
+        database.webhook_repository(),
+        config.webhook_dedupe_window_secs,
+        tasks.clone(),
+    )
+}
+
+/// Shared by the HTTP server and the `create-user` command
+pub fn auth_service(database: &Database, config: &Config, tasks: &TaskManager) -> AuthService {
+    auth_service_with(
+        database.user_repository(),
+        database.session_repository(),
+        webhook_service(database, config, tasks),
+        Arc::new(LogMailer),
+        config,
+        tasks,
+    )
+}
+
+/// `auth_service` over the given repositories and mailer
+pub fn auth_service_with(
+    user_repository: Arc<dyn UserRepository>,
+    session_repository: Arc<dyn SessionRepository>,
+    webhook_service: WebhookService,
+    mailer: Arc<dyn Mailer>,
+    config: &Config,
+    tasks: &TaskManager,
+) -> AuthService {
+    let jwt_keys = JwtKeys::from_config(config).expect("Failed to load JWT keys");
+    let captcha = config.captcha.as_ref().map(|captcha| {
+        Arc::new(SiteverifyCaptcha::new(
+            captcha.verify_url.clone(),
+            captcha.secret.clone(),
+        )) as Arc<dyn CaptchaVerifier>
+    });
+    let registration_hooks = RegistrationHooks::new(
+        config
+            .registration_hooks
+            .iter()
+            .map(|name| match name.as_str() {
+                WelcomeEmail::NAME => {
+                    Arc::new(WelcomeEmail::new(mailer.clone(), config.app_url.clone()))
+                        as Arc<dyn PostRegistrationHook>
+                }
+                _ => unreachable!("REGISTRATION_HOOKS is checked against HOOK_NAMES"),
+            })
+            .collect(),
+    );
+
+    AuthService::new(
         user_repository,
-        config.jwt_secret.clone(),
+        session_repository,
+        jwt_keys,
         config.jwt_expiration_hours,
+        config.jwt_not_before_secs,
+        config.refresh_token_expiration_days,
+        config.session_idle_timeout_secs,
+        PasswordHashing {
+            algorithm: config.argon2_algorithm,
+            output_len: config.argon2_output_len,
+            salt_len: config.argon2_salt_len,
+        },
+        config.password_rehash_on_login,
+        config.login_response_include_user,
+        captcha,
+        webhook_service,
+        EmailDomainPolicy::new(
+            config.registration_allowed_domains.clone(),
+            config.registration_blocked_domains.clone(),
+        ),
+        config.max_email_length,
+        config.registration_enabled,
+        config.default_registration_role,
+        registration_hooks,
+        mailer,
+        config.app_url.clone(),
+        config.email_change_token_minutes,
+        config.impersonation_token_minutes,
+        config.password_reset_token_minutes,
+        config.email_verification_token_minutes,
+        config.verification_resends_per_hour,
+        config.password_history_depth,
+        config.account_deletion_grace_days,
+        config.token_binding_enabled,
+        config.max_sessions_per_user,
+        config.max_token_age_secs,
+        config.jwt_minimal_claims,
+        tasks.clone(),
+    )
+}
+
+/// `auth_service` is shared with the caller so background work such as the
+/// account purge sees the same JWT keys as the routes after a reload
+pub fn create_routes(
+    database: Database,
+    config: Config,
+    lifecycle: Lifecycle,
+    auth_service: AuthService,
+) -> Router {
+    // Initialize services
+    let user_service = UserService::new(
+        database.user_repository(),
+        config.default_page_size,
+        config.max_page_size,
+    );
+    let reloaded = auth_service.clone();
+    lifecycle.on_reload(move |config| match JwtKeys::from_config(config) {
+        Ok(keys) => {
+            reloaded.reload_jwt_keys(keys, Duration::from_secs(config.jwt_reload_overlap_secs))
+        }
+        Err(e) => tracing::error!("Keeping the current JWT keys: {}", e),
+    });
+    let api_key_service = ApiKeyService::new(
+        database.api_key_repository(),
+        database.user_repository(),
+        config.api_key_rotation_overlap_secs,
     );
+    let admin_audit = database.admin_audit_repository();
+    let audit_service = AuditService::new(
+        admin_audit.clone(),
+        config.default_page_size,
+        config.max_page_size,
+    );
+
+    let maintenance =
+        MaintenanceMode::new(config.maintenance_mode, config.maintenance_retry_after_secs);
 
-    // Initialize rate limiter
-    let rate_limit_layer = RateLimitLayer::new(config.rate_limit_rps, config.rate_limit_burst);
-    let limiter = rate_limit_layer.limiter();
+    // Dependencies shown by /healthz/dependencies
+    let mut dependencies =
+        HealthRegistry::new(Duration::from_secs(config.health_dependencies_cache_secs))
+            .register(DatabaseChecker::new(database.clone()));
+    if let Some(captcha) = &config.captcha {
+        dependencies =
+            dependencies.register(HttpChecker::new("captcha", captcha.verify_url.clone()));
+    }
+    if let Some(url) = &config.webhook_url {
+        dependencies = dependencies.register(HttpChecker::new("webhook", url.clone()));
+    }
+    if let Some(redis) = &config.redis {
+        let readiness = if redis.required {
+            Readiness::Required
+        } else {
+            Readiness::Optional
+        };
+        dependencies = dependencies.register_for_readiness(RedisChecker::new(redis), readiness);
+    }
 
     // Health check routes (no rate limiting)
+    let health_state = HealthState {
+        database,
+        dependencies,
+        readiness_query: config.readiness_query.as_str().into(),
+        check_timeout: Duration::from_millis(config.health_check_timeout_ms),
+        lifecycle,
+    };
     let health_routes = Router::new()
         .route("/healthz", get(handlers::healthz))
+        .route("/healthz/live", get(handlers::live))
+        .route("/healthz/dependencies", get(handlers::dependencies))
         .route("/ready", get(handlers::ready))
-        .with_state(pool.clone());
+        .route_layer(cache::no_store())
+        .with_state(health_state);
+
+    // Only changes on deploy, so a short shared cache is safe
+    let version_routes = Router::new()
+        .route("/version", get(handlers::version))
+        .route_layer(cache::public_max_age(VERSION_CACHE_MAX_AGE_SECS));
+
+    // What browsers get instead of a JSON 401 on protected routes
+    let html_sign_in = HtmlSignIn::new(config.unauthenticated_html, &config.login_url);
+
+    // Per-user in-flight cap; each use must sit inside `auth_middleware`
+    let concurrency = UserConcurrencyLimit::new(config.max_concurrent_per_user);
+    let concurrency_layer =
+        middleware::from_fn_with_state(concurrency.clone(), user_concurrency_middleware);
+
+    // Tight per-user budget so password verification can't be brute-forced
+    let verify_password_limit = UserRateLimit::per_minute(config.verify_password_per_minute);
+
+    // Exports read every table that holds user data, so keep them rare
+    let data_export_limit = UserRateLimit::per_hour(config.data_export_per_hour);
+
+    let rate_limit = config
+        .rate_limit_rps
+        .map(|rps| RateLimitLayer::new(rps, config.rate_limit_burst));
+
+    // Everything except health and admin goes dark in maintenance mode
+    let maintenance_layer =
+        middleware::from_fn_with_state(maintenance.clone(), maintenance_middleware);
 
     // Auth routes
-    let auth_routes = Router::new()
+    let mut auth_routes = Router::new()
         .route("/auth/register", post(handlers::register))
         .route("/auth/login", post(handlers::login))
+        .route(
+            "/auth/refresh",
+            post(handlers::refresh).delete(handlers::logout),
+        )
+        .route("/auth/email/confirm", post(handlers::confirm_email))
+        .route("/auth/email/cancel", post(handlers::cancel_email_change))
+        .route("/auth/password/forgot", post(handlers::forgot_password))
+        .route("/auth/password/reset", post(handlers::reset_password))
+        .route("/auth/email/verify", post(handlers::verify_email))
+        .route(
+            "/auth/resend-verification",
+            post(handlers::resend_verification),
+        )
+        .route_layer(maintenance_layer.clone())
+        .route_layer(cache::no_store())
+        .with_state(auth_service.clone());
+
+    if config.refresh_token_cookie {
+        auth_routes = auth_routes.layer(Extension(handlers::RefreshCookie::new(
+            config.refresh_token_expiration_days,
+            config.tls.is_some(),
+            config.forwarded_proto_trusted.clone(),
+        )));
+    }
+
+    // Public keys change rarely, so let verifiers and proxies cache them
+    let jwks_routes = Router::new()
+        .route("/.well-known/jwks.json", get(handlers::jwks))
+        .route_layer(maintenance_layer.clone())
+        .route_layer(cache::public_max_age(config.jwks_cache_max_age_secs))
+        .with_state(auth_service.clone());
+
+    // Current user's routes (require authentication)
+    let me_routes = Router::new()
+        .route("/users/me", get(handlers::me).patch(handlers::update_me))
+        .with_state(user_service.clone())
+        .merge(
+            Router::new()
+                .route("/users/me", delete(handlers::delete_me))
+                .route("/users/me/cancel-deletion", post(handlers::cancel_deletion))
+                .route("/users/me/password", put(handlers::change_password))
+                .route("/users/me/email", put(handlers::change_email))
+                .route("/users/me/sessions", get(handlers::list_sessions))
+                .route("/users/me/sessions/:id", delete(handlers::revoke_session))
+                .route("/auth/me", get(handlers::token_claims))
+                .with_state(auth_service.clone()),
+        )
+        .merge(
+            Router::new()
+                .route("/users/me/verify-password", post(handlers::verify_password))
+                .route_layer(middleware::from_fn_with_state(
+                    verify_password_limit,
+                    user_rate_limit_middleware,
+                ))
+                .with_state(auth_service.clone()),
+        )
+        .merge(
+            Router::new()
+                .route("/users/me/export", get(handlers::export_me))
+                .route_layer(middleware::from_fn_with_state(
+                    data_export_limit,
+                    user_rate_limit_middleware,
+                ))
+                .with_state(handlers::DataExportState {
+                    auth_service: auth_service.clone(),
+                    audit_service: audit_service.clone(),
+                }),
+        )
+        .merge(
+            Router::new()
+                .route(
+                    "/users/me/api-keys",
+                    get(handlers::list_api_keys).post(handlers::create_api_key),
+                )
+                .route("/users/me/api-keys/:id", delete(handlers::revoke_api_key))
+                .route(
+                    "/users/me/api-keys/:id/rotate",
+                    post(handlers::rotate_api_key),
+                )
+                .with_state(api_key_service.clone()),
+        )
+        .route_layer(concurrency_layer.clone())
+        .route_layer(middleware::from_fn_with_state(
+            AuthGate::new(
+                auth_service.clone(),
+                config.jwt_api_audience.clone(),
+                html_sign_in.clone(),
+            )
+            .with_api_keys(
+                api_key_service.clone(),
+                ApiKeyRateLimit::new(config.api_key_rate_limit_rps),
+            ),
+            auth_middleware,
+        ))
+        .route_layer(maintenance_layer)
+        .route_layer(cache::no_store());
+
+    // User routes (require admin)
+    let user_routes = Router::new()
+        .route("/users", get(handlers::list_users))
+        .with_state(user_service);
+
+    // Admin routes (require admin)
+    let admin_routes = Router::new()
+        .route(
+            "/admin/users/:id/revoke-sessions",
+            post(handlers::revoke_sessions),
+        )
+        .route("/admin/users/:id/impersonate", post(handlers::impersonate))
+        .route("/auth/introspect", post(handlers::introspect))
         .with_state(auth_service.clone());
 
+    let maintenance_routes = Router::new()
+        .route("/admin/maintenance", put(handlers::set_maintenance))
+        .with_state(maintenance);
+
+    let rate_limit_routes = Router::new()
+        .route("/admin/rate-limit/status", get(handlers::rate_limit_status))
+        .with_state(handlers::RateLimitState {
+            rate_limit: rate_limit.clone(),
+            concurrency,
+        });
+
+    let api_key_routes = Router::new()
+        .route(
+            "/admin/api-keys/:id/rate-limit",
+            put(handlers::set_api_key_rate_limit),
+        )
+        .with_state(api_key_service);
+
+    let audit_routes = Router::new()
+        .route("/admin/audit", get(handlers::list_audit_events))
+        .with_state(audit_service);
+
+    let admin_only = Router::new()
+        .merge(user_routes)
+        .merge(admin_routes)
+        .merge(maintenance_routes)
+        .merge(rate_limit_routes)
+        .merge(api_key_routes)
+        .merge(audit_routes)
+        .route_layer(concurrency_layer)
+        .route_layer(middleware::from_fn(require_admin))
+        .route_layer(middleware::from_fn_with_state(
+            admin_audit,
+            admin_audit_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            AuthGate::new(
+                auth_service.clone(),
+                config.jwt_admin_audience.clone(),
+                html_sign_in,
+            ),
+            auth_middleware,
+        ))
+        .route_layer(cache::no_store());
+
     // Combine routes
     let mut app = Router::new()
         .merge(health_routes)
+        .merge(version_routes)
         .merge(auth_routes)
-        .layer(middleware::from_fn(move |req, next| {
-            rate_limit_middleware(limiter.clone(), req, next)
+        .merge(me_routes)
+        .merge(admin_only);
+
+    if config.json_case == JsonCase::Camel {
+        app = app.layer(middleware::from_fn(camel_case_json));
+    }
+
+    if config.strict_json_bodies {
+        app = app.layer(Extension(handlers::StrictJson));
+    }
+
+    // JWK member names are fixed by RFC 7517, so JWKS skips the renaming
+    app = app.merge(jwks_routes);
+
+    let metrics = config.metrics_enabled.then(|| {
+        Metrics::new(
+            config.metrics_exemplars,
+            config.runtime_metrics_interval_secs.is_some(),
+        )
+    });
+    if let Some(metrics) = &metrics {
+        app = app.merge(
+            Router::new()
+                .route("/metrics", get(handlers::metrics))
+                .with_state(metrics.clone()),
+        );
+    }
+
+    if let Some(rate_limit) = rate_limit {
+        app = app.layer(middleware::from_fn(move |req, next| {
+            rate_limit_middleware(rate_limit.clone(), req, next)
         }));
+    } else {
+        tracing::warn!("Rate limiting disabled (set RATE_LIMIT_RPS to enable)");
+    }
 
-    // Add Swagger UI in development mode
+    // Wrapped rather than layered so paths are fixed up before routing. The
+    // development routes below are merged outside it: Swagger UI's index is
+    // `/api-docs/` and must keep its slash.
+    if config.trailing_slash != TrailingSlash::Strict {
+        app = Router::new()
+            .fallback_service(app)
+            .layer(middleware::from_fn_with_state(
+                config.trailing_slash,
+                trailing_slash_middleware,
+            ));
+    }
+
+    // Add Swagger UI, diagnostics and error details in development mode. In
+    // production none of these are installed.
     if !config.is_production() {
-        app =
-            app.merge(SwaggerUi::new("/api-docs").url("/api-docs/openapi.json", ApiDoc::openapi()));
+        let mut openapi =
+            serde_json::to_value(ApiDoc::openapi()).expect("OpenAPI document serializes to JSON");
+        if config.json_case == JsonCase::Camel {
+            handlers::docs_handler::camel_case_schemas(&mut openapi);
+        }
+        app = app.merge(
+            SwaggerUi::new("/api-docs").config(SwaggerConfig::from("/api-docs/openapi.json")),
+        );
+        app = app.merge(
+            Router::new()
+                .route("/api-docs/openapi.json", get(handlers::openapi_json))
+                .route("/api-docs/openapi.yaml", get(handlers::openapi_yaml))
+                .with_state(handlers::OpenApiDocs::new(&openapi)),
+        );
+        app = app.merge(
+            Router::new()
+                .route("/debug/config", get(handlers::debug_config))
+                .route_layer(cache::no_store())
+                .with_state(config.clone()),
+        );
+        app = app.layer(middleware::from_fn(expose_error_detail));
+        if config.json_pretty {
+            app = app.layer(middleware::from_fn(pretty_json));
+        }
+    }
+
+    let mut pool_timeout = PoolTimeoutPolicy::new(
+        config.pool_timeout_retry_after_secs,
+        &config.pool_timeout_message,
+    );
+    if let Some(metrics) = &metrics {
+        pool_timeout = pool_timeout.with_metrics(metrics.clone());
     }
 
-    app
+    let mut app = app
+        .layer(middleware::from_fn_with_state(
+            pool_timeout,
+            pool_timeout_middleware,
+        ))
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(middleware::from_fn_with_state(
+            Duration::from_secs(config.request_timeout_secs),
+            request_timeout_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            Duration::from_millis(config.slow_request_ms),
+            slow_request_middleware,
+        ))
+        .layer(CatchPanicLayer::custom(panic_response));
+    // Outside the panic handler, so requests that panicked are counted too
+    if let Some(metrics) = metrics {
+        app = app.layer(middleware::from_fn_with_state(metrics, metrics_middleware));
+    }
+    let app = app.layer(PropagateRequestIdLayer::x_request_id());
+
+    with_cors(app, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lifecycle::Lifecycle;
+    use crate::test_support::{self, request, send, PASSWORD};
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn version_is_briefly_cacheable() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        let response = send(&app, request(Method::GET, "/version", None, None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=60"
+        );
+    }
+
+    #[tokio::test]
+    async fn login_is_never_cached() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+        test_support::sign_up(&app, "cache@example.com", PASSWORD).await;
+
+        let credentials = json!({ "email": "cache@example.com", "password": PASSWORD });
+        let response = send(
+            &app,
+            request(Method::POST, "/auth/login", None, Some(credentials)),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+    }
+
+    async fn throttled(app: &axum::Router, requests: usize) -> usize {
+        let mut throttled = 0;
+        for _ in 0..requests {
+            let response = send(app, request(Method::GET, "/healthz/live", None, None)).await;
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                throttled += 1;
+            }
+        }
+        throttled
+    }
+
+    #[tokio::test]
+    async fn development_default_does_not_throttle() {
+        let database = test_support::database().await;
+        let app = test_support::app(&database, test_support::config(&[]));
+
+        assert_eq!(throttled(&app, 100).await, 0);
+    }
+
+    #[tokio::test]
+    async fn production_default_throttles() {
+        let database = test_support::database().await;
+        let config = test_support::config(&[("ENV", "production")]);
+        let app = test_support::app(&database, config);
+
+        assert!(throttled(&app, 100).await > 0);
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_earlier_tokens_working() {
+        let database = test_support::database().await;
+        let lifecycle = Lifecycle::new();
+        let app = test_support::app_with_lifecycle(
+            &database,
+            test_support::config(&[]),
+            lifecycle.clone(),
+        );
+        let before = test_support::sign_up(&app, "user@example.com", PASSWORD).await;
+
+        lifecycle.reload(&test_support::config(&[(
+            "JWT_SECRET",
+            "rotated-secret-that-is-long-enough-too",
+        )]));
+
+        assert_eq!(
+            test_support::me(&app, &before).await.status(),
+            StatusCode::OK
+        );
+        let after = test_support::log_in(&app, "user@example.com", PASSWORD).await;
+        assert_eq!(
+            test_support::me(&app, &after).await.status(),
+            StatusCode::OK
+        );
+    }
 }
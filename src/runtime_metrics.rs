@@ -0,0 +1,23 @@
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+
+/// Log a sample of the Tokio runtime's scheduler state every `interval`
+/// until shutdown. A steadily growing `global_queue_depth` means tasks are
+/// spawned faster than the workers can poll them.
+pub async fn run(interval: Duration, shutdown: CancellationToken) {
+    let metrics = Handle::current().metrics();
+    loop {
+        tracing::info!(
+            runtime_workers = metrics.num_workers(),
+            runtime_alive_tasks = metrics.num_alive_tasks(),
+            runtime_global_queue_depth = metrics.global_queue_depth(),
+            "Runtime metrics"
+        );
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
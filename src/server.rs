@@ -0,0 +1,207 @@
+use axum::Router;
+use axum_server::{accept::DefaultAcceptor, tls_rustls::RustlsConfig, Handle, Server};
+use hyper_util::rt::TokioTimer;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::connection_limit::ConnectionLimit;
+use crate::tls::{self, LogHandshake};
+
+/// Serve `app` until `shutdown` resolves, over TLS when it's configured
+pub async fn serve(
+    app: Router,
+    config: &Config,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) {
+    let addr: SocketAddr = format!("{}:{}", config.server_host, config.server_port)
+        .parse()
+        .expect("Invalid server address");
+
+    // axum-server drives graceful shutdown through a handle
+    let handle = Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown.await;
+            handle.graceful_shutdown(None);
+        }
+    });
+
+    serve_with_handle(app, config, addr, handle).await;
+}
+
+/// `serve` on `addr`, controlled through `handle`
+pub async fn serve_with_handle(app: Router, config: &Config, addr: SocketAddr, handle: Handle) {
+    match &config.tls {
+        Some(tls) => {
+            let server_config = tls::server_config(tls).await.unwrap_or_else(|e| {
+                panic!(
+                    "Failed to load TLS certificate ({}) or key ({}): {}",
+                    tls.cert_path, tls.key_path, e
+                )
+            });
+            let rustls_config = RustlsConfig::from_config(Arc::new(server_config));
+
+            // Counted before the handshake, so refused peers cost no TLS work
+            let mut server = axum_server::bind_rustls(addr, rustls_config)
+                .map(|tls| LogHandshake::new(tls.acceptor(connection_limit(config))));
+            configure(&mut server, config);
+
+            tracing::info!("Server listening on {} (TLS)", addr);
+            tracing::info!("API Documentation: https://{}/api-docs", addr);
+
+            server
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .expect("Failed to start server");
+        }
+        None => {
+            let mut server = axum_server::bind(addr).acceptor(connection_limit(config));
+            configure(&mut server, config);
+
+            tracing::info!("Server listening on {}", addr);
+            tracing::info!("API Documentation: http://{}/api-docs", addr);
+
+            server
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .expect("Failed to start server");
+        }
+    }
+}
+
+fn connection_limit(config: &Config) -> ConnectionLimit<DefaultAcceptor> {
+    ConnectionLimit::new(DefaultAcceptor, config.max_connections_per_ip)
+}
+
+// Each connection speaks HTTP/1.1 or HTTP/2, whichever the client opens
+// with: `h2` is offered over TLS ALPN, and plaintext HTTP/2 (h2c) is accepted
+// with prior knowledge
+fn configure<A>(server: &mut Server<A>, config: &Config) {
+    let builder = server.http_builder();
+
+    // Oversized or too many headers are answered with 431 by hyper. Its
+    // timeouts need a timer, without which none of them fire.
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.http_keep_alive)
+        .header_read_timeout(
+            config
+                .http_header_read_timeout_secs
+                .map(Duration::from_secs),
+        )
+        .max_buf_size(config.max_header_bytes)
+        .max_headers(config.max_header_count);
+
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(
+            config
+                .http2_keep_alive_interval_secs
+                .map(Duration::from_secs),
+        )
+        .keep_alive_timeout(Duration::from_secs(config.http2_keep_alive_timeout_secs))
+        .max_header_list_size(config.max_header_bytes as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Serve a stub app on an ephemeral port, returning its address
+    async fn start(config: Config) -> (SocketAddr, Handle) {
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        let handle = Handle::new();
+        tokio::spawn({
+            let handle = handle.clone();
+            async move {
+                let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+                serve_with_handle(app, &config, addr, handle).await;
+            }
+        });
+        let addr = handle.listening().await.expect("server listens");
+        (addr, handle)
+    }
+
+    /// Status line of the response to a raw HTTP/1.1 request, empty if the
+    /// server closed the connection without answering
+    async fn status_line(addr: SocketAddr, headers: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+            headers
+        );
+        let _ = stream.write_all(request.as_bytes()).await;
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+        let response = String::from_utf8_lossy(&response);
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn oversized_header_is_431() {
+        let config = test_support::config(&[("MAX_HEADER_BYTES", "8192")]);
+        let (addr, _handle) = start(config).await;
+
+        let ok = status_line(addr, "X-Small: value\r\n").await;
+        assert_eq!(ok, "HTTP/1.1 200 OK");
+
+        let oversized = format!("X-Large: {}\r\n", "a".repeat(16 * 1024));
+        let status = status_line(addr, &oversized).await;
+        assert_eq!(status, "HTTP/1.1 431 Request Header Fields Too Large");
+    }
+
+    #[tokio::test]
+    async fn too_many_headers_is_431() {
+        let config = test_support::config(&[("MAX_HEADER_COUNT", "10")]);
+        let (addr, _handle) = start(config).await;
+
+        let headers: String = (0..20)
+            .map(|i| format!("X-Header-{}: {}\r\n", i, i))
+            .collect();
+        let status = status_line(addr, &headers).await;
+        assert_eq!(status, "HTTP/1.1 431 Request Header Fields Too Large");
+    }
+
+    #[tokio::test]
+    async fn connections_over_the_per_ip_limit_are_refused() {
+        let config = test_support::config(&[("MAX_CONNECTIONS_PER_IP", "1")]);
+        let (addr, _handle) = start(config).await;
+
+        // An idle connection holds the only slot
+        let held = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(status_line(addr, "").await, "");
+
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(status_line(addr, "").await, "HTTP/1.1 200 OK");
+    }
+
+    #[tokio::test]
+    async fn plaintext_connections_negotiate_http2_with_prior_knowledge() {
+        let (addr, _handle) = start(test_support::config(&[])).await;
+        let url = format!("http://{}/", addr);
+
+        let client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_11);
+    }
+}
@@ -0,0 +1,171 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::models::{ApiKey, ApiKeyResponse, Claims, CreateApiKeyRequest, NewApiKeyResponse};
+use crate::repositories::{ApiKeyRepository, UserRepository};
+
+/// Marks a string as one of our keys, for secret scanners and for humans
+const KEY_PREFIX: &str = "tsk_";
+
+/// Characters of the key kept in the clear to tell keys apart
+const SHOWN_PREFIX_LEN: usize = KEY_PREFIX.len() + 6;
+
+#[derive(Error, Debug)]
+pub enum ApiKeyError {
+    #[error("API key not found")]
+    NotFound,
+    #[error("Invalid API key")]
+    InvalidKey,
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Long-lived keys a user issues to scripts, sent as `X-API-Key` instead of
+/// a bearer token. Only a hash of each key is stored.
+#[derive(Clone)]
+pub struct ApiKeyService {
+    api_keys: Arc<dyn ApiKeyRepository>,
+    user_repository: Arc<dyn UserRepository>,
+    rotation_overlap: Duration,
+}
+
+impl ApiKeyService {
+    pub fn new(
+        api_keys: Arc<dyn ApiKeyRepository>,
+        user_repository: Arc<dyn UserRepository>,
+        rotation_overlap_secs: i64,
+    ) -> Self {
+        Self {
+            api_keys,
+            user_repository,
+            rotation_overlap: Duration::seconds(rotation_overlap_secs),
+        }
+    }
+
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        request: CreateApiKeyRequest,
+    ) -> Result<NewApiKeyResponse, ApiKeyError> {
+        let key = generate_key();
+        let api_key = self
+            .api_keys
+            .create(user_id, &request.name, shown_prefix(&key), &hash_key(&key))
+            .await?;
+
+        Ok(NewApiKeyResponse {
+            key,
+            api_key: api_key.into(),
+        })
+    }
+
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<ApiKeyResponse>, ApiKeyError> {
+        let keys = self.api_keys.list_for_user(user_id).await?;
+        Ok(keys.into_iter().map(Into::into).collect())
+    }
+
+    /// Issue a replacement for a key. The old key keeps working for
+    /// `API_KEY_ROTATION_OVERLAP_SECS`, so clients can switch over without
+    /// downtime.
+    pub async fn rotate(&self, user_id: Uuid, id: Uuid) -> Result<NewApiKeyResponse, ApiKeyError> {
+        let key = generate_key();
+        let api_key = self
+            .api_keys
+            .rotate(
+                id,
+                user_id,
+                shown_prefix(&key),
+                &hash_key(&key),
+                Utc::now() + self.rotation_overlap,
+            )
+            .await?
+            .ok_or(ApiKeyError::NotFound)?;
+
+        Ok(NewApiKeyResponse {
+            key,
+            api_key: api_key.into(),
+        })
+    }
+
+    /// Delete a key, and with it any key it replaced that is still in its
+    /// overlap
+    pub async fn revoke(&self, user_id: Uuid, id: Uuid) -> Result<(), ApiKeyError> {
+        if !self.api_keys.delete(id, user_id).await? {
+            return Err(ApiKeyError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Give a key its own quota, or return it to the default tier with
+    /// `None`
+    pub async fn set_rate_limit(
+        &self,
+        id: Uuid,
+        rate_limit_rps: Option<u32>,
+    ) -> Result<ApiKeyResponse, ApiKeyError> {
+        // Validated to fit; the column is a signed INTEGER
+        let rate_limit_rps = rate_limit_rps.map(|rps| rps.min(i32::MAX as u32) as i32);
+        let api_key = self
+            .api_keys
+            .set_rate_limit(id, rate_limit_rps)
+            .await?
+            .ok_or(ApiKeyError::NotFound)?;
+        Ok(api_key.into())
+    }
+
+    /// The record for `key`, and claims for its owner as if they had
+    /// presented a token issued just now
+    pub async fn authenticate(&self, key: &str) -> Result<(ApiKey, Claims), ApiKeyError> {
+        let now = Utc::now();
+        let api_key = self
+            .api_keys
+            .find_by_hash(&hash_key(key), now)
+            .await?
+            .ok_or(ApiKeyError::InvalidKey)?;
+
+        let user = self
+            .user_repository
+            .find_by_id(api_key.user_id)
+            .await?
+            .ok_or(ApiKeyError::InvalidKey)?;
+
+        // Signed out everywhere until the deletion is cancelled
+        if user.deletion_scheduled_at.is_some() {
+            return Err(ApiKeyError::InvalidKey);
+        }
+
+        let claims = Claims {
+            sub: user.id.to_string(),
+            email: user.email,
+            role: user.role,
+            token_version: user.token_version,
+            exp: now.timestamp(),
+            iat: now.timestamp(),
+            nbf: now.timestamp(),
+            aud: Vec::new(),
+            act: None,
+            cnf: None,
+        };
+        Ok((api_key, claims))
+    }
+}
+
+fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", KEY_PREFIX, URL_SAFE_NO_PAD.encode(bytes))
+}
+
+fn shown_prefix(key: &str) -> &str {
+    &key[..SHOWN_PREFIX_LEN]
+}
+
+// Keys carry 256 random bits, so a fast hash is enough
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
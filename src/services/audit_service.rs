@@ -0,0 +1,132 @@
+use futures_util::{stream, Stream, TryStreamExt};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::models::{
+    AdminAuditFilter, AuditEventResponse, AuditLogPage, AuditOutcome, ListAuditQuery, SortOrder,
+};
+use crate::repositories::AdminAuditRepository;
+use crate::services::auth_service::EXPORT_BATCH_SIZE;
+
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("Invalid outcome: {0}")]
+    InvalidOutcome(String),
+    #[error("Invalid sort order: {0}")]
+    InvalidSortOrder(String),
+    #[error("Time range ends before it starts")]
+    InvalidTimeRange,
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Read side of the admin audit log written by `admin_audit_middleware`
+#[derive(Clone)]
+pub struct AuditService {
+    audit_repository: Arc<dyn AdminAuditRepository>,
+    default_page_size: u32,
+    max_page_size: u32,
+}
+
+impl AuditService {
+    pub fn new(
+        audit_repository: Arc<dyn AdminAuditRepository>,
+        default_page_size: u32,
+        max_page_size: u32,
+    ) -> Self {
+        Self {
+            audit_repository,
+            default_page_size,
+            max_page_size,
+        }
+    }
+
+    pub async fn list(&self, query: ListAuditQuery) -> Result<AuditLogPage, AuditError> {
+        let outcome = match query.outcome.as_deref() {
+            Some(value) => Some(
+                AuditOutcome::parse(value)
+                    .ok_or_else(|| AuditError::InvalidOutcome(value.to_string()))?,
+            ),
+            None => None,
+        };
+
+        let order = match query.order.as_deref() {
+            Some(value) => SortOrder::parse(value)
+                .ok_or_else(|| AuditError::InvalidSortOrder(value.to_string()))?,
+            None => SortOrder::Desc,
+        };
+
+        if let (Some(from), Some(to)) = (query.from, query.to) {
+            if to < from {
+                return Err(AuditError::InvalidTimeRange);
+            }
+        }
+
+        let filter = AdminAuditFilter {
+            actor_id: query.actor_id,
+            involving: None,
+            action: query.action,
+            outcome,
+            from: query.from,
+            to: query.to,
+        };
+
+        let page = query.page.unwrap_or(1).max(1);
+        let per_page = query
+            .per_page
+            .unwrap_or(self.default_page_size)
+            .clamp(1, self.max_page_size.max(1));
+        let offset = i64::from(page - 1) * i64::from(per_page);
+
+        let entries = self
+            .audit_repository
+            .query(&filter, order, i64::from(per_page), offset)
+            .await?;
+        let total = self.audit_repository.count(&filter).await?;
+
+        Ok(AuditLogPage {
+            events: entries.into_iter().map(Into::into).collect(),
+            page,
+            per_page,
+            total,
+        })
+    }
+
+    /// Entries where `user_id` is the admin or the target, oldest first, for
+    /// data exports. Read `EXPORT_BATCH_SIZE` at a time as the stream is
+    /// consumed; entries logged meanwhile only append to the order.
+    pub fn export_for_user(
+        &self,
+        user_id: Uuid,
+    ) -> impl Stream<Item = Result<AuditEventResponse, AuditError>> + Send + 'static {
+        let filter = AdminAuditFilter {
+            involving: Some(user_id),
+            ..AdminAuditFilter::default()
+        };
+        let audit_repository = self.audit_repository.clone();
+
+        // `None` once the last batch has been read
+        stream::try_unfold(Some(0), move |offset: Option<i64>| {
+            let audit_repository = audit_repository.clone();
+            let filter = filter.clone();
+            async move {
+                let Some(offset) = offset else {
+                    return Ok::<_, AuditError>(None);
+                };
+                let batch = audit_repository
+                    .query(&filter, SortOrder::Asc, EXPORT_BATCH_SIZE, offset)
+                    .await?;
+                if batch.is_empty() {
+                    return Ok(None);
+                }
+
+                let next =
+                    (batch.len() as i64 == EXPORT_BATCH_SIZE).then_some(offset + EXPORT_BATCH_SIZE);
+                let events = batch.into_iter().map(|entry| Ok(entry.into()));
+                Ok(Some((stream::iter(events), next)))
+            }
+        })
+        .try_flatten()
+    }
+}
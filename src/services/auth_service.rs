@@ -1,20 +1,75 @@
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    password_hash::{
+        rand_core::OsRng, Output, PasswordHash, PasswordHasher, PasswordVerifier, Salt, SaltString,
+    },
+    Algorithm, Argon2, Params, Version,
 };
-use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use jsonwebtoken::{decode, decode_header, encode, jwk::JwkSet, Header};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::num::NonZeroU32;
+use std::sync::{Arc, RwLock};
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
-use crate::models::{Claims, LoginRequest, LoginResponse, RegisterRequest, User};
-use crate::repositories::UserRepository;
+use crate::models::{
+    Actor, Claims, ClientInfo, Confirmation, Email, EmailVerificationClaims, ExportRecord,
+    ImpersonationResponse, IntrospectResponse, LoginRequest, LoginResponse, MinimalClaims,
+    PasswordResetClaims, RefreshRequest, RegisterRequest, Role, Session, SessionResponse, User,
+    UserResponse, EMAIL_VERIFICATION_PURPOSE, PASSWORD_RESET_PURPOSE,
+};
+use crate::repositories::{SessionRepository, UserRepository};
+use crate::services::{
+    bcrypt, CaptchaVerifier, EmailMessage, JwtKeys, Mailer, RegistrationHooks, WebhookService,
+};
+use crate::tasks::TaskManager;
 
 #[derive(Error, Debug)]
 pub enum AuthError {
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Password was used recently")]
+    PasswordReused,
     #[error("User already exists")]
     UserAlreadyExists,
+    #[error("User not found")]
+    UserNotFound,
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("Token has been revoked")]
+    TokenRevoked,
+    #[error("Token is older than the maximum accepted age")]
+    TokenTooOld,
+    #[error("Token signed with unknown key id: {0}")]
+    UnknownKeyId(String),
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("Session expired after inactivity")]
+    SessionExpired,
+    #[error("Token is bound to a different client")]
+    TokenBindingMismatch,
+    #[error("Impersonation tokens cannot start another impersonation")]
+    NestedImpersonation,
+    #[error("Registration is disabled")]
+    RegistrationDisabled,
+    #[error("Email is longer than {0} characters")]
+    EmailTooLong(usize),
+    #[error("Email domain not allowed: {0}")]
+    EmailDomainNotAllowed(String),
+    #[error("CAPTCHA verification failed")]
+    CaptchaFailed,
+    #[error("CAPTCHA provider unavailable: {0}")]
+    CaptchaUnavailable(reqwest::Error),
+    #[error("Email delivery failed: {0}")]
+    MailUnavailable(anyhow::Error),
+    #[error("{0:#}")]
+    RegistrationHookFailed(anyhow::Error),
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
     #[error("Password hashing error")]
@@ -23,29 +78,170 @@ pub enum AuthError {
     JwtError(#[from] jsonwebtoken::errors::Error),
 }
 
+/// Rows read per query while streaming a data export
+pub const EXPORT_BATCH_SIZE: i64 = 100;
+
+/// Hash lengths, in bytes, that a PHC string can carry
+pub const ARGON2_OUTPUT_LEN_RANGE: std::ops::RangeInclusive<usize> =
+    Output::MIN_LENGTH..=Output::MAX_LENGTH;
+// The salt is stored as unpadded base64 of at most `Salt::MAX_LENGTH` chars
+pub const ARGON2_SALT_LEN_RANGE: std::ops::RangeInclusive<usize> =
+    argon2::MIN_SALT_LEN..=Salt::MAX_LENGTH / 4 * 3;
+
+/// How new password hashes are made. Stored hashes made differently still
+/// verify, and are re-hashed on login when that's enabled.
+#[derive(Clone, Copy, Debug)]
+pub struct PasswordHashing {
+    pub algorithm: Algorithm,
+    pub output_len: usize,
+    pub salt_len: usize,
+}
+
 #[derive(Clone)]
 pub struct AuthService {
-    user_repository: UserRepository,
-    jwt_secret: String,
+    user_repository: Arc<dyn UserRepository>,
+    session_repository: Arc<dyn SessionRepository>,
+    // Swapped whole by `reload_jwt_keys`
+    jwt_keys: Arc<RwLock<Arc<JwtKeys>>>,
     jwt_expiration_hours: i64,
+    jwt_not_before_secs: i64,
+    refresh_token_expiration_days: i64,
+    /// Sessions unused for longer than this can't be refreshed; `None` only
+    /// enforces `refresh_token_expiration_days`
+    session_idle_timeout_secs: Option<i64>,
+    password_hashing: PasswordHashing,
+    /// Re-hash passwords stored under older settings on successful login
+    rehash_on_login: bool,
+    // Verified against when a login names an unknown email
+    dummy_password_hash: String,
+    login_response_include_user: bool,
+    /// `None` when CAPTCHA checks are disabled
+    captcha: Option<Arc<dyn CaptchaVerifier>>,
+    webhook_service: WebhookService,
+    registration_domains: EmailDomainPolicy,
+    max_email_length: usize,
+    registration_enabled: bool,
+    default_registration_role: Role,
+    registration_hooks: RegistrationHooks,
+    mailer: Arc<dyn Mailer>,
+    // Base URL for the confirm and cancel links in email-change messages
+    app_url: String,
+    email_change_token_minutes: i64,
+    impersonation_token_minutes: i64,
+    password_reset_token_minutes: i64,
+    email_verification_token_minutes: i64,
+    // Verification emails re-sent per account, shared by both ways of asking
+    verification_resends: Arc<DefaultKeyedRateLimiter<Uuid>>,
+    // How many recent passwords, the current one included, can't be reused
+    password_history_depth: i64,
+    account_deletion_grace_days: i64,
+    // Bind access tokens to the client fingerprint they were issued to
+    token_binding_enabled: bool,
+    /// `None` leaves the number of sessions per user uncapped
+    max_sessions_per_user: Option<i64>,
+    /// Oldest `iat` accepted, whatever `exp` says; `None` relies on `exp` alone
+    max_token_age_secs: Option<i64>,
+    /// Sign access tokens without email and role; both are read from the
+    /// user record on every request instead
+    minimal_claims: bool,
+    tasks: TaskManager,
 }
 
 impl AuthService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        user_repository: UserRepository,
-        jwt_secret: String,
+        user_repository: Arc<dyn UserRepository>,
+        session_repository: Arc<dyn SessionRepository>,
+        jwt_keys: JwtKeys,
         jwt_expiration_hours: i64,
+        jwt_not_before_secs: i64,
+        refresh_token_expiration_days: i64,
+        session_idle_timeout_secs: Option<i64>,
+        password_hashing: PasswordHashing,
+        rehash_on_login: bool,
+        login_response_include_user: bool,
+        captcha: Option<Arc<dyn CaptchaVerifier>>,
+        webhook_service: WebhookService,
+        registration_domains: EmailDomainPolicy,
+        max_email_length: usize,
+        registration_enabled: bool,
+        default_registration_role: Role,
+        registration_hooks: RegistrationHooks,
+        mailer: Arc<dyn Mailer>,
+        app_url: String,
+        email_change_token_minutes: i64,
+        impersonation_token_minutes: i64,
+        password_reset_token_minutes: i64,
+        email_verification_token_minutes: i64,
+        verification_resends_per_hour: u32,
+        password_history_depth: i64,
+        account_deletion_grace_days: i64,
+        token_binding_enabled: bool,
+        max_sessions_per_user: Option<i64>,
+        max_token_age_secs: Option<i64>,
+        minimal_claims: bool,
+        tasks: TaskManager,
     ) -> Self {
         Self {
             user_repository,
-            jwt_secret,
+            session_repository,
+            jwt_keys: Arc::new(RwLock::new(Arc::new(jwt_keys))),
             jwt_expiration_hours,
+            jwt_not_before_secs,
+            refresh_token_expiration_days,
+            session_idle_timeout_secs,
+            password_hashing,
+            rehash_on_login,
+            dummy_password_hash: hash_password(&password_hashing, &generate_opaque_token())
+                .expect("Failed to hash dummy password"),
+            login_response_include_user,
+            captcha,
+            webhook_service,
+            registration_domains,
+            max_email_length,
+            registration_enabled,
+            default_registration_role,
+            registration_hooks,
+            mailer,
+            app_url,
+            email_change_token_minutes,
+            impersonation_token_minutes,
+            password_reset_token_minutes,
+            email_verification_token_minutes,
+            verification_resends: Arc::new(RateLimiter::keyed(Quota::per_hour(
+                NonZeroU32::new(verification_resends_per_hour)
+                    .expect("VERIFICATION_RESENDS_PER_HOUR is checked to be positive"),
+            ))),
+            password_history_depth,
+            account_deletion_grace_days,
+            token_binding_enabled,
+            max_sessions_per_user,
+            max_token_age_secs,
+            minimal_claims,
+            tasks,
         }
     }
 
-    pub async fn register(&self, request: RegisterRequest) -> Result<LoginResponse, AuthError> {
+    pub async fn register(
+        &self,
+        request: RegisterRequest,
+        client: ClientInfo,
+    ) -> Result<LoginResponse, AuthError> {
+        if !self.registration_enabled {
+            return Err(AuthError::RegistrationDisabled);
+        }
+        self.check_email_length(&request.email)?;
+        self.check_email_domain(&request.email)?;
+        self.verify_captcha(request.captcha_token.as_deref(), &client)
+            .await?;
+
         // Check if user already exists
-        if let Some(_) = self.user_repository.find_by_email(&request.email).await? {
+        if self
+            .user_repository
+            .find_by_email(&request.email)
+            .await?
+            .is_some()
+        {
             return Err(AuthError::UserAlreadyExists);
         }
 
@@ -54,88 +250,2097 @@ impl AuthService {
 
         // Create user
         let user = self
-            .user_repository
-            .create(&request.email, &password_hash)
+            .insert_user(
+                &request.email,
+                &password_hash,
+                self.default_registration_role,
+            )
             .await?;
 
+        // A critical hook failing undoes the registration, so the email can
+        // be used to try again
+        if let Err(e) = self.registration_hooks.run(&user).await {
+            tracing::error!(user_id = %user.id, "Rolling back registration: {:#}", e);
+            if let Err(e) = self.user_repository.delete(user.id).await {
+                tracing::error!(user_id = %user.id, "Failed to roll back registration: {}", e);
+            }
+            return Err(AuthError::RegistrationHookFailed(e));
+        }
+
         // Generate JWT token
-        let token = self.generate_token(&user)?;
+        let token = self.generate_token(&user, &client)?;
+        let refresh_token = self.start_session(&user, &client).await?;
 
-        Ok(LoginResponse {
-            token,
-            user: user.into(),
-        })
+        // Notify downstream systems
+        self.webhook_service.user_registered(&user);
+
+        // The account works unverified, and the link can be re-sent
+        if let Err(e) = self.send_verification_email(&user).await {
+            tracing::warn!(user_id = %user.id, "Failed to send verification email: {}", e);
+        }
+
+        Ok(self.login_response(token, refresh_token, user))
     }
 
-    pub async fn login(&self, request: LoginRequest) -> Result<LoginResponse, AuthError> {
-        // Find user by email
-        let user = self
-            .user_repository
-            .find_by_email(&request.email)
+    /// Provision a user without a session, for operators bootstrapping an
+    /// instance. Skips CAPTCHA, webhooks and registration hooks.
+    pub async fn create_user(
+        &self,
+        email: &Email,
+        password: &str,
+        role: Role,
+    ) -> Result<User, AuthError> {
+        self.check_email_length(email)?;
+        if self.user_repository.find_by_email(email).await?.is_some() {
+            return Err(AuthError::UserAlreadyExists);
+        }
+
+        let password_hash = self.hash_password(password)?;
+        self.insert_user(email, &password_hash, role).await
+    }
+
+    // Repositories create users with the `user` role; promote afterwards
+    // when another role was asked for
+    async fn insert_user(
+        &self,
+        email: &Email,
+        password_hash: &str,
+        role: Role,
+    ) -> Result<User, AuthError> {
+        let user = self.user_repository.create(email, password_hash).await?;
+        if user.role == role {
+            return Ok(user);
+        }
+
+        self.user_repository
+            .set_role(user.id, role)
             .await?
-            .ok_or(AuthError::InvalidCredentials)?;
+            .ok_or(AuthError::UserNotFound)
+    }
+
+    pub async fn login(
+        &self,
+        request: LoginRequest,
+        client: ClientInfo,
+    ) -> Result<LoginResponse, AuthError> {
+        // Find user by email
+        let Some(mut user) = self.user_repository.find_by_email(&request.email).await? else {
+            // Do the same Argon2 work as a wrong password, so response time
+            // doesn't reveal which emails have accounts
+            let _ = self.verify_password(&request.password, &self.dummy_password_hash);
+            return Err(AuthError::InvalidCredentials);
+        };
 
         // Verify password
         self.verify_password(&request.password, &user.password_hash)?;
 
+        // Past its deletion date the account is as good as gone, even if
+        // the purge hasn't run yet
+        if user
+            .deletion_scheduled_at
+            .is_some_and(|at| at <= Utc::now())
+        {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        if self.rehash_on_login {
+            self.upgrade_password_hash(&user, &request.password).await;
+        }
+
+        // Best effort: a failed write shouldn't turn a valid login into an error
+        match self.user_repository.touch_last_login(user.id).await {
+            Ok(last_login_at) => user.last_login_at = last_login_at,
+            Err(e) => tracing::warn!("Failed to record last login for user {}: {}", user.id, e),
+        }
+
         // Generate JWT token
-        let token = self.generate_token(&user)?;
+        let token = self.generate_token(&user, &client)?;
+        let refresh_token = self.start_session(&user, &client).await?;
+        self.prune_sessions(user.id);
 
-        Ok(LoginResponse {
-            token,
-            user: user.into(),
+        Ok(self.login_response(token, refresh_token, user))
+    }
+
+    /// Exchange a refresh token for a new access token. The refresh token is
+    /// rotated, so each one can only be used once.
+    pub async fn refresh(
+        &self,
+        request: RefreshRequest,
+        client: ClientInfo,
+    ) -> Result<LoginResponse, AuthError> {
+        let session = self
+            .session_repository
+            .find_by_token_hash(&hash_opaque_token(&request.refresh_token))
+            .await?
+            .filter(|session| session.expires_at > Utc::now())
+            .ok_or(AuthError::InvalidToken)?;
+
+        if self.is_idle(&session) {
+            self.session_repository
+                .delete(session.id, session.user_id)
+                .await?;
+            return Err(AuthError::SessionExpired);
+        }
+
+        let user = self
+            .user_repository
+            .find_by_id(session.user_id)
+            .await?
+            .ok_or(AuthError::InvalidToken)?;
+
+        let token = self.generate_token(&user, &client)?;
+        let refresh_token = generate_opaque_token();
+        self.session_repository
+            .rotate(session.id, &hash_opaque_token(&refresh_token))
+            .await?;
+
+        Ok(self.login_response(token, refresh_token, user))
+    }
+
+    /// End the session behind a refresh token, if it still exists
+    pub async fn logout(&self, request: RefreshRequest) -> Result<(), AuthError> {
+        let session = self
+            .session_repository
+            .find_by_token_hash(&hash_opaque_token(&request.refresh_token))
+            .await?;
+
+        if let Some(session) = session {
+            self.session_repository
+                .delete(session.id, session.user_id)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_sessions(&self, user_id: Uuid) -> Result<Vec<SessionResponse>, AuthError> {
+        let sessions = self
+            .session_repository
+            .list_active_for_user(user_id)
+            .await?;
+
+        Ok(sessions
+            .into_iter()
+            .filter(|session| !self.is_idle(session))
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Whether the session went unused past `session_idle_timeout_secs`.
+    /// Rotating its refresh token is what counts as use.
+    fn is_idle(&self, session: &Session) -> bool {
+        self.session_idle_timeout_secs
+            .is_some_and(|secs| session.last_used_at + Duration::seconds(secs) <= Utc::now())
+    }
+
+    /// Everything held about the user, for data portability requests: the
+    /// profile, then the active sessions read `EXPORT_BATCH_SIZE` at a time
+    /// as the stream is consumed, so memory stays bounded however many there
+    /// are. An unknown user fails here, before anything is streamed.
+    pub async fn export_data(
+        &self,
+        user_id: Uuid,
+    ) -> Result<impl Stream<Item = Result<ExportRecord, AuthError>> + Send + 'static, AuthError>
+    {
+        let user = self
+            .user_repository
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+        let profile = ExportRecord::Profile {
+            exported_at: Utc::now(),
+            updated_at: user.updated_at,
+            profile: user.into(),
+        };
+
+        // `None` once the last batch has been read
+        let sessions = stream::try_unfold(Some(None), {
+            let service = self.clone();
+            move |after: Option<Option<Uuid>>| {
+                let service = service.clone();
+                async move {
+                    let Some(after) = after else {
+                        return Ok::<_, AuthError>(None);
+                    };
+                    let batch = service
+                        .session_repository
+                        .list_active_for_user_after(user_id, after, EXPORT_BATCH_SIZE)
+                        .await?;
+                    if batch.is_empty() {
+                        return Ok(None);
+                    }
+
+                    let next = (batch.len() as i64 == EXPORT_BATCH_SIZE)
+                        .then(|| batch.last().map(|session| session.id));
+                    let records: Vec<Result<ExportRecord, AuthError>> = batch
+                        .into_iter()
+                        .filter(|session| !service.is_idle(session))
+                        .map(|session| Ok(ExportRecord::Session(session.into())))
+                        .collect();
+                    Ok(Some((stream::iter(records), next)))
+                }
+            }
+        })
+        .try_flatten();
+
+        Ok(stream::once(async { Ok(profile) }).chain(sessions))
+    }
+
+    /// Revoke one of the user's own sessions; its refresh token stops working
+    pub async fn revoke_session(&self, user_id: Uuid, session_id: Uuid) -> Result<(), AuthError> {
+        if !self.session_repository.delete(session_id, user_id).await? {
+            return Err(AuthError::SessionNotFound);
+        }
+
+        Ok(())
+    }
+
+    pub async fn verify_token(&self, token: &str) -> Result<Claims, AuthError> {
+        // Reject any token whose header names a different algorithm up front,
+        // so a forged HS256 token can never be checked against an RSA key
+        let header = decode_header(token).map_err(|_| AuthError::InvalidToken)?;
+        let keys = self.jwt_keys();
+        if header.alg != keys.algorithm() {
+            return Err(AuthError::InvalidToken);
+        }
+
+        // A kid we don't hold usually means a key rotation went wrong, so
+        // fail distinctly instead of checking it against the wrong key
+        let Some(decoding_key) = keys.decoding_key(header.kid.as_deref()) else {
+            let kid = header.kid.unwrap_or_default();
+            tracing::debug!(kid = %kid, "Token signed with unknown key id");
+            return Err(AuthError::UnknownKeyId(kid));
+        };
+
+        let token_data = decode::<Claims>(token, decoding_key, &keys.validation())?;
+        let mut claims = token_data.claims;
+
+        // Caps the lifetime of tokens minted with a longer expiry than
+        // current policy allows
+        if let Some(max_age) = self.max_token_age_secs {
+            if Utc::now().timestamp() - claims.iat > max_age {
+                return Err(AuthError::TokenTooOld);
+            }
+        }
+
+        // Tokens issued before the user's last revocation are no longer valid
+        let user_id = claims.user_id().map_err(|_| AuthError::InvalidToken)?;
+        let user = self
+            .user_repository
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AuthError::InvalidToken)?;
+
+        if user.token_version != claims.token_version {
+            return Err(AuthError::TokenRevoked);
+        }
+
+        // A minimal token carries no email, and must not be trusted for a
+        // role even if the setting has changed since it was issued
+        if self.minimal_claims || claims.email.is_empty() {
+            claims.email = user.email;
+            claims.role = user.role;
+        }
+
+        Ok(claims)
+    }
+
+    /// A token bound to a client fingerprint is only accepted from a request
+    /// presenting the same one. Unbound tokens are always accepted.
+    pub fn check_token_binding(
+        &self,
+        claims: &Claims,
+        presented: Option<&str>,
+    ) -> Result<(), AuthError> {
+        match &claims.cnf {
+            Some(cnf) if presented != Some(cnf.fingerprint.as_str()) => {
+                Err(AuthError::TokenBindingMismatch)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check a token on behalf of another service. Only infrastructure
+    /// failures are errors; any rejected token is simply inactive.
+    pub async fn introspect(&self, token: &str) -> Result<IntrospectResponse, AuthError> {
+        match self.verify_token(token).await {
+            Ok(claims) => Ok(claims.into()),
+            Err(AuthError::DatabaseError(e)) => Err(AuthError::DatabaseError(e)),
+            Err(_) => Ok(IntrospectResponse::inactive()),
+        }
+    }
+
+    pub fn jwks(&self) -> Option<JwkSet> {
+        self.jwt_keys().jwks()
+    }
+
+    fn jwt_keys(&self) -> Arc<JwtKeys> {
+        self.jwt_keys.read().unwrap().clone()
+    }
+
+    /// Sign with `keys` from now on. Tokens signed with the keys they
+    /// replace keep verifying for `overlap`, so a secret change on reload
+    /// doesn't sign everyone out.
+    pub fn reload_jwt_keys(&self, keys: JwtKeys, overlap: std::time::Duration) {
+        let overlap = Duration::from_std(overlap).unwrap_or(Duration::MAX);
+        let mut current = self.jwt_keys.write().unwrap();
+        let keys = keys.replacing(&current, Utc::now() + overlap);
+        tracing::info!(
+            kid = keys.kid(),
+            replaced = current.kid(),
+            "JWT keys reloaded"
+        );
+        *current = Arc::new(keys);
+    }
+
+    /// Invalidate every outstanding token for a user
+    pub async fn revoke_sessions(&self, user_id: Uuid) -> Result<(), AuthError> {
+        self.user_repository
+            .increment_token_version(user_id)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+        self.session_repository.delete_all_for_user(user_id).await?;
+
+        tracing::info!("Revoked all sessions for user {}", user_id);
+
+        Ok(())
+    }
+
+    /// Change a signed-in user's password. Every token and session is
+    /// revoked, so the user signs in again with the new password.
+    pub async fn change_password(
+        &self,
+        user_id: Uuid,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), AuthError> {
+        let user = self
+            .user_repository
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+        self.verify_password(current_password, &user.password_hash)?;
+        self.set_password(&user, new_password).await?;
+
+        tracing::info!("Password changed for user {}", user.id);
+
+        Ok(())
+    }
+
+    /// Email a password-reset link if an account uses `email`. Succeeds
+    /// either way, so the endpoint can't be used to probe for accounts.
+    pub async fn request_password_reset(&self, email: &Email) -> Result<(), AuthError> {
+        let Some(user) = self.user_repository.find_by_email(email).await? else {
+            return Ok(());
+        };
+
+        let now = Utc::now();
+        let token = self.sign_link_token(&PasswordResetClaims {
+            sub: user.id.to_string(),
+            purpose: PASSWORD_RESET_PURPOSE.to_string(),
+            pwh: password_fingerprint(&user.password_hash),
+            exp: (now + Duration::minutes(self.password_reset_token_minutes)).timestamp(),
+            iat: now.timestamp(),
+        })?;
+
+        self.send_email(EmailMessage {
+            to: user.email,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Choose a new password for your account:\n{}/password/reset?token={}\n\n\
+                 The link expires in {} minutes and works once. If you didn't ask \
+                 for this, ignore this message.",
+                self.app_url, token, self.password_reset_token_minutes
+            ),
+        })
+        .await
+    }
+
+    /// Set a new password from a reset link. Like a password change, this
+    /// signs the user out everywhere.
+    pub async fn reset_password(&self, token: &str, new_password: &str) -> Result<(), AuthError> {
+        let claims: PasswordResetClaims = self.decode_link_token(token)?;
+        if claims.purpose != PASSWORD_RESET_PURPOSE {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AuthError::InvalidToken)?;
+        let user = self
+            .user_repository
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AuthError::InvalidToken)?;
+
+        // Single use: a changed password no longer matches the fingerprint
+        if claims.pwh != password_fingerprint(&user.password_hash) {
+            return Err(AuthError::InvalidToken);
+        }
+
+        self.set_password(&user, new_password).await?;
+
+        tracing::info!("Password reset for user {}", user.id);
+
+        Ok(())
+    }
+
+    /// Email a verification link if `email` belongs to an unverified
+    /// account. Succeeds either way, and also when the account has asked too
+    /// often, so the endpoint reveals nothing about the address.
+    pub async fn resend_verification(&self, email: &Email) -> Result<(), AuthError> {
+        match self.user_repository.find_by_email(email).await? {
+            Some(user) => self.resend_verification_to(&user).await,
+            None => Ok(()),
+        }
+    }
+
+    /// `resend_verification` for a signed-in user
+    pub async fn resend_verification_for(&self, user_id: Uuid) -> Result<(), AuthError> {
+        let user = self
+            .user_repository
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+        self.resend_verification_to(&user).await
+    }
+
+    async fn resend_verification_to(&self, user: &User) -> Result<(), AuthError> {
+        if user.email_verified_at.is_some() {
+            return Ok(());
+        }
+        if self.verification_resends.check_key(&user.id).is_err() {
+            tracing::info!("Verification resend for user {} throttled", user.id);
+            return Ok(());
+        }
+
+        self.send_verification_email(user).await
+    }
+
+    async fn send_verification_email(&self, user: &User) -> Result<(), AuthError> {
+        let now = Utc::now();
+        let token = self.sign_link_token(&EmailVerificationClaims {
+            sub: user.id.to_string(),
+            purpose: EMAIL_VERIFICATION_PURPOSE.to_string(),
+            email: user.email.clone(),
+            exp: (now + Duration::minutes(self.email_verification_token_minutes)).timestamp(),
+            iat: now.timestamp(),
+        })?;
+
+        self.send_email(EmailMessage {
+            to: user.email.clone(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Confirm this is your address:\n{}/email/verify?token={}\n\n\
+                 The link expires in {} minutes. If you didn't create an account, \
+                 ignore this message.",
+                self.app_url, token, self.email_verification_token_minutes
+            ),
+        })
+        .await
+    }
+
+    /// Mark the address a verification link was sent to as verified. Links
+    /// for an address the account no longer uses are refused.
+    pub async fn verify_email(&self, token: &str) -> Result<UserResponse, AuthError> {
+        let claims: EmailVerificationClaims = self.decode_link_token(token)?;
+        if claims.purpose != EMAIL_VERIFICATION_PURPOSE {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AuthError::InvalidToken)?;
+        let user = self
+            .user_repository
+            .mark_email_verified(user_id, &claims.email)
+            .await?
+            .ok_or(AuthError::InvalidToken)?;
+
+        tracing::info!("User {} verified their email address", user.id);
+
+        Ok(user.into())
+    }
+
+    /// Sign the claims of a link sent by email. These carry no audience, so
+    /// they can't pass for access tokens.
+    fn sign_link_token(&self, claims: &impl Serialize) -> Result<String, AuthError> {
+        let keys = self.jwt_keys();
+        let mut header = Header::new(keys.algorithm());
+        header.kid = Some(keys.kid().to_string());
+        Ok(encode(&header, claims, keys.encoding_key())?)
+    }
+
+    /// Any failure is `InvalidToken`; callers still check `purpose`
+    fn decode_link_token<T: DeserializeOwned>(&self, token: &str) -> Result<T, AuthError> {
+        let keys = self.jwt_keys();
+        let kid = decode_header(token)
+            .map_err(|_| AuthError::InvalidToken)?
+            .kid;
+        let decoding_key = keys
+            .decoding_key(kid.as_deref())
+            .ok_or(AuthError::InvalidToken)?;
+        let validation = keys.validation_without_audience();
+        Ok(decode::<T>(token, decoding_key, &validation)
+            .map_err(|_| AuthError::InvalidToken)?
+            .claims)
+    }
+
+    /// Check `password` against the user's current one. Nothing is recorded
+    /// either way.
+    pub async fn verify_current_password(
+        &self,
+        user_id: Uuid,
+        password: &str,
+    ) -> Result<(), AuthError> {
+        let user = self
+            .user_repository
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+        self.verify_password(password, &user.password_hash)
+    }
+
+    /// Issue a short-lived access token for `user_id` on behalf of an admin.
+    /// The token carries an `act` claim naming the admin and has no refresh
+    /// token; revoking the user's sessions also kills it.
+    pub async fn impersonate(
+        &self,
+        admin: &Claims,
+        user_id: Uuid,
+    ) -> Result<ImpersonationResponse, AuthError> {
+        if admin.is_impersonation() {
+            return Err(AuthError::NestedImpersonation);
+        }
+
+        let user = self
+            .user_repository
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+
+        let expires_at = Utc::now() + Duration::minutes(self.impersonation_token_minutes);
+        let actor = Actor {
+            sub: admin.sub.clone(),
+            email: admin.email.clone(),
+        };
+        let token = self.sign_token(&user, expires_at, Some(actor), None)?;
+
+        tracing::warn!(
+            admin_id = %admin.sub,
+            user_id = %user.id,
+            %expires_at,
+            "Admin {} started impersonating user {}",
+            admin.email,
+            user.email
+        );
+
+        Ok(ImpersonationResponse { token, expires_at })
+    }
+
+    /// Start moving a user to a new address. Nothing changes until the new
+    /// address confirms; meanwhile the current one is told and can cancel.
+    pub async fn request_email_change(
+        &self,
+        user_id: Uuid,
+        email: Email,
+    ) -> Result<UserResponse, AuthError> {
+        let user = self
+            .user_repository
+            .find_by_id(user_id)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+
+        // Otherwise an email change would sidestep the registration policy
+        self.check_email_length(&email)?;
+        self.check_email_domain(&email)?;
+        if self.user_repository.find_by_email(&email).await?.is_some() {
+            return Err(AuthError::UserAlreadyExists);
+        }
+
+        let confirm_token = generate_opaque_token();
+        let cancel_token = generate_opaque_token();
+        let expires_at = Utc::now() + Duration::minutes(self.email_change_token_minutes);
+
+        let updated = self
+            .user_repository
+            .set_pending_email(
+                user.id,
+                &email,
+                &hash_opaque_token(&confirm_token),
+                &hash_opaque_token(&cancel_token),
+                expires_at,
+            )
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+
+        // The current address goes first: it's the one a hijacker can't read
+        self.send_email(EmailMessage {
+            to: user.email,
+            subject: "Your email address is being changed".to_string(),
+            body: format!(
+                "Someone asked to change the email address on your account to {}.\n\n\
+                 If this wasn't you, cancel the change and sign out everywhere:\n{}/email/cancel?token={}\n\n\
+                 The link expires in {} minutes.",
+                email, self.app_url, cancel_token, self.email_change_token_minutes
+            ),
+        })
+        .await?;
+        self.send_email(EmailMessage {
+            to: email.to_string(),
+            subject: "Confirm your new email address".to_string(),
+            body: format!(
+                "Confirm this address for your account:\n{}/email/confirm?token={}\n\n\
+                 The link expires in {} minutes.",
+                self.app_url, confirm_token, self.email_change_token_minutes
+            ),
         })
+        .await?;
+
+        Ok(updated.into())
+    }
+
+    /// Make a pending email address current
+    pub async fn confirm_email_change(&self, token: &str) -> Result<UserResponse, AuthError> {
+        let user = self
+            .user_repository
+            .confirm_pending_email(&hash_opaque_token(token))
+            .await
+            .map_err(|e| match e.as_database_error() {
+                Some(db) if db.is_unique_violation() => AuthError::UserAlreadyExists,
+                _ => AuthError::DatabaseError(e),
+            })?
+            .ok_or(AuthError::InvalidToken)?;
+
+        tracing::info!("User {} confirmed a new email address", user.id);
+
+        Ok(user.into())
+    }
+
+    /// Drop a pending email change from the link sent to the old address.
+    /// Whoever requested it may hold a stolen session, so all are revoked.
+    pub async fn cancel_email_change(&self, token: &str) -> Result<(), AuthError> {
+        let user = self
+            .user_repository
+            .cancel_pending_email(&hash_opaque_token(token))
+            .await?
+            .ok_or(AuthError::InvalidToken)?;
+
+        tracing::warn!(
+            "Email change for user {} cancelled from the old address",
+            user.id
+        );
+
+        self.revoke_sessions(user.id).await
+    }
+
+    /// Schedule the account for deletion after the grace period and sign it
+    /// out everywhere. Signing back in and cancelling keeps the account.
+    pub async fn schedule_deletion(&self, user_id: Uuid) -> Result<UserResponse, AuthError> {
+        let at = Utc::now() + Duration::days(self.account_deletion_grace_days);
+        let user = self
+            .user_repository
+            .set_deletion_scheduled_at(user_id, Some(at))
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+        self.revoke_sessions(user.id).await?;
+
+        tracing::warn!("User {} scheduled for deletion at {}", user.id, at);
+
+        Ok(user.into())
+    }
+
+    pub async fn cancel_deletion(&self, user_id: Uuid) -> Result<UserResponse, AuthError> {
+        let user = self
+            .user_repository
+            .set_deletion_scheduled_at(user_id, None)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+
+        tracing::info!("Deletion cancelled for user {}", user.id);
+
+        Ok(user.into())
+    }
+
+    /// Delete accounts whose grace period has ended, every `interval` until
+    /// `shutdown` is cancelled
+    pub async fn run_account_purge(
+        self,
+        interval: std::time::Duration,
+        shutdown: CancellationToken,
+    ) {
+        loop {
+            match self
+                .user_repository
+                .purge_scheduled_deletions(Utc::now())
+                .await
+            {
+                Ok(0) => {}
+                Ok(purged) => tracing::warn!("Purged {} accounts scheduled for deletion", purged),
+                Err(e) => tracing::error!("Failed to purge accounts scheduled for deletion: {}", e),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = shutdown.cancelled() => return,
+            }
+        }
+    }
+
+    async fn send_email(&self, message: EmailMessage) -> Result<(), AuthError> {
+        self.mailer
+            .send(message)
+            .await
+            .map_err(AuthError::MailUnavailable)
+    }
+
+    /// Applied where an address is stored; login accepts any length so
+    /// accounts created under a higher limit can still sign in
+    fn check_email_length(&self, email: &Email) -> Result<(), AuthError> {
+        if email.as_str().chars().count() > self.max_email_length {
+            return Err(AuthError::EmailTooLong(self.max_email_length));
+        }
+
+        Ok(())
+    }
+
+    fn check_email_domain(&self, email: &Email) -> Result<(), AuthError> {
+        if !self.registration_domains.permits(email) {
+            return Err(AuthError::EmailDomainNotAllowed(email.domain().to_string()));
+        }
+
+        Ok(())
     }
 
-    #[allow(dead_code)]
-    pub fn verify_token(&self, token: &str) -> Result<Claims, AuthError> {
-        let token_data = decode::<Claims>(
+    /// A no-op unless CAPTCHA checks are enabled
+    async fn verify_captcha(
+        &self,
+        token: Option<&str>,
+        client: &ClientInfo,
+    ) -> Result<(), AuthError> {
+        let Some(captcha) = &self.captcha else {
+            return Ok(());
+        };
+
+        let token = token
+            .filter(|t| !t.is_empty())
+            .ok_or(AuthError::CaptchaFailed)?;
+        let passed = captcha
+            .verify(token, client.ip_address.as_deref())
+            .await
+            .map_err(AuthError::CaptchaUnavailable)?;
+
+        if !passed {
+            return Err(AuthError::CaptchaFailed);
+        }
+
+        Ok(())
+    }
+
+    fn login_response(&self, token: String, refresh_token: String, user: User) -> LoginResponse {
+        LoginResponse {
             token,
-            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
-            &Validation::default(),
-        )?;
+            refresh_token: Some(refresh_token),
+            user: self.login_response_include_user.then(|| user.into()),
+        }
+    }
+
+    /// Record a new session and return its raw refresh token
+    async fn start_session(&self, user: &User, client: &ClientInfo) -> Result<String, AuthError> {
+        let refresh_token = generate_opaque_token();
+        let expires_at = Utc::now() + Duration::days(self.refresh_token_expiration_days);
+
+        self.session_repository
+            .create(
+                user.id,
+                &hash_opaque_token(&refresh_token),
+                client,
+                expires_at,
+            )
+            .await?;
 
-        Ok(token_data.claims)
+        Ok(refresh_token)
+    }
+
+    /// Drop the user's expired sessions and evict the least recently used
+    /// beyond `MAX_SESSIONS_PER_USER`. Runs in the background; failures are
+    /// only logged.
+    fn prune_sessions(&self, user_id: Uuid) {
+        let session_repository = self.session_repository.clone();
+        let keep = self.max_sessions_per_user;
+        self.tasks.spawn(async move {
+            match session_repository.prune_for_user(user_id, keep).await {
+                Ok(0) => {}
+                Ok(pruned) => {
+                    tracing::debug!("Pruned {} sessions for user {}", pruned, user_id)
+                }
+                Err(e) => tracing::warn!("Failed to prune sessions for user {}: {}", user_id, e),
+            }
+        });
     }
 
     fn hash_password(&self, password: &str) -> Result<String, AuthError> {
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
+        hash_password(&self.password_hashing, password)
+    }
 
-        let password_hash = argon2
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|_| AuthError::PasswordHashError)?
-            .to_string();
+    /// After a successful login, re-hash a password stored under other
+    /// settings than new hashes get (bcrypt, an earlier `ARGON2_VARIANT`, or
+    /// older parameters) so stored hashes migrate without forcing resets. Best
+    /// effort: a failure leaves the old hash, which still verifies.
+    async fn upgrade_password_hash(&self, user: &User, password: &str) {
+        if !needs_rehash(&self.password_hashing, &user.password_hash) {
+            return;
+        }
 
-        Ok(password_hash)
+        let result = match self.hash_password(password) {
+            Ok(new_hash) => self
+                .user_repository
+                .replace_password_hash(user.id, &user.password_hash, &new_hash)
+                .await
+                .map_err(AuthError::from),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(true) => tracing::info!(user_id = %user.id, "Upgraded password hash"),
+            Ok(false) => {}
+            Err(e) => tracing::warn!(user_id = %user.id, "Failed to upgrade password hash: {}", e),
+        }
     }
 
     fn verify_password(&self, password: &str, password_hash: &str) -> Result<(), AuthError> {
+        if bcrypt::is_bcrypt(password_hash) {
+            return match bcrypt::verify(password, password_hash) {
+                Some(true) => Ok(()),
+                Some(false) => Err(AuthError::InvalidCredentials),
+                None => Err(AuthError::PasswordHashError),
+            };
+        }
+
         let parsed_hash =
             PasswordHash::new(password_hash).map_err(|_| AuthError::PasswordHashError)?;
 
+        // The variant is read from the stored hash, so hashes created under a
+        // different ARGON2_VARIANT still verify
         Argon2::default()
             .verify_password(password.as_bytes(), &parsed_hash)
             .map_err(|_| AuthError::InvalidCredentials)
     }
 
-    fn generate_token(&self, user: &User) -> Result<String, AuthError> {
-        let now = Utc::now();
-        let expiration = now + Duration::hours(self.jwt_expiration_hours);
+    /// Replace the user's password, subject to the reuse policy, and end
+    /// every session and token
+    async fn set_password(&self, user: &User, new_password: &str) -> Result<(), AuthError> {
+        self.check_password_history(user, new_password).await?;
+
+        let password_hash = self.hash_password(new_password)?;
+        self.user_repository
+            .update_password(user.id, &password_hash, self.password_history_depth - 1)
+            .await?
+            .ok_or(AuthError::UserNotFound)?;
+        self.session_repository.delete_all_for_user(user.id).await?;
+
+        Ok(())
+    }
+
+    /// Reject `password` if it matches the current password or one of the
+    /// replaced ones still inside `password_history_depth`
+    async fn check_password_history(&self, user: &User, password: &str) -> Result<(), AuthError> {
+        if self.password_history_depth <= 0 {
+            return Ok(());
+        }
+
+        let mut hashes = vec![user.password_hash.clone()];
+        hashes.extend(
+            self.user_repository
+                .password_history(user.id, self.password_history_depth - 1)
+                .await?,
+        );
+
+        for hash in &hashes {
+            match self.verify_password(password, hash) {
+                Ok(()) => return Err(AuthError::PasswordReused),
+                Err(AuthError::InvalidCredentials) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn generate_token(&self, user: &User, client: &ClientInfo) -> Result<String, AuthError> {
+        let expiration = Utc::now() + Duration::hours(self.jwt_expiration_hours);
+        let cnf = client
+            .fingerprint
+            .clone()
+            .filter(|_| self.token_binding_enabled)
+            .map(|fingerprint| Confirmation { fingerprint });
+        self.sign_token(user, expiration, None, cnf)
+    }
 
+    fn sign_token(
+        &self,
+        user: &User,
+        expiration: DateTime<Utc>,
+        act: Option<Actor>,
+        cnf: Option<Confirmation>,
+    ) -> Result<String, AuthError> {
+        let keys = self.jwt_keys();
+        let now = Utc::now();
         let claims = Claims {
             sub: user.id.to_string(),
             email: user.email.clone(),
+            role: user.role,
+            token_version: user.token_version,
             exp: expiration.timestamp(),
             iat: now.timestamp(),
+            nbf: (now + Duration::seconds(self.jwt_not_before_secs)).timestamp(),
+            aud: keys.audience().to_vec(),
+            act,
+            cnf,
         };
 
-        let token = encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
-        )?;
+        let mut header = Header::new(keys.algorithm());
+        header.kid = Some(keys.kid().to_string());
+
+        let token = if self.minimal_claims {
+            encode(&header, &MinimalClaims::from(&claims), keys.encoding_key())?
+        } else {
+            encode(&header, &claims, keys.encoding_key())?
+        };
 
         Ok(token)
     }
 }
+
+/// Which email domains may sign up. Domains are compared exactly, so
+/// `example.com` doesn't cover `mail.example.com`.
+#[derive(Clone)]
+pub struct EmailDomainPolicy {
+    // Empty means every domain not blocked is allowed
+    allowed: Vec<String>,
+    blocked: Vec<String>,
+}
+
+impl EmailDomainPolicy {
+    /// Both lists must already be lowercased, as `Email` domains are
+    pub fn new(allowed: Vec<String>, blocked: Vec<String>) -> Self {
+        Self { allowed, blocked }
+    }
+
+    pub fn permits(&self, email: &Email) -> bool {
+        let domain = email.domain();
+        if self.blocked.iter().any(|d| d == domain) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|d| d == domain)
+    }
+}
+
+fn hash_password(settings: &PasswordHashing, password: &str) -> Result<String, AuthError> {
+    let mut salt = vec![0u8; settings.salt_len];
+    OsRng.fill_bytes(&mut salt);
+    let salt = SaltString::encode_b64(&salt).map_err(|_| AuthError::PasswordHashError)?;
+    let argon2 = Argon2::new(
+        settings.algorithm,
+        Version::V0x13,
+        hash_params(settings).map_err(|_| AuthError::PasswordHashError)?,
+    );
+
+    let password_hash = argon2
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|_| AuthError::PasswordHashError)?
+        .to_string();
+
+    Ok(password_hash)
+}
+
+/// Default costs with the configured output length. Config keeps the
+/// length in range, so this only fails on a bug.
+fn hash_params(settings: &PasswordHashing) -> argon2::Result<Params> {
+    Params::new(
+        Params::DEFAULT_M_COST,
+        Params::DEFAULT_T_COST,
+        Params::DEFAULT_P_COST,
+        Some(settings.output_len),
+    )
+}
+
+/// Whether `password_hash` was made with a different algorithm, version,
+/// cost parameters or lengths than `hash_password` uses now
+fn needs_rehash(settings: &PasswordHashing, password_hash: &str) -> bool {
+    if bcrypt::is_bcrypt(password_hash) {
+        return true;
+    }
+    let Ok(parsed) = PasswordHash::new(password_hash) else {
+        return false;
+    };
+    let Ok(current) = hash_params(settings) else {
+        return false;
+    };
+    let salt_len = parsed.salt.and_then(|salt| {
+        let mut buf = [0u8; Salt::MAX_LENGTH];
+        salt.decode_b64(&mut buf).ok().map(<[u8]>::len)
+    });
+
+    parsed.algorithm != settings.algorithm.ident()
+        || parsed.version != Some(u32::from(Version::V0x13))
+        || parsed.hash.map(|hash| hash.len()) != current.output_len()
+        || salt_len != Some(settings.salt_len)
+        || Params::try_from(&parsed).map_or(true, |params| {
+            params.m_cost() != current.m_cost()
+                || params.t_cost() != current.t_cost()
+                || params.p_cost() != current.p_cost()
+        })
+}
+
+fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Only the hash is stored, so a leaked sessions or users table can't be replayed
+fn hash_opaque_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// Embedded in reset tokens; never the hash itself, even though it's salted
+fn password_fingerprint(password_hash: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(password_hash.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::{
+        InMemorySessionRepository, InMemoryUserRepository, InMemoryWebhookRepository,
+    };
+    use crate::routes::auth_service_with;
+    use crate::services::{RecordingMailer, StubHook};
+    use crate::test_support::{self, PASSWORD};
+
+    /// An `AuthService` on the in-memory repositories, configured by `vars`
+    fn service(vars: &[(&str, &str)]) -> (AuthService, InMemoryUserRepository) {
+        let (service, users, _) = mailing_service(vars);
+        (service, users)
+    }
+
+    /// `service`, also returning the mail it sends
+    fn mailing_service(
+        vars: &[(&str, &str)],
+    ) -> (AuthService, InMemoryUserRepository, RecordingMailer) {
+        let config = test_support::config(vars);
+        let mailer = RecordingMailer::default();
+        let tasks = TaskManager::new();
+        let users = InMemoryUserRepository::new();
+        let webhooks = WebhookService::new(
+            None,
+            None,
+            Arc::new(InMemoryWebhookRepository::new()),
+            0,
+            tasks.clone(),
+        );
+        let service = auth_service_with(
+            Arc::new(users.clone()),
+            Arc::new(InMemorySessionRepository::new()),
+            webhooks,
+            Arc::new(mailer.clone()),
+            &config,
+            &tasks,
+        );
+        (service, users, mailer)
+    }
+
+    fn email(value: &str) -> Email {
+        Email::try_from(value.to_string()).unwrap()
+    }
+
+    fn register_request(address: &str, password: &str) -> RegisterRequest {
+        RegisterRequest {
+            email: email(address),
+            password: password.to_string(),
+            captcha_token: None,
+        }
+    }
+
+    fn login_request(address: &str, password: &str) -> LoginRequest {
+        LoginRequest {
+            email: email(address),
+            password: password.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn register_stores_the_user_and_issues_a_token() {
+        let (service, users) = service(&[]);
+
+        let response = service
+            .register(
+                register_request("new@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+
+        let user = users
+            .find_by_email(&email("new@example.com"))
+            .await
+            .unwrap()
+            .expect("user stored");
+        assert_ne!(user.password_hash, PASSWORD);
+        let claims = service.verify_token(&response.token).await.unwrap();
+        assert_eq!(claims.sub, user.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn register_runs_hooks_and_survives_a_non_critical_failure() {
+        let (mut service, users) = service(&[]);
+        let failing = StubHook::failing(false);
+        service.registration_hooks = RegistrationHooks::new(vec![Arc::new(failing.clone())]);
+
+        let (user, token) = registered(&service, &users, "user@example.com").await;
+
+        assert_eq!(failing.runs(), 1);
+        assert_eq!(
+            service.verify_token(&token).await.unwrap().sub,
+            user.id.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn critical_hook_failure_rolls_back_the_registration() {
+        let (mut service, users) = service(&[]);
+        let failing = StubHook::failing(true);
+        service.registration_hooks = RegistrationHooks::new(vec![Arc::new(failing.clone())]);
+
+        let result = service
+            .register(
+                register_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(AuthError::RegistrationHookFailed(_))));
+        assert!(users
+            .find_by_email(&email("user@example.com"))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn register_rejects_duplicate_email() {
+        let (service, _) = service(&[]);
+        service
+            .register(
+                register_request("new@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+
+        let result = service
+            .register(
+                register_request("NEW@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(AuthError::UserAlreadyExists)));
+    }
+
+    #[tokio::test]
+    async fn register_applies_the_domain_lists() {
+        let (service, _) = service(&[
+            (
+                "REGISTRATION_ALLOWED_DOMAINS",
+                "Corp.Example, partner.example",
+            ),
+            ("REGISTRATION_BLOCKED_DOMAINS", "partner.example"),
+        ]);
+        let register = |address: &'static str| {
+            service.register(register_request(address, PASSWORD), ClientInfo::default())
+        };
+
+        // Listed, in any case
+        assert!(register("ada@CORP.example").await.is_ok());
+        // Listed but also blocked
+        assert!(matches!(
+            register("ada@partner.example").await,
+            Err(AuthError::EmailDomainNotAllowed(domain)) if domain == "partner.example"
+        ));
+        // Unlisted while an allowlist is set
+        assert!(matches!(
+            register("ada@example.com").await,
+            Err(AuthError::EmailDomainNotAllowed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn register_without_an_allowlist_only_rejects_blocked_domains() {
+        let (service, _) = service(&[("REGISTRATION_BLOCKED_DOMAINS", "mailinator.test")]);
+
+        let blocked = service
+            .register(
+                register_request("ada@Mailinator.test", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await;
+        assert!(matches!(blocked, Err(AuthError::EmailDomainNotAllowed(_))));
+
+        let unlisted = service
+            .register(
+                register_request("ada@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await;
+        assert!(unlisted.is_ok());
+    }
+
+    #[tokio::test]
+    async fn change_password_rejects_passwords_inside_the_history() {
+        let (service, users) = service(&[("PASSWORD_HISTORY_DEPTH", "2")]);
+        let first = PASSWORD;
+        let second = "second-horse-battery";
+        let third = "third-horse-battery";
+        service
+            .register(
+                register_request("user@example.com", first),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        let user_id = users
+            .find_by_email(&email("user@example.com"))
+            .await
+            .unwrap()
+            .unwrap()
+            .id;
+
+        service
+            .change_password(user_id, first, second)
+            .await
+            .unwrap();
+        // The current password and the one just replaced are both off limits
+        let reused = service.change_password(user_id, second, second).await;
+        assert!(matches!(reused, Err(AuthError::PasswordReused)));
+        let reused = service.change_password(user_id, second, first).await;
+        assert!(matches!(reused, Err(AuthError::PasswordReused)));
+
+        service
+            .change_password(user_id, second, third)
+            .await
+            .unwrap();
+        // Two changes back, `first` has fallen out of the history
+        service
+            .change_password(user_id, third, first)
+            .await
+            .unwrap();
+    }
+
+    async fn registered(
+        service: &AuthService,
+        users: &InMemoryUserRepository,
+        address: &str,
+    ) -> (User, String) {
+        let token = service
+            .register(register_request(address, PASSWORD), ClientInfo::default())
+            .await
+            .unwrap()
+            .token;
+        let user = users.find_by_email(&email(address)).await.unwrap().unwrap();
+        (user, token)
+    }
+
+    async fn refresh_with(
+        service: &AuthService,
+        refresh_token: String,
+    ) -> Result<String, AuthError> {
+        let response = service
+            .refresh(RefreshRequest { refresh_token }, ClientInfo::default())
+            .await?;
+        Ok(response.refresh_token.expect("refresh token in the body"))
+    }
+
+    #[tokio::test]
+    async fn session_unused_past_the_idle_timeout_is_ended() {
+        let (service, users) = service(&[("SESSION_IDLE_TIMEOUT_SECS", "1")]);
+        let (user, _) = registered(&service, &users, "user@example.com").await;
+        let refresh_token = service
+            .login(
+                login_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap()
+            .refresh_token
+            .unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+        assert!(service.list_sessions(user.id).await.unwrap().is_empty());
+        let result = refresh_with(&service, refresh_token.clone()).await;
+        assert!(matches!(result, Err(AuthError::SessionExpired)));
+        // The session is gone, not just refused
+        let result = refresh_with(&service, refresh_token).await;
+        assert!(matches!(result, Err(AuthError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn refreshing_within_the_idle_timeout_keeps_the_session_alive() {
+        let (service, users) = service(&[("SESSION_IDLE_TIMEOUT_SECS", "2")]);
+        let (user, _) = registered(&service, &users, "user@example.com").await;
+        let mut refresh_token = service
+            .login(
+                login_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap()
+            .refresh_token
+            .unwrap();
+
+        // Each refresh restarts the clock, so the session outlives the
+        // timeout as long as it keeps being used
+        for _ in 0..2 {
+            tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+            refresh_token = refresh_with(&service, refresh_token).await.unwrap();
+        }
+
+        // Only the registration's session, never refreshed, has gone idle
+        assert_eq!(service.list_sessions(user.id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn schedule_deletion_signs_out_until_the_grace_period_ends() {
+        let (service, users) = service(&[("ACCOUNT_DELETION_GRACE_DAYS", "30")]);
+        let (user, token) = registered(&service, &users, "user@example.com").await;
+
+        let scheduled = service.schedule_deletion(user.id).await.unwrap();
+
+        let at = scheduled.deletion_scheduled_at.expect("deletion scheduled");
+        let expected = Utc::now() + Duration::days(30);
+        assert!((expected - at).num_seconds().abs() < 5, "{}", at);
+        assert!(matches!(
+            service.verify_token(&token).await,
+            Err(AuthError::TokenRevoked)
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancel_deletion_keeps_the_account() {
+        let (service, users) = service(&[]);
+        let (user, _) = registered(&service, &users, "user@example.com").await;
+        service.schedule_deletion(user.id).await.unwrap();
+
+        let cancelled = service.cancel_deletion(user.id).await.unwrap();
+
+        assert_eq!(cancelled.deletion_scheduled_at, None);
+        let far_future = Utc::now() + Duration::days(3650);
+        assert_eq!(
+            users.purge_scheduled_deletions(far_future).await.unwrap(),
+            0
+        );
+        assert!(users.find_by_id(user.id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn purge_deletes_only_accounts_past_their_deadline() {
+        let (service, users) = service(&[]);
+        let (due, _) = registered(&service, &users, "due@example.com").await;
+        let (pending, _) = registered(&service, &users, "pending@example.com").await;
+        let (kept, _) = registered(&service, &users, "kept@example.com").await;
+        let now = Utc::now();
+        users
+            .set_deletion_scheduled_at(due.id, Some(now - Duration::seconds(1)))
+            .await
+            .unwrap();
+        users
+            .set_deletion_scheduled_at(pending.id, Some(now + Duration::days(1)))
+            .await
+            .unwrap();
+
+        // Already cancelled, so the task returns after one pass
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+        service
+            .clone()
+            .run_account_purge(std::time::Duration::from_secs(3600), shutdown)
+            .await;
+
+        assert!(users.find_by_id(due.id).await.unwrap().is_none());
+        assert!(users.find_by_id(pending.id).await.unwrap().is_some());
+        assert!(users.find_by_id(kept.id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn tokens_name_every_audience_and_need_only_one() {
+        let (service, users) = service(&[("JWT_AUDIENCE", "api, billing")]);
+        let (_, token) = registered(&service, &users, "user@example.com").await;
+
+        let claims = service.verify_token(&token).await.unwrap();
+        assert_eq!(claims.aud, ["api", "billing"]);
+
+        let billing_only =
+            test_support::resign(&token, |claims| claims.aud = vec!["billing".into()]);
+        assert!(service.verify_token(&billing_only).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn tokens_for_other_audiences_are_rejected() {
+        let (service, users) = service(&[("JWT_AUDIENCE", "api,billing")]);
+        let (_, token) = registered(&service, &users, "user@example.com").await;
+
+        for aud in [vec!["reports".to_string()], Vec::new()] {
+            let other = test_support::resign(&token, |claims| claims.aud = aud.clone());
+            let result = service.verify_token(&other).await;
+            assert!(
+                matches!(&result, Err(AuthError::JwtError(_))),
+                "{:?}: {:?}",
+                aud,
+                result
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn login_with_correct_password() {
+        let (service, users) = service(&[]);
+        service
+            .register(
+                register_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+
+        let response = service
+            .login(
+                login_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+
+        let user = users
+            .find_by_email(&email("user@example.com"))
+            .await
+            .unwrap()
+            .unwrap();
+        let claims = service.verify_token(&response.token).await.unwrap();
+        assert_eq!(claims.sub, user.id.to_string());
+        assert!(response.refresh_token.is_some());
+    }
+
+    #[tokio::test]
+    async fn login_rejects_wrong_password() {
+        let (service, _) = service(&[]);
+        service
+            .register(
+                register_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+
+        let result = service
+            .login(
+                login_request("user@example.com", "wrong-password"),
+                ClientInfo::default(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+    }
+
+    #[tokio::test]
+    async fn login_for_an_unknown_email_verifies_against_the_dummy_hash() {
+        let (service, _) = service(&[]);
+
+        // Hashed like a real password, so checking it costs the same
+        let dummy = PasswordHash::new(&service.dummy_password_hash).unwrap();
+        let real_hash = service.hash_password(PASSWORD).unwrap();
+        let real = PasswordHash::new(&real_hash).unwrap();
+        assert_eq!(dummy.algorithm, real.algorithm);
+        assert_eq!(dummy.params, real.params);
+
+        // Well formed, so the verify runs Argon2 to the end rather than
+        // failing fast on a parse error
+        let started = std::time::Instant::now();
+        assert!(matches!(
+            service.verify_password(PASSWORD, &service.dummy_password_hash),
+            Err(AuthError::InvalidCredentials)
+        ));
+        let one_verify = started.elapsed();
+
+        let started = std::time::Instant::now();
+        let result = service
+            .login(
+                login_request("nobody@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await;
+        assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+        // Generous, to stay clear of scheduler noise; skipping the verify
+        // would take microseconds
+        assert!(started.elapsed() >= one_verify / 4);
+    }
+
+    #[tokio::test]
+    async fn login_advances_last_login_at() {
+        let (service, users) = service(&[]);
+        service
+            .register(
+                register_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        let last_login_at = || async {
+            users
+                .find_by_email(&email("user@example.com"))
+                .await
+                .unwrap()
+                .unwrap()
+                .last_login_at
+        };
+
+        service
+            .login(
+                login_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        let first = last_login_at().await.expect("set by the first login");
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        service
+            .login(
+                login_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        let second = last_login_at().await.expect("set by the second login");
+
+        assert!(second > first);
+    }
+
+    #[tokio::test]
+    async fn token_with_unexpected_alg_is_rejected() {
+        let (service, _) = service(&[]);
+        let token = service
+            .register(
+                register_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap()
+            .token;
+        let claims = service.verify_token(&token).await.unwrap();
+
+        // Same claims and secret, but a different member of the HMAC family
+        let hs384 = encode(
+            &Header::new(jsonwebtoken::Algorithm::HS384),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(test_support::JWT_SECRET.as_bytes()),
+        )
+        .unwrap();
+        // Same claims, unsigned
+        let payload = token.split('.').nth(1).unwrap();
+        let none = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#),
+            payload
+        );
+
+        for forged in [hs384, none] {
+            assert!(matches!(
+                service.verify_token(&forged).await,
+                Err(AuthError::InvalidToken)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn token_with_unknown_kid_is_rejected() {
+        let (service, _) = service(&[("JWT_KEY_ID", "current")]);
+        let token = service
+            .register(
+                register_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap()
+            .token;
+        let claims = service.verify_token(&token).await.unwrap();
+
+        let mut header = Header::new(jsonwebtoken::Algorithm::HS256);
+        header.kid = Some("bogus".to_string());
+        let bogus = encode(
+            &header,
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(test_support::JWT_SECRET.as_bytes()),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            service.verify_token(&bogus).await,
+            Err(AuthError::UnknownKeyId(kid)) if kid == "bogus"
+        ));
+    }
+
+    #[tokio::test]
+    async fn token_signed_before_a_key_reload_verifies_during_the_overlap() {
+        let (service, _) = service(&[]);
+        let before = service
+            .register(
+                register_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap()
+            .token;
+
+        let rotated = JwtKeys::hmac(b"rotated-secret-that-is-long-enough-too", None);
+        service.reload_jwt_keys(rotated, std::time::Duration::from_secs(60));
+
+        assert!(service.verify_token(&before).await.is_ok());
+        let after = service
+            .login(
+                login_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap()
+            .token;
+        let kid_of = |token: &str| decode_header(token).unwrap().kid.unwrap();
+        assert_ne!(kid_of(&after), kid_of(&before));
+        assert!(service.verify_token(&after).await.is_ok());
+
+        // Without an overlap the replaced key stops verifying at once, while
+        // the one before it keeps the rest of its own overlap
+        let again = JwtKeys::hmac(b"third-secret-that-is-long-enough-also", None);
+        service.reload_jwt_keys(again, std::time::Duration::ZERO);
+        assert!(matches!(
+            service.verify_token(&after).await,
+            Err(AuthError::UnknownKeyId(kid)) if kid == kid_of(&after)
+        ));
+        assert!(service.verify_token(&before).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn token_is_rejected_until_its_nbf() {
+        let (service, _) = service(&[("JWT_NOT_BEFORE_SECS", "3600")]);
+        let token = service
+            .register(
+                register_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap()
+            .token;
+
+        assert!(service.verify_token(&token).await.is_err());
+
+        // Once `nbf` is within the leeway the same claims verify
+        let due = test_support::resign(&token, |claims| {
+            claims.nbf = Utc::now().timestamp() + 30;
+        });
+        assert!(service.verify_token(&due).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn token_older_than_the_max_age_is_rejected_before_it_expires() {
+        let (service, users) = service(&[("MAX_TOKEN_AGE_SECONDS", "3600")]);
+        let (_, token) = registered(&service, &users, "user@example.com").await;
+        assert!(service.verify_token(&token).await.is_ok());
+
+        // Issued two hours ago with a day-long expiry
+        let old = test_support::resign(&token, |claims| {
+            let now = Utc::now().timestamp();
+            claims.iat = now - 7200;
+            claims.nbf = now - 7200;
+            claims.exp = now + 86400;
+        });
+        assert!(matches!(
+            service.verify_token(&old).await,
+            Err(AuthError::TokenTooOld)
+        ));
+    }
+
+    /// The `token` query parameter of the link in `message`
+    fn link_token(message: &EmailMessage) -> &str {
+        let start = message.body.find("token=").expect("message has a link") + "token=".len();
+        message.body[start..].split_whitespace().next().unwrap()
+    }
+
+    /// Register `old@example.com` and request a change to `new@example.com`,
+    /// returning its access token and the cancel and confirm messages
+    async fn change_email(
+        service: &AuthService,
+        users: &InMemoryUserRepository,
+        mailer: &RecordingMailer,
+    ) -> (String, EmailMessage, EmailMessage) {
+        let token = service
+            .register(
+                register_request("old@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap()
+            .token;
+        let user = users
+            .find_by_email(&email("old@example.com"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let pending = service
+            .request_email_change(user.id, email("new@example.com"))
+            .await
+            .unwrap();
+
+        assert_eq!(pending.email, "old@example.com");
+        assert_eq!(pending.pending_email.as_deref(), Some("new@example.com"));
+        let [_verification, cancel, confirm] = mailer
+            .sent()
+            .try_into()
+            .expect("verification and two change messages");
+        assert_eq!(cancel.to, "old@example.com");
+        assert_eq!(confirm.to, "new@example.com");
+        (token, cancel, confirm)
+    }
+
+    #[tokio::test]
+    async fn email_change_applies_once_confirmed() {
+        let (service, users, mailer) = mailing_service(&[]);
+        let (_, cancel, confirm) = change_email(&service, &users, &mailer).await;
+
+        let user = service
+            .confirm_email_change(link_token(&confirm))
+            .await
+            .unwrap();
+
+        assert_eq!(user.email, "new@example.com");
+        assert_eq!(user.pending_email, None);
+        // Following the link proved the new address works
+        assert!(user.email_verified_at.is_some());
+        service
+            .login(
+                login_request("new@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+        for token in [link_token(&confirm), link_token(&cancel)] {
+            assert!(matches!(
+                service.cancel_email_change(token).await,
+                Err(AuthError::InvalidToken)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn email_change_cancelled_from_the_old_address() {
+        let (service, users, mailer) = mailing_service(&[]);
+        let (token, cancel, confirm) = change_email(&service, &users, &mailer).await;
+
+        service
+            .cancel_email_change(link_token(&cancel))
+            .await
+            .unwrap();
+
+        let user = users
+            .find_by_email(&email("old@example.com"))
+            .await
+            .unwrap()
+            .expect("address unchanged");
+        assert_eq!(user.pending_email, None);
+        assert!(matches!(
+            service.confirm_email_change(link_token(&confirm)).await,
+            Err(AuthError::InvalidToken)
+        ));
+        // Whoever asked for the change is signed out
+        assert!(matches!(
+            service.verify_token(&token).await,
+            Err(AuthError::TokenRevoked)
+        ));
+    }
+
+    #[tokio::test]
+    async fn unverified_account_is_sent_a_new_verification_link() {
+        let (service, users, mailer) = mailing_service(&[]);
+        let (user, _) = registered(&service, &users, "user@example.com").await;
+        assert_eq!(user.email_verified_at, None);
+
+        service
+            .resend_verification(&email("user@example.com"))
+            .await
+            .unwrap();
+
+        let [_, resent] = mailer.sent().try_into().expect("two messages");
+        assert_eq!(resent.to, "user@example.com");
+        let verified = service.verify_email(link_token(&resent)).await.unwrap();
+        assert!(verified.email_verified_at.is_some());
+        let stored = users.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(stored.email_verified_at, verified.email_verified_at);
+    }
+
+    #[tokio::test]
+    async fn resend_to_a_verified_account_sends_nothing() {
+        let (service, users, mailer) = mailing_service(&[]);
+        let (user, _) = registered(&service, &users, "user@example.com").await;
+        let [welcome] = mailer.sent().try_into().expect("one message");
+        service.verify_email(link_token(&welcome)).await.unwrap();
+
+        service
+            .resend_verification(&email("user@example.com"))
+            .await
+            .unwrap();
+        service.resend_verification_for(user.id).await.unwrap();
+
+        assert_eq!(mailer.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resends_past_the_hourly_quota_and_to_unknown_addresses_are_silent() {
+        let (service, users, mailer) = mailing_service(&[("VERIFICATION_RESENDS_PER_HOUR", "1")]);
+        let (user, _) = registered(&service, &users, "user@example.com").await;
+
+        service.resend_verification_for(user.id).await.unwrap();
+        service
+            .resend_verification(&email("user@example.com"))
+            .await
+            .unwrap();
+        service
+            .resend_verification(&email("nobody@example.com"))
+            .await
+            .unwrap();
+
+        // The registration message and one resend
+        assert_eq!(mailer.sent().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn verification_link_for_a_replaced_address_is_refused() {
+        let (service, users, mailer) = mailing_service(&[]);
+        let (_, _, confirm) = change_email(&service, &users, &mailer).await;
+        let verification = mailer.sent().remove(0);
+        service
+            .confirm_email_change(link_token(&confirm))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.verify_email(link_token(&verification)).await,
+            Err(AuthError::InvalidToken)
+        ));
+    }
+
+    fn hashing(algorithm: Algorithm) -> PasswordHashing {
+        PasswordHashing {
+            algorithm,
+            output_len: Params::DEFAULT_OUTPUT_LEN,
+            salt_len: Salt::RECOMMENDED_LENGTH,
+        }
+    }
+
+    #[tokio::test]
+    async fn argon2i_hash_verifies_under_argon2id_config() {
+        let (service, users) = service(&[("PASSWORD_REHASH_ON_LOGIN", "false")]);
+        let argon2i = hash_password(&hashing(Algorithm::Argon2i), PASSWORD).unwrap();
+        assert!(argon2i.starts_with("$argon2i$"));
+        users
+            .create(&email("legacy@example.com"), &argon2i)
+            .await
+            .unwrap();
+
+        let result = service
+            .login(
+                login_request("legacy@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn bcrypt_hash_is_replaced_with_argon2_on_login() {
+        let (service, users) = service(&[]);
+        // `crypt.crypt(PASSWORD, "$2b$04$abcdefghijklmnopqrstuu")`
+        let bcrypt = "$2b$04$abcdefghijklmnopqrstuupOWRSKKNe562z1DsLEArFuJDHVyRIMa";
+        users
+            .create(&email("imported@example.com"), bcrypt)
+            .await
+            .unwrap();
+
+        let wrong = service
+            .login(
+                login_request("imported@example.com", "wrong-horse-battery"),
+                ClientInfo::default(),
+            )
+            .await;
+        assert!(matches!(wrong, Err(AuthError::InvalidCredentials)));
+
+        service
+            .login(
+                login_request("imported@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+
+        let user = users
+            .find_by_email(&email("imported@example.com"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            user.password_hash.starts_with("$argon2id$"),
+            "{}",
+            user.password_hash
+        );
+        service
+            .login(
+                login_request("imported@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn login_past_the_deletion_date_leaves_the_hash_alone() {
+        let (service, users) = service(&[]);
+        let argon2i = hash_password(&hashing(Algorithm::Argon2i), PASSWORD).unwrap();
+        let user = users
+            .create(&email("leaving@example.com"), &argon2i)
+            .await
+            .unwrap();
+        users
+            .set_deletion_scheduled_at(user.id, Some(Utc::now() - Duration::seconds(1)))
+            .await
+            .unwrap();
+
+        let result = service
+            .login(
+                login_request("leaving@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+        let user = users.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(user.password_hash, argon2i);
+    }
+
+    #[tokio::test]
+    async fn new_hashes_use_the_configured_variant() {
+        for (variant, prefix) in [
+            ("argon2i", "$argon2i$"),
+            ("argon2d", "$argon2d$"),
+            ("argon2id", "$argon2id$"),
+        ] {
+            let (service, users) = service(&[("ARGON2_VARIANT", variant)]);
+            service
+                .register(
+                    register_request("new@example.com", PASSWORD),
+                    ClientInfo::default(),
+                )
+                .await
+                .unwrap();
+
+            let user = users
+                .find_by_email(&email("new@example.com"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(
+                user.password_hash.starts_with(prefix),
+                "{}",
+                user.password_hash
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_output_and_salt_lengths_verify_without_rehashing() {
+        let (service, users) = service(&[("ARGON2_OUTPUT_LEN", "64"), ("ARGON2_SALT_LEN", "24")]);
+        let (user, _) = registered(&service, &users, "user@example.com").await;
+        let parsed = PasswordHash::new(&user.password_hash).unwrap();
+        assert_eq!(parsed.hash.unwrap().len(), 64);
+        assert_eq!(
+            parsed.salt.unwrap().len(),
+            32,
+            "24 bytes as unpadded base64"
+        );
+
+        service
+            .login(
+                login_request("user@example.com", PASSWORD),
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+
+        // Already matches the settings, so login leaves it alone
+        let stored = users.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(stored.password_hash, user.password_hash);
+    }
+
+    #[tokio::test]
+    async fn reset_tokens_carry_no_audience_yet_verify() {
+        let (service, users, mailer) = mailing_service(&[("JWT_AUDIENCE", "api")]);
+        registered(&service, &users, "user@example.com").await;
+
+        service
+            .request_password_reset(&email("user@example.com"))
+            .await
+            .unwrap();
+        let message = mailer.sent().pop().expect("reset link sent");
+
+        service
+            .reset_password(link_token(&message), "brand-new-horse-battery")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn reset_token_is_invalid_once_the_password_changes() {
+        let (service, users, mailer) = mailing_service(&[]);
+        let (user, _) = registered(&service, &users, "user@example.com").await;
+
+        service
+            .request_password_reset(&email("user@example.com"))
+            .await
+            .unwrap();
+        let message = mailer.sent().pop().expect("reset link sent");
+        service
+            .change_password(user.id, PASSWORD, "brand-new-horse-battery")
+            .await
+            .unwrap();
+
+        let result = service
+            .reset_password(link_token(&message), "another-new-horse-battery")
+            .await;
+        assert!(matches!(result, Err(AuthError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn reset_token_works_only_once() {
+        let (service, users, mailer) = mailing_service(&[]);
+        registered(&service, &users, "user@example.com").await;
+
+        service
+            .request_password_reset(&email("user@example.com"))
+            .await
+            .unwrap();
+        let message = mailer.sent().pop().expect("reset link sent");
+        service
+            .reset_password(link_token(&message), "brand-new-horse-battery")
+            .await
+            .unwrap();
+
+        let result = service
+            .reset_password(link_token(&message), "another-new-horse-battery")
+            .await;
+        assert!(matches!(result, Err(AuthError::InvalidToken)));
+    }
+}
@@ -0,0 +1,128 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Checks CAPTCHA tokens submitted by clients. Behind a trait so the HTTP
+/// provider can be swapped out, e.g. for a fake when testing.
+#[async_trait]
+pub trait CaptchaVerifier: Send + Sync {
+    /// Whether the provider accepted the token. `Err` means the provider
+    /// couldn't be asked, not that the token was bad.
+    async fn verify(&self, token: &str, remote_ip: Option<&str>) -> Result<bool, reqwest::Error>;
+}
+
+/// Verifier for providers speaking the hCaptcha/Turnstile `siteverify`
+/// protocol: a form POST answered with `{"success": bool, ...}`
+pub struct SiteverifyCaptcha {
+    client: reqwest::Client,
+    url: String,
+    secret: String,
+}
+
+#[derive(Deserialize)]
+struct SiteverifyResponse {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+impl SiteverifyCaptcha {
+    pub fn new(url: String, secret: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .expect("Failed to build CAPTCHA HTTP client");
+
+        Self {
+            client,
+            url,
+            secret,
+        }
+    }
+}
+
+#[async_trait]
+impl CaptchaVerifier for SiteverifyCaptcha {
+    async fn verify(&self, token: &str, remote_ip: Option<&str>) -> Result<bool, reqwest::Error> {
+        let mut form = vec![("secret", self.secret.as_str()), ("response", token)];
+        if let Some(ip) = remote_ip {
+            form.push(("remoteip", ip));
+        }
+
+        let response: SiteverifyResponse = self
+            .client
+            .post(&self.url)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if !response.success {
+            tracing::debug!("CAPTCHA rejected: {:?}", response.error_codes);
+        }
+
+        Ok(response.success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, json, request, send, PASSWORD};
+    use axum::{http::Method, http::StatusCode, routing::post, Form, Json, Router};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    /// Provider accepting the token `pass`, and only with the right secret
+    async fn siteverify() -> String {
+        let provider = Router::new().route(
+            "/siteverify",
+            post(|Form(form): Form<HashMap<String, String>>| async move {
+                let success = form["secret"] == "captcha-secret" && form["response"] == "pass";
+                Json(json!({ "success": success }))
+            }),
+        );
+        format!("{}/siteverify", test_support::serve(provider).await)
+    }
+
+    async fn register(captcha_token: Option<&str>) -> (StatusCode, Value) {
+        let database = test_support::database().await;
+        let url = siteverify().await;
+        let config = test_support::config(&[
+            ("CAPTCHA_ENABLED", "true"),
+            ("CAPTCHA_SECRET", "captcha-secret"),
+            ("CAPTCHA_VERIFY_URL", &url),
+        ]);
+        let app = test_support::app(&database, config);
+
+        let body = json!({
+            "email": "user@example.com",
+            "password": PASSWORD,
+            "captcha_token": captcha_token,
+        });
+        let response = send(
+            &app,
+            request(Method::POST, "/auth/register", None, Some(body)),
+        )
+        .await;
+        (response.status(), json(response).await)
+    }
+
+    #[tokio::test]
+    async fn registration_with_a_passing_captcha() {
+        let (status, _) = register(Some("pass")).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn registration_with_a_failing_captcha() {
+        for token in [Some("fail"), None] {
+            let (status, body) = register(token).await;
+
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["code"], "captcha_failed");
+        }
+    }
+}
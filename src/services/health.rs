@@ -0,0 +1,389 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+
+use crate::config::RedisConfig;
+use crate::db::Database;
+use crate::models::{DependencyState, DependencyStatus};
+
+// A dependency that hasn't answered by then counts as down
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Report = BTreeMap<String, DependencyStatus>;
+
+/// Whether a dependency being down affects `/ready`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Readiness {
+    /// Only reported by `/healthz/dependencies`
+    Ignored,
+    /// `/ready` stays 200 but reports `degraded`
+    Optional,
+    /// `/ready` answers 503
+    Required,
+}
+
+/// One downstream dependency reported by `/healthz/dependencies`
+#[async_trait]
+pub trait HealthChecker: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// `Err` carries why the dependency is considered down
+    async fn check(&self) -> Result<(), String>;
+}
+
+pub struct DatabaseChecker {
+    database: Database,
+}
+
+impl DatabaseChecker {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+}
+
+#[async_trait]
+impl HealthChecker for DatabaseChecker {
+    fn name(&self) -> &str {
+        "database"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.database
+            .execute("SELECT 1")
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Treats any HTTP response as up: only reachability is checked, since a
+/// bare HEAD isn't a meaningful request to these endpoints
+pub struct HttpChecker {
+    name: String,
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpChecker {
+    pub fn new(name: &str, url: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(CHECK_TIMEOUT)
+            .build()
+            .expect("Failed to build health check HTTP client");
+
+        Self {
+            name: name.to_string(),
+            url,
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl HealthChecker for HttpChecker {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.client
+            .head(&self.url)
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Sends `PING` over a plain connection and expects `PONG`, after `AUTH`
+/// when the URL carries a password. Speaks just enough RESP for that, so no
+/// Redis client is needed.
+pub struct RedisChecker {
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl RedisChecker {
+    pub fn new(config: &RedisConfig) -> Self {
+        Self {
+            address: config.address.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl HealthChecker for RedisChecker {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let stream = TcpStream::connect(&self.address)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut stream = BufReader::new(stream);
+
+        if let Some(password) = &self.password {
+            let mut auth = vec!["AUTH"];
+            auth.extend(self.username.as_deref());
+            auth.push(password);
+            redis_command(&mut stream, &auth, "+OK").await?;
+        }
+        redis_command(&mut stream, &["PING"], "+PONG").await
+    }
+}
+
+/// Send one command and compare its single-line reply with `expected`
+async fn redis_command(
+    stream: &mut BufReader<TcpStream>,
+    args: &[&str],
+    expected: &str,
+) -> Result<(), String> {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut reply = String::new();
+    stream
+        .read_line(&mut reply)
+        .await
+        .map_err(|e| e.to_string())?;
+    match reply.trim_end() {
+        "" => Err("connection closed".to_string()),
+        reply if reply == expected => Ok(()),
+        // Errors such as `-NOAUTH` say what's wrong; AUTH's never echo the password
+        reply => Err(format!("unexpected reply: {}", reply)),
+    }
+}
+
+/// Every registered dependency check, run together and cached for `ttl` so
+/// a busy dashboard doesn't hammer the dependencies
+#[derive(Clone)]
+pub struct HealthRegistry {
+    checkers: Vec<(Arc<dyn HealthChecker>, Readiness)>,
+    ttl: Duration,
+    cache: Arc<Mutex<Option<(Instant, Report)>>>,
+}
+
+impl HealthRegistry {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            checkers: Vec::new(),
+            ttl,
+            cache: Arc::default(),
+        }
+    }
+
+    pub fn register(self, checker: impl HealthChecker + 'static) -> Self {
+        self.register_for_readiness(checker, Readiness::Ignored)
+    }
+
+    pub fn register_for_readiness(
+        mut self,
+        checker: impl HealthChecker + 'static,
+        readiness: Readiness,
+    ) -> Self {
+        self.checkers.push((Arc::new(checker), readiness));
+        self
+    }
+
+    pub async fn report(&self) -> Report {
+        if let Some((at, report)) = self.cache.lock().unwrap().as_ref() {
+            if at.elapsed() < self.ttl {
+                return report.clone();
+            }
+        }
+
+        let report = run_checks(self.checkers.iter().map(|(checker, _)| checker)).await;
+        *self.cache.lock().unwrap() = Some((Instant::now(), report.clone()));
+        report
+    }
+
+    /// State of each dependency that affects `/ready`, checked now rather
+    /// than from the cache so a recovery is noticed on the next probe
+    pub async fn readiness(&self) -> BTreeMap<String, (Readiness, DependencyState)> {
+        let checkers = self
+            .checkers
+            .iter()
+            .filter(|(_, readiness)| *readiness != Readiness::Ignored);
+        let readiness: BTreeMap<&str, Readiness> = checkers
+            .clone()
+            .map(|(checker, readiness)| (checker.name(), *readiness))
+            .collect();
+
+        run_checks(checkers.map(|(checker, _)| checker))
+            .await
+            .into_iter()
+            .map(|(name, status)| {
+                let readiness = readiness[name.as_str()];
+                (name, (readiness, status.status))
+            })
+            .collect()
+    }
+}
+
+async fn run_checks<'a>(checkers: impl Iterator<Item = &'a Arc<dyn HealthChecker>>) -> Report {
+    let mut checks = JoinSet::new();
+    for checker in checkers {
+        let checker = checker.clone();
+        checks.spawn(async move {
+            let started = Instant::now();
+            let result = tokio::time::timeout(CHECK_TIMEOUT, checker.check())
+                .await
+                .unwrap_or_else(|_| Err("timed out".to_string()));
+            if let Err(e) = &result {
+                tracing::warn!("Health check for {} failed: {}", checker.name(), e);
+            }
+
+            let status = DependencyStatus {
+                status: if result.is_ok() {
+                    DependencyState::Up
+                } else {
+                    DependencyState::Down
+                },
+                latency_ms: started.elapsed().as_millis() as u64,
+                checked_at: Utc::now(),
+            };
+            (checker.name().to_string(), status)
+        });
+    }
+
+    let mut report = BTreeMap::new();
+    while let Some(result) = checks.join_next().await {
+        match result {
+            Ok((name, status)) => {
+                report.insert(name, status);
+            }
+            Err(e) => tracing::error!("Health check task failed: {}", e),
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers with `result`, counting how often it was asked
+    struct StubChecker {
+        name: &'static str,
+        result: Result<(), String>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl StubChecker {
+        fn new(name: &'static str, result: Result<(), String>) -> Self {
+            Self {
+                name,
+                result,
+                calls: Arc::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl HealthChecker for StubChecker {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.result.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_each_dependency_and_caches_the_result() {
+        let cache = StubChecker::new("cache", Ok(()));
+        let calls = cache.calls.clone();
+        let registry = HealthRegistry::new(Duration::from_secs(60))
+            .register(cache)
+            .register(StubChecker::new("search", Err("refused".to_string())));
+
+        let report = registry.report().await;
+        assert_eq!(report.len(), 2);
+        assert_eq!(report["cache"].status, DependencyState::Up);
+        assert_eq!(report["search"].status, DependencyState::Down);
+
+        let again = registry.report().await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(again["cache"].checked_at, report["cache"].checked_at);
+    }
+
+    #[tokio::test]
+    async fn redis_checker_authenticates_before_ping() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            for reply in [&b"+OK\r\n"[..], b"+PONG\r\n"] {
+                let mut buffer = [0; 128];
+                let n = socket.read(&mut buffer).await.unwrap();
+                received.extend_from_slice(&buffer[..n]);
+                socket.write_all(reply).await.unwrap();
+            }
+            String::from_utf8(received).unwrap()
+        });
+        let checker = RedisChecker::new(&RedisConfig {
+            address,
+            username: Some("app".to_string()),
+            password: Some("secret".to_string()),
+            required: true,
+        });
+
+        assert_eq!(checker.check().await, Ok(()));
+        assert_eq!(
+            server.await.unwrap(),
+            "*3\r\n$4\r\nAUTH\r\n$3\r\napp\r\n$6\r\nsecret\r\n*1\r\n$4\r\nPING\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_readiness_dependencies_are_checked_for_ready() {
+        let cache = StubChecker::new("cache", Err("refused".to_string()));
+        let calls = cache.calls.clone();
+        let registry = HealthRegistry::new(Duration::from_secs(60))
+            .register(cache)
+            .register_for_readiness(StubChecker::new("redis", Ok(())), Readiness::Required);
+
+        let readiness = registry.readiness().await;
+
+        assert_eq!(readiness.len(), 1);
+        assert_eq!(
+            readiness["redis"],
+            (Readiness::Required, DependencyState::Up)
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn checks_again_once_the_cache_expires() {
+        let cache = StubChecker::new("cache", Ok(()));
+        let calls = cache.calls.clone();
+        let registry = HealthRegistry::new(Duration::ZERO).register(cache);
+
+        registry.report().await;
+        registry.report().await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,222 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse,
+        RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Validation,
+};
+use rsa::{
+    pkcs1::DecodeRsaPrivateKey, pkcs8::DecodePrivateKey, traits::PublicKeyParts, RsaPrivateKey,
+};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// Signing and verification keys for access tokens
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    kid: String,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    // Public half of an asymmetric key, published via JWKS
+    jwk: Option<Jwk>,
+    // Set as `aud` on issued tokens; verified tokens must name one of them
+    audience: Vec<String>,
+    // Keys these replaced on a reload, still verifying (and published)
+    // until their deadline so tokens signed just before keep working
+    retired: Vec<RetiredKey>,
+}
+
+#[derive(Clone)]
+struct RetiredKey {
+    kid: String,
+    decoding: DecodingKey,
+    jwk: Option<Jwk>,
+    until: DateTime<Utc>,
+}
+
+impl JwtKeys {
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let keys = match config.jwt_algorithm {
+            Algorithm::HS256 => Ok(Self::hmac(
+                config.jwt_secret.as_bytes(),
+                config.jwt_key_id.clone(),
+            )),
+            Algorithm::RS256 => {
+                let path = config
+                    .jwt_private_key_path
+                    .as_deref()
+                    .ok_or("JWT_PRIVATE_KEY_PATH must be set for RS256")?;
+                let pem = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read JWT private key {}: {}", path, e))?;
+                Self::rsa(&pem, config.jwt_key_id.clone())
+            }
+            other => Err(format!("Unsupported JWT algorithm: {:?}", other)),
+        }?;
+
+        Ok(keys.with_audience(config.jwt_audience.clone()))
+    }
+
+    /// The key id defaults to a hash of the secret, domain-separated so it
+    /// reveals nothing usable about it
+    pub fn hmac(secret: &[u8], kid: Option<String>) -> Self {
+        let kid = kid.unwrap_or_else(|| {
+            let digest = Sha256::new()
+                .chain_update(b"jwt-kid:")
+                .chain_update(secret)
+                .finalize();
+            hex::encode(&digest[..8])
+        });
+
+        Self {
+            algorithm: Algorithm::HS256,
+            kid,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            jwk: None,
+            audience: Vec::new(),
+            retired: Vec::new(),
+        }
+    }
+
+    /// Accepts PKCS#8 or PKCS#1 PEM. The key id defaults to a thumbprint of
+    /// the public modulus so it changes whenever the key does.
+    pub fn rsa(private_pem: &str, kid: Option<String>) -> Result<Self, String> {
+        let private_key = RsaPrivateKey::from_pkcs8_pem(private_pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(private_pem))
+            .map_err(|e| format!("Invalid RSA private key: {}", e))?;
+        let public_key = private_key.to_public_key();
+
+        let n = URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be());
+        let e = URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be());
+        let kid = kid.unwrap_or_else(|| hex::encode(&Sha256::digest(n.as_bytes())[..8]));
+
+        let encoding = EncodingKey::from_rsa_pem(private_pem.as_bytes())
+            .map_err(|e| format!("Invalid RSA private key: {}", e))?;
+        let decoding = DecodingKey::from_rsa_components(&n, &e)
+            .map_err(|e| format!("Invalid RSA public key: {}", e))?;
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(KeyAlgorithm::RS256),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n,
+                e,
+            }),
+        };
+
+        Ok(Self {
+            algorithm: Algorithm::RS256,
+            kid,
+            encoding,
+            decoding,
+            jwk: Some(jwk),
+            audience: Vec::new(),
+            retired: Vec::new(),
+        })
+    }
+
+    /// These keys, taking over from `previous`: its signing key, and any key
+    /// it still accepted, keep verifying until `until`. Only tokens of this
+    /// algorithm verify at all, so switching algorithms gets no overlap.
+    pub fn replacing(mut self, previous: &JwtKeys, until: DateTime<Utc>) -> Self {
+        let now = Utc::now();
+        let replaced = RetiredKey {
+            kid: previous.kid.clone(),
+            decoding: previous.decoding.clone(),
+            jwk: previous.jwk.clone(),
+            until,
+        };
+        let still_accepted = previous.retired.iter().filter(|key| key.until > now);
+
+        self.retired = std::iter::once(&replaced)
+            .chain(still_accepted)
+            .filter(|key| key.kid != self.kid)
+            .cloned()
+            .collect();
+        self
+    }
+
+    pub fn with_audience(mut self, audience: Vec<String>) -> Self {
+        self.audience = audience;
+        self
+    }
+
+    pub fn audience(&self) -> &[String] {
+        &self.audience
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding
+    }
+
+    /// The key that checks a token whose header names `kid`: the signing
+    /// key, or a replaced one still in its overlap. Tokens without a `kid`
+    /// predate key ids and are checked against the signing key.
+    pub fn decoding_key(&self, kid: Option<&str>) -> Option<&DecodingKey> {
+        match kid {
+            None => Some(&self.decoding),
+            Some(kid) if kid == self.kid => Some(&self.decoding),
+            Some(kid) => self
+                .retired
+                .iter()
+                .find(|key| key.kid == kid && key.until > Utc::now())
+                .map(|key| &key.decoding),
+        }
+    }
+
+    /// Accepts exactly the configured algorithm. `alg: none` has no
+    /// `Algorithm` variant, so such tokens fail to parse at all. `nbf` is
+    /// checked with the same leeway as `exp`. With an audience configured,
+    /// a token must name at least one of its members in `aud`; one without
+    /// `aud` is refused too, not waved through.
+    pub fn validation(&self) -> Validation {
+        let mut validation = Validation::new(self.algorithm);
+        validation.algorithms = vec![self.algorithm];
+        validation.validate_nbf = true;
+        if !self.audience.is_empty() {
+            validation.set_audience(&self.audience);
+            validation.required_spec_claims.insert("aud".to_string());
+        }
+        validation
+    }
+
+    /// `validation` for tokens that only ever come back to this service,
+    /// which carry no `aud`
+    pub fn validation_without_audience(&self) -> Validation {
+        let mut validation = self.validation();
+        validation.validate_aud = false;
+        validation.required_spec_claims.remove("aud");
+        validation
+    }
+
+    /// `None` for symmetric algorithms, which must never be published.
+    /// Replaced keys are listed until their overlap ends.
+    pub fn jwks(&self) -> Option<JwkSet> {
+        let current = self.jwk.clone()?;
+        let now = Utc::now();
+        let retired = self
+            .retired
+            .iter()
+            .filter(|key| key.until > now)
+            .filter_map(|key| key.jwk.clone());
+        Some(JwkSet {
+            keys: std::iter::once(current).chain(retired).collect(),
+        })
+    }
+}
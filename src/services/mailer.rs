@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+
+/// A plain-text email
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Sends transactional email. Behind a trait so a real transport (SMTP, a
+/// provider's HTTP API) can be plugged in without touching the services.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> anyhow::Result<()>;
+}
+
+/// Writes each message to the log instead of sending it. Bodies carry
+/// single-use links, so this is only fit for development.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, message: EmailMessage) -> anyhow::Result<()> {
+        tracing::info!(
+            to = %message.to,
+            subject = %message.subject,
+            "Email not sent (no mail transport configured):\n{}",
+            message.body
+        );
+        Ok(())
+    }
+}
+
+/// Keeps every message instead of sending it, for tests to read links from
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingMailer {
+    sent: Arc<Mutex<Vec<EmailMessage>>>,
+}
+
+#[cfg(test)]
+impl RecordingMailer {
+    pub fn sent(&self) -> Vec<EmailMessage> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Mailer for RecordingMailer {
+    async fn send(&self, message: EmailMessage) -> anyhow::Result<()> {
+        self.sent.lock().unwrap().push(message);
+        Ok(())
+    }
+}
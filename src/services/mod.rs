@@ -1,3 +1,26 @@
+pub mod api_key_service;
+pub mod audit_service;
 pub mod auth_service;
+pub mod bcrypt;
+pub mod captcha;
+pub mod health;
+pub mod jwt_keys;
+pub mod mailer;
+pub mod registration_hooks;
+pub mod user_service;
+pub mod webhook_service;
 
-pub use auth_service::AuthService;
+pub use api_key_service::ApiKeyService;
+pub use audit_service::AuditService;
+pub use auth_service::{AuthService, EmailDomainPolicy, PasswordHashing};
+pub use captcha::{CaptchaVerifier, SiteverifyCaptcha};
+pub use health::{DatabaseChecker, HealthRegistry, HttpChecker, Readiness, RedisChecker};
+pub use jwt_keys::JwtKeys;
+#[cfg(test)]
+pub use mailer::RecordingMailer;
+pub use mailer::{EmailMessage, LogMailer, Mailer};
+#[cfg(test)]
+pub use registration_hooks::StubHook;
+pub use registration_hooks::{PostRegistrationHook, RegistrationHooks, WelcomeEmail};
+pub use user_service::UserService;
+pub use webhook_service::WebhookService;
@@ -0,0 +1,190 @@
+use async_trait::async_trait;
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::models::User;
+use crate::services::{EmailMessage, Mailer};
+
+/// Work run after a user registers, such as a welcome email or provisioning
+/// rows in other tables. Enabled by name through `REGISTRATION_HOOKS`.
+#[async_trait]
+pub trait PostRegistrationHook: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// A critical hook's failure undoes the registration; any other hook's
+    /// failure is only logged
+    fn critical(&self) -> bool {
+        false
+    }
+
+    async fn run(&self, user: &User) -> anyhow::Result<()>;
+}
+
+/// Names accepted in `REGISTRATION_HOOKS`
+pub const HOOK_NAMES: &[&str] = &[WelcomeEmail::NAME];
+
+/// The enabled hooks, run in the configured order
+#[derive(Clone, Default)]
+pub struct RegistrationHooks {
+    hooks: Vec<Arc<dyn PostRegistrationHook>>,
+}
+
+impl RegistrationHooks {
+    pub fn new(hooks: Vec<Arc<dyn PostRegistrationHook>>) -> Self {
+        Self { hooks }
+    }
+
+    /// Run every hook for `user`. Stops at the first critical failure and
+    /// returns it; later hooks don't run.
+    pub async fn run(&self, user: &User) -> anyhow::Result<()> {
+        for hook in &self.hooks {
+            match hook.run(user).await {
+                Ok(()) => {}
+                Err(e) if hook.critical() => {
+                    return Err(e.context(format!("registration hook {} failed", hook.name())));
+                }
+                Err(e) => tracing::warn!(
+                    hook = hook.name(),
+                    user_id = %user.id,
+                    "Registration hook failed: {:#}",
+                    e
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Emails new users a greeting with a link to the frontend
+pub struct WelcomeEmail {
+    mailer: Arc<dyn Mailer>,
+    app_url: String,
+}
+
+impl WelcomeEmail {
+    pub const NAME: &'static str = "welcome_email";
+
+    pub fn new(mailer: Arc<dyn Mailer>, app_url: String) -> Self {
+        Self { mailer, app_url }
+    }
+}
+
+#[async_trait]
+impl PostRegistrationHook for WelcomeEmail {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    async fn run(&self, user: &User) -> anyhow::Result<()> {
+        self.mailer
+            .send(EmailMessage {
+                to: user.email.clone(),
+                subject: "Welcome".to_string(),
+                body: format!("Your account is ready. Sign in at {}/login", self.app_url),
+            })
+            .await
+    }
+}
+
+/// Counts its runs and fails on demand, for tests of hook handling
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct StubHook {
+    critical: bool,
+    fails: bool,
+    runs: Arc<AtomicUsize>,
+}
+
+#[cfg(test)]
+impl StubHook {
+    /// A hook whose every run fails
+    pub fn failing(critical: bool) -> Self {
+        Self {
+            critical,
+            fails: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn runs(&self) -> usize {
+        self.runs.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl PostRegistrationHook for StubHook {
+    fn name(&self) -> &'static str {
+        "stub"
+    }
+
+    fn critical(&self) -> bool {
+        self.critical
+    }
+
+    async fn run(&self, _user: &User) -> anyhow::Result<()> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        if self.fails {
+            anyhow::bail!("stub hook failed");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Role;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            email: "user@example.com".to_string(),
+            password_hash: String::new(),
+            role: Role::User,
+            token_version: 0,
+            name: None,
+            avatar_url: None,
+            pending_email: None,
+            email_verified_at: None,
+            version: 1,
+            deletion_scheduled_at: None,
+            last_login_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn hooks(stubs: &[&StubHook]) -> RegistrationHooks {
+        RegistrationHooks::new(
+            stubs
+                .iter()
+                .map(|stub| Arc::new((*stub).clone()) as Arc<dyn PostRegistrationHook>)
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn non_critical_failures_are_logged_and_later_hooks_still_run() {
+        let failing = StubHook::failing(false);
+        let next = StubHook::default();
+
+        hooks(&[&failing, &next]).run(&user()).await.unwrap();
+
+        assert_eq!((failing.runs(), next.runs()), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn critical_failure_stops_the_run() {
+        let failing = StubHook::failing(true);
+        let next = StubHook::default();
+
+        let error = hooks(&[&failing, &next]).run(&user()).await.unwrap_err();
+
+        assert!(format!("{:#}", error).contains("registration hook stub failed"));
+        assert_eq!((failing.runs(), next.runs()), (1, 0));
+    }
+}
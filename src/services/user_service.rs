@@ -0,0 +1,149 @@
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::models::{
+    ListUsersQuery, SortOrder, UpdateProfileRequest, UserCursor, UserCursorPage, UserListResponse,
+    UserResponse, UserSortColumn,
+};
+use crate::repositories::UserRepository;
+
+#[derive(Error, Debug)]
+pub enum UserError {
+    #[error("Invalid sort column: {0}")]
+    InvalidSortColumn(String),
+    #[error("Invalid sort order: {0}")]
+    InvalidSortOrder(String),
+    #[error("Invalid cursor")]
+    InvalidCursor,
+    #[error("Cursor paging can't be combined with page or sort parameters")]
+    MixedPagination,
+    #[error("User not found")]
+    UserNotFound,
+    #[error("`version` is required")]
+    VersionRequired,
+    #[error("User was modified concurrently")]
+    VersionConflict,
+    #[error("If-Match does not match the current ETag")]
+    PreconditionFailed,
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+#[derive(Clone)]
+pub struct UserService {
+    user_repository: Arc<dyn UserRepository>,
+    default_page_size: u32,
+    max_page_size: u32,
+}
+
+impl UserService {
+    pub fn new(
+        user_repository: Arc<dyn UserRepository>,
+        default_page_size: u32,
+        max_page_size: u32,
+    ) -> Self {
+        Self {
+            user_repository,
+            default_page_size,
+            max_page_size,
+        }
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<UserResponse, UserError> {
+        let user = self
+            .user_repository
+            .find_by_id(id)
+            .await?
+            .ok_or(UserError::UserNotFound)?;
+
+        Ok(user.into())
+    }
+
+    pub async fn update_profile(
+        &self,
+        id: Uuid,
+        changes: UpdateProfileRequest,
+    ) -> Result<UserResponse, UserError> {
+        if let Some(user) = self.user_repository.update_profile(id, &changes).await? {
+            return Ok(user.into());
+        }
+
+        // No row matched: either the user is gone or the version moved on
+        match changes.version {
+            Some(_) if self.user_repository.find_by_id(id).await?.is_some() => {
+                Err(UserError::VersionConflict)
+            }
+            _ => Err(UserError::UserNotFound),
+        }
+    }
+
+    pub async fn list(&self, query: ListUsersQuery) -> Result<UserListResponse, UserError> {
+        let sort_by = match query.sort_by.as_deref() {
+            Some(value) => UserSortColumn::parse(value)
+                .ok_or_else(|| UserError::InvalidSortColumn(value.to_string()))?,
+            None => UserSortColumn::CreatedAt,
+        };
+
+        let order = match query.order.as_deref() {
+            Some(value) => SortOrder::parse(value)
+                .ok_or_else(|| UserError::InvalidSortOrder(value.to_string()))?,
+            None => SortOrder::Desc,
+        };
+
+        let page = query.page.unwrap_or(1).max(1);
+        let per_page = query
+            .per_page
+            .unwrap_or(self.default_page_size)
+            .clamp(1, self.max_page_size.max(1));
+        let offset = i64::from(page - 1) * i64::from(per_page);
+
+        let users = self
+            .user_repository
+            .list(sort_by, order, i64::from(per_page), offset)
+            .await?;
+        let total = self.user_repository.count().await?;
+
+        Ok(UserListResponse {
+            users: users.into_iter().map(Into::into).collect(),
+            page,
+            per_page,
+            total,
+        })
+    }
+
+    /// Keyset pagination in `(created_at, id)` order. Unlike offset paging,
+    /// cost doesn't grow with how deep the client has paged.
+    pub async fn list_after(&self, query: ListUsersQuery) -> Result<UserCursorPage, UserError> {
+        if query.page.is_some() || query.sort_by.is_some() || query.order.is_some() {
+            return Err(UserError::MixedPagination);
+        }
+
+        let cursor = match query.after.as_deref() {
+            Some(value) => Some(UserCursor::decode(value).ok_or(UserError::InvalidCursor)?),
+            None => None,
+        };
+        let limit = query
+            .limit
+            .or(query.per_page)
+            .unwrap_or(self.default_page_size)
+            .clamp(1, self.max_page_size.max(1)) as usize;
+
+        // Fetch one extra row to learn whether another page exists
+        let mut users = self
+            .user_repository
+            .list_after(cursor, limit as i64 + 1)
+            .await?;
+        let next_cursor = if users.len() > limit {
+            users.truncate(limit);
+            users.last().map(|user| UserCursor::from(user).encode())
+        } else {
+            None
+        };
+
+        Ok(UserCursorPage {
+            users: users.into_iter().map(Into::into).collect(),
+            next_cursor,
+        })
+    }
+}
@@ -0,0 +1,408 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::models::{User, UserResponse, WebhookDelivery};
+use crate::repositories::WebhookRepository;
+use crate::tasks::TaskManager;
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+const MAX_ATTEMPTS: i32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+#[derive(Clone)]
+pub struct WebhookService {
+    client: reqwest::Client,
+    url: Option<String>,
+    secret: String,
+    deliveries: Arc<dyn WebhookRepository>,
+    // The same logical event is sent at most once per window
+    dedupe_window: chrono::Duration,
+    // Deliveries run here so shutdown waits for them
+    tasks: TaskManager,
+}
+
+impl WebhookService {
+    pub fn new(
+        url: Option<String>,
+        secret: Option<String>,
+        deliveries: Arc<dyn WebhookRepository>,
+        dedupe_window_secs: i64,
+        tasks: TaskManager,
+    ) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build webhook HTTP client");
+
+        Self {
+            client,
+            url,
+            secret: secret.unwrap_or_default(),
+            deliveries,
+            dedupe_window: chrono::Duration::seconds(dedupe_window_secs),
+            tasks,
+        }
+    }
+
+    /// Notify downstream systems that a user registered. Delivery runs on a
+    /// background task so the caller never waits on the receiver.
+    pub fn user_registered(&self, user: &User) {
+        let response = UserResponse::from(user.clone());
+        self.dispatch("user.registered", &user.id.to_string(), json!(response));
+    }
+
+    /// Restart deliveries cut short by the previous shutdown. A delivery
+    /// that went out just before the process died may be sent again; its
+    /// `event_id` is unchanged, so receivers can drop it.
+    pub async fn resume_pending(&self) {
+        if self.url.is_none() {
+            return;
+        }
+
+        let pending = match self.deliveries.list_pending().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                tracing::error!("Failed to load pending webhook deliveries: {}", e);
+                return;
+            }
+        };
+        if pending.is_empty() {
+            return;
+        }
+
+        tracing::info!("Resuming {} pending webhook deliveries", pending.len());
+        for delivery in pending {
+            let service = self.clone();
+            self.tasks
+                .spawn(async move { service.deliver(delivery).await });
+        }
+    }
+
+    /// `subject` names what the event is about; together with the event it
+    /// forms the idempotency key
+    fn dispatch(&self, event: &str, subject: &str, data: Value) {
+        if self.url.is_none() {
+            return;
+        }
+
+        let now = Utc::now();
+        let event_id = Uuid::new_v4();
+        let idempotency_key = format!("{}:{}", event, subject);
+        let payload = json!({
+            "event_id": event_id,
+            "idempotency_key": idempotency_key,
+            "event": event,
+            "created_at": now,
+            "data": data,
+        })
+        .to_string();
+
+        let delivery = WebhookDelivery {
+            id: event_id,
+            idempotency_key,
+            event: event.to_string(),
+            payload,
+            attempts: 0,
+            created_at: now,
+        };
+        let since = now - self.dedupe_window;
+        let service = self.clone();
+
+        self.tasks.spawn(async move {
+            match service.deliveries.create(&delivery, since).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    tracing::debug!(
+                        "Webhook {} already dispatched within the window, skipping",
+                        delivery.idempotency_key
+                    );
+                    return;
+                }
+                // Better to risk a duplicate than to drop the event
+                Err(e) => tracing::warn!(
+                    "Failed to record webhook {}, delivering untracked: {}",
+                    delivery.idempotency_key,
+                    e
+                ),
+            }
+            service.deliver(delivery).await;
+        });
+    }
+
+    async fn deliver(&self, delivery: WebhookDelivery) {
+        let Some(url) = self.url.as_deref() else {
+            return;
+        };
+        let signature = self.sign(&delivery.payload);
+        let event = &delivery.event;
+        let mut backoff = INITIAL_BACKOFF;
+
+        // Attempts made before a restart count towards the limit
+        for attempt in delivery.attempts + 1..=MAX_ATTEMPTS {
+            let result = self
+                .client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .header(IDEMPOTENCY_KEY_HEADER, &delivery.idempotency_key)
+                .body(delivery.payload.clone())
+                .send()
+                .await;
+            self.track(self.deliveries.record_attempt(delivery.id).await);
+
+            match result {
+                Ok(response) if response.status().is_success() => {
+                    tracing::debug!("Webhook {} delivered on attempt {}", event, attempt);
+                    self.track(self.deliveries.mark_delivered(delivery.id).await);
+                    return;
+                }
+                // 4xx means the receiver rejected the event; retrying won't help
+                Ok(response) if !response.status().is_server_error() => {
+                    tracing::warn!(
+                        "Webhook {} rejected with status {}",
+                        event,
+                        response.status()
+                    );
+                    break;
+                }
+                Ok(response) => {
+                    tracing::warn!(
+                        "Webhook {} attempt {} failed with status {}",
+                        event,
+                        attempt,
+                        response.status()
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!("Webhook {} attempt {} failed: {}", event, attempt, e);
+                }
+            }
+
+            if attempt < MAX_ATTEMPTS {
+                // On shutdown, leave the delivery pending for the next start
+                // rather than hold the process up through the backoff
+                let token = self.tasks.token();
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = token.cancelled() => {
+                        tracing::info!("Webhook {} left pending for resume at shutdown", event);
+                        return;
+                    }
+                }
+                backoff *= 2;
+            }
+        }
+
+        self.track(self.deliveries.mark_failed(delivery.id).await);
+
+        // Dead letter: keep the full payload in the logs so it can be replayed
+        tracing::error!(
+            event = %event,
+            event_id = %delivery.id,
+            url = %url,
+            payload = %delivery.payload,
+            "Webhook delivery failed, giving up"
+        );
+    }
+
+    // Delivery state is bookkeeping; failing to save it mustn't stop delivery
+    fn track(&self, result: Result<(), sqlx::Error>) {
+        if let Err(e) = result {
+            tracing::warn!("Failed to update webhook delivery state: {}", e);
+        }
+    }
+
+    fn sign(&self, payload: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Role;
+    use crate::test_support;
+    use axum::{
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::mpsc;
+
+    const SECRET: &str = "webhook-secret";
+
+    type Received = mpsc::UnboundedSender<(HeaderMap, String)>;
+
+    /// Receiver that answers the first `failures` posts with 503
+    async fn receiver(failures: usize) -> (String, mpsc::UnboundedReceiver<(HeaderMap, String)>) {
+        let (sender, received) = mpsc::unbounded_channel();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let router = Router::new()
+            .route(
+                "/hook",
+                post(
+                    move |State((sender, attempts)): State<(Received, Arc<AtomicUsize>)>,
+                          headers: HeaderMap,
+                          body: String| async move {
+                        if attempts.fetch_add(1, Ordering::SeqCst) < failures {
+                            return StatusCode::SERVICE_UNAVAILABLE;
+                        }
+                        sender.send((headers, body)).unwrap();
+                        StatusCode::NO_CONTENT
+                    },
+                ),
+            )
+            .with_state((sender, attempts));
+        let url = format!("{}/hook", test_support::serve(router).await);
+        (url, received)
+    }
+
+    fn user() -> User {
+        let now = Utc::now();
+        User {
+            id: Uuid::new_v4(),
+            email: "new@example.com".to_string(),
+            password_hash: "$argon2id$secret-hash".to_string(),
+            role: Role::User,
+            token_version: 0,
+            name: None,
+            avatar_url: None,
+            pending_email: None,
+            email_verified_at: None,
+            version: 1,
+            deletion_scheduled_at: None,
+            last_login_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn service(url: Option<String>, database: &crate::db::Database) -> WebhookService {
+        windowed_service(url, database, 3600)
+    }
+
+    fn windowed_service(
+        url: Option<String>,
+        database: &crate::db::Database,
+        dedupe_window_secs: i64,
+    ) -> WebhookService {
+        WebhookService::new(
+            url,
+            Some(SECRET.to_string()),
+            database.webhook_repository(),
+            dedupe_window_secs,
+            TaskManager::new(),
+        )
+    }
+
+    async fn next_delivery(received: &mut mpsc::UnboundedReceiver<(HeaderMap, String)>) -> Value {
+        let (_, body) = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("webhook delivered")
+            .unwrap();
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_registration_payload() {
+        let database = test_support::database().await;
+        let (url, mut received) = receiver(0).await;
+        let user = user();
+
+        service(Some(url), &database).user_registered(&user);
+        let (headers, body) = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("webhook delivered")
+            .unwrap();
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert_eq!(headers[SIGNATURE_HEADER], expected.as_str());
+        assert_eq!(
+            headers[IDEMPOTENCY_KEY_HEADER],
+            format!("user.registered:{}", user.id).as_str()
+        );
+
+        let payload: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload["event"], "user.registered");
+        assert_eq!(payload["data"]["email"], "new@example.com");
+        assert!(!body.contains("secret-hash"));
+    }
+
+    #[tokio::test]
+    async fn retries_after_server_error() {
+        let database = test_support::database().await;
+        let (url, mut received) = receiver(1).await;
+
+        service(Some(url), &database).user_registered(&user());
+
+        tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("webhook delivered on the second attempt")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn does_nothing_without_url() {
+        let database = test_support::database().await;
+        let service = service(None, &database);
+
+        service.user_registered(&user());
+        service.tasks.shutdown(Duration::from_secs(1)).await;
+
+        assert!(database
+            .webhook_repository()
+            .list_pending()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn same_event_is_sent_once_within_the_window() {
+        let database = test_support::database().await;
+        let (url, mut received) = receiver(0).await;
+        let service = service(Some(url), &database);
+        let user = user();
+
+        service.user_registered(&user);
+        service.user_registered(&user);
+
+        let payload = next_delivery(&mut received).await;
+        assert_eq!(
+            payload["idempotency_key"],
+            format!("user.registered:{}", user.id)
+        );
+        let again = tokio::time::timeout(Duration::from_millis(500), received.recv()).await;
+        assert!(again.is_err(), "duplicate delivered: {:?}", again);
+    }
+
+    #[tokio::test]
+    async fn same_event_is_sent_again_after_the_window() {
+        let database = test_support::database().await;
+        let (url, mut received) = receiver(0).await;
+        let service = windowed_service(Some(url), &database, 0);
+        let user = user();
+
+        service.user_registered(&user);
+        let first = next_delivery(&mut received).await;
+        service.user_registered(&user);
+        let second = next_delivery(&mut received).await;
+
+        assert_eq!(first["idempotency_key"], second["idempotency_key"]);
+        assert_ne!(first["event_id"], second["event_id"]);
+    }
+}
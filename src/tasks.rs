@@ -0,0 +1,99 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Background tasks that should finish before the process exits. Tasks get
+/// a shared `CancellationToken` to stop waiting on new work; whatever they
+/// have in flight is given until the shutdown timeout to complete.
+#[derive(Clone, Default)]
+pub struct TaskManager {
+    token: CancellationToken,
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl TaskManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancelled once shutdown begins
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task);
+        let mut handles = self.handles.lock().expect("task list lock poisoned");
+        // Drop finished tasks so a long-running process doesn't accumulate them
+        handles.retain(|handle| !handle.is_finished());
+        handles.push(handle);
+    }
+
+    /// Cancel the token and wait up to `timeout` for every task to return.
+    /// Tasks still running after that are left to die with the runtime.
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.token.cancel();
+
+        let handles = std::mem::take(&mut *self.handles.lock().expect("task list lock poisoned"));
+        let running = handles.iter().filter(|h| !h.is_finished()).count();
+        if running == 0 {
+            return;
+        }
+
+        tracing::info!("Waiting for {} background tasks to finish", running);
+        let join_all = async {
+            for handle in handles {
+                if let Err(e) = handle.await {
+                    tracing::error!("Background task failed: {}", e);
+                }
+            }
+        };
+        if tokio::time::timeout(timeout, join_all).await.is_err() {
+            tracing::warn!(
+                "Background tasks still running after {:?}, abandoning them",
+                timeout
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn shutdown_cancels_and_awaits_tasks() {
+        let tasks = TaskManager::new();
+        let finished = Arc::new(AtomicBool::new(false));
+        let token = tasks.token();
+        let flag = finished.clone();
+        tasks.spawn(async move {
+            token.cancelled().await;
+            // Work still in flight when cancelled is waited for
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        tasks.shutdown(Duration::from_secs(5)).await;
+
+        assert!(tasks.token().is_cancelled());
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn shutdown_gives_up_after_the_timeout() {
+        let tasks = TaskManager::new();
+        tasks.spawn(std::future::pending());
+
+        let started = std::time::Instant::now();
+        tasks.shutdown(Duration::from_millis(50)).await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
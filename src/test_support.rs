@@ -0,0 +1,344 @@
+//! Fixtures shared by the in-module tests
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Method, Request, StatusCode},
+    response::Response,
+    Extension, Router,
+};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::Sqlite;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tower::ServiceExt;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::subscriber::DefaultGuard;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+use crate::config::Config;
+use crate::db::{Database, StatementLogging};
+use crate::lifecycle::Lifecycle;
+use crate::models::{Claims, Email, Role};
+use crate::routes::{auth_service, create_routes};
+
+/// Satisfies the password policy
+pub const PASSWORD: &str = "correct-horse-battery";
+
+pub const JWT_SECRET: &str = "test-secret-that-is-long-enough-for-hs256";
+
+/// A 2048-bit RSA key for RS256 tests
+pub const RSA_KEY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/jwt_rsa.pem");
+
+/// Self-signed P-256 certificate for `localhost`, and its key
+pub const TLS_CERT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls_cert.pem");
+pub const TLS_KEY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls_key.pem");
+
+/// Config as `from_env` would build it from `vars` alone, plus a JWT
+/// secret. Later pairs win.
+pub fn config(vars: &[(&str, &str)]) -> Config {
+    try_config(vars).expect("test config is valid")
+}
+
+pub fn try_config(vars: &[(&str, &str)]) -> Result<Config, String> {
+    let defaults = [
+        ("DATABASE_URL", "sqlite::memory:"),
+        ("JWT_SECRET", JWT_SECRET),
+    ];
+    Config::from_lookup(|name| {
+        vars.iter()
+            .rev()
+            .chain(defaults.iter())
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+    })
+}
+
+/// A SQLite database in a temporary directory, removed on drop.
+/// A file rather than `:memory:` so every pooled connection sees it.
+pub struct TestDatabase {
+    pub database: Database,
+    path: PathBuf,
+    _dir: TempDir,
+}
+
+impl TestDatabase {
+    /// The same database behind a one-connection pool whose connection is
+    /// held by the returned guard, so every query times out acquiring
+    pub async fn exhausted(&self) -> (Database, PoolConnection<Sqlite>) {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_millis(50))
+            .connect_with(SqliteConnectOptions::new().filename(&self.path))
+            .await
+            .expect("second pool connects");
+        let held = pool.acquire().await.expect("the only connection");
+        (Database::Sqlite(pool), held)
+    }
+}
+
+impl std::ops::Deref for TestDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.database
+    }
+}
+
+/// A database with every migration applied
+pub async fn database() -> TestDatabase {
+    let database = unmigrated_database().await;
+    database.migrate().await.expect("test migrations run");
+    database
+}
+
+/// Like `database`, but without any tables
+pub async fn unmigrated_database() -> TestDatabase {
+    let dir = TempDir::new().expect("temporary directory");
+    let path = dir.path().join("test.db");
+    let url = format!("sqlite:{}", path.display());
+    let logging = StatementLogging {
+        statements: false,
+        slow_threshold: None,
+    };
+    let database = Database::connect(&url, logging)
+        .await
+        .expect("test database connects");
+
+    TestDatabase {
+        database,
+        path,
+        _dir: dir,
+    }
+}
+
+/// The full router, as `main` serves it minus tracing, on `database`.
+/// Requests come from 127.0.0.1.
+pub fn app(database: &Database, config: Config) -> Router {
+    app_with_lifecycle(database, config, Lifecycle::new())
+}
+
+pub fn app_with_lifecycle(database: &Database, config: Config, lifecycle: Lifecycle) -> Router {
+    let auth_service = auth_service(database, &config, lifecycle.tasks());
+    create_routes(database.clone(), config, lifecycle, auth_service).layer(Extension(ConnectInfo(
+        SocketAddr::from(([127, 0, 0, 1], 40000)),
+    )))
+}
+
+/// A request with an optional JSON body and bearer token
+pub fn request(
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> Request<Body> {
+    let mut builder = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    match body {
+        Some(body) => builder
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => builder.body(Body::empty()),
+    }
+    .expect("valid test request")
+}
+
+pub async fn send(app: &Router, request: Request<Body>) -> Response {
+    app.clone()
+        .oneshot(request)
+        .await
+        .expect("router is infallible")
+}
+
+pub async fn body_bytes(response: Response) -> Vec<u8> {
+    response
+        .into_body()
+        .collect()
+        .await
+        .expect("body collects")
+        .to_bytes()
+        .to_vec()
+}
+
+pub async fn json(response: Response) -> Value {
+    serde_json::from_slice(&body_bytes(response).await).expect("body is JSON")
+}
+
+/// Register `email`, returning its access token
+pub async fn sign_up(app: &Router, email: &str, password: &str) -> String {
+    let credentials = serde_json::json!({ "email": email, "password": password });
+    let response = send(
+        app,
+        request(Method::POST, "/auth/register", None, Some(credentials)),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    json(response).await["token"]
+        .as_str()
+        .expect("registration returns a token")
+        .to_string()
+}
+
+/// Register `email` as an admin, returning an access token that carries
+/// the role
+pub async fn sign_up_admin(app: &Router, database: &Database, email: &str) -> String {
+    sign_up(app, email, PASSWORD).await;
+    let users = database.user_repository();
+    let user = users
+        .find_by_email(&Email::try_from(email.to_string()).expect("valid email"))
+        .await
+        .expect("user lookup")
+        .expect("user exists");
+    users
+        .set_role(user.id, Role::Admin)
+        .await
+        .expect("role update")
+        .expect("user exists");
+    log_in(app, email, PASSWORD).await
+}
+
+/// Log in as `email`, returning the access token
+pub async fn log_in(app: &Router, email: &str, password: &str) -> String {
+    let credentials = serde_json::json!({ "email": email, "password": password });
+    let response = send(
+        app,
+        request(Method::POST, "/auth/login", None, Some(credentials)),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    json(response).await["token"]
+        .as_str()
+        .expect("login returns a token")
+        .to_string()
+}
+
+/// `GET /users/me` as the holder of `token`
+pub async fn me(app: &Router, token: &str) -> Response {
+    send(app, request(Method::GET, "/users/me", Some(token), None)).await
+}
+
+/// `token` with its claims changed by `edit`, re-signed with `JWT_SECRET`
+pub fn resign(token: &str, edit: impl FnOnce(&mut Claims)) -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    let payload = token.split('.').nth(1).expect("token has a payload");
+    let payload = URL_SAFE_NO_PAD.decode(payload).expect("payload is base64");
+    let mut claims: Claims = serde_json::from_slice(&payload).expect("payload is claims");
+    edit(&mut claims);
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .expect("claims encode")
+}
+
+/// Serve `router` on an ephemeral local port for the rest of the test,
+/// returning its base URL
+pub async fn serve(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("ephemeral port binds");
+    let addr = listener.local_addr().expect("bound address");
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    format!("http://{}", addr)
+}
+
+/// A span or event seen by `Captured`, with its fields rendered as strings
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub name: &'static str,
+    pub level: Level,
+    pub fields: HashMap<&'static str, String>,
+}
+
+/// Layer keeping every span and event it sees, for asserting on logs
+#[derive(Clone, Default)]
+pub struct Captured {
+    spans: Arc<Mutex<Spans>>,
+    events: Arc<Mutex<Vec<Record>>>,
+}
+
+/// Closed spans' ids are reused, so records outlive their id's entry
+#[derive(Default)]
+struct Spans {
+    records: Vec<Record>,
+    open: HashMap<Id, usize>,
+}
+
+impl Captured {
+    /// Record everything logged on this thread until the guard drops
+    pub fn install() -> (Self, DefaultGuard) {
+        let captured = Self::default();
+        let guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(captured.clone()));
+        (captured, guard)
+    }
+
+    pub fn spans(&self, name: &str) -> Vec<Record> {
+        let spans = self.spans.lock().unwrap();
+        let records = spans.records.iter().filter(|s| s.name == name);
+        records.cloned().collect()
+    }
+
+    pub fn events(&self) -> Vec<Record> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+impl Visit for Fields<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Captured {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _ctx: Context<'_, S>) {
+        let mut record = Record {
+            name: attrs.metadata().name(),
+            level: *attrs.metadata().level(),
+            fields: HashMap::new(),
+        };
+        attrs.record(&mut Fields(&mut record.fields));
+        let mut spans = self.spans.lock().unwrap();
+        let index = spans.records.len();
+        spans.records.push(record);
+        spans.open.insert(id.clone(), index);
+    }
+
+    fn on_record(&self, id: &Id, values: &tracing::span::Record<'_>, _ctx: Context<'_, S>) {
+        let mut spans = self.spans.lock().unwrap();
+        if let Some(&index) = spans.open.get(id) {
+            values.record(&mut Fields(&mut spans.records[index].fields));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut record = Record {
+            name: event.metadata().name(),
+            level: *event.metadata().level(),
+            fields: HashMap::new(),
+        };
+        event.record(&mut Fields(&mut record.fields));
+        self.events.lock().unwrap().push(record);
+    }
+}
@@ -0,0 +1,157 @@
+use axum_server::accept::Accept;
+use rustls::{Certificate, PrivateKey, ServerConfig, SupportedProtocolVersion};
+use rustls_pemfile::Item;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use tokio_rustls::server::TlsStream;
+
+use crate::config::{TlsConfig, TlsVersion};
+
+static TLS12_AND_UP: &[&SupportedProtocolVersion] =
+    &[&rustls::version::TLS13, &rustls::version::TLS12];
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// Server config for `tls`, accepting no protocol version below its
+/// `min_version`. Otherwise matches what `RustlsConfig::from_pem_file`
+/// builds: rustls' default cipher suites and `h2`/`http/1.1` ALPN.
+pub async fn server_config(tls: &TlsConfig) -> io::Result<ServerConfig> {
+    let cert = tokio::fs::read(&tls.cert_path).await?;
+    let key = tokio::fs::read(&tls.key_path).await?;
+
+    let certs = rustls_pemfile::certs(&mut cert.as_slice())
+        .map(|cert| cert.map(|cert| Certificate(cert.to_vec())))
+        .collect::<Result<Vec<_>, _>>()?;
+    // The key may follow other sections, so check the whole file
+    let mut keys: Vec<PrivateKey> = rustls_pemfile::read_all(&mut key.as_slice())
+        .filter_map(|item| match item.ok()? {
+            Item::Sec1Key(key) => Some(PrivateKey(key.secret_sec1_der().to_vec())),
+            Item::Pkcs1Key(key) => Some(PrivateKey(key.secret_pkcs1_der().to_vec())),
+            Item::Pkcs8Key(key) => Some(PrivateKey(key.secret_pkcs8_der().to_vec())),
+            _ => None,
+        })
+        .collect();
+    if keys.len() != 1 {
+        return Err(invalid_data("expected exactly one private key"));
+    }
+
+    let versions = match tls.min_version {
+        TlsVersion::Tls12 => TLS12_AND_UP,
+        TlsVersion::Tls13 => TLS13_ONLY,
+    };
+    let mut config = ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .map_err(invalid_data)?
+        .with_no_client_auth()
+        .with_single_cert(certs, keys.remove(0))
+        .map_err(invalid_data)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Acceptor that logs each completed handshake's protocol version and
+/// cipher suite at debug
+#[derive(Clone)]
+pub struct LogHandshake<A> {
+    inner: A,
+}
+
+impl<A> LogHandshake<A> {
+    pub fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+impl<A, I, S, T> Accept<I, S> for LogHandshake<A>
+where
+    A: Accept<I, S, Stream = TlsStream<T>>,
+    A::Future: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(A::Stream, A::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let accept = self.inner.accept(stream, service);
+        Box::pin(async move {
+            let (stream, service) = accept.await?;
+            let (_, connection) = stream.get_ref();
+            tracing::debug!(
+                version = ?connection.protocol_version(),
+                cipher_suite = ?connection.negotiated_cipher_suite().map(|suite| suite.suite()),
+                "TLS handshake completed"
+            );
+            Ok((stream, service))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TLS_CERT_PATH, TLS_KEY_PATH};
+    use rustls::{ClientConfig, ProtocolVersion, RootCertStore, ServerName};
+    use std::sync::Arc;
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    /// Handshake between a server requiring `min_version` and a client
+    /// offering only `offered`, returning the version agreed on
+    async fn handshake(
+        min_version: TlsVersion,
+        offered: &[&'static SupportedProtocolVersion],
+    ) -> io::Result<ProtocolVersion> {
+        let server = server_config(&TlsConfig {
+            cert_path: TLS_CERT_PATH.to_string(),
+            key_path: TLS_KEY_PATH.to_string(),
+            min_version,
+        })
+        .await?;
+
+        let cert = std::fs::read(TLS_CERT_PATH)?;
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut cert.as_slice()) {
+            roots.add(&Certificate(cert?.to_vec())).unwrap();
+        }
+        let client = ClientConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(offered)
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let (accepted, connected) = tokio::join!(
+            TlsAcceptor::from(Arc::new(server)).accept(server_io),
+            TlsConnector::from(Arc::new(client))
+                .connect(ServerName::try_from("localhost").unwrap(), client_io),
+        );
+        accepted?;
+        let (_, connection) = connected?.into_inner();
+        Ok(connection.protocol_version().expect("handshake completed"))
+    }
+
+    #[tokio::test]
+    async fn tls13_minimum_refuses_tls12_only_clients() {
+        let result = handshake(TlsVersion::Tls13, &[&rustls::version::TLS12]).await;
+        assert!(result.is_err(), "{:?}", result);
+
+        let version = handshake(TlsVersion::Tls13, TLS12_AND_UP).await.unwrap();
+        assert_eq!(version, ProtocolVersion::TLSv1_3);
+    }
+
+    #[tokio::test]
+    async fn tls12_minimum_still_accepts_tls12_only_clients() {
+        let version = handshake(TlsVersion::Tls12, &[&rustls::version::TLS12])
+            .await
+            .unwrap();
+        assert_eq!(version, ProtocolVersion::TLSv1_2);
+    }
+}